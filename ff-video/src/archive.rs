@@ -0,0 +1,367 @@
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tracing::{debug, info, warn};
+
+use crate::{
+    error::{redact, VideoProcError, VideoResult},
+    spawn::{command, ffmpeg_path, path_arg},
+};
+
+/// How often the copying process is checked on
+const POLL: Duration = Duration::from_millis(100);
+/// Time given to a quitting process to finish its file before it's killed
+const QUIT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest wait before copying again after failures
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// When to continue archiving in a new file, whichever limit is reached first
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rotation {
+    /// Seconds of input per file
+    pub secs: Option<f64>,
+    /// Bytes per file
+    pub bytes: Option<u64>,
+}
+
+/// Which archived files to keep, the oldest ones are removed first
+///
+/// Limits are applied whenever a file is started, to the files finished before.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Retention {
+    /// Most files
+    pub files: Option<usize>,
+    /// Most bytes of all files
+    pub bytes: Option<u64>,
+}
+
+/// Copies an input's streams into files as they are, without decoding them
+///
+/// Each file is written by an ffmpeg process of its own, which reopens the input. Files are
+/// named by the Unix time they were started at. Failed copies are retried after a backoff,
+/// an input ending before it's rotated finishes archiving.
+pub struct FFMpegArchiver {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    pub dir: PathBuf,
+}
+
+/// Settings of each copying process
+struct ArchiveCopy {
+    program: PathBuf,
+    input: Vec<OsString>,
+    dir: PathBuf,
+    ext: String,
+    rotation: Rotation,
+    retention: Retention,
+}
+
+impl ArchiveCopy {
+    fn cmd(&self, path: &Path) -> Command {
+        let mut cmd = command(&self.program);
+        cmd.args(["-hide_banner", "-nostats", "-loglevel", "warning"]);
+        cmd.arg("-i").args(&self.input);
+        // all streams, e.g. audio too
+        cmd.args(["-map", "0", "-c", "copy"]);
+        if let Some(secs) = self.rotation.secs {
+            cmd.args(["-t", &secs.to_string()]);
+        }
+        if let Some(bytes) = self.rotation.bytes {
+            cmd.args(["-fs", &bytes.to_string()]);
+        }
+        cmd.arg("-y").arg(path_arg(path.as_os_str()));
+        cmd.stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::piped());
+        cmd
+    }
+
+    /// File to start next, not overwriting earlier ones
+    fn next_path(&self) -> PathBuf {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = self.dir.join(format!("{secs}.{}", self.ext));
+        (1..)
+            .map(|n| match n {
+                1 => path.clone(),
+                n => self.dir.join(format!("{secs}-{n}.{}", self.ext)),
+            })
+            .find(|p| !p.exists())
+            .unwrap_or(path)
+    }
+
+    /// Files written by archivers into the directory and their sizes, oldest first
+    fn archived(&self) -> std::io::Result<Vec<(PathBuf, u64)>> {
+        let mut files = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension() != Some(OsStr::new(&self.ext)) {
+                continue;
+            }
+            if let Some(started) = path.file_stem().and_then(OsStr::to_str).and_then(started) {
+                let len = fs::metadata(&path)?.len();
+                files.push((started, path, len));
+            }
+        }
+        files.sort();
+        Ok(files.into_iter().map(|(_, path, len)| (path, len)).collect())
+    }
+
+    /// Remove the oldest files beyond retention
+    fn prune(&self) -> std::io::Result<()> {
+        let files = self.archived()?;
+        let mut total = 0;
+        for (kept, (path, len)) in files.iter().rev().enumerate() {
+            total += len;
+            let too_many = self.retention.files.map_or(false, |files| kept >= files);
+            let too_large = self.retention.bytes.map_or(false, |bytes| total > bytes);
+            if too_many || too_large {
+                debug!("removing archived {}", path.display());
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a copy ended by reaching a limit rather than by the input's end
+    ///
+    /// Copies of live inputs take as long as they last, of files that ended far less.
+    fn rotated(&self, path: &Path, took: Duration) -> bool {
+        let size = fs::metadata(path).map_or(0, |m| m.len());
+        let by_size = self.rotation.bytes.map_or(false, |bytes| size >= bytes);
+        let by_time = self.rotation.secs.map_or(false, |secs| took.as_secs_f64() >= secs / 2.0);
+        by_size || by_time
+    }
+}
+
+impl FFMpegArchiver {
+    /// Start copying ffmpeg `input` arguments (following `-i`) into `ext` files in `dir`
+    pub fn start<I, S>(
+        input: I,
+        dir: impl AsRef<Path>,
+        ext: &str,
+        rotation: Rotation,
+        retention: Retention,
+    ) -> VideoResult<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .map_err(|e| VideoProcError::explain_io("couldn't create archive directory", e))?;
+        let copy = ArchiveCopy {
+            program: ffmpeg_path(),
+            input: input.into_iter().map(|s| path_arg(s.as_ref()).into_owned()).collect(),
+            dir: dir.clone(),
+            ext: ext.trim_start_matches('.').to_string(),
+            rotation,
+            retention,
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = thread::Builder::new()
+            .name("archive".to_string())
+            .spawn({
+                let stop = stop.clone();
+                move || archive(copy, &stop)
+            })
+            .map_err(|e| VideoProcError::explain_io("couldn't spawn archive thread", e))?;
+        Ok(Self { stop, thread: Some(thread), dir })
+    }
+
+    /// Whether files are still being written
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().map_or(false, |t| !t.is_finished())
+    }
+
+    /// Finish the current file and stop archiving
+    pub fn stop(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
+
+impl Drop for FFMpegArchiver {
+    fn drop(&mut self) {
+        // the current file is still finished
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Unix time and count within the same second of a file named by [`ArchiveCopy::next_path`]
+fn started(stem: &str) -> Option<(u64, u32)> {
+    let (secs, n) = stem.split_once('-').unwrap_or((stem, "1"));
+    Some((secs.parse().ok()?, n.parse().ok()?))
+}
+
+/// Copy one file after another until stopped
+fn archive(copy: ArchiveCopy, stop: &AtomicBool) {
+    let mut failures = 0;
+    while !stop.load(Ordering::Relaxed) {
+        if let Err(e) = copy.prune() {
+            warn!("couldn't remove old archived files: {}", e);
+        }
+        let path = copy.next_path();
+        let started = Instant::now();
+        let copied = copy.cmd(&path).spawn().and_then(|child| run(child, stop));
+        if stop.load(Ordering::Relaxed) {
+            debug!("stopped archiving into {}", path.display());
+            return;
+        }
+        match copied {
+            Ok(true) if copy.rotated(&path, started.elapsed()) => {
+                debug!("archived {}", path.display());
+                failures = 0;
+                continue;
+            }
+            Ok(true) => {
+                info!("archived input until its end in {}", path.display());
+                return;
+            }
+            Ok(false) => warn!("archiving into {} failed", path.display()),
+            Err(e) => warn!("couldn't archive into {}: {}", path.display(), e),
+        }
+        failures += 1;
+        let backoff = Duration::from_secs(1 << failures.min(5)).min(MAX_BACKOFF);
+        let until = Instant::now() + backoff;
+        while Instant::now() < until && !stop.load(Ordering::Relaxed) {
+            thread::sleep(POLL);
+        }
+    }
+}
+
+/// Wait until a copy exits or quit it once stopped, true if successful
+fn run(mut child: Child, stop: &AtomicBool) -> std::io::Result<bool> {
+    let log_thread = child.stderr.take().map(|stderr| {
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                warn!(target: "ffmpeg", "archiving: {}", redact(&line));
+            }
+        })
+    });
+    let mut quit_at = None;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        match quit_at {
+            None if stop.load(Ordering::Relaxed) => {
+                // finishes the file, e.g. writes an index
+                if let Some(mut stdin) = child.stdin.take() {
+                    // killed after the timeout otherwise
+                    _ = stdin.write_all(b"q");
+                }
+                quit_at = Some(Instant::now());
+            }
+            Some(at) if at.elapsed() > QUIT_TIMEOUT => child.kill()?,
+            _ => {}
+        }
+        thread::sleep(POLL);
+    };
+    if let Some(thread) = log_thread {
+        _ = thread.join();
+    }
+    Ok(status.success() || quit_at.is_some())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn copy_args() {
+        let copy = ArchiveCopy {
+            program: "ffmpeg".into(),
+            input: vec!["rtsp://cam/stream".into()],
+            dir: "archive".into(),
+            ext: "mkv".to_string(),
+            rotation: Rotation { secs: Some(60.0), bytes: Some(1_000_000) },
+            retention: Retention::default(),
+        };
+        let cmd = copy.cmd(Path::new("archive/1.mkv"));
+        let args = cmd.get_args().map(|a| a.to_string_lossy()).collect::<Vec<_>>();
+        let expected = [
+            "-i",
+            "rtsp://cam/stream",
+            "-map",
+            "0",
+            "-c",
+            "copy",
+            "-t",
+            "60",
+            "-fs",
+            "1000000",
+            "-y",
+            "archive/1.mkv",
+        ];
+        assert_eq!(args[4..], expected);
+        assert!(copy.rotated(Path::new("archive/1.mkv"), Duration::from_secs(50)));
+        assert!(!copy.rotated(Path::new("archive/1.mkv"), Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn archive_until_end() {
+        let dir = std::env::temp_dir().join(format!("ff_video_archive_{}", std::process::id()));
+        let video = dir.with_extension("mp4");
+        let mut enc = crate::FFMpegEncoder::create(&video, 32, 24, 10.0).unwrap();
+        for _ in 0..5 {
+            enc.write_rgb(&[128; 32 * 24 * 3]).unwrap();
+        }
+        enc.finish().unwrap();
+
+        let rotation = Rotation { secs: Some(60.0), bytes: None };
+        let archiver =
+            FFMpegArchiver::start([&video], &dir, "mkv", rotation, Retention::default()).unwrap();
+        let started = Instant::now();
+        while archiver.is_running() && started.elapsed() < Duration::from_secs(10) {
+            thread::sleep(POLL);
+        }
+        assert!(!archiver.is_running(), "files end before rotating");
+        archiver.stop();
+        let files = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&video).unwrap();
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn prune_oldest() {
+        let dir = std::env::temp_dir().join(format!("ff_video_prune_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, len) in [("100.mkv", 3), ("100-2.mkv", 3), ("101.mkv", 2), ("102.mkv", 1)] {
+            fs::write(dir.join(name), vec![0; len]).unwrap();
+        }
+        fs::write(dir.join("notes.mkv"), "not archived").unwrap();
+        let mut copy = ArchiveCopy {
+            program: "ffmpeg".into(),
+            input: vec![],
+            dir: dir.clone(),
+            ext: "mkv".to_string(),
+            rotation: Rotation::default(),
+            retention: Retention { files: Some(3), bytes: None },
+        };
+        let names = |copy: &ArchiveCopy| {
+            let files = copy.archived().unwrap().into_iter();
+            files
+                .map(|(p, _)| p.file_name().unwrap().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        };
+        copy.prune().unwrap();
+        assert_eq!(names(&copy), ["100-2.mkv", "101.mkv", "102.mkv"]);
+        copy.retention = Retention { files: None, bytes: Some(4) };
+        copy.prune().unwrap();
+        assert_eq!(names(&copy), ["101.mkv", "102.mkv"]);
+        assert!(dir.join("notes.mkv").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
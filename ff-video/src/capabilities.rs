@@ -0,0 +1,116 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Output,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    error::{VideoProcError, VideoResult},
+    spawn::command,
+};
+
+/// Probed capabilities by executable, only successful probes are kept
+static PROBED: Mutex<Vec<(PathBuf, Arc<Capabilities>)>> = Mutex::new(Vec::new());
+
+/// What an ffmpeg executable supports
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Release like `5.1.2` or of a build from git like `N-109421-g...`
+    pub version: Option<String>,
+    /// Hardware acceleration methods
+    pub hwaccels: Vec<String>,
+}
+
+impl Capabilities {
+    /// Run `program` to find out what it supports
+    pub fn probe(program: &Path) -> VideoResult<Self> {
+        let version = run(program, "-version")?;
+        let hwaccels = run(program, "-hwaccels")?;
+        Ok(Self {
+            version: parse_version(&version).map(str::to_string),
+            hwaccels: parse_hwaccels(&hwaccels).into_iter().map(str::to_string).collect(),
+        })
+    }
+
+    /// Major version of releases, builds from git are assumed to be recent
+    pub fn major(&self) -> Option<u32> {
+        self.version.as_deref()?.split('.').next()?.parse().ok()
+    }
+
+    /// Whether this is at least `major` or possibly newer
+    pub fn since(&self, major: u32) -> bool {
+        self.major().map_or(true, |m| m >= major)
+    }
+
+    /// Whether the hardware acceleration method is available, `auto` always is
+    pub fn has_hwaccel(&self, method: &str) -> bool {
+        method == "auto" || self.hwaccels.iter().any(|m| m == method)
+    }
+}
+
+/// Capabilities of `program`, probed once
+pub fn capabilities(program: &Path) -> VideoResult<Arc<Capabilities>> {
+    let probed = PROBED.lock().unwrap().iter().find(|(p, _)| p == program).map(|(_, c)| c.clone());
+    if let Some(caps) = probed {
+        return Ok(caps);
+    }
+    let caps = Arc::new(Capabilities::probe(program)?);
+    PROBED.lock().unwrap().push((program.to_path_buf(), caps.clone()));
+    Ok(caps)
+}
+
+fn run(program: &Path, arg: &str) -> VideoResult<String> {
+    let Output { status, stdout, .. } = command(program)
+        .args(["-hide_banner", arg])
+        .output()
+        .map_err(|e| VideoProcError::explain_io("couldn't run ffmpeg", e))?;
+    match status.code() {
+        Some(0) => Ok(String::from_utf8_lossy(&stdout).to_string()),
+        Some(code) => Err(VideoProcError::ExitCode(code)),
+        None => Err(VideoProcError::Other("ffmpeg killed by signal".to_string())),
+    }
+}
+
+/// Version from the output of `ffmpeg -version`
+fn parse_version(out: &str) -> Option<&str> {
+    out.lines().next()?.strip_prefix("ffmpeg version ")?.split_whitespace().next()
+}
+
+/// Methods listed by `ffmpeg -hwaccels`
+fn parse_hwaccels(out: &str) -> Vec<&str> {
+    out.lines()
+        .skip_while(|l| !l.starts_with("Hardware acceleration methods"))
+        .skip(1)
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ffmpeg_outputs() {
+        let version = "ffmpeg version 5.1.2 Copyright (c) 2000-2022 the FFmpeg developers\n\
+                       built with gcc 12.2.0";
+        assert_eq!(parse_version(version), Some("5.1.2"));
+        assert_eq!(parse_version("command not found"), None);
+
+        let hwaccels = "Hardware acceleration methods:\nvdpau\ncuda\nvaapi\n\n";
+        assert_eq!(parse_hwaccels(hwaccels), ["vdpau", "cuda", "vaapi"]);
+        assert!(parse_hwaccels("Hardware acceleration methods:\n\n").is_empty());
+    }
+
+    #[test]
+    fn versions() {
+        let caps = |version: &str| Capabilities {
+            version: Some(version.to_string()),
+            hwaccels: vec!["cuda".to_string()],
+        };
+        assert_eq!(caps("5.1.2").major(), Some(5));
+        assert!(!caps("3.4.11").since(4));
+        assert!(caps("N-109421-g3f1a").since(99));
+        assert!(caps("4.4").has_hwaccel("auto") && !caps("4.4").has_hwaccel("vaapi"));
+    }
+}
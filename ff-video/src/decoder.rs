@@ -1,7 +1,12 @@
 use std::{
+    ffi::OsString,
     io::{ErrorKind, Read, Write},
-    process::Command,
-    sync::mpsc::{Receiver, RecvTimeoutError},
+    path::PathBuf,
+    process::{Child, Command},
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc, Mutex, Weak,
+    },
     thread::{self, JoinHandle},
     time::Duration,
 };
@@ -9,48 +14,298 @@ use std::{
 use image_ext::{BgrImage, ImageBuffer};
 use tracing::{debug, error, info, warn};
 
-use crate::{error::VideoResult, parse::FFMpegLineIter};
 use crate::{
-    error::{InfoResult, VideoProcError},
-    parse::{InfoParser, Stream, StreamInfo, VideoInfo},
+    capabilities::{capabilities, Capabilities},
+    error::VideoResult,
+    log::LogLine,
+    parse::FFMpegLineIter,
+    spawn::{command, ffmpeg_path, path_arg, PROGRESS_URL},
+};
+use crate::{
+    error::{redact, InfoResult, VideoProcError},
+    parse::{FrameUpdate, InfoParser, InputFailure, ProgressParser, Stream, StreamInfo, VideoInfo},
 };
 
 pub struct FFMpegDecoderBuilder {
-    cmd: Command,
+    /// The ffmpeg executable
+    program: PathBuf,
+    /// Options that must precede the input
+    input_opts: Vec<String>,
+    input: Vec<OsString>,
+    /// Video filters applied in order
+    filters: Vec<String>,
+    /// Log the component of ffmpeg's messages as a field
+    module_fields: bool,
+    /// Hardware acceleration method to decode with if available
+    hwaccel: Option<String>,
+    /// Report progress by `-progress` instead of stats lines
+    progress_pipe: bool,
+    /// Don't buffer input packets for latency
+    low_delay: bool,
+    /// Bytes read to detect the input's streams, ffmpeg's default if none
+    probesize: Option<u64>,
+    /// Seconds of the input analyzed to detect its streams, ffmpeg's default if none
+    analyzeduration: Option<f64>,
+    /// Reconnect HTTP inputs when they end or fail
+    reconnect: bool,
+}
+
+/// First major version prefixing log lines with `-loglevel +level`
+const LEVEL_PREFIX_SINCE: u32 = 4;
+
+/// Keeps the size of frames once the filter graph was set up, without rescaling them otherwise
+const KEEP_SIZE_FILTER: &str = "scale=w=iw:h=ih:eval=init";
+
+/// How long the output stream may take to be reported, besides analyzing the input
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bytes probed of low latency inputs, ffmpeg's default of 5 MB may take seconds to arrive
+const LOW_LATENCY_PROBESIZE: u64 = 32 * 1024;
+
+/// Seconds analyzed of low latency inputs instead of ffmpeg's default of 5
+const LOW_LATENCY_ANALYZEDURATION: f64 = 0.5;
+
+/// Timestamp to burn into frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BurnIn {
+    /// Local time when the frame was decoded
+    WallClock,
+    /// Presentation timestamp of the frame within the video
+    Pts,
+}
+
+impl BurnIn {
+    /// `drawtext` filter (requires ffmpeg with libfreetype)
+    fn filter(&self) -> String {
+        let text = match self {
+            Self::WallClock => r"%{localtime\:%Y-%m-%d %T}",
+            Self::Pts => r"%{pts\:hms}",
+        };
+        format!(
+            "drawtext=text='{text}':x=8:y=8:fontsize=20:fontcolor=white:box=1:boxcolor=black@0.5"
+        )
+    }
 }
 
 pub struct FFMpegDecoder {
-    child: std::process::Child,
+    child: Arc<Mutex<Child>>,
     stdout: std::process::ChildStdout,
     info_thread: JoinHandle<String>,
+    progress: Arc<Mutex<Option<FrameUpdate>>>,
+    /// Stream infos reported after the start
+    stream_info_rx: Receiver<InfoResult<StreamInfoTerm>>,
     pub frame_counter: u64,
+    /// Output stream, frames keep its size even if the input's size changes
     pub video_output: Stream,
+    /// Input stream and where it's from, if reported before the output
+    pub video_input: Option<(String, Stream)>,
+    /// Of the ffmpeg executable decoding
+    pub capabilities: Arc<Capabilities>,
+}
+
+/// Kills a video process from another thread, e.g. to abort a blocking read
+#[derive(Clone, Debug)]
+pub struct AbortHandle {
+    child: Weak<Mutex<Child>>,
+}
+
+impl AbortHandle {
+    /// Kill the process if it's still around, pending reads fail subsequently
+    pub fn abort(&self) {
+        if let Some(child) = self.child.upgrade() {
+            match child.lock().unwrap().kill() {
+                Ok(_) => info!("aborted video process"),
+                Err(e) => debug!("couldn't abort video process: {}", e),
+            }
+        }
+    }
+}
+
+/// Latest progress reported by a video process, readable from other threads
+#[derive(Clone, Debug)]
+pub struct ProgressHandle {
+    progress: Arc<Mutex<Option<FrameUpdate>>>,
+}
+
+impl ProgressHandle {
+    pub fn latest(&self) -> Option<FrameUpdate> {
+        self.progress.lock().unwrap().clone()
+    }
 }
 
 impl Default for FFMpegDecoderBuilder {
     fn default() -> Self {
-        let mut cmd = Command::new("ffmpeg");
-        // options
-        cmd.arg("-hide_banner");
-        // escape input
-        cmd.arg("-i");
-        Self { cmd }
+        Self {
+            program: ffmpeg_path(),
+            input_opts: vec![],
+            input: vec![],
+            filters: vec![],
+            module_fields: false,
+            hwaccel: None,
+            progress_pipe: false,
+            low_delay: false,
+            probesize: None,
+            analyzeduration: None,
+            reconnect: false,
+        }
     }
 }
 
 impl FFMpegDecoderBuilder {
+    /// Run this ffmpeg executable instead of the default one, see [`ffmpeg_path`]
+    pub fn ffmpeg(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
     pub fn input<I, S>(mut self, input: I) -> Self
     where
         I: IntoIterator<Item = S>,
         S: AsRef<std::ffi::OsStr>,
     {
-        self.cmd.args(input);
+        self.input.extend(input.into_iter().map(|s| path_arg(s.as_ref()).into_owned()));
+        self
+    }
+
+    /// Seek the input to start at `secs`
+    pub fn start(mut self, secs: f64) -> Self {
+        self.input_opts.extend(["-ss".to_string(), secs.to_string()]);
         self
     }
 
-    fn cmd(mut self) -> Command {
+    /// Stop at `secs` of the input's timeline
+    pub fn end(mut self, secs: f64) -> Self {
+        self.input_opts.extend(["-to".to_string(), secs.to_string()]);
+        self
+    }
+
+    /// Draw a timestamp in the top left corner, e.g. to correlate frames with logs
+    pub fn burn_in(mut self, burn_in: BurnIn) -> Self {
+        self.filters.push(burn_in.filter());
+        self
+    }
+
+    /// Log the component of ffmpeg's messages, e.g. `h264 @ 0x5600`, as field `module`
+    /// instead of as part of the message
+    pub fn module_fields(mut self, module_fields: bool) -> Self {
+        self.module_fields = module_fields;
+        self
+    }
+
+    /// Decode with a hardware acceleration method, e.g. `cuda` or `auto`
+    ///
+    /// Methods the ffmpeg executable doesn't have are skipped.
+    pub fn hwaccel(mut self, method: impl Into<String>) -> Self {
+        self.hwaccel = Some(method.into());
+        self
+    }
+
+    /// Report progress by ffmpeg's `key=value` protocol besides scraping its stats lines
+    ///
+    /// The protocol is read from a pipe of its own on Unix, elsewhere it's interleaved with
+    /// stderr. Its lines are well-defined across versions, stats lines remain as fallback.
+    pub fn progress_pipe(mut self, progress_pipe: bool) -> Self {
+        self.progress_pipe = progress_pipe;
+        self
+    }
+
+    /// Open live inputs like cameras quickly and keep their latency low
+    ///
+    /// Packets aren't buffered, streams are detected from a few packets and HTTP inputs
+    /// reconnect.
+    pub fn low_latency(mut self) -> Self {
+        self.low_delay = true;
+        self.probesize = Some(LOW_LATENCY_PROBESIZE);
+        self.analyzeduration = Some(LOW_LATENCY_ANALYZEDURATION);
+        self.reconnect = true;
+        self
+    }
+
+    /// Play local files as ffmpeg does by default, buffered and thoroughly probed
+    pub fn file_playback(mut self) -> Self {
+        self.low_delay = false;
+        self.probesize = None;
+        self.analyzeduration = None;
+        self.reconnect = false;
+        self
+    }
+
+    /// Play remote videos smoothly, buffered and probed briefly, reconnecting HTTP inputs
+    pub fn network_stream(mut self) -> Self {
+        self.low_delay = false;
+        self.probesize = Some(1024 * 1024);
+        self.analyzeduration = Some(2.0);
+        self.reconnect = true;
+        self
+    }
+
+    /// Read at most this many bytes of the input to detect its streams
+    ///
+    /// Fewer start faster but may miss parameters of streams, ffmpeg's minimum is 32.
+    pub fn probesize(mut self, bytes: u64) -> Self {
+        self.probesize = Some(bytes);
+        self
+    }
+
+    /// Analyze at most this many seconds of the input to detect its streams
+    ///
+    /// Opening waits as much longer for the output stream.
+    pub fn analyzeduration(mut self, secs: f64) -> Self {
+        self.analyzeduration = Some(secs);
+        self
+    }
+
+    /// Whether the input is read by ffmpeg's HTTP protocol, which alone can reconnect
+    fn is_http(&self) -> bool {
+        self.input.iter().any(|arg| {
+            let arg = arg.to_string_lossy().to_ascii_lowercase();
+            arg.starts_with("http://") || arg.starts_with("https://")
+        })
+    }
+
+    /// Command with arguments adapted to what the executable supports
+    fn cmd(self, caps: &Capabilities) -> Command {
+        let mut cmd = command(&self.program);
+        // options
+        cmd.arg("-hide_banner");
+        if caps.since(LEVEL_PREFIX_SINCE) {
+            // prefix lines with their level to log them accordingly
+            cmd.args(["-loglevel", "+level"]);
+        }
+        if self.progress_pipe {
+            cmd.args(["-progress", PROGRESS_URL]);
+        }
+        if let Some(method) = &self.hwaccel {
+            if caps.has_hwaccel(method) {
+                cmd.args(["-hwaccel", method]);
+            } else {
+                warn!("skipping unavailable hardware acceleration {}", method);
+            }
+        }
+        if self.low_delay {
+            cmd.args(["-fflags", "nobuffer", "-flags", "low_delay"]);
+        }
+        if let Some(bytes) = self.probesize {
+            cmd.args(["-probesize", &bytes.to_string()]);
+        }
+        if let Some(secs) = self.analyzeduration {
+            // in microseconds
+            cmd.args(["-analyzeduration", &((secs * 1e6) as u64).to_string()]);
+        }
+        // other protocols fail on options they don't know
+        if self.reconnect && self.is_http() {
+            cmd.args(["-reconnect", "1", "-reconnect_streamed", "1", "-reconnect_delay_max", "5"]);
+        }
+        cmd.args(&self.input_opts);
+        // frames of another size mid-stream are scaled to the first, instead of being piped at a
+        // size ffmpeg doesn't report again
+        cmd.args(["-reinit_filter", "0"]);
+        // escape input
+        cmd.arg("-i").args(&self.input);
+        let filters = self.filters.iter().map(String::as_str).chain([KEEP_SIZE_FILTER]);
+        cmd.args(["-vf", &filters.collect::<Vec<_>>().join(",")]);
         // output
-        self.cmd.args([
+        cmd.args([
             "-an",
             "-f",
             "image2pipe",
@@ -64,32 +319,63 @@ impl FFMpegDecoderBuilder {
         ]);
         // piping
         use std::process::Stdio;
-        self.cmd.stderr(Stdio::piped()).stdout(Stdio::piped()).stdin(Stdio::piped());
-        // todo: rm
-        eprintln!(
+        cmd.stderr(Stdio::piped()).stdout(Stdio::piped()).stdin(Stdio::piped());
+        debug!(
             "ffmpeg {}",
-            self.cmd.get_args().map(|s| s.to_string_lossy()).collect::<Vec<_>>().join(" ")
+            cmd.get_args().map(|s| redact(&s.to_string_lossy())).collect::<Vec<_>>().join(" ")
         );
-        self.cmd
+        cmd
     }
 }
 
 impl FFMpegDecoder {
     pub fn try_new(builder: FFMpegDecoderBuilder) -> VideoResult<Self> {
-        let mut cmd = builder.cmd();
+        // echoed by errors, ffmpeg doesn't always name it
+        let input = builder.input.first().map(|i| i.to_string_lossy().to_string());
+        let module_fields = builder.module_fields;
+        let progress_pipe = builder.progress_pipe;
+        let analyzing = builder.analyzeduration.and_then(|s| Duration::try_from_secs_f64(s).ok());
+        let start_timeout = START_TIMEOUT + analyzing.unwrap_or_default();
+        // without it, spawning fails anyway
+        let capabilities = capabilities(&builder.program).unwrap_or_else(|e| {
+            warn!("couldn't probe ffmpeg: {}", e);
+            Arc::default()
+        });
+        let mut cmd = builder.cmd(&capabilities);
+        #[cfg(unix)]
+        let piped = progress_pipe
+            .then(|| crate::spawn::progress_pipe(&mut cmd))
+            .transpose()
+            .map_err(|e| VideoProcError::explain_io("couldn't pipe progress", e))?;
         let mut child = cmd
             .spawn()
             .map_err(|e| VideoProcError::explain_io("couldn't spawn video process", e))?;
         let stderr =
             child.stderr.take().ok_or_else(|| VideoProcError::is_missing("stderr pipe"))?;
-        let (stream_info_rx, info_thread) = spawn_info_thread(stderr)?;
+        let progress = Arc::new(Mutex::new(None));
+        #[cfg(unix)]
+        if let Some((pipe, passed)) = piped {
+            drop(passed);
+            spawn_progress_thread(pipe, progress.clone())?;
+        }
+        // protocol lines are told apart from log lines
+        let interleaved = progress_pipe && !cfg!(unix);
+        let (stream_info_rx, info_thread) =
+            spawn_info_thread(stderr, progress.clone(), module_fields, interleaved)?;
 
         // determine output
         let mut final_line = None;
+        let mut video_input = None;
+        // e.g. of an audio stream, fails only if no video is output
+        let mut unsupported = None;
         let video_output = loop {
-            let msg = match stream_info_rx.recv_timeout(Duration::from_secs(10)) {
+            let msg = match stream_info_rx.recv_timeout(start_timeout) {
                 Ok(msg) => msg,
                 Err(e) => {
+                    if let Some(failure) = unsupported {
+                        let input = input.unwrap_or_default();
+                        return Err(VideoProcError::input_failed(failure, input));
+                    }
                     let why = match e {
                         RecvTimeoutError::Timeout => "timeout",
                         RecvTimeoutError::Disconnected => "disconnected",
@@ -99,26 +385,54 @@ impl FFMpegDecoder {
                         None => why.to_string(),
                         Some(line) => format!("{why} - {line}"),
                     };
-                    return Err(VideoProcError::Start(explanation));
+                    return Err(VideoProcError::Start(redact(&explanation)));
                 }
             };
-            if let Ok(StreamInfoTerm::Info(StreamInfo::Output { stream, .. })) = msg {
-                break stream;
-            };
-            if let Ok(StreamInfoTerm::Final(line)) = msg {
-                final_line = Some(line);
+            match msg {
+                Ok(StreamInfoTerm::Info(StreamInfo::Output { stream, .. })) => break stream,
+                Ok(StreamInfoTerm::Info(StreamInfo::Input { from, stream })) => {
+                    video_input = Some((from, stream));
+                }
+                Ok(StreamInfoTerm::Failure(failure @ InputFailure::UnsupportedCodec(_))) => {
+                    unsupported = Some(failure);
+                }
+                Ok(StreamInfoTerm::Failure(failure)) => {
+                    return Err(VideoProcError::input_failed(failure, input.unwrap_or_default()));
+                }
+                Ok(StreamInfoTerm::Final(line)) => final_line = Some(line),
+                Err(_) => {}
             }
         };
 
         let stdout =
             child.stdout.take().ok_or_else(|| VideoProcError::is_missing("stdout pipe"))?;
-        Ok(Self { child, stdout, info_thread, video_output, frame_counter: 0 })
+        let child = Arc::new(Mutex::new(child));
+        Ok(Self {
+            child,
+            stdout,
+            info_thread,
+            progress,
+            stream_info_rx,
+            video_output,
+            video_input,
+            capabilities,
+            frame_counter: 0,
+        })
+    }
+
+    pub fn abort_handle(&self) -> AbortHandle {
+        AbortHandle { child: Arc::downgrade(&self.child) }
+    }
+
+    pub fn progress_handle(&self) -> ProgressHandle {
+        ProgressHandle { progress: self.progress.clone() }
     }
 
     /// stop process gracefully and await exit code
-    pub fn close(mut self) -> VideoResult<()> {
+    pub fn close(self) -> VideoResult<()> {
+        let mut child = self.child.lock().unwrap();
         let mut stdin =
-            self.child.stdin.take().ok_or_else(|| VideoProcError::is_missing("stdin pipe"))?;
+            child.stdin.take().ok_or_else(|| VideoProcError::is_missing("stdin pipe"))?;
         // to close, we first send quit message (stdin is available since we encode nothing from it)
         // any stdin buffer is flushed in the drop() of wait()
         // thus it should break output pipe since ffmpeg has already hung up
@@ -132,10 +446,9 @@ impl FFMpegDecoder {
         //  ... unless we don't drain stdout as well, which we do here
         self.stdout.bytes().for_each(|_| {});
 
-        let exit_code = self
-            .child
-            .wait()
-            .map_err(|e| VideoProcError::explain_io("waiting on video process", e))?;
+        let exit_code =
+            child.wait().map_err(|e| VideoProcError::explain_io("waiting on video process", e))?;
+        drop(child);
         _ = self
             .info_thread
             .join()
@@ -152,23 +465,52 @@ impl FFMpegDecoder {
         ImageBuffer::new(width, height)
     }
 
+    /// Take input infos reported since, the output keeps the size it started with
+    fn poll_streams(&mut self) {
+        for msg in self.stream_info_rx.try_iter() {
+            if let Ok(StreamInfoTerm::Info(StreamInfo::Input { from, stream })) = msg {
+                self.video_input = Some((from, stream));
+            }
+        }
+    }
+
     /// Write new image and return its frame id.
+    ///
+    /// The image is resized to the output stream if it differs.
     pub fn read_frame(&mut self, image: &mut BgrImage) -> VideoResult<u64> {
-        self.stdout.read_exact(image.as_mut()).map_err(|e| match self.child.try_wait() {
-            Ok(Some(status)) if status.code() == Some(0) => {
-                VideoProcError::FinishedNormally { source: e }
+        self.poll_streams();
+        let size = (self.video_output.width, self.video_output.height);
+        read_image(&mut self.stdout, image, size).map_err(|e| {
+            match self.child.lock().unwrap().try_wait() {
+                Ok(Some(status)) if status.code() == Some(0) => {
+                    VideoProcError::FinishedNormally { source: e }
+                }
+                _ => VideoProcError::ExactReadError { source: e },
             }
-            _ => VideoProcError::ExactReadError { source: e },
         })?;
         self.frame_counter += 1;
         Ok(self.frame_counter)
     }
 }
 
+/// Read raw BGR pixels of the next image, reallocated if its size changed
+pub(crate) fn read_image(
+    pixels: &mut impl Read,
+    image: &mut BgrImage,
+    (width, height): (u32, u32),
+) -> std::io::Result<()> {
+    if image.dimensions() != (width, height) {
+        *image = ImageBuffer::new(width, height);
+    }
+    pixels.read_exact(image.as_mut())
+}
+
 /// StreamInfo or variant to signal EOF
 enum StreamInfoTerm {
     /// Video IO stream infos
     Info(StreamInfo),
+    /// No video can be decoded
+    Failure(InputFailure),
     /// Last line read without parsing after which
     /// no more messages will be sent
     Final(String),
@@ -177,18 +519,24 @@ enum StreamInfoTerm {
 /// Deliver infos about an ffmpeg video process through its stderr file
 ///
 /// The receiver can be read until satisfying info was obtained and dropped anytime.
+/// The latest frame update is kept in `progress`, also of the progress protocol if `interleaved`.
 /// By default, frame updates and other infos are logged as tracing event.
+/// Messages of ffmpeg's components, its warnings and errors are logged at their level
+/// to target `ffmpeg`.
 /// The last line is returned if the thread joins without errors.
 ///
 /// todo: offer a custom callback for info messages
 fn spawn_info_thread<R>(
     stderr: R,
+    progress: Arc<Mutex<Option<FrameUpdate>>>,
+    module_fields: bool,
+    interleaved: bool,
 ) -> VideoResult<(Receiver<InfoResult<StreamInfoTerm>>, JoinHandle<String>)>
 where
     R: Read + Send + 'static,
 {
-    let (stream_info_tx, stream_info_rx) =
-        std::sync::mpsc::sync_channel::<InfoResult<StreamInfoTerm>>(2);
+    // unbounded since later infos are only taken with frames
+    let (stream_info_tx, stream_info_rx) = std::sync::mpsc::channel::<InfoResult<StreamInfoTerm>>();
 
     let info_thread = thread::Builder::new()
         .name("Video".to_string())
@@ -202,6 +550,24 @@ where
                 }
                 Ok(r) => Some(r),
             });
+            let lines = lines.map(|line| {
+                let line = LogLine::parse(&line);
+                if line.is_notable() {
+                    line.log(module_fields);
+                }
+                line.unleveled()
+            });
+            let mut progress_parser = ProgressParser::default();
+            let lines = lines.filter(|line| {
+                if !interleaved || !ProgressParser::is_progress(line) {
+                    return true;
+                }
+                if let Some(update) = progress_parser.push(line) {
+                    log_info_handler(Ok(VideoInfo::Frame(update.clone())));
+                    *progress.lock().unwrap() = Some(update);
+                }
+                false
+            });
             //.inspect(|l| println!("!!{}", l));
 
             // Delivery semantics depend on the message type:
@@ -215,6 +581,14 @@ where
                             .map_err(|e| warn!("could not send stream info: {:?}", e));
                         log_info_handler(Ok(VideoInfo::Stream(msg)));
                     }
+                    Ok(VideoInfo::Failure(failure)) => {
+                        _ = stream_info_tx.send(Ok(StreamInfoTerm::Failure(failure.clone())));
+                        log_info_handler(Ok(VideoInfo::Failure(failure)));
+                    }
+                    Ok(VideoInfo::Frame(msg)) => {
+                        *progress.lock().unwrap() = Some(msg.clone());
+                        log_info_handler(Ok(VideoInfo::Frame(msg)));
+                    }
                     Ok(msg) => {
                         log_info_handler(Ok(msg));
                     }
@@ -224,7 +598,8 @@ where
                     }
                 };
             }
-            let last_line = String::from_utf8_lossy(ffmpeg_lines.state()).to_string();
+            let last_line = String::from_utf8_lossy(ffmpeg_lines.state());
+            let last_line = LogLine::parse(&last_line).unleveled();
             info!("finished reading stderr: {}", &last_line);
             _ = stream_info_tx.send(Ok(StreamInfoTerm::Final(last_line.clone())));
             last_line
@@ -233,6 +608,30 @@ where
     Ok((stream_info_rx, info_thread))
 }
 
+/// Keep the latest update of the progress protocol read from a pipe of its own in `progress`
+#[cfg(unix)]
+fn spawn_progress_thread<R>(pipe: R, progress: Arc<Mutex<Option<FrameUpdate>>>) -> VideoResult<()>
+where
+    R: Read + Send + 'static,
+{
+    use std::io::BufRead;
+
+    thread::Builder::new()
+        .name("Progress".to_string())
+        .spawn(move || {
+            let mut parser = ProgressParser::default();
+            // ends with the process
+            for line in std::io::BufReader::new(pipe).lines().map_while(Result::ok) {
+                if let Some(update) = parser.push(&line) {
+                    log_info_handler(Ok(VideoInfo::Frame(update.clone())));
+                    *progress.lock().unwrap() = Some(update);
+                }
+            }
+        })
+        .map_err(|e| VideoProcError::explain_io("couldn't spawn progress thread", e))?;
+    Ok(())
+}
+
 fn log_info_handler(msg: crate::parse::Result) {
     match msg {
         Ok(msg) => match msg {
@@ -242,8 +641,10 @@ fn log_info_handler(msg: crate::parse::Result) {
             VideoInfo::Frame(msg) => {
                 debug!("frame: {:?}", msg);
             }
-            VideoInfo::Codec(msg) => {
-                info!("codec: {}", msg);
+            // logged at its level before parsing
+            VideoInfo::Codec(_) => {}
+            VideoInfo::Failure(failure) => {
+                warn!("video input failed: {:?}", failure);
             }
         },
         Err(e) => {
@@ -251,3 +652,103 @@ fn log_info_handler(msg: crate::parse::Result) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn burn_in_filter() {
+        let cmd = FFMpegDecoderBuilder::default().input(["in.mp4"]).burn_in(BurnIn::Pts);
+        let cmd = cmd.cmd(&Capabilities::default());
+        let args = cmd.get_args().map(|a| a.to_string_lossy()).collect::<Vec<_>>();
+        let vf = args.iter().position(|a| a == "-vf").unwrap();
+        assert_eq!(args[vf - 1], "in.mp4");
+        assert!(args[vf + 1].starts_with(r"drawtext=text='%{pts\:hms}'"));
+        assert!(args[vf + 1].ends_with(KEEP_SIZE_FILTER), "frames keep their size");
+    }
+
+    #[test]
+    fn read_truncated_images() {
+        let mut image = BgrImage::default();
+        let mut pixels = &[7u8; 15][..];
+        read_image(&mut pixels, &mut image, (2, 2)).unwrap();
+        assert_eq!((image.dimensions(), image.as_raw()[11]), ((2, 2), 7));
+        let e = read_image(&mut pixels, &mut image, (1, 2)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn missing_input() {
+        let builder = FFMpegDecoderBuilder::default().input(["missing.mp4"]);
+        let e = FFMpegDecoder::try_new(builder).err().unwrap();
+        assert!(matches!(e, VideoProcError::NotFound(input) if input == "missing.mp4"));
+    }
+
+    #[test]
+    fn progress_args() {
+        let cmd = FFMpegDecoderBuilder::default().input(["in.mp4"]).progress_pipe(true);
+        let cmd = cmd.cmd(&Capabilities::default());
+        let args = cmd.get_args().map(|a| a.to_string_lossy()).collect::<Vec<_>>();
+        let progress = args.iter().position(|a| a == "-progress").unwrap();
+        assert_eq!(args[progress + 1], PROGRESS_URL);
+        // stats lines remain as fallback
+        assert!(!args.contains(&"-nostats".into()));
+    }
+
+    #[test]
+    fn presets() {
+        let args = |builder: FFMpegDecoderBuilder| {
+            let cmd = builder.cmd(&Capabilities::default());
+            cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect::<Vec<_>>()
+        };
+        let camera = args(FFMpegDecoderBuilder::default().input(["rtsp://cam"]).low_latency());
+        let input = camera.iter().position(|a| a == "-i").unwrap();
+        let before_input = &camera[..input];
+        assert!(before_input.windows(2).any(|w| w == ["-fflags", "nobuffer"]));
+        assert!(before_input.windows(2).any(|w| w == ["-analyzeduration", "500000"]));
+        assert!(!camera.contains(&"-reconnect".to_string()), "only HTTP reconnects");
+
+        let remote = FFMpegDecoderBuilder::default().input(["HTTPS://host/a.mp4"]);
+        let remote = args(remote.low_latency().network_stream());
+        assert!(remote.contains(&"-reconnect".to_string()));
+        assert!(!remote.contains(&"low_delay".to_string()));
+
+        let tuned = FFMpegDecoderBuilder::default().input(["rtsp://cam"]).low_latency();
+        let tuned = args(tuned.probesize(32).analyzeduration(0.0));
+        assert!(tuned.windows(2).any(|w| w == ["-probesize", "32"]));
+        assert!(tuned.windows(2).any(|w| w == ["-analyzeduration", "0"]));
+
+        let file = args(FFMpegDecoderBuilder::default().input(["a.mp4"]).file_playback());
+        assert_eq!(file, args(FFMpegDecoderBuilder::default().input(["a.mp4"])));
+    }
+
+    #[test]
+    fn clip_before_input() {
+        let cmd = FFMpegDecoderBuilder::default().input(["in.mp4"]).start(1.5).end(3.0);
+        let cmd = cmd.cmd(&Capabilities::default());
+        let args = cmd.get_args().map(|a| a.to_string_lossy()).collect::<Vec<_>>();
+        assert_eq!(args[3..11], ["-ss", "1.5", "-to", "3", "-reinit_filter", "0", "-i", "in.mp4"]);
+    }
+
+    #[test]
+    fn adapt_to_capabilities() {
+        let args = |version: &str, hwaccel: &str| {
+            let caps = Capabilities {
+                version: Some(version.to_string()),
+                hwaccels: vec!["cuda".to_string()],
+            };
+            let cmd = FFMpegDecoderBuilder::default().input(["in.mp4"]).hwaccel(hwaccel);
+            let cmd = cmd.cmd(&caps);
+            cmd.get_args().map(|a| a.to_string_lossy().to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            args("5.1.2", "cuda")[..5],
+            ["-hide_banner", "-loglevel", "+level", "-hwaccel", "cuda"]
+        );
+        assert_eq!(
+            args("3.4.11", "vaapi")[..5],
+            ["-hide_banner", "-reinit_filter", "0", "-i", "in.mp4"]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    ffi::OsStr,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Stdio},
+};
+
+use crate::{
+    error::{VideoProcError, VideoResult},
+    spawn::{command, ffmpeg_path, path_arg},
+};
+
+/// Encodes RGB frames into a video file by an ffmpeg process
+///
+/// The format follows the file's extension, GIFs get a palette of their own.
+pub struct FFMpegEncoder {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    frame_len: usize,
+    pub path: PathBuf,
+}
+
+impl FFMpegEncoder {
+    /// Start encoding frames of `width` x `height` at `fps` into `path`, which is overwritten
+    pub fn create(path: impl AsRef<Path>, width: u32, height: u32, fps: f64) -> VideoResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut cmd = command(ffmpeg_path());
+        cmd.args(["-hide_banner", "-nostats", "-loglevel", "error", "-y"]);
+        cmd.args(["-f", "rawvideo", "-pix_fmt", "rgb24"]);
+        cmd.args(["-s", &format!("{width}x{height}"), "-r", &fps.to_string(), "-i", "pipe:0"]);
+        let gif = path.extension().and_then(OsStr::to_str).map(str::to_lowercase);
+        if gif.as_deref() == Some("gif") {
+            cmd.args(["-vf", "split[a][b];[a]palettegen[p];[b][p]paletteuse"]);
+        } else {
+            // most players only support 4:2:0, which needs even dimensions
+            cmd.args(["-vf", "scale=trunc(iw/2)*2:trunc(ih/2)*2", "-pix_fmt", "yuv420p"]);
+        }
+        cmd.arg(path_arg(path.as_os_str()));
+        cmd.stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::piped());
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| VideoProcError::explain_io("couldn't spawn encoding process", e))?;
+        let stdin = child.stdin.take();
+        let frame_len = width as usize * height as usize * 3;
+        Ok(Self { child, stdin, frame_len, path })
+    }
+
+    /// Encode the next frame of packed RGB pixels
+    pub fn write_rgb(&mut self, rgb: &[u8]) -> VideoResult<()> {
+        if rgb.len() != self.frame_len {
+            return Err(VideoProcError::Other(format!(
+                "frame of {} bytes doesn't match encoded size of {} bytes",
+                rgb.len(),
+                self.frame_len
+            )));
+        }
+        let stdin = self.stdin.as_mut().ok_or_else(|| VideoProcError::is_missing("stdin pipe"))?;
+        stdin.write_all(rgb).map_err(|e| VideoProcError::explain_io("couldn't write frame", e))
+    }
+
+    /// Wait until all frames are encoded
+    pub fn finish(mut self) -> VideoResult<PathBuf> {
+        // closing stdin ends the input
+        drop(self.stdin.take());
+        let output = self
+            .child
+            .wait_with_output()
+            .map_err(|e| VideoProcError::explain_io("couldn't wait on encoding process", e))?;
+        match output.status.code() {
+            Some(0) => Ok(self.path),
+            Some(code) if output.stderr.is_empty() => Err(VideoProcError::ExitCode(code)),
+            _ => Err(VideoProcError::Other(String::from_utf8_lossy(&output.stderr).to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{FFMpegDecoder, FFMpegDecoderBuilder};
+
+    fn encode(path: &Path, frames: u8) {
+        let mut enc = FFMpegEncoder::create(path, 33, 20, 10.0).unwrap();
+        for i in 0..frames {
+            enc.write_rgb(&vec![i * 20; 33 * 20 * 3]).unwrap();
+        }
+        enc.finish().unwrap();
+    }
+
+    #[test]
+    fn encode_decode() {
+        let dir = std::env::temp_dir();
+        for ext in ["mp4", "gif"] {
+            let path = dir.join(format!("ff_video_encode_{}.{ext}", std::process::id()));
+            encode(&path, 5);
+            let builder = FFMpegDecoderBuilder::default().input([&path]);
+            let mut vid = FFMpegDecoder::try_new(builder).unwrap();
+            let mut img = vid.empty_image();
+            let mut frames = 0;
+            while vid.read_frame(&mut img).is_ok() {
+                frames += 1;
+            }
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(frames, 5, "{ext} should have all frames");
+        }
+    }
+
+    #[test]
+    fn reject_wrong_size() {
+        let path = std::env::temp_dir().join(format!("ff_video_size_{}.mp4", std::process::id()));
+        let mut enc = FFMpegEncoder::create(&path, 4, 4, 1.0).unwrap();
+        assert!(enc.write_rgb(&[0; 3]).is_err());
+        let _ = enc.finish();
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::parse::ParseError;
+use crate::parse::{InputFailure, ParseError};
 pub use thiserror::Error;
 
 /// Results from processing a video.
@@ -26,6 +26,18 @@ pub enum VideoProcError {
     },
     #[error("couldn't parse stream info in time ({0})")]
     Start(String),
+    #[error("input has no video stream")]
+    NoVideoStream,
+    #[error("unsupported video codec {0}")]
+    UnsupportedCodec(String),
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("connection refused by {0}")]
+    ConnectionRefused(String),
+    #[error("authentication failed for {0}")]
+    Unauthorized(String),
+    #[error("invalid data found in {0}")]
+    InvalidData(String),
     #[error("couldn't obtain {0}")]
     MissingValue(String),
     #[error("video process exit code: {0}")]
@@ -41,6 +53,39 @@ impl VideoProcError {
     pub(crate) fn explain_io(msg: impl ToString, e: std::io::Error) -> Self {
         Self::IO { msg: msg.to_string(), source: e }
     }
+
+    /// Failure of opening `input` as reported by ffmpeg
+    pub(crate) fn input_failed(failure: InputFailure, input: impl ToString) -> Self {
+        let input = redact(&input.to_string());
+        match failure {
+            InputFailure::NoVideoStream => Self::NoVideoStream,
+            InputFailure::UnsupportedCodec(codec) => Self::UnsupportedCodec(codec),
+            InputFailure::NotFound => Self::NotFound(input),
+            InputFailure::ConnectionRefused => Self::ConnectionRefused(input),
+            InputFailure::Unauthorized => Self::Unauthorized(input),
+            InputFailure::InvalidData => Self::InvalidData(input),
+        }
+    }
+}
+
+/// Text with credentials of URLs in it masked, e.g. `rtsp://***@cam` for `rtsp://user:pw@cam`
+pub fn redact(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find("://") {
+        let (scheme, tail) = rest.split_at(i + 3);
+        redacted.push_str(scheme);
+        let end = tail.find(|c: char| "/?#'\"".contains(c) || c.is_whitespace());
+        rest = match tail[..end.unwrap_or(tail.len())].rfind('@') {
+            Some(at) => {
+                redacted.push_str("***");
+                &tail[at..]
+            }
+            None => tail,
+        };
+    }
+    redacted.push_str(rest);
+    redacted
 }
 
 #[derive(Error, Debug)]
@@ -61,6 +106,14 @@ mod tests {
     // This will fail to compile if the size of this type is large.
     const ASSERT_SMALLISH: usize = [0][(mem::size_of::<FFVideoError>() >= 200) as usize];
 
+    #[test]
+    fn redact_credentials() {
+        let text = "from 'rtsp://user:p@ss@cam:554/live?a=@' to http://host/a@b, rtsp://cam";
+        let redacted = "from 'rtsp://***@cam:554/live?a=@' to http://host/a@b, rtsp://cam";
+        assert_eq!(redact(text), redacted);
+        assert_eq!(redact("video.mp4"), "video.mp4");
+    }
+
     #[test]
     fn test_send_sync_stability() {
         fn assert_send_sync<T: Send + Sync>() {}
@@ -0,0 +1,35 @@
+//! Entry points of the fuzz targets in `fuzz/`, not part of the API
+use std::io::Read;
+
+use image_ext::BgrImage;
+
+use crate::{
+    decoder::read_image,
+    log::LogLine,
+    parse::{FFMpegLineIter, InfoParser},
+};
+
+/// Parse arbitrary bytes as ffmpeg's stderr like the info thread does, returns infos parsed
+pub fn parse_stderr(bytes: &[u8]) -> usize {
+    let lines = bytes.bytes().ffmpeg_lines().filter_map(Result::ok);
+    let lines = lines.map(|line| LogLine::parse(&line).unleveled());
+    InfoParser::default().iter_on(lines).filter(Result::is_ok).count()
+}
+
+/// Read frames of a size given by the first two bytes from the rest, returns frames read
+pub fn read_frames(bytes: &[u8]) -> usize {
+    let (size, mut pixels) = match bytes {
+        [width, height, pixels @ ..] => ((*width as u32, *height as u32), pixels),
+        _ => return 0,
+    };
+    let mut image = BgrImage::default();
+    let mut frames = 0;
+    while read_image(&mut pixels, &mut image, size).is_ok() {
+        frames += 1;
+        if size.0 * size.1 == 0 {
+            // nothing to read would loop forever
+            break;
+        }
+    }
+    frames
+}
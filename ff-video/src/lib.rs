@@ -1,6 +1,19 @@
+mod archive;
+mod capabilities;
 mod decoder;
+mod encoder;
 mod error;
+#[cfg(feature = "fuzz")]
+#[doc(hidden)]
+pub mod fuzz;
+mod log;
 mod parse;
+mod spawn;
 
-pub use crate::error::{FFVideoError, VideoProcError, VideoResult};
-pub use decoder::{FFMpegDecoder, FFMpegDecoderBuilder};
+pub use crate::archive::{FFMpegArchiver, Retention, Rotation};
+pub use crate::capabilities::{capabilities, Capabilities};
+pub use crate::error::{redact, FFVideoError, VideoProcError, VideoResult};
+pub use decoder::{AbortHandle, BurnIn, FFMpegDecoder, FFMpegDecoderBuilder, ProgressHandle};
+pub use encoder::FFMpegEncoder;
+pub use parse::{FrameUpdate, Stream};
+pub use spawn::{ffmpeg_command, ffmpeg_path};
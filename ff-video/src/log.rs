@@ -0,0 +1,107 @@
+use tracing::{event, Level};
+
+use crate::error::redact;
+
+/// Target of ffmpeg's own messages, e.g. to filter them by `ffmpeg=warn`
+const TARGET: &str = "ffmpeg";
+
+/// Tracing level of ffmpeg's level as prefixed with `-loglevel +level`
+fn level(name: &str) -> Option<Level> {
+    match name {
+        "panic" | "fatal" | "error" => Some(Level::ERROR),
+        "warning" => Some(Level::WARN),
+        "info" => Some(Level::INFO),
+        "verbose" | "debug" => Some(Level::DEBUG),
+        "trace" => Some(Level::TRACE),
+        _ => None,
+    }
+}
+
+/// Content of a leading `[...]` and the rest after its space
+fn bracketed(line: &str) -> Option<(&str, &str)> {
+    let (inner, rest) = line.strip_prefix('[')?.split_once(']')?;
+    Some((inner, rest.strip_prefix(' ').unwrap_or(rest)))
+}
+
+/// Line of ffmpeg's log split into its level, module and message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LogLine {
+    /// Mapped from ffmpeg's level, if prefixed
+    pub(crate) level: Option<Level>,
+    /// Component that logged, e.g. `h264 @ 0x5600`
+    pub(crate) module: Option<String>,
+    pub(crate) msg: String,
+}
+
+impl LogLine {
+    /// Split a line formatted as `[module] [level] msg`, where both prefixes are optional
+    pub(crate) fn parse(line: &str) -> Self {
+        let (module, rest) = match bracketed(line) {
+            Some((inner, rest)) if level(inner).is_none() => (Some(inner.to_string()), rest),
+            _ => (None, line),
+        };
+        let (level, msg) = match bracketed(rest).and_then(|(inner, msg)| Some((level(inner)?, msg)))
+        {
+            Some((level, msg)) => (Some(level), msg),
+            None => (None, rest),
+        };
+        Self { level, module, msg: msg.to_string() }
+    }
+
+    /// Line as logged without levels, which is what parsing expects
+    pub(crate) fn unleveled(&self) -> String {
+        match &self.module {
+            Some(module) => format!("[{}] {}", module, self.msg),
+            None => self.msg.clone(),
+        }
+    }
+
+    /// Messages of components, warnings and errors, others are logged once parsed
+    pub(crate) fn is_notable(&self) -> bool {
+        self.module.is_some() || self.level.map_or(false, |level| level <= Level::WARN)
+    }
+
+    /// Log at the mapped level, info if unknown, with the module as field if `module_field`
+    pub(crate) fn log(&self, module_field: bool) {
+        let module = self.module.as_deref().filter(|_| module_field);
+        let msg = match module {
+            Some(_) => redact(&self.msg),
+            None => redact(&self.unleveled()),
+        };
+        // levels of events are static
+        match self.level.unwrap_or(Level::INFO) {
+            Level::ERROR => event!(target: TARGET, Level::ERROR, module, "{}", msg),
+            Level::WARN => event!(target: TARGET, Level::WARN, module, "{}", msg),
+            Level::INFO => event!(target: TARGET, Level::INFO, module, "{}", msg),
+            Level::DEBUG => event!(target: TARGET, Level::DEBUG, module, "{}", msg),
+            _ => event!(target: TARGET, Level::TRACE, module, "{}", msg),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_levels_and_modules() {
+        let line = LogLine::parse("[h264 @ 0x5600] [warning] no frame!");
+        assert_eq!(line.level, Some(Level::WARN));
+        assert_eq!(line.module.as_deref(), Some("h264 @ 0x5600"));
+        assert_eq!(line.unleveled(), "[h264 @ 0x5600] no frame!");
+        assert!(line.is_notable());
+
+        let line = LogLine::parse("[info]   Stream #0:0: Video: h264");
+        assert_eq!((line.level, line.module.as_deref()), (Some(Level::INFO), None));
+        assert_eq!(line.unleveled(), "  Stream #0:0: Video: h264");
+        assert!(!line.is_notable());
+
+        let line = LogLine::parse("[fatal] missing.mp4: No such file or directory");
+        assert_eq!(line.level, Some(Level::ERROR));
+        assert!(line.is_notable());
+
+        let line = LogLine::parse("frame=  1 fps=0.0");
+        assert_eq!((line.level, line.module), (None, None));
+        assert_eq!(line.msg, "frame=  1 fps=0.0");
+    }
+}
@@ -2,6 +2,8 @@
 
 use std::{error::Error, fmt::Display};
 
+use crate::error::redact;
+
 /// Describes one video stream
 #[derive(Debug, Clone, PartialEq)]
 pub struct Stream {
@@ -9,6 +11,15 @@ pub struct Stream {
     pub width: u32,
     pub height: u32,
     pub fps: Option<f32>,
+    /// Name of the codec, e.g. `h264`
+    pub codec: Option<String>,
+}
+
+impl Stream {
+    /// First stream of a video
+    pub fn new(width: u32, height: u32, fps: Option<f32>, codec: Option<String>) -> Self {
+        Self { num: 0, width, height, fps, codec }
+    }
 }
 
 /// Describe in- or output video stream
@@ -19,12 +30,39 @@ pub enum StreamInfo {
 }
 
 /// Describes a stream's update
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct FrameUpdate {
-    frame: u64,
-    fps: Option<f32>,
-    dup: Option<u32>,
-    drop: Option<u32>,
+    /// Frames output so far
+    pub frame: u64,
+    /// Current decoding rate
+    pub fps: Option<f32>,
+    /// Seconds of video output so far
+    pub time: Option<f64>,
+    /// Seconds of video output per second, below 1 falls behind real time
+    pub speed: Option<f32>,
+    /// Of the output in kbit/s
+    pub bitrate: Option<f64>,
+    /// Frames duplicated to keep up the output rate
+    pub dup: Option<u32>,
+    /// Frames dropped by ffmpeg
+    pub drop: Option<u32>,
+}
+
+/// Why no video can be decoded from an input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputFailure {
+    /// Only other streams such as audio were found
+    NoVideoStream,
+    /// No decoder for this codec
+    UnsupportedCodec(String),
+    /// No such file or directory
+    NotFound,
+    /// Nothing accepts connections at the input's address
+    ConnectionRefused,
+    /// The server rejected the credentials, if any were given
+    Unauthorized,
+    /// Not a format ffmpeg recognizes
+    InvalidData,
 }
 
 /// Describes a video's stream updates
@@ -33,6 +71,111 @@ pub enum VideoInfo {
     Stream(StreamInfo),
     Frame(FrameUpdate),
     Codec(String),
+    Failure(InputFailure),
+}
+
+/// Most pixels of a frame believed, larger sizes are rather garbled lines than frames to allocate
+const MAX_PIXELS: u64 = 1 << 28;
+
+/// Failure reported by a line, before or after ffmpeg 6 prefixed it with its context
+fn parse_failure(line: &str) -> Option<InputFailure> {
+    let reasons = [
+        ("does not contain any stream", InputFailure::NoVideoStream),
+        ("No such file or directory", InputFailure::NotFound),
+        ("Connection refused", InputFailure::ConnectionRefused),
+        ("401 Unauthorized", InputFailure::Unauthorized),
+        ("Invalid data found when processing input", InputFailure::InvalidData),
+    ];
+    if let Some((_, failure)) = reasons.into_iter().find(|(reason, _)| line.contains(reason)) {
+        return Some(failure);
+    }
+    // e.g. "Decoder (codec av1) not found for input stream #0:0"
+    if let Some((_, rest)) = line.split_once("Decoder (codec ") {
+        return rest.split_once(')').map(|(codec, _)| InputFailure::UnsupportedCodec(codec.into()));
+    }
+    // e.g. "Unsupported codec with id 0 for input stream 0"
+    let (_, rest) = line.split_once("Unsupported codec with ")?;
+    let codec = rest.split_once(" for").map_or(rest, |(codec, _)| codec);
+    Some(InputFailure::UnsupportedCodec(codec.trim().into()))
+}
+
+/// Fields of a `key=value ...` line
+///
+/// Values may be padded, e.g. `frame=  10`, or run into the next key without a space when they
+/// outgrow their padding, e.g. `fps=30.0q=-0.0`. Keys are letters only, e.g. `Lsize`, so that
+/// digits of a value don't become part of the next key.
+fn key_values(line: &str) -> Vec<(&str, &str)> {
+    let is_key = |c: char| c.is_ascii_alphabetic() || c == '_';
+    let keys = line
+        .match_indices('=')
+        .map(|(eq, _)| (line[..eq].trim_end_matches(is_key).len(), eq))
+        .filter(|(start, eq)| start < eq)
+        .collect::<Vec<_>>();
+    keys.iter()
+        .enumerate()
+        .map(|(i, &(start, eq))| {
+            let end = keys.get(i + 1).map_or(line.len(), |(next, _)| *next);
+            (&line[start..eq], line[eq + 1..end].trim())
+        })
+        .collect()
+}
+
+/// Seconds of a `[-]HH:MM:SS.frac` time, none if `N/A`
+fn parse_time(value: &str) -> Option<f64> {
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(value) => (-1.0, value),
+        None => (1.0, value),
+    };
+    let mut parts = value.splitn(3, ':');
+    let hours = parts.next()?.parse::<f64>().ok()?;
+    let minutes = parts.next()?.parse::<f64>().ok()?;
+    let secs = parts.next()?.parse::<f64>().ok()?;
+    Some(sign * (hours * 3600.0 + minutes * 60.0 + secs))
+}
+
+/// Factor of a `1.5x` speed, none if `N/A`
+fn parse_speed(value: &str) -> Option<f32> {
+    value.strip_suffix('x')?.trim().parse().ok()
+}
+
+/// Of a `1200.5kbits/s` bitrate, none if `N/A`
+fn parse_bitrate(value: &str) -> Option<f64> {
+    value.strip_suffix("kbits/s")?.trim().parse().ok()
+}
+
+/// Parses ffmpeg's `-progress` protocol, blocks of `key=value` lines ending with `progress=`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProgressParser {
+    update: FrameUpdate,
+}
+
+impl ProgressParser {
+    /// Whether a line is of the protocol rather than a log line for `InfoParser`
+    pub(crate) fn is_progress(line: &str) -> bool {
+        let is_key = |key: &str| {
+            !key.is_empty()
+                && key.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+        };
+        matches!(line.split_once('='), Some((key, value)) if is_key(key) && !value.contains('='))
+    }
+
+    /// Take a line of the protocol, the update is complete at the end of a block
+    pub(crate) fn push(&mut self, line: &str) -> Option<FrameUpdate> {
+        let (key, value) = line.split_once('=')?;
+        let value = value.trim();
+        match key {
+            "frame" => self.update.frame = value.parse().unwrap_or(self.update.frame),
+            "fps" => self.update.fps = value.parse().ok(),
+            "out_time" => self.update.time = parse_time(value),
+            "speed" => self.update.speed = parse_speed(value),
+            "bitrate" => self.update.bitrate = parse_bitrate(value),
+            "dup_frames" => self.update.dup = value.parse().ok(),
+            "drop_frames" => self.update.drop = value.parse().ok(),
+            "progress" => return Some(self.update.clone()),
+            _ => {}
+        }
+        None
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -95,22 +238,32 @@ impl InfoParser {
                 .trim()
                 .parse::<u32>()
                 .map_err(|e| error_on_(format!("# not a number but {:?}", e)))?;
-            let to_from =
-                parts.last().ok_or_else(|| error_on("no last stream element (from or to)"))?.trim();
+            let quote = if is_input { "from '" } else { "to '" };
+            let to_from = match remaining.split_once(&format!(" {quote}")) {
+                // quoted paths may contain commas as well
+                Some((_, quoted)) => quoted.trim_end(),
+                None => parts
+                    .last()
+                    .ok_or_else(|| error_on("no last stream element (from or to)"))?
+                    .trim(),
+            };
 
             // unquote and extract if possible
-            let to_from =
-                to_from.strip_prefix(if is_input { "from '" } else { "to '" }).unwrap_or(to_from);
+            let to_from = to_from.strip_prefix(quote).unwrap_or(to_from);
             let to_from = to_from.strip_suffix("':").unwrap_or(to_from);
 
             self.mode = if is_input {
-                ParseContext::Input(num_stream, to_from.to_string())
+                ParseContext::Input(num_stream, redact(to_from))
             } else {
-                ParseContext::Output(num_stream, to_from.to_string())
+                ParseContext::Output(num_stream, redact(to_from))
             };
             return Ok(None);
         }
 
+        if let Some(failure) = parse_failure(line) {
+            return Ok(Some(VideoInfo::Failure(failure)));
+        }
+
         // Codec
         if line.starts_with('[') && line.contains(']') {
             return Ok(Some(VideoInfo::Codec(line.into())));
@@ -120,7 +273,7 @@ impl InfoParser {
         let frame_str = line_trimmed.strip_prefix("frame=").unwrap_or(line_trimmed);
 
         // reset if some other header comes up
-        if line_trimmed.len() == line.len() && !frame_str.len() < line.len() {
+        if line_trimmed.len() == line.len() && frame_str.len() == line_trimmed.len() {
             self.mode = ParseContext::Stateless;
             return Ok(None);
         }
@@ -148,15 +301,24 @@ impl InfoParser {
             let mut is_video = false;
             let mut width_height = None;
             let mut fps = None;
+            let mut codec = None;
             for p in parts {
                 if !is_video && p.trim() == "Video" {
                     is_video = true;
+                    continue;
+                }
+                if is_video && codec.is_none() {
+                    // first word after "Video:"
+                    codec =
+                        p.split_whitespace().next().map(|c| c.trim_end_matches(',').to_string());
                 }
                 if is_video {
                     for key_vals in p.split(',') {
                         let key_vals = key_vals.trim();
                         let fps_vals = key_vals.trim_end_matches(" fps");
-                        if fps_vals.len() < key_vals.len() {
+                        if fps_vals == "N/A" {
+                            fps = None;
+                        } else if fps_vals.len() < key_vals.len() {
                             fps = fps_vals
                                 .parse::<f32>()
                                 .map_err(|_| error_on("fps not a number"))?
@@ -183,7 +345,11 @@ impl InfoParser {
                 return Ok(None);
             }
             return if let Some((width, height)) = width_height {
-                let stream = Stream { num: num_stream, width, height, fps };
+                let pixels = width as u64 * height as u64;
+                if pixels == 0 || pixels > MAX_PIXELS {
+                    return Err(error_on_(format!("implausible size {width}x{height}")));
+                }
+                let stream = Stream { num: num_stream, width, height, fps, codec };
                 let info = if is_input {
                     VideoInfo::Stream(StreamInfo::Input { from: to_from.clone(), stream })
                 } else {
@@ -198,33 +364,22 @@ impl InfoParser {
 
         // Frame message
         if frame_str.len() < line_trimmed.len() {
-            // frame required
-            if let Some((frame_num_str, mut frame_rest)) = frame_str.trim().split_once(' ') {
-                let frame = frame_num_str
-                    .trim()
-                    .parse::<u64>()
-                    .map_err(|_| error_on("frame is no number"))?;
-
-                // other key values if available
-                let (mut fps, mut dup, mut drop) = (None, None, None);
-                while let Some((key, rest)) = frame_rest.split_once('=') {
-                    if let Some((value, rest)) = rest.trim_start().split_once(' ') {
-                        match key {
-                            "fps" => fps = value.parse().ok(),
-                            "dup" => dup = value.parse().ok(),
-                            "drop" => drop = value.parse().ok(),
-                            _ => {}
-                        }
-                        frame_rest = rest;
-                    } else {
-                        frame_rest = rest;
+            let mut update = FrameUpdate::default();
+            for (key, value) in key_values(line_trimmed) {
+                match key {
+                    "frame" => {
+                        update.frame = value.parse().map_err(|_| error_on("frame is no number"))?;
                     }
+                    "fps" => update.fps = value.parse().ok(),
+                    "time" => update.time = parse_time(value),
+                    "speed" => update.speed = parse_speed(value),
+                    "bitrate" => update.bitrate = parse_bitrate(value),
+                    "dup" => update.dup = value.parse().ok(),
+                    "drop" => update.drop = value.parse().ok(),
+                    _ => {}
                 }
-                let frame_upd = FrameUpdate { frame, fps, dup, drop };
-                Ok(Some(VideoInfo::Frame(frame_upd)))
-            } else {
-                Ok(None)
             }
+            Ok(Some(VideoInfo::Frame(update)))
         } else {
             Ok(None)
         }
@@ -320,7 +475,7 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::{FrameUpdate, InfoParser, Stream, StreamInfo, VideoInfo};
+    use super::{FrameUpdate, InfoParser, InputFailure, Stream, StreamInfo, VideoInfo};
 
     static TEST_INFO: &str = r#"Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'media/huhu_test.mp4':
   Metadata:
@@ -366,7 +521,13 @@ frame=27045 fps= 1019.6 q=-0.0 size=73021500kB time=00:15:01.50 bitrate=663552.0
         assert_eq!(
             infos.next().unwrap(),
             Ok(VideoInfo::Stream(StreamInfo::Input {
-                stream: Stream { num: 0, width: 1280, height: 720, fps: Some(29.59f32) },
+                stream: Stream {
+                    num: 0,
+                    width: 1280,
+                    height: 720,
+                    fps: Some(29.59f32),
+                    codec: Some("h264".to_string())
+                },
                 from: "media/huhu_test.mp4".to_string(),
             }))
         );
@@ -380,7 +541,13 @@ frame=27045 fps= 1019.6 q=-0.0 size=73021500kB time=00:15:01.50 bitrate=663552.0
         assert_eq!(
             infos.next().unwrap(),
             Ok(VideoInfo::Stream(StreamInfo::Output {
-                stream: Stream { num: 0, width: 1280, height: 720, fps: Some(30f32) },
+                stream: Stream {
+                    num: 0,
+                    width: 1280,
+                    height: 720,
+                    fps: Some(30f32),
+                    codec: Some("rawvideo".to_string())
+                },
                 to: "pipe:".to_string(),
             }))
         );
@@ -391,6 +558,9 @@ frame=27045 fps= 1019.6 q=-0.0 size=73021500kB time=00:15:01.50 bitrate=663552.0
             Ok(VideoInfo::Frame(FrameUpdate {
                 frame: 3926,
                 fps: Some(978f32),
+                time: Some(130.86),
+                speed: Some(32.6),
+                bitrate: Some(663552.0),
                 dup: None,
                 drop: None,
             }))
@@ -400,6 +570,9 @@ frame=27045 fps= 1019.6 q=-0.0 size=73021500kB time=00:15:01.50 bitrate=663552.0
             Ok(VideoInfo::Frame(FrameUpdate {
                 frame: 4026,
                 fps: Some(1002f32),
+                time: Some(134.2),
+                speed: Some(33.4),
+                bitrate: Some(663552.0),
                 dup: None,
                 drop: None,
             }))
@@ -409,11 +582,37 @@ frame=27045 fps= 1019.6 q=-0.0 size=73021500kB time=00:15:01.50 bitrate=663552.0
             Ok(VideoInfo::Frame(FrameUpdate {
                 frame: 27045,
                 fps: Some(1019.6f32),
+                time: Some(901.5),
+                speed: Some(34.0),
+                bitrate: Some(663552.0),
                 dup: Some(0),
                 drop: Some(5),
             }))
         );
     }
+    #[test]
+    fn test_output_change() {
+        let output = |size: &str| {
+            [
+                "Output #0, image2pipe, to 'pipe:':".to_string(),
+                format!("  Stream #0:0: Video: rawvideo (BGR[24] / 0x18524742), bgr24, {size}, q=2-31, 30 fps"),
+            ]
+        };
+        let mut lines = output("640x480").to_vec();
+        lines.push("frame=   10 fps=0.0 q=-0.0 size=    9000kB time=00:00:00.33 speed=1x".into());
+        lines.extend(output("320x240"));
+        let sizes = InfoParser::default()
+            .iter_on(lines)
+            .filter_map(|info| match info {
+                Ok(VideoInfo::Stream(StreamInfo::Output { stream, .. })) => {
+                    Some((stream.width, stream.height))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, [(640, 480), (320, 240)]);
+    }
+
     #[test]
     fn test_illegal_input() {
         assert!(InfoParser::default()
@@ -430,6 +629,18 @@ frame=27045 fps= 1019.6 q=-0.0 size=73021500kB time=00:15:01.50 bitrate=663552.0
             .unwrap()
             .is_err());
     }
+    #[test]
+    fn test_implausible_size() {
+        for size in ["0x720", "4294967295x4294967295"] {
+            let lines = [
+                "Output #0, image2pipe, to 'pipe:':".to_string(),
+                format!("  Stream #0:0: Video: rawvideo, bgr24, {size}, 30 fps"),
+            ];
+            let err = InfoParser::default().iter_on(lines).next().unwrap().unwrap_err();
+            assert!(err.reason.starts_with("implausible size"), "{}", err.reason);
+        }
+    }
+
     #[test]
     fn test_illegal_frame() {
         assert_eq!(InfoParser::default()
@@ -455,6 +666,138 @@ frame=27045 fps= 1019.6 q=-0.0 size=73021500kB time=00:15:01.50 bitrate=663552.0
         }
     }
 
+    #[test]
+    fn test_bull_run_no_video() {
+        let lines = BULL_RUN.lines().chain(["Output file #0 does not contain any stream"]);
+        let failure = InfoParser::default().iter_on(lines).last().unwrap();
+        assert_eq!(failure, Ok(VideoInfo::Failure(InputFailure::NoVideoStream)));
+    }
+
+    #[test]
+    fn test_unsupported_codec() {
+        let lines = [
+            "[vist#0:0/av1 @ 0x5600] Decoder (codec av1) not found for input stream #0:0",
+            "Unsupported codec with id 0 for input stream 1",
+        ];
+        let failures = InfoParser::default().iter_on(lines).collect::<Vec<_>>();
+        assert_eq!(
+            failures,
+            [
+                Ok(VideoInfo::Failure(InputFailure::UnsupportedCodec("av1".into()))),
+                Ok(VideoInfo::Failure(InputFailure::UnsupportedCodec("id 0".into()))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_open_failures() {
+        let lines = [
+            "missing.mp4: No such file or directory",
+            "[in#0 @ 0x5600] Error opening input: No such file or directory",
+            "[tcp @ 0x5600] Connection to tcp://cam:554?timeout=0 failed: Connection refused",
+            "[rtsp @ 0x5600] method DESCRIBE failed: 401 Unauthorized",
+            "notes.txt: Invalid data found when processing input",
+        ];
+        let failures = InfoParser::default().iter_on(lines).collect::<Vec<_>>();
+        assert_eq!(
+            failures,
+            [
+                InputFailure::NotFound,
+                InputFailure::NotFound,
+                InputFailure::ConnectionRefused,
+                InputFailure::Unauthorized,
+                InputFailure::InvalidData,
+            ]
+            .map(|f| Ok(VideoInfo::Failure(f)))
+        );
+    }
+
+    #[test]
+    fn test_key_values() {
+        let line = "frame=100000 fps=1000.5q=-0.0 Lsize=  405000kB dup= 2 speed=4.91x";
+        assert_eq!(
+            super::key_values(line),
+            [
+                ("frame", "100000"),
+                ("fps", "1000.5"),
+                ("q", "-0.0"),
+                ("Lsize", "405000kB"),
+                ("dup", "2"),
+                ("speed", "4.91x")
+            ]
+        );
+    }
+
+    /// Stderr of various ffmpeg versions and inputs as the info thread parses it
+    #[test]
+    fn test_versions_corpus() {
+        let corpus = [
+            (include_str!("../stderr/ffmpeg-4.4.txt"), "media/synth_640x480_40secs_10fps.mp4"),
+            (include_str!("../stderr/ffmpeg-6.1.txt"), "media/parking lot, north.mp4"),
+            (include_str!("../stderr/ffmpeg-7.0.txt"), "http://127.0.0.1:8554/stream.mjpg"),
+        ];
+        let expected = [
+            ((640, 480), Some(10.0), (640, 480), (400, Some(398.0), None)),
+            ((1280, 720), Some(30.0), (1280, 720), (150, Some(1000.5), None)),
+            ((320, 240), None, (320, 240), (37, Some(24.0), Some(2))),
+        ];
+        for ((stderr, from), expected) in corpus.into_iter().zip(expected) {
+            let lines = stderr.lines().map(|line| crate::log::LogLine::parse(line).unleveled());
+            let infos =
+                InfoParser::default().iter_on(lines).collect::<Result<Vec<_>, _>>().unwrap();
+            let input = infos.iter().find_map(|info| match info {
+                VideoInfo::Stream(StreamInfo::Input { from, stream }) => Some((from, stream)),
+                _ => None,
+            });
+            let (input_from, input) = input.unwrap();
+            assert_eq!(input_from, from);
+            assert_eq!(((input.width, input.height), input.fps), (expected.0, expected.1));
+            let output = infos.iter().find_map(|info| match info {
+                VideoInfo::Stream(StreamInfo::Output { stream, .. }) => Some(stream),
+                _ => None,
+            });
+            let output = output.unwrap();
+            assert_eq!((output.width, output.height), expected.2, "{from}");
+            let last = infos.iter().rev().find_map(|info| match info {
+                VideoInfo::Frame(update) => Some(update),
+                _ => None,
+            });
+            let last = last.unwrap();
+            assert_eq!((last.frame, last.fps, last.dup), expected.3, "{from}");
+        }
+    }
+
+    #[test]
+    fn test_progress_protocol() {
+        use super::ProgressParser;
+
+        let block = "frame=37\nfps=24.00\nstream_0_0_q=-0.0\nbitrate=46080.0kbits/s\n\
+                     out_time_us=1480000\nout_time=00:00:01.480000\ndup_frames=2\n\
+                     drop_frames=0\nspeed=0.981x\nprogress=continue";
+        let mut parser = ProgressParser::default();
+        let mut updates = vec![];
+        for line in block.lines() {
+            assert!(ProgressParser::is_progress(line), "{line}");
+            updates.extend(parser.push(line));
+        }
+        assert_eq!(
+            updates,
+            [FrameUpdate {
+                frame: 37,
+                fps: Some(24.0),
+                time: Some(1.48),
+                speed: Some(0.981),
+                bitrate: Some(46080.0),
+                dup: Some(2),
+                drop: Some(0)
+            }]
+        );
+        assert!(!ProgressParser::is_progress("frame=   37 fps= 24 q=-0.0 size=    8325KiB"));
+        assert!(!ProgressParser::is_progress("  Stream #0:0: Video: mjpeg"));
+        assert_eq!(super::parse_time("-00:01:02.5"), Some(-62.5));
+        assert_eq!(super::parse_time("N/A"), None);
+    }
+
     #[test]
     fn test_ffmpeg_lines() {
         use super::FFMpegLineIter;
@@ -0,0 +1,142 @@
+use std::{
+    borrow::Cow,
+    env,
+    ffi::{OsStr, OsString},
+    path::PathBuf,
+    process::Command,
+};
+
+/// Executable run unless overridden: `FFMPEG` if set, otherwise `ffmpeg` on the path
+pub fn ffmpeg_path() -> PathBuf {
+    env::var_os("FFMPEG").map_or_else(|| PathBuf::from("ffmpeg"), PathBuf::from)
+}
+
+/// Command running the default ffmpeg executable, see [`ffmpeg_path`]
+pub fn ffmpeg_command() -> Command {
+    command(ffmpeg_path())
+}
+
+/// Command running `program` without flashing a console window on Windows
+pub(crate) fn command(program: impl AsRef<OsStr>) -> Command {
+    #[allow(unused_mut)]
+    let mut cmd = Command::new(program);
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        // https://learn.microsoft.com/en-us/windows/win32/procthread/process-creation-flags
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        cmd.creation_flags(CREATE_NO_WINDOW);
+    }
+    cmd
+}
+
+/// Where ffmpeg writes its `-progress` protocol, a pipe of its own where one can be passed
+pub(crate) const PROGRESS_URL: &str = if cfg!(unix) { "pipe:3" } else { "pipe:2" };
+
+/// Pass a pipe to `cmd` as descriptor 3 and return the end to read from and the one passed
+///
+/// The passed end must be dropped once spawned, so that reading ends with the process.
+#[cfg(unix)]
+pub(crate) fn progress_pipe(
+    cmd: &mut Command,
+) -> std::io::Result<(std::fs::File, std::os::unix::io::OwnedFd)> {
+    use std::{
+        io::Error,
+        os::unix::{
+            io::{AsRawFd, FromRawFd},
+            process::CommandExt,
+        },
+    };
+    const PROGRESS_FD: i32 = 3;
+
+    let mut fds = [0; 2];
+    // neither end is inherited by other processes spawned meanwhile
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    let piped = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) };
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+    let piped = unsafe {
+        match libc::pipe(fds.as_mut_ptr()) {
+            0 => fds.iter().map(|&fd| libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC)).sum(),
+            e => e,
+        }
+    };
+    if piped != 0 {
+        return Err(Error::last_os_error());
+    }
+    let (read, write) = unsafe {
+        (std::fs::File::from_raw_fd(fds[0]), std::os::unix::io::OwnedFd::from_raw_fd(fds[1]))
+    };
+    let fd = write.as_raw_fd();
+    // only async-signal-safe calls between fork and exec
+    unsafe {
+        cmd.pre_exec(move || {
+            let inherited = match fd {
+                // dup2 onto itself keeps close-on-exec
+                PROGRESS_FD => libc::fcntl(fd, libc::F_SETFD, 0),
+                _ => libc::dup2(fd, PROGRESS_FD),
+            };
+            match inherited {
+                -1 => Err(Error::last_os_error()),
+                _ => Ok(()),
+            }
+        })
+    };
+    Ok((read, write))
+}
+
+/// Path argument as ffmpeg understands it
+///
+/// Verbatim paths (`\\?\C:\...`, e.g. of `canonicalize`) are turned into regular ones,
+/// which ffmpeg 5.1 and later prefix again by itself if they are too long. Other arguments,
+/// including ones with spaces, are passed as they are, `Command` quotes them.
+pub(crate) fn path_arg(arg: &OsStr) -> Cow<'_, OsStr> {
+    if !cfg!(windows) {
+        return Cow::Borrowed(arg);
+    }
+    unverbatim(arg)
+}
+
+fn unverbatim(arg: &OsStr) -> Cow<'_, OsStr> {
+    let s = match arg.to_str() {
+        Some(s) => s,
+        None => return Cow::Borrowed(arg),
+    };
+    if let Some(unc) = s.strip_prefix(r"\\?\UNC\") {
+        return Cow::Owned(OsString::from(format!(r"\\{unc}")));
+    }
+    match s.strip_prefix(r"\\?\") {
+        // only drive paths, e.g. not volume GUIDs
+        Some(disk) if disk.as_bytes().get(1) == Some(&b':') => Cow::Owned(disk.into()),
+        _ => Cow::Borrowed(arg),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn pipe_progress() {
+        use std::io::Read;
+
+        let mut cmd = command("sh");
+        cmd.args(["-c", "echo frame=1 >&3; echo progress=end >&3"]);
+        let (mut read, write) = progress_pipe(&mut cmd).unwrap();
+        let status = cmd.status().unwrap();
+        drop(write);
+        let mut protocol = String::new();
+        read.read_to_string(&mut protocol).unwrap();
+        assert!(status.success());
+        assert_eq!(protocol, "frame=1\nprogress=end\n");
+    }
+
+    #[test]
+    fn verbatim_paths() {
+        let unverbatim = |s: &str| unverbatim(OsStr::new(s)).to_string_lossy().to_string();
+        assert_eq!(unverbatim(r"\\?\C:\My Videos\a.mp4"), r"C:\My Videos\a.mp4");
+        assert_eq!(unverbatim(r"\\?\UNC\nas\share\a.mp4"), r"\\nas\share\a.mp4");
+        assert_eq!(unverbatim(r"\\?\Volume{1234}\a.mp4"), r"\\?\Volume{1234}\a.mp4");
+        assert_eq!(unverbatim("rtsp://cam/stream"), "rtsp://cam/stream");
+    }
+}
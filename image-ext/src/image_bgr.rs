@@ -10,6 +10,15 @@ pub struct Bgr(pub [u8; 3]);
 
 pub type BgrImage = ImageBuffer<Bgr, Vec<u8>>;
 
+/// Luma of sRGB primaries, same weights as `image` uses for `Rgb`
+#[inline]
+pub(crate) fn luma([b, g, r]: [u8; 3]) -> u8 {
+    const R: u32 = 2126;
+    const G: u32 = 7152;
+    const B: u32 = 722;
+    ((R * r as u32 + G * g as u32 + B * b as u32) / 10000) as u8
+}
+
 impl image::Pixel for Bgr {
     type Subpixel = u8;
 
@@ -62,11 +71,11 @@ impl image::Pixel for Bgr {
     }
 
     fn to_luma(&self) -> image::Luma<Self::Subpixel> {
-        todo!()
+        image::Luma([luma(self.0)])
     }
 
     fn to_luma_alpha(&self) -> image::LumaA<Self::Subpixel> {
-        todo!()
+        image::LumaA([luma(self.0), Self::Subpixel::MAX])
     }
 
     fn map<F>(&self, f: F) -> Self
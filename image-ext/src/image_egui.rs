@@ -0,0 +1,46 @@
+use epaint::{Color32, ColorImage};
+
+use crate::image_bgr::BgrImage;
+
+/// Pixels per job when converting in parallel
+#[cfg(feature = "rayon")]
+const CHUNK_PIXELS: usize = 1 << 14;
+
+#[inline]
+fn convert(bgr: &[u8], rgba: &mut [Color32]) {
+    for (src, dst) in bgr.chunks_exact(3).zip(rgba.iter_mut()) {
+        *dst = Color32::from_rgb(src[2], src[1], src[0]);
+    }
+}
+
+/// Convert to egui's image without per-pixel trait calls
+///
+/// With the `rayon` feature, chunks of pixels are converted in parallel.
+pub fn to_color_image(img: &BgrImage) -> ColorImage {
+    let size = [img.width() as usize, img.height() as usize];
+    let mut pixels = vec![Color32::BLACK; size[0] * size[1]];
+    let bgr = img.as_raw().as_slice();
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        bgr.par_chunks(CHUNK_PIXELS * 3)
+            .zip(pixels.par_chunks_mut(CHUNK_PIXELS))
+            .for_each(|(src, dst)| convert(src, dst));
+    }
+    #[cfg(not(feature = "rayon"))]
+    convert(bgr, &mut pixels);
+    ColorImage { size, pixels }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bgr_to_rgb() {
+        let img = BgrImage::from_raw(2, 1, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let color = to_color_image(&img);
+        assert_eq!(color.size, [2, 1]);
+        assert_eq!(color.pixels, [Color32::from_rgb(3, 2, 1), Color32::from_rgb(6, 5, 4)]);
+    }
+}
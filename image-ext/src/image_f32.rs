@@ -0,0 +1,148 @@
+use crate::image_view::StridedView;
+
+/// Memory order of a 3-channel f32 image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelLayout {
+    /// HWC, channels of a pixel are adjacent
+    Interleaved,
+    /// CHW, each channel is one plane
+    Planar,
+}
+
+/// 3-channel f32 image to prepare model inputs in place
+///
+/// The buffer is reused when writing new frames of the same size.
+#[derive(Clone, Debug)]
+pub struct F32Image {
+    width: u32,
+    height: u32,
+    layout: ChannelLayout,
+    data: Vec<f32>,
+}
+
+impl F32Image {
+    pub fn new(width: u32, height: u32, layout: ChannelLayout) -> Self {
+        Self { width, height, layout, data: vec![0.0; 3 * width as usize * height as usize] }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn layout(&self) -> ChannelLayout {
+        self.layout
+    }
+
+    /// Dimensions in memory order
+    pub fn shape(&self) -> [usize; 3] {
+        let (w, h) = (self.width as usize, self.height as usize);
+        match self.layout {
+            ChannelLayout::Interleaved => [h, w, 3],
+            ChannelLayout::Planar => [3, h, w],
+        }
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn into_vec(self) -> Vec<f32> {
+        self.data
+    }
+
+    /// Index of a pixel's channel in the buffer
+    #[inline]
+    fn index(&self, pixel: usize, channel: usize) -> usize {
+        match self.layout {
+            ChannelLayout::Interleaved => pixel * 3 + channel,
+            ChannelLayout::Planar => channel * self.width as usize * self.height as usize + pixel,
+        }
+    }
+
+    /// Take the size and pixels of a BGR image, scaled to 0-1
+    ///
+    /// With `rgb`, the first channel becomes red instead of blue.
+    pub fn write_bgr(&mut self, img: &StridedView, rgb: bool) {
+        self.width = img.width();
+        self.height = img.height();
+        self.data.resize(3 * self.width as usize * self.height as usize, 0.0);
+        let width = self.width as usize;
+        for (r, row) in img.rows().enumerate() {
+            for (c, px) in row.chunks_exact(3).enumerate() {
+                let pixel = r * width + c;
+                for (channel, &v) in px.iter().enumerate() {
+                    let channel = if rgb { 2 - channel } else { channel };
+                    let i = self.index(pixel, channel);
+                    self.data[i] = f32::from(v) / 255.0;
+                }
+            }
+        }
+    }
+
+    /// Multiply all values
+    pub fn scale(&mut self, factor: f32) {
+        self.data.iter_mut().for_each(|v| *v *= factor);
+    }
+
+    /// Subtract mean and divide by standard deviation of each channel
+    pub fn normalize(&mut self, mean: [f32; 3], std: [f32; 3]) {
+        let inv_std = std.map(|s| 1.0 / s);
+        match self.layout {
+            ChannelLayout::Interleaved => {
+                for px in self.data.chunks_exact_mut(3) {
+                    for c in 0..3 {
+                        px[c] = (px[c] - mean[c]) * inv_std[c];
+                    }
+                }
+            }
+            ChannelLayout::Planar => {
+                let plane = (self.width as usize * self.height as usize).max(1);
+                for (c, values) in self.data.chunks_exact_mut(plane).enumerate() {
+                    values.iter_mut().for_each(|v| *v = (*v - mean[c]) * inv_std[c]);
+                }
+            }
+        }
+    }
+
+    /// Reorder memory into another layout
+    pub fn permute(&mut self, layout: ChannelLayout) {
+        if layout == self.layout {
+            return;
+        }
+        let mut permuted = Self { layout, data: vec![0.0; self.data.len()], ..*self };
+        let pixels = self.width as usize * self.height as usize;
+        for pixel in 0..pixels {
+            for channel in 0..3 {
+                let i = permuted.index(pixel, channel);
+                permuted.data[i] = self.data[self.index(pixel, channel)];
+            }
+        }
+        *self = permuted;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Bgr, BgrImage};
+
+    #[test]
+    fn prepare_input() {
+        let img = BgrImage::from_fn(2, 1, |x, _| Bgr([0, 51 * x as u8, 255]));
+        let mut f = F32Image::new(0, 0, ChannelLayout::Planar);
+        f.write_bgr(&StridedView::from(&img), true);
+        assert_eq!(f.shape(), [3, 1, 2]);
+        assert_eq!(f.as_slice(), &[1.0, 1.0, 0.0, 0.2, 0.0, 0.0]);
+
+        f.normalize([0.5, 0.0, 0.0], [0.5, 0.1, 1.0]);
+        assert_eq!(f.as_slice()[..4], [1.0, 1.0, 0.0, 2.0]);
+
+        f.permute(ChannelLayout::Interleaved);
+        assert_eq!(f.shape(), [1, 2, 3]);
+        assert_eq!(f.as_slice(), &[1.0, 0.0, 0.0, 1.0, 2.0, 0.0]);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{fmt, num::NonZeroU32};
+
+use fast_image_resize as fr;
+use image::{ImageBuffer, Luma, Pixel, Rgb, Rgba};
+
+use crate::{image_bgr::Bgr, image_view::StridedView};
+
+/// Pixels with an equivalent in fast_image_resize
+pub trait FrPixel: Pixel<Subpixel = u8> {
+    const PIXEL_TYPE: fr::PixelType;
+}
+
+impl FrPixel for Bgr {
+    const PIXEL_TYPE: fr::PixelType = fr::PixelType::U8x3;
+}
+
+impl FrPixel for Rgb<u8> {
+    const PIXEL_TYPE: fr::PixelType = fr::PixelType::U8x3;
+}
+
+impl FrPixel for Rgba<u8> {
+    const PIXEL_TYPE: fr::PixelType = fr::PixelType::U8x4;
+}
+
+impl FrPixel for Luma<u8> {
+    const PIXEL_TYPE: fr::PixelType = fr::PixelType::U8;
+}
+
+/// Error viewing an image for resizing
+#[derive(Debug)]
+pub enum FrViewError {
+    /// Width or height is 0
+    ZeroSize,
+    Buffer(fr::ImageBufferError),
+    Crop(fr::CropBoxError),
+}
+
+impl fmt::Display for FrViewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroSize => write!(f, "image has zero size"),
+            Self::Buffer(e) => e.fmt(f),
+            Self::Crop(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for FrViewError {}
+
+impl From<fr::ImageBufferError> for FrViewError {
+    fn from(e: fr::ImageBufferError) -> Self {
+        Self::Buffer(e)
+    }
+}
+
+fn non_zero(width: u32, height: u32) -> Result<(NonZeroU32, NonZeroU32), FrViewError> {
+    match (NonZeroU32::new(width), NonZeroU32::new(height)) {
+        (Some(width), Some(height)) => Ok((width, height)),
+        _ => Err(FrViewError::ZeroSize),
+    }
+}
+
+/// Borrow an image as resizer input without copying
+pub trait AsFrImageView {
+    fn fr_view(&self) -> Result<fr::ImageView<'_>, FrViewError>;
+}
+
+/// Borrow an image as resizer output without copying
+pub trait AsFrImageViewMut {
+    fn fr_view_mut(&mut self) -> Result<fr::ImageViewMut<'_>, FrViewError>;
+}
+
+impl<P: FrPixel> AsFrImageView for ImageBuffer<P, Vec<u8>> {
+    fn fr_view(&self) -> Result<fr::ImageView<'_>, FrViewError> {
+        let (width, height) = non_zero(self.width(), self.height())?;
+        Ok(fr::ImageView::from_buffer(width, height, self.as_raw(), P::PIXEL_TYPE)?)
+    }
+}
+
+impl<P: FrPixel> AsFrImageViewMut for ImageBuffer<P, Vec<u8>> {
+    fn fr_view_mut(&mut self) -> Result<fr::ImageViewMut<'_>, FrViewError> {
+        let (width, height) = non_zero(self.width(), self.height())?;
+        Ok(fr::ImageViewMut::from_buffer(width, height, self.as_mut(), P::PIXEL_TYPE)?)
+    }
+}
+
+impl AsFrImageView for StridedView<'_> {
+    fn fr_view(&self) -> Result<fr::ImageView<'_>, FrViewError> {
+        let (width, height) = non_zero(self.width(), self.height())?;
+        let rows = self.data.len() / self.stride;
+        let (full_width, full_height) = non_zero((self.stride / 3) as u32, rows as u32)?;
+        let mut view =
+            fr::ImageView::from_buffer(full_width, full_height, self.data, fr::PixelType::U8x3)?;
+        let [left, top] = self.position();
+        view.set_crop_box(fr::CropBox { left, top, width, height }).map_err(FrViewError::Crop)?;
+        Ok(view)
+    }
+}
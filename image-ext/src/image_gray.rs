@@ -0,0 +1,34 @@
+use image::{GrayImage, Luma};
+
+use crate::image_bgr::{luma, Bgr, BgrImage};
+
+/// Single channel image from BGR
+pub fn bgr_to_gray(img: &BgrImage) -> GrayImage {
+    let data = img.as_raw().chunks_exact(3).map(|p| luma([p[0], p[1], p[2]])).collect();
+    GrayImage::from_raw(img.width(), img.height(), data).expect("one luma per pixel")
+}
+
+/// BGR image with equal channels from gray
+pub fn gray_to_bgr(img: &GrayImage) -> BgrImage {
+    let data = img.as_raw().iter().flat_map(|&l| [l, l, l]).collect();
+    BgrImage::from_raw(img.width(), img.height(), data).expect("three channels per pixel")
+}
+
+impl From<Luma<u8>> for Bgr {
+    fn from(Luma([l]): Luma<u8>) -> Self {
+        Bgr([l, l, l])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gray_roundtrip() {
+        let bgr = BgrImage::from_raw(2, 1, vec![0, 0, 255, 10, 10, 10]).unwrap();
+        let gray = bgr_to_gray(&bgr);
+        assert_eq!(gray.as_raw(), &[54, 10]);
+        assert_eq!(gray_to_bgr(&gray).as_raw(), &[54, 54, 54, 10, 10, 10]);
+    }
+}
@@ -0,0 +1,67 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use image::{codecs::jpeg::JpegEncoder, ImageFormat, ImageResult, RgbImage};
+
+use crate::image_bgr::BgrImage;
+
+/// Reverse the order of 3-channel pixels
+fn swap_channels(raw: &[u8]) -> Vec<u8> {
+    raw.chunks_exact(3).flat_map(|p| [p[2], p[1], p[0]]).collect()
+}
+
+/// Read and write BGR images in their actual colors
+///
+/// `ImageBuffer::save` would write red and blue swapped, since encoders assume RGB.
+pub trait BgrImageIo: Sized {
+    /// Decode any format `image` supports
+    fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self>;
+
+    fn to_rgb(&self) -> RgbImage;
+
+    fn save_png<P: AsRef<Path>>(&self, path: P) -> ImageResult<()>;
+
+    /// Save with a quality from 1 to 100
+    fn save_jpeg<P: AsRef<Path>>(&self, path: P, quality: u8) -> ImageResult<()>;
+}
+
+impl BgrImageIo for BgrImage {
+    fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        let rgb = image::open(path)?.into_rgb8();
+        let (width, height) = rgb.dimensions();
+        Ok(BgrImage::from_raw(width, height, swap_channels(rgb.as_raw()))
+            .expect("three channels per pixel"))
+    }
+
+    fn to_rgb(&self) -> RgbImage {
+        RgbImage::from_raw(self.width(), self.height(), swap_channels(self.as_raw()))
+            .expect("three channels per pixel")
+    }
+
+    fn save_png<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        self.to_rgb().save_with_format(path, ImageFormat::Png)
+    }
+
+    fn save_jpeg<P: AsRef<Path>>(&self, path: P, quality: u8) -> ImageResult<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        JpegEncoder::new_with_quality(writer, quality).encode_image(&self.to_rgb())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::image_bgr::Bgr;
+
+    #[test]
+    fn png_roundtrip() {
+        let path = std::env::temp_dir().join(format!("image_ext_{}.png", std::process::id()));
+        let img = BgrImage::from_pixel(2, 2, Bgr([10, 20, 30]));
+        img.save_png(&path).unwrap();
+
+        let rgb = image::open(&path).unwrap().into_rgb8();
+        assert_eq!(rgb.get_pixel(0, 0).0, [30, 20, 10]);
+        let loaded = BgrImage::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.as_raw(), img.as_raw());
+    }
+}
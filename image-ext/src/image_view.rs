@@ -0,0 +1,101 @@
+use crate::image_bgr::BgrImage;
+
+/// Borrowed rectangle of a BGR image whose rows are `stride` bytes apart
+///
+/// Cropping doesn't copy pixels, the view keeps referring to the whole buffer.
+#[derive(Clone, Copy, Debug)]
+pub struct StridedView<'a> {
+    /// Buffer of the whole image
+    pub(crate) data: &'a [u8],
+    /// Bytes until the first pixel
+    pub(crate) offset: usize,
+    width: u32,
+    height: u32,
+    pub(crate) stride: usize,
+}
+
+impl<'a> From<&'a BgrImage> for StridedView<'a> {
+    fn from(img: &'a BgrImage) -> Self {
+        let stride = img.width() as usize * 3;
+        Self { data: img.as_raw(), offset: 0, width: img.width(), height: img.height(), stride }
+    }
+}
+
+impl<'a> StridedView<'a> {
+    /// Rectangle of an image, `None` if it doesn't fit
+    pub fn crop(img: &'a BgrImage, x: u32, y: u32, width: u32, height: u32) -> Option<Self> {
+        Self::from(img).sub_view(x, y, width, height)
+    }
+
+    /// Rectangle within this view, `None` if it doesn't fit
+    pub fn sub_view(&self, x: u32, y: u32, width: u32, height: u32) -> Option<Self> {
+        let fits = x.checked_add(width)? <= self.width && y.checked_add(height)? <= self.height;
+        fits.then_some(Self {
+            offset: self.offset + y as usize * self.stride + x as usize * 3,
+            width,
+            height,
+            ..*self
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Left and top pixel within the whole image
+    pub fn position(&self) -> [u32; 2] {
+        // images without width have no rows to speak of
+        let x = self.offset.checked_rem(self.stride).unwrap_or(0) / 3;
+        [x as u32, self.offset.checked_div(self.stride).unwrap_or(0) as u32]
+    }
+
+    /// Strides of rows, columns and channels in bytes
+    pub fn strides(&self) -> [usize; 3] {
+        [self.stride, 3, 1]
+    }
+
+    /// Bytes from the first to the last pixel
+    pub fn as_slice(&self) -> &'a [u8] {
+        let len = match self.height as usize {
+            0 => 0,
+            h => (h - 1) * self.stride + self.width as usize * 3,
+        };
+        &self.data[self.offset..self.offset + len]
+    }
+
+    /// Pixels of each row
+    pub fn rows(&self) -> impl Iterator<Item = &'a [u8]> + 'a {
+        let (data, row_len, stride) = (self.as_slice(), self.width as usize * 3, self.stride);
+        (0..self.height as usize).map(move |r| &data[r * stride..r * stride + row_len])
+    }
+
+    /// Copy into a new image
+    pub fn to_image(&self) -> BgrImage {
+        let data = self.rows().flatten().copied().collect();
+        BgrImage::from_raw(self.width, self.height, data).expect("three channels per pixel")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crop_strided() {
+        let img = BgrImage::from_fn(4, 3, |x, y| crate::Bgr([x as u8, y as u8, 0]));
+        let view = StridedView::crop(&img, 1, 1, 2, 2).unwrap();
+        assert_eq!(view.position(), [1, 1]);
+        assert_eq!(view.strides(), [12, 3, 1]);
+        assert_eq!(view.to_image().as_raw(), &[1, 1, 0, 2, 1, 0, 1, 2, 0, 2, 2, 0]);
+
+        let pixel = view.sub_view(1, 1, 1, 1).unwrap();
+        assert_eq!(pixel.position(), [2, 2]);
+        assert_eq!(pixel.as_slice(), &[2, 2, 0]);
+        assert!(view.sub_view(1, 0, 2, 1).is_none());
+        assert!(StridedView::crop(&img, 0, 0, 4, 3).is_some());
+    }
+}
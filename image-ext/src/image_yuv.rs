@@ -0,0 +1,143 @@
+use crate::image_bgr::BgrImage;
+
+/// Planar YUV 4:2:0 image (`yuv420p` in ffmpeg)
+///
+/// A full resolution Y plane is followed by U and V planes subsampled by 2 in each dimension.
+#[derive(Clone, Debug)]
+pub struct Yuv420Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+#[inline]
+fn clamp_u8(v: i32) -> u8 {
+    v.clamp(0, 255) as u8
+}
+
+/// RGB of BT.601 limited range YUV
+#[inline]
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = 298 * (y as i32 - 16);
+    let (d, e) = (u as i32 - 128, v as i32 - 128);
+    [
+        clamp_u8((c + 409 * e + 128) >> 8),
+        clamp_u8((c - 100 * d - 208 * e + 128) >> 8),
+        clamp_u8((c + 516 * d + 128) >> 8),
+    ]
+}
+
+impl Yuv420Image {
+    /// Black image
+    pub fn new(width: u32, height: u32) -> Self {
+        let (luma, chroma) = Self::plane_sizes(width, height);
+        let mut data = vec![16; luma + 2 * chroma];
+        data[luma..].fill(128);
+        Self { width, height, data }
+    }
+
+    /// Image from Y, U and V planes in one buffer of the exact size
+    pub fn from_raw(width: u32, height: u32, data: Vec<u8>) -> Option<Self> {
+        let (luma, chroma) = Self::plane_sizes(width, height);
+        (data.len() == luma + 2 * chroma).then_some(Self { width, height, data })
+    }
+
+    /// Size of the Y and of each chroma plane
+    fn plane_sizes(width: u32, height: u32) -> (usize, usize) {
+        let (w, h) = (width as usize, height as usize);
+        (w * h, w.div_ceil(2) * h.div_ceil(2))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn as_raw(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Buffer to read raw frames into
+    pub fn as_raw_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Y, U and V planes
+    pub fn planes(&self) -> (&[u8], &[u8], &[u8]) {
+        let (luma, chroma) = Self::plane_sizes(self.width, self.height);
+        let (y, uv) = self.data.split_at(luma);
+        let (u, v) = uv.split_at(chroma);
+        (y, u, v)
+    }
+
+    /// Call `f` with the position and RGB of each pixel in row-major order
+    #[inline]
+    fn for_each_rgb(&self, mut f: impl FnMut(usize, [u8; 3])) {
+        let (y, u, v) = self.planes();
+        let (w, h) = (self.width as usize, self.height as usize);
+        let cw = w.div_ceil(2);
+        for row in 0..h {
+            let crow = (row / 2) * cw;
+            for col in 0..w {
+                let i = row * w + col;
+                let c = crow + col / 2;
+                f(i, yuv_to_rgb(y[i], u[c], v[c]));
+            }
+        }
+    }
+
+    /// Convert into an existing BGR image of the same size
+    pub fn write_bgr(&self, out: &mut BgrImage) {
+        assert_eq!(out.dimensions(), (self.width, self.height), "BGR image of same size");
+        let bgr = out.as_mut();
+        self.for_each_rgb(|i, [r, g, b]| bgr[i * 3..i * 3 + 3].copy_from_slice(&[b, g, r]));
+    }
+
+    pub fn to_bgr(&self) -> BgrImage {
+        let mut out = BgrImage::new(self.width, self.height);
+        self.write_bgr(&mut out);
+        out
+    }
+
+    /// Write R, G and B planes (CHW) with values from 0 to 1, e.g. into a model's input tensor
+    pub fn write_planar_f32(&self, out: &mut [f32]) {
+        let n = self.width as usize * self.height as usize;
+        assert_eq!(out.len(), 3 * n, "three planes of f32");
+        let (r, gb) = out.split_at_mut(n);
+        let (g, b) = gb.split_at_mut(n);
+        self.for_each_rgb(|i, [vr, vg, vb]| {
+            r[i] = vr as f32 / 255.0;
+            g[i] = vg as f32 / 255.0;
+            b[i] = vb as f32 / 255.0;
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn convert_yuv() {
+        // 3x2 has 2x1 chroma samples, left is red-ish, right gray
+        let mut img = Yuv420Image::new(3, 2);
+        img.as_raw_mut()[..6].copy_from_slice(&[81, 81, 126, 81, 81, 126]);
+        let (_, u, v) = img.planes();
+        assert_eq!((u.len(), v.len()), (2, 2));
+        img.as_raw_mut()[6..].copy_from_slice(&[90, 128, 240, 128]);
+
+        let bgr = img.to_bgr();
+        assert_eq!(bgr.as_raw()[..3], [0, 0, 255]);
+        assert_eq!(bgr.as_raw()[6..9], [128, 128, 128]);
+
+        let mut planes = vec![0.0; 3 * 6];
+        img.write_planar_f32(&mut planes);
+        assert_eq!([planes[0], planes[6], planes[12]], [1.0, 0.0, 0.0]);
+
+        assert!(Yuv420Image::from_raw(3, 2, vec![0; 9]).is_none());
+        assert_eq!(Yuv420Image::new(1, 1).to_bgr().as_raw(), &[0, 0, 0]);
+    }
+}
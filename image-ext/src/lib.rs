@@ -1,5 +1,23 @@
 mod image_bgr;
+#[cfg(feature = "egui")]
+mod image_egui;
+mod image_f32;
+#[cfg(feature = "fast_image_resize")]
+mod image_fr;
+mod image_gray;
+mod image_io;
+mod image_view;
+mod image_yuv;
 
 pub use image::imageops;
 pub use image::*;
 pub use image_bgr::{Bgr, BgrImage};
+#[cfg(feature = "egui")]
+pub use image_egui::to_color_image;
+pub use image_f32::{ChannelLayout, F32Image};
+#[cfg(feature = "fast_image_resize")]
+pub use image_fr::{AsFrImageView, AsFrImageViewMut, FrPixel, FrViewError};
+pub use image_gray::{bgr_to_gray, gray_to_bgr};
+pub use image_io::BgrImageIo;
+pub use image_view::StridedView;
+pub use image_yuv::Yuv420Image;
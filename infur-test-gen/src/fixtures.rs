@@ -0,0 +1,159 @@
+//! Synthesize and download fixtures, shared by the build script and runtime API
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use sha2::{Digest, Sha256};
+
+/// Models to download
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModelFixture {
+    /// Segmentation model, see: <https://github.com/onnx/models/tree/main/vision/object_detection_segmentation/fcn>
+    FcnResnet50Int8,
+}
+
+impl ModelFixture {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Self::FcnResnet50Int8 => "fcn-resnet50-12-int8.onnx",
+        }
+    }
+
+    pub fn url(&self) -> &'static str {
+        match self {
+            Self::FcnResnet50Int8 => "https://github.com/onnx/models/raw/main/vision/object_detection_segmentation/fcn/model/fcn-resnet50-12-int8.onnx",
+        }
+    }
+}
+
+/// Directory of the workspace with `media` and `models`
+pub fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().to_path_buf()
+}
+
+pub fn synth_video_name(width: u32, height: u32, fps: u32, secs: u32) -> String {
+    format!("synth_{width}x{height}_{secs}secs_{fps}fps.mp4")
+}
+
+fn other_err(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, msg)
+}
+
+/// Move a finished file in place, so that concurrent users never see partial ones
+pub(crate) fn finish(part: &Path, path: &Path) -> io::Result<()> {
+    fs::rename(part, path)
+}
+
+pub(crate) fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.part", std::process::id()));
+    path.with_file_name(name)
+}
+
+/// Generate a test pattern video with ffmpeg
+pub fn synth_video_to(path: &Path, width: u32, height: u32, fps: u32, secs: u32) -> io::Result<()> {
+    // ffmpeg infers the container from the extension
+    let part = part_path(path).with_extension("mp4");
+    let status = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-f", "lavfi", "-i"])
+        .arg(format!("testsrc=duration={secs}:size={width}x{height}:rate={fps}"))
+        .args(["-pix_fmt", "yuv420p", "-y"])
+        .arg(&part)
+        .status()
+        .map_err(|e| other_err(format!("couldn't run ffmpeg ({e}), do you have it in PATH?")))?;
+    if !status.success() {
+        let _ = fs::remove_file(&part);
+        return Err(other_err(format!("synthesizing {} failed with {status}", path.display())));
+    }
+    finish(&part, path)
+}
+
+fn checksum_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".sha256");
+    path.with_file_name(name)
+}
+
+/// Hex encoded SHA256 of a file
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// True if the file is present and matches the checksum recorded when it was fetched
+pub fn is_cached(path: &Path) -> bool {
+    match (fs::read_to_string(checksum_path(path)), sha256_file(path)) {
+        (Ok(expected), Ok(actual)) => expected.trim() == actual,
+        _ => false,
+    }
+}
+
+/// Set to 1 to never download, e.g. behind firewalls
+pub const OFFLINE_VAR: &str = "INFUR_TEST_OFFLINE";
+
+fn is_offline() -> bool {
+    std::env::var(OFFLINE_VAR).ok().as_deref() == Some("1")
+}
+
+/// Download a file unless present with a matching checksum
+///
+/// Offline, files put in place by hand are accepted and their checksum is recorded.
+pub fn fetch(url: &str, path: &Path) -> io::Result<()> {
+    if is_cached(path) {
+        return Ok(());
+    }
+    if !is_offline() {
+        return download_to(url, path);
+    }
+    if path.is_file() && !checksum_path(path).exists() {
+        return fs::write(checksum_path(path), sha256_file(path)?);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "{OFFLINE_VAR} is set but {} is missing or doesn't match its checksum, download {url} there",
+            path.display()
+        ),
+    ))
+}
+
+/// Download a file and record its checksum next to it
+pub fn download_to(url: &str, path: &Path) -> io::Result<()> {
+    // borrowed from onnxruntime
+    let resp = ureq::get(url)
+        .timeout(std::time::Duration::from_secs(300))
+        .call()
+        .map_err(|e| other_err(format!("failed to download {url}: {e}")))?;
+    let len = resp.header("Content-Length").and_then(|s| s.parse::<u64>().ok());
+
+    let part = part_path(path);
+    let mut writer = io::BufWriter::new(fs::File::create(&part)?);
+    let mut hasher = Sha256::new();
+    let mut reader = resp.into_reader();
+    let mut buffer = [0; 1 << 16];
+    let mut read_len = 0;
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buffer[..n])?;
+        hasher.update(&buffer[..n]);
+        read_len += n as u64;
+    }
+    writer.flush()?;
+    drop(writer);
+    if len.map_or(false, |len| len != read_len) {
+        let _ = fs::remove_file(&part);
+        return Err(other_err(format!("downloaded {read_len} of {len:?} bytes from {url}")));
+    }
+    let sha256: String = hasher.finalize().iter().map(|b| format!("{b:02x}")).collect();
+    fs::write(checksum_path(path), sha256)?;
+    finish(&part, path)
+}
@@ -1,8 +1,15 @@
-use std::{env, path::PathBuf};
+mod fixtures;
+mod scene;
+mod stream;
+
+use std::{io, path::PathBuf};
+
+pub use fixtures::ModelFixture;
+pub use scene::{default_objects, synth_objects_video_to, MovingObject, ObjectBox, Shape};
+pub use stream::StreamServer;
 
 fn media_root() -> PathBuf {
-    let gen_root = PathBuf::from(&env::var("CARGO_MANIFEST_DIR").unwrap());
-    gen_root.parent().unwrap().join("media")
+    fixtures::workspace_root().join("media")
 }
 
 pub fn long_small_video() -> PathBuf {
@@ -14,8 +21,39 @@ pub fn short_large_video() -> PathBuf {
 }
 
 pub fn fcn_resnet50_12_int8_onnx() -> PathBuf {
-    let gen_root = PathBuf::from(&env::var("CARGO_MANIFEST_DIR").unwrap());
-    gen_root.parent().unwrap().join("models").join("fcn-resnet50-12-int8.onnx")
+    fixtures::workspace_root().join("models").join(ModelFixture::FcnResnet50Int8.file_name())
+}
+
+/// Path to a test pattern video, generated if not done before
+///
+/// Requires ffmpeg in PATH.
+pub fn synth_video(width: u32, height: u32, fps: u32, secs: u32) -> io::Result<PathBuf> {
+    let path = media_root().join(fixtures::synth_video_name(width, height, fps, secs));
+    if !path.is_file() {
+        fixtures::synth_video_to(&path, width, height, fps, secs)?;
+    }
+    Ok(path)
+}
+
+/// Video of `default_objects` and its ground truth, generated if not done before
+///
+/// The JSON lists the bounding box of each object per frame id.
+pub fn moving_objects_video() -> io::Result<(PathBuf, PathBuf)> {
+    let path = media_root().join("synth_objects_320x240_10fps.mp4");
+    let truth = path.with_extension("json");
+    if !(path.is_file() && truth.is_file()) {
+        scene::synth_objects_video_to(&path, &default_objects(), 320, 240, 10, 30)?;
+    }
+    Ok((path, truth))
+}
+
+/// Path to a model, downloaded unless present with a matching checksum
+///
+/// Fails instead of downloading if `INFUR_TEST_OFFLINE=1`.
+pub fn ensure_model(model: ModelFixture) -> io::Result<PathBuf> {
+    let path = fixtures::workspace_root().join("models").join(model.file_name());
+    fixtures::fetch(model.url(), &path)?;
+    Ok(path)
 }
 
 #[cfg(test)]
@@ -36,4 +74,18 @@ mod tests {
     fn fcn_resnet50_12_int8_onnx_exists() {
         assert!(fcn_resnet50_12_int8_onnx().is_file())
     }
+
+    #[test]
+    fn synth_on_demand() {
+        let video = synth_video(64, 48, 5, 1).unwrap();
+        assert!(video.ends_with("synth_64x48_1secs_5fps.mp4"));
+        assert!(video.is_file());
+    }
+
+    #[test]
+    fn objects_with_ground_truth() {
+        let (video, truth) = moving_objects_video().unwrap();
+        assert!(video.is_file());
+        assert!(std::fs::read_to_string(truth).unwrap().contains(r#""label":"circle""#));
+    }
 }
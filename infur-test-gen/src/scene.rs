@@ -0,0 +1,201 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::fixtures::{finish, part_path};
+
+/// Outline of a synthetic object
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Rect,
+    Circle,
+}
+
+/// Object moving at constant speed, bouncing off the frame's edges
+#[derive(Clone, Debug)]
+pub struct MovingObject {
+    pub label: &'static str,
+    pub shape: Shape,
+    /// Width and height of its bounding box
+    pub size: [u32; 2],
+    pub bgr: [u8; 3],
+    /// Top left corner in the first frame
+    pub start: [u32; 2],
+    /// Pixels moved per frame
+    pub velocity: [i32; 2],
+}
+
+/// Where an object is in a frame
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectBox {
+    pub label: &'static str,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Position along one axis, reflected at 0 and `span`
+fn bounce(start: u32, velocity: i32, frame: u32, span: u32) -> u32 {
+    if span == 0 {
+        return 0;
+    }
+    let period = 2 * span as i64;
+    let pos = (start as i64 + velocity as i64 * frame as i64).rem_euclid(period);
+    (if pos > span as i64 { period - pos } else { pos }) as u32
+}
+
+impl MovingObject {
+    /// Bounding box in a frame (0 is the first) of a `width` x `height` video
+    pub fn at(&self, frame: u32, width: u32, height: u32) -> ObjectBox {
+        let [w, h] = [self.size[0].min(width), self.size[1].min(height)];
+        ObjectBox {
+            label: self.label,
+            x: bounce(self.start[0].min(width - w), self.velocity[0], frame, width - w),
+            y: bounce(self.start[1].min(height - h), self.velocity[1], frame, height - h),
+            width: w,
+            height: h,
+        }
+    }
+
+    fn draw(&self, bgr: &mut [u8], frame: u32, width: u32, height: u32) {
+        let b = self.at(frame, width, height);
+        let (rx, ry) = (b.width as f32 / 2.0, b.height as f32 / 2.0);
+        for y in 0..b.height {
+            for x in 0..b.width {
+                let inside = match self.shape {
+                    Shape::Rect => true,
+                    Shape::Circle => {
+                        let (dx, dy) = ((x as f32 + 0.5 - rx) / rx, (y as f32 + 0.5 - ry) / ry);
+                        dx * dx + dy * dy <= 1.0
+                    }
+                };
+                if inside {
+                    let i = (((b.y + y) * width + b.x + x) * 3) as usize;
+                    bgr[i..i + 3].copy_from_slice(&self.bgr);
+                }
+            }
+        }
+    }
+}
+
+/// A red rectangle and a green circle crossing each other
+pub fn default_objects() -> Vec<MovingObject> {
+    vec![
+        MovingObject {
+            label: "rect",
+            shape: Shape::Rect,
+            size: [40, 30],
+            bgr: [0, 0, 255],
+            start: [10, 20],
+            velocity: [7, 3],
+        },
+        MovingObject {
+            label: "circle",
+            shape: Shape::Circle,
+            size: [32, 32],
+            bgr: [0, 255, 0],
+            start: [200, 150],
+            velocity: [-5, -4],
+        },
+    ]
+}
+
+/// Ground truth as JSON, frame ids start at 1 like the decoder's
+fn ground_truth(
+    objects: &[MovingObject],
+    width: u32,
+    height: u32,
+    fps: u32,
+    frames: u32,
+) -> String {
+    let mut json = format!(r#"{{"width":{width},"height":{height},"fps":{fps},"frames":["#);
+    for frame in 0..frames {
+        let boxes = objects
+            .iter()
+            .map(|o| o.at(frame, width, height))
+            .map(|b| {
+                format!(
+                    r#"{{"label":"{}","x":{},"y":{},"width":{},"height":{}}}"#,
+                    b.label, b.x, b.y, b.width, b.height
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let sep = if frame + 1 < frames { "," } else { "" };
+        let _ = write!(json, r#"{{"id":{},"objects":[{boxes}]}}{sep}"#, frame + 1);
+    }
+    json.push_str("]}");
+    json
+}
+
+/// Encode objects moving over a dark background and write their boxes to a `.json` next to it
+pub fn synth_objects_video_to(
+    path: &Path,
+    objects: &[MovingObject],
+    width: u32,
+    height: u32,
+    fps: u32,
+    frames: u32,
+) -> io::Result<PathBuf> {
+    // ffmpeg infers the container from the extension
+    let part = part_path(path).with_extension(path.extension().unwrap_or_default());
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "bgr24"])
+        .args(["-s", &format!("{width}x{height}"), "-r", &fps.to_string(), "-i", "pipe:0"])
+        .args(["-pix_fmt", "yuv420p", "-y"])
+        .arg(&part)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("couldn't run ffmpeg ({e})")))?;
+    {
+        let mut stdin = ffmpeg.stdin.take().expect("piped stdin");
+        let background = vec![32; (width * height * 3) as usize];
+        for frame in 0..frames {
+            let mut bgr = background.clone();
+            for object in objects {
+                object.draw(&mut bgr, frame, width, height);
+            }
+            stdin.write_all(&bgr)?;
+        }
+    }
+    let status = ffmpeg.wait()?;
+    if !status.success() {
+        let _ = fs::remove_file(&part);
+        let msg = format!("encoding {} failed with {status}", path.display());
+        return Err(io::Error::new(io::ErrorKind::Other, msg));
+    }
+    finish(&part, path)?;
+    let truth_path = path.with_extension("json");
+    let truth_part = part_path(&truth_path);
+    fs::write(&truth_part, ground_truth(objects, width, height, fps, frames))?;
+    finish(&truth_part, &truth_path)?;
+    Ok(truth_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bounce_off_edges() {
+        let positions = (0..6).map(|f| bounce(1, 2, f, 4)).collect::<Vec<_>>();
+        assert_eq!(positions, [1, 3, 3, 1, 1, 3]);
+        assert_eq!(bounce(3, -1, 4, 4), 1);
+        assert_eq!(bounce(5, 1, 0, 0), 0);
+    }
+
+    #[test]
+    fn boxes_as_json() {
+        let objects = &default_objects()[..1];
+        let json = ground_truth(objects, 320, 240, 10, 2);
+        assert_eq!(
+            json,
+            r#"{"width":320,"height":240,"fps":10,"frames":[{"id":1,"objects":[{"label":"rect","x":10,"y":20,"width":40,"height":30}]},{"id":2,"objects":[{"label":"rect","x":17,"y":23,"width":40,"height":30}]}]}"#
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, TcpListener},
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How often the serving process is checked on
+const POLL: Duration = Duration::from_millis(20);
+
+/// Local HTTP server streaming a looping test pattern as multipart JPEG
+///
+/// Each connection is served by a new ffmpeg process, so clients can reconnect.
+/// The server stops when dropped.
+pub struct StreamServer {
+    url: String,
+    stop: Arc<AtomicBool>,
+    child: Arc<Mutex<Option<Child>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+fn free_addr() -> io::Result<SocketAddr> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?.local_addr()
+}
+
+/// True once some process listens on the address
+fn is_listening(addr: SocketAddr) -> bool {
+    matches!(TcpListener::bind(addr), Err(e) if e.kind() == io::ErrorKind::AddrInUse)
+}
+
+fn spawn_ffmpeg(url: &str, width: u32, height: u32, fps: u32) -> io::Result<Child> {
+    Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-re", "-f", "lavfi", "-i"])
+        .arg(format!("testsrc=size={width}x{height}:rate={fps}"))
+        .args(["-f", "mpjpeg", "-listen", "1"])
+        .arg(url)
+        .stdin(Stdio::null())
+        .spawn()
+}
+
+impl StreamServer {
+    /// Serve at `http://127.0.0.1:PORT/stream` on a free port, ready to connect on return
+    pub fn mjpeg(width: u32, height: u32, fps: u32) -> io::Result<Self> {
+        let addr = free_addr()?;
+        let url = format!("http://{addr}/stream");
+        let stop = Arc::new(AtomicBool::new(false));
+        let child = Arc::new(Mutex::new(Some(spawn_ffmpeg(&url, width, height, fps)?)));
+
+        let start = Instant::now();
+        while !is_listening(addr) {
+            if start.elapsed() > Duration::from_secs(10) {
+                Self::kill(&child);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "ffmpeg isn't listening"));
+            }
+            thread::sleep(POLL);
+        }
+
+        let thread = {
+            let (url, stop, child) = (url.clone(), stop.clone(), child.clone());
+            thread::Builder::new().name("StreamServer".to_string()).spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let mut current = child.lock().unwrap();
+                    let running = matches!(current.as_mut().map(Child::try_wait), Some(Ok(None)));
+                    if !running {
+                        // serve the next client
+                        *current = spawn_ffmpeg(&url, width, height, fps).ok();
+                    }
+                    drop(current);
+                    thread::sleep(POLL);
+                }
+            })?
+        };
+        Ok(Self { url, stop, child, thread: Some(thread) })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Drop the current client, e.g. to test reconnecting
+    pub fn disconnect(&self) {
+        Self::kill(&self.child);
+    }
+
+    fn kill(child: &Mutex<Option<Child>>) {
+        if let Some(child) = child.lock().unwrap().as_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Drop for StreamServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        Self::kill(&self.child);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+
+    fn read_head(url: &str) -> String {
+        let addr = url.trim_start_matches("http://").trim_end_matches("/stream");
+        let mut conn = std::net::TcpStream::connect(addr).unwrap();
+        write!(conn, "GET /stream HTTP/1.1\r\nHost: {addr}\r\n\r\n").unwrap();
+        let mut head = [0; 256];
+        conn.read_exact(&mut head).unwrap();
+        String::from_utf8_lossy(&head).to_string()
+    }
+
+    #[test]
+    fn serve_and_reconnect() {
+        let server = StreamServer::mjpeg(64, 48, 10).unwrap();
+        assert!(read_head(server.url()).contains("multipart"));
+        server.disconnect();
+        let start = Instant::now();
+        while !is_listening(server.url()[7..].trim_end_matches("/stream").parse().unwrap()) {
+            assert!(start.elapsed() < Duration::from_secs(10), "no restart");
+            thread::sleep(POLL);
+        }
+        assert!(read_head(server.url()).contains("multipart"));
+    }
+}
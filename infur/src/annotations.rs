@@ -0,0 +1,339 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::class_map::label;
+
+/// Segments with fewer pixels aren't annotated, e.g. speckles at class borders
+const MIN_SEGMENT_PIXELS: usize = 16;
+
+/// File of all images' annotations in CVAT's format
+const CVAT_FILE: &str = "annotations.xml";
+/// File of YOLO's class names by index
+const YOLO_NAMES_FILE: &str = "classes.txt";
+
+/// Formats of labeling tools to import annotations of exported frames into
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AnnotationFormat {
+    /// A text file per image of `class cx cy w h` rows, relative to its size
+    Yolo,
+    /// A JSON file per image
+    Labelme,
+    /// One `annotations.xml` of all images (CVAT for images 1.1)
+    Cvat,
+}
+
+/// Box around something a model found, by class index
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Detection {
+    pub(crate) class: usize,
+    /// `x1, y1, x2, y2` relative to the image's size
+    pub(crate) bbox: [f32; 4],
+}
+
+/// Model outputs of a frame to pre-annotate it with
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Annotations {
+    /// Class per pixel of the first segmentation
+    pub(crate) classes: Option<Arc<Array2<u16>>>,
+    /// Of outputs decoded as boxes
+    pub(crate) boxes: Vec<Detection>,
+    /// Labels of classes by index, their number if unnamed
+    pub(crate) names: Arc<Vec<String>>,
+}
+
+impl Annotations {
+    /// Boxes and the bounds of each segment, except for the background (class 0)
+    pub(crate) fn detections(&self) -> Vec<Detection> {
+        let mut found = self.boxes.clone();
+        if let Some(classes) = &self.classes {
+            found.extend(segments(classes));
+        }
+        found
+    }
+}
+
+/// Bounds of 4-connected pixels of the same class
+fn segments(classes: &Array2<u16>) -> Vec<Detection> {
+    let (h, w) = classes.dim();
+    let mut seen = Array2::from_elem((h, w), false);
+    let mut found = vec![];
+    let mut todo = vec![];
+    for ((y, x), &class) in classes.indexed_iter() {
+        if class == 0 || seen[[y, x]] {
+            continue;
+        }
+        seen[[y, x]] = true;
+        todo.push((y, x));
+        let (mut min, mut max, mut pixels) = ((x, y), (x, y), 0);
+        while let Some((y, x)) = todo.pop() {
+            pixels += 1;
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+            let neighbors =
+                [(y.wrapping_sub(1), x), (y + 1, x), (y, x.wrapping_sub(1)), (y, x + 1)];
+            for (ny, nx) in neighbors {
+                if ny < h && nx < w && !seen[[ny, nx]] && classes[[ny, nx]] == class {
+                    seen[[ny, nx]] = true;
+                    todo.push((ny, nx));
+                }
+            }
+        }
+        if pixels >= MIN_SEGMENT_PIXELS {
+            let (w, h) = (w as f32, h as f32);
+            let bbox = [
+                min.0 as f32 / w,
+                min.1 as f32 / h,
+                (max.0 + 1) as f32 / w,
+                (max.1 + 1) as f32 / h,
+            ];
+            found.push(Detection { class: class as usize, bbox });
+        }
+    }
+    found
+}
+
+/// Rows of `class cx cy w h`
+fn yolo(detections: &[Detection]) -> String {
+    let mut rows = String::new();
+    for Detection { class, bbox: [x1, y1, x2, y2] } in detections {
+        let (cx, cy) = ((x1 + x2) / 2.0, (y1 + y2) / 2.0);
+        _ = writeln!(rows, "{} {:.6} {:.6} {:.6} {:.6}", class, cx, cy, x2 - x1, y2 - y1);
+    }
+    rows
+}
+
+/// Labelme's annotations of an image of `width`x`height`, rectangles in pixels
+fn labelme(
+    image: &str,
+    [width, height]: [u32; 2],
+    detections: &[Detection],
+    names: &[String],
+) -> serde_json::Value {
+    let (w, h) = (width as f32, height as f32);
+    let shapes = detections.iter().map(|&Detection { class, bbox: [x1, y1, x2, y2] }| {
+        json!({
+            "label": label(names, class),
+            "points": [[x1 * w, y1 * h], [x2 * w, y2 * h]],
+            "group_id": null,
+            "shape_type": "rectangle",
+            "flags": {},
+        })
+    });
+    json!({
+        "version": "5.0.1",
+        "flags": {},
+        "shapes": shapes.collect::<Vec<_>>(),
+        "imagePath": image,
+        "imageData": null,
+        "imageHeight": height,
+        "imageWidth": width,
+    })
+}
+
+/// Text escaped for XML attributes and elements
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// CVAT's `<image>` element of the `id`th image, boxes in pixels
+fn cvat_image(
+    id: usize,
+    image: &str,
+    [width, height]: [u32; 2],
+    detections: &[Detection],
+    names: &[String],
+) -> String {
+    let (w, h) = (width as f32, height as f32);
+    let mut xml = format!(
+        "  <image id=\"{}\" name=\"{}\" width=\"{}\" height=\"{}\">\n",
+        id,
+        xml_escape(image),
+        width,
+        height
+    );
+    for &Detection { class, bbox: [x1, y1, x2, y2] } in detections {
+        _ = writeln!(
+            xml,
+            "    <box label=\"{}\" source=\"auto\" occluded=\"0\" \
+             xtl=\"{:.2}\" ytl=\"{:.2}\" xbr=\"{:.2}\" ybr=\"{:.2}\" z_order=\"0\"/>",
+            xml_escape(&label(names, class)),
+            x1 * w,
+            y1 * h,
+            x2 * w,
+            y2 * h
+        );
+    }
+    xml.push_str("  </image>\n");
+    xml
+}
+
+/// CVAT's `<meta>` element declaring the labels of all named and annotated classes
+fn cvat_meta(names: &[String], classes: &BTreeSet<usize>) -> String {
+    let mut xml = String::from("  <meta>\n    <task>\n      <labels>\n");
+    for class in (0..names.len()).chain(classes.iter().copied()).collect::<BTreeSet<_>>() {
+        _ = writeln!(
+            xml,
+            "        <label>\n          <name>{}</name>\n        </label>",
+            xml_escape(&label(names, class))
+        );
+    }
+    xml.push_str("      </labels>\n    </task>\n  </meta>\n");
+    xml
+}
+
+/// Writes annotations of images in a directory for a labeling tool to import
+pub(crate) struct AnnotationWriter {
+    format: AnnotationFormat,
+    dir: PathBuf,
+    /// Images annotated so far
+    images: usize,
+    /// `<image>` elements written at the end
+    cvat: String,
+    /// Of all boxes written into `cvat`, to declare their labels
+    classes: BTreeSet<usize>,
+    /// Of classes of the last image
+    names: Arc<Vec<String>>,
+}
+
+impl AnnotationWriter {
+    pub(crate) fn new(format: AnnotationFormat, dir: PathBuf) -> Self {
+        let (cvat, classes) = (String::new(), BTreeSet::new());
+        Self { format, dir, images: 0, cvat, classes, names: Arc::default() }
+    }
+
+    /// Annotate the image `name` of `size` in the directory
+    pub(crate) fn write(
+        &mut self,
+        name: &str,
+        size: [u32; 2],
+        annotations: &Annotations,
+    ) -> io::Result<()> {
+        let detections = annotations.detections();
+        let names = &annotations.names;
+        let path = |ext: &str| self.dir.join(Path::new(name).with_extension(ext));
+        match self.format {
+            AnnotationFormat::Yolo => fs::write(path("txt"), yolo(&detections))?,
+            AnnotationFormat::Labelme => {
+                let json = labelme(name, size, &detections, names);
+                fs::write(path("json"), serde_json::to_string_pretty(&json)?)?
+            }
+            AnnotationFormat::Cvat => {
+                self.cvat.push_str(&cvat_image(self.images, name, size, &detections, names));
+                self.classes.extend(detections.iter().map(|d| d.class));
+            }
+        }
+        self.names = names.clone();
+        self.images += 1;
+        Ok(())
+    }
+
+    /// Write what's collected of all images
+    ///
+    /// YOLO's class indices are named line by line in `classes.txt`, if named.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        match self.format {
+            AnnotationFormat::Yolo if !self.names.is_empty() => {
+                fs::write(self.dir.join(YOLO_NAMES_FILE), self.names.join("\n") + "\n")
+            }
+            AnnotationFormat::Cvat => {
+                let xml = format!(
+                    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<annotations>\n  \
+                     <version>1.1</version>\n{}{}</annotations>\n",
+                    cvat_meta(&self.names, &self.classes),
+                    self.cvat
+                );
+                fs::write(self.dir.join(CVAT_FILE), xml)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn detection() -> Detection {
+        Detection { class: 2, bbox: [0.25, 0.5, 0.75, 1.0] }
+    }
+
+    #[test]
+    fn segment_bounds() {
+        let mut classes = Array2::<u16>::zeros((10, 20));
+        classes.slice_mut(ndarray::s![2..6, 0..4]).fill(1);
+        classes.slice_mut(ndarray::s![6.., 10..14]).fill(1);
+        // too small
+        classes[[0, 19]] = 3;
+        let annotations = Annotations {
+            classes: Some(Arc::new(classes)),
+            boxes: vec![detection()],
+            ..Annotations::default()
+        };
+        let found = annotations.detections();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0], detection());
+        assert_eq!(found[1], Detection { class: 1, bbox: [0.0, 0.2, 0.2, 0.6] });
+        assert_eq!(found[2], Detection { class: 1, bbox: [0.5, 0.6, 0.7, 1.0] });
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(yolo(&[detection()]), "2 0.500000 0.750000 0.500000 0.500000\n");
+
+        let json = labelme("000001.png", [40, 20], &[detection()], &[]);
+        assert_eq!(json["shapes"][0]["points"], json!([[10.0, 10.0], [30.0, 20.0]]));
+        assert_eq!(json["shapes"][0]["label"], "2");
+        assert_eq!(json["imageWidth"], 40);
+        let names = ["other", "person", "vehicle"].map(String::from);
+        let json = labelme("000001.png", [40, 20], &[detection()], &names);
+        assert_eq!(json["shapes"][0]["label"], "vehicle");
+
+        let xml = cvat_image(0, "000001.png", [40, 20], &[detection()], &[]);
+        assert!(xml.contains(r#"name="000001.png" width="40" height="20""#));
+        assert!(xml.contains(r#"label="2""#) && xml.contains(r#"xtl="10.00" ytl="10.00""#));
+        let xml = cvat_image(0, "000001.png", [40, 20], &[detection()], &names);
+        assert!(xml.contains(r#"label="vehicle""#));
+        let names = ["other", "person", "car & <\"truck\">"].map(String::from);
+        let xml = cvat_image(0, "a&b.png", [40, 20], &[detection()], &names);
+        assert!(xml.contains(r#"name="a&amp;b.png""#));
+        assert!(xml.contains(r#"label="car &amp; &lt;&quot;truck&quot;&gt;""#));
+    }
+
+    #[test]
+    fn write_cvat() {
+        let dir = std::env::temp_dir().join(format!("infur_annotations_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut writer = AnnotationWriter::new(AnnotationFormat::Cvat, dir.clone());
+        let annotations = Annotations { boxes: vec![detection()], ..Annotations::default() };
+        writer.write("000001.png", [40, 20], &annotations).unwrap();
+        writer.write("000002.png", [40, 20], &Annotations::default()).unwrap();
+        writer.finish().unwrap();
+        let xml = fs::read_to_string(dir.join(CVAT_FILE)).unwrap();
+        assert_eq!(xml.matches("<image ").count(), 2);
+        assert!(xml.contains("<meta>\n    <task>\n      <labels>\n        <label>\n"));
+        assert_eq!(xml.matches("<name>2</name>").count(), 1, "boxes' class should be declared");
+        assert!(xml.ends_with("</annotations>\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,14 +1,32 @@
 /// Example Application
+use std::{
+    mem::discriminant,
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
 use eframe::epaint::ColorImage;
-use ff_video::{FFVideoError, VideoProcError};
-use image_ext::Pixel;
-use onnxruntime::ndarray::Array3;
+use ff_video::{AbortHandle, FFVideoError, VideoProcError};
+use image_ext::{BgrImage, RgbImage};
+use ndarray::Array3;
 use thiserror::Error;
+use tracing::{debug, debug_span, field, warn, Span};
 
 use crate::{
-    decode_predict::ColorCode,
-    predict_onnx::{Model, ModelCmd, ModelCmdError, ModelInfo, ModelProcError},
-    processing::{Frame, Scale, ScaleProcError, ValidScaleError, VideoCmd, VideoPlayer},
+    annotations::Annotations,
+    calibration::{Calibration, Position},
+    decode_predict::{Histogram, OverlayCmd},
+    embeddings::Embedding,
+    inference::InferenceWorker,
+    predict_onnx::{ModelCmd, ModelCmdError, ModelInfo, ModelOutput, ModelProcError},
+    preview::{composite, OverlayStyle},
+    processing::{
+        ArchiveOpts, Frame, ReadPolicy, Scale, ScaleProcError, ScaledFrame, ValidScaleError,
+        VideoCmd, VideoInfo, VideoPlayer,
+    },
+    routing::{OutputRoutes, Router},
+    shm::ShmOpts,
+    zones::{zone_stats, Zone, ZoneStats},
 };
 
 pub(crate) use crate::processing::Processor;
@@ -36,7 +54,7 @@ pub(crate) enum AppCmdError {
 }
 
 /// Control entire application
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub(crate) enum AppCmd {
     /// Control video input
     Video(VideoCmd),
@@ -44,44 +62,684 @@ pub(crate) enum AppCmd {
     Scale(f32),
     /// Control loaded model, empty disables it
     Model(ModelCmd),
+    /// Visualize model outputs by name
+    Route(OutputRoutes),
+    /// Control how processed frames are handed to the GUI
+    FramePolicy(FramePolicy),
+    /// Process every frame and time it by the video instead of the wall clock
+    Deterministic(bool),
+    /// Control how model outputs are drawn
+    Overlay(OverlayCmd),
+    /// Control how overlays are blended onto frames
+    OverlayStyle(OverlayStyle),
+    /// Tell results within these zones
+    Zones(Vec<Zone>),
+    /// Keep classes per pixel to pre-annotate frames with, zones keep them regardless
+    Annotate(bool),
+    /// Report zone areas and positions of boxes in world coordinates, in pixels if none
+    Calibration(Option<Calibration>),
+    /// Control how failed reads of the video are retried
+    ReadPolicy(ReadPolicy),
+    /// Control how stages recover from failing on frames
+    ErrorPolicy(ErrorPolicy),
+    /// Control how processing keeps up with live videos
+    LagPolicy(LagPolicy),
+    /// Count frames whose results take longer than this many seconds, none if none
+    LatencyBudget(Option<f64>),
+    /// Copy live inputs to files as they are, nothing if none
+    Archive(Option<ArchiveOpts>),
+    /// Share decoded frames with other local processes, nothing if none
+    Share(Option<ShmOpts>),
+    /// Restart processing, e.g. after it gave up
+    Restart,
     /// Exit App
     Exit,
 }
 
+/// Whether a later command supersedes an earlier one
+fn same_kind(a: &AppCmd, b: &AppCmd) -> bool {
+    match (a, b) {
+        (AppCmd::Model(a), AppCmd::Model(b)) => discriminant(a) == discriminant(b),
+        (AppCmd::Overlay(a), AppCmd::Overlay(b)) => discriminant(a) == discriminant(b),
+        (a, b) => discriminant(a) == discriminant(b),
+    }
+}
+
+/// Order and coalesce queued commands so that the latest intent is applied quickly
+///
+/// `Exit` discards everything else. Video commands come first, starting with the last
+/// `Play` or `Stop` since earlier ones would be superseded anyway, except for the last `Clip`
+/// and `Pause` which still apply to what is played next. Of the other commands,
+/// only the last one of each kind is kept.
+pub(crate) fn prioritize(cmds: Vec<AppCmd>) -> Vec<AppCmd> {
+    if cmds.iter().any(|c| matches!(c, AppCmd::Exit)) {
+        return vec![AppCmd::Exit];
+    }
+    let restart = cmds
+        .iter()
+        .rposition(|c| matches!(c, AppCmd::Video(VideoCmd::Play(_) | VideoCmd::Stop)))
+        .unwrap_or(0);
+    let mut clip = None;
+    let mut paused = None;
+    let mut video = vec![];
+    let mut other: Vec<AppCmd> = vec![];
+    for (i, cmd) in cmds.into_iter().enumerate() {
+        match cmd {
+            // pausing and clipping outlast restarts
+            AppCmd::Video(VideoCmd::Pause(_)) if i < restart => paused = Some(cmd),
+            AppCmd::Video(VideoCmd::Clip(_)) if i < restart => clip = Some(cmd),
+            AppCmd::Video(_) if i < restart => {}
+            AppCmd::Video(_) => video.push(cmd),
+            cmd => {
+                other.retain(|c| !same_kind(c, &cmd));
+                other.push(cmd);
+            }
+        }
+    }
+    clip.into_iter().chain(paused).chain(video).chain(other).collect()
+}
+
+/// How processed frames are delivered if the GUI can't keep up
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub(crate) enum FramePolicy {
+    /// Block processing until the GUI took the frame (may lag behind)
+    #[default]
+    ShowEvery,
+    /// Never block processing, the GUI shows the latest frame and decoding skips frames
+    /// processing can't keep up with (drops others)
+    ShowLatest,
+}
+
+/// What a stage does once it failed on too many frames in a row
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub(crate) enum Recovery {
+    /// Go on without the frames it fails on, quietly
+    #[default]
+    SkipFrame,
+    /// Bypass the stage until it's configured again, frames are shown without its results
+    DisableStage,
+    /// Set the stage up again, e.g. reload the model, and give it as many tries
+    RestartStage,
+    /// Stop the video
+    StopPipeline,
+}
+
+/// How often a stage may fail in a row before it recovers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct StagePolicy {
+    /// Failures in a row reported as they happen
+    pub(crate) failures: u32,
+    pub(crate) recovery: Recovery,
+}
+
+impl Default for StagePolicy {
+    fn default() -> Self {
+        Self { failures: 3, recovery: Recovery::default() }
+    }
+}
+
+/// Recovery of each stage that may fail on a frame, decoding has its `ReadPolicy`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct ErrorPolicy {
+    pub(crate) scale: StagePolicy,
+    pub(crate) model: StagePolicy,
+}
+
+/// Failures of a stage so far
+#[derive(Clone, Copy, Debug, Default, serde::Serialize)]
+pub(crate) struct StageStats {
+    /// Frames the stage failed on
+    pub(crate) failures: u64,
+    /// Failures since the stage last succeeded or recovered
+    pub(crate) in_a_row: u32,
+    /// Times the stage recovered by its policy
+    pub(crate) recoveries: u64,
+    /// Bypassed after failing
+    pub(crate) disabled: bool,
+}
+
+impl StageStats {
+    /// Failed beyond what `policy` recovers from, e.g. stopped or still failing on a broken model
+    pub(crate) fn failed(&self, policy: StagePolicy) -> bool {
+        self.recoveries > 0
+            && (policy.recovery == Recovery::StopPipeline || self.disabled || self.in_a_row > 0)
+    }
+}
+
+/// Failures of each stage
+#[derive(Clone, Copy, Debug, Default, serde::Serialize)]
+pub(crate) struct ErrorStats {
+    pub(crate) scale: StageStats,
+    pub(crate) model: StageStats,
+}
+
+/// Most frames to infer on only one of when falling behind
+const MAX_INFER_EVERY: u32 = 16;
+/// Least scale factor to scale down to when falling behind
+const MIN_SCALE: f32 = 0.1;
+/// Factor to scale down by at a time when falling behind
+const SCALE_STEP: f32 = 0.75;
+
+/// What's traded to keep up with live videos
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub(crate) enum Adaptation {
+    /// Infer on every other frame, then every 4th and so on, others show the last results
+    #[default]
+    InferenceInterval,
+    /// Scale frames down further
+    Scale,
+}
+
+/// How far processing may fall behind a live video before it adapts to keep up
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct LagPolicy {
+    /// Seconds behind the live video to adapt at, each time the lag grew by as much again.
+    /// Never adapts if none.
+    pub(crate) max_lag: Option<f64>,
+    pub(crate) adapt: Adaptation,
+}
+
+/// How processing keeps up with a live video
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+pub(crate) struct KeepUpStats {
+    /// Seconds behind the live video as of the last frame
+    pub(crate) lag: Option<f64>,
+    /// Frames to infer on only one of
+    pub(crate) infer_every: u32,
+    /// Of frames passed to the model
+    pub(crate) scale: f32,
+    /// Times processing adapted to keep up
+    pub(crate) adaptations: u64,
+    /// Least lag since the last adaptation
+    #[serde(skip)]
+    baseline: f64,
+}
+
+impl Default for KeepUpStats {
+    fn default() -> Self {
+        Self { lag: None, infer_every: 1, scale: 1.0, adaptations: 0, baseline: 0.0 }
+    }
+}
+
+/// Adapt to `lag` behind a live video if it grew too much, the scale to set if scaled down
+fn keep_up(policy: LagPolicy, stats: &mut KeepUpStats, lag: f64, scale: f32) -> Option<f32> {
+    stats.lag = Some(lag);
+    stats.scale = scale;
+    stats.baseline = stats.baseline.min(lag);
+    let max_lag = policy.max_lag?;
+    if lag <= stats.baseline + max_lag {
+        return None;
+    }
+    let scaled = match policy.adapt {
+        Adaptation::InferenceInterval if stats.infer_every < MAX_INFER_EVERY => {
+            stats.infer_every = (stats.infer_every * 2).min(MAX_INFER_EVERY);
+            None
+        }
+        Adaptation::Scale if scale > MIN_SCALE => Some((scale * SCALE_STEP).max(MIN_SCALE)),
+        // as good as it gets
+        _ => return None,
+    };
+    stats.baseline = lag;
+    stats.adaptations += 1;
+    warn!(
+        "{:.1} s behind live video, inferring on every {} frame(s) at scale {:.2}",
+        lag,
+        stats.infer_every,
+        scaled.unwrap_or(scale)
+    );
+    scaled
+}
+
+/// Latency of results against a budget
+#[derive(Clone, Copy, Debug, Default, serde::Serialize)]
+pub(crate) struct LatencyStats {
+    /// Seconds from decoding the last frame to its results
+    pub(crate) last: Option<f64>,
+    /// Most seconds any frame took
+    pub(crate) max: f64,
+    /// Seconds results may take
+    pub(crate) budget: Option<f64>,
+    /// Frames counted
+    pub(crate) frames: u64,
+    /// Frames whose results took longer than the budget
+    pub(crate) missed: u64,
+}
+
+impl LatencyStats {
+    fn count(&mut self, latency: f64) {
+        self.last = Some(latency);
+        self.max = self.max.max(latency);
+        self.frames += 1;
+        if self.budget.map_or(false, |budget| latency > budget) {
+            self.missed += 1;
+        }
+    }
+}
+
+/// Stage that may fail on a frame
+#[derive(Clone, Copy, Debug)]
+enum Stage {
+    Scale,
+    Model,
+}
+
+/// Count a stage's outcome, the recovery to apply if it failed too often in a row
+fn check_stage<E: std::fmt::Display>(
+    stage: Stage,
+    policy: StagePolicy,
+    stats: &mut StageStats,
+    result: Result<(), E>,
+) -> Result<Option<Recovery>, E> {
+    let e = match result {
+        Ok(()) => {
+            stats.in_a_row = 0;
+            return Ok(None);
+        }
+        Err(e) => e,
+    };
+    stats.failures += 1;
+    stats.in_a_row += 1;
+    if stats.in_a_row <= policy.failures {
+        return Err(e);
+    }
+    // skipping goes on quietly
+    if stats.in_a_row == policy.failures + 1 {
+        warn!(
+            "{:?} failed {} times in a row, recovering by {:?}: {}",
+            stage, stats.in_a_row, policy.recovery, e
+        );
+        stats.recoveries += 1;
+    }
+    if matches!(policy.recovery, Recovery::RestartStage | Recovery::StopPipeline) {
+        stats.in_a_row = 0;
+    }
+    if policy.recovery == Recovery::DisableStage {
+        stats.disabled = true;
+    }
+    Ok(Some(policy.recovery))
+}
+
 /// Example app
 #[derive(Default)]
-pub(crate) struct ProcessingApp<'m> {
+pub(crate) struct ProcessingApp {
     vid: VideoPlayer,
     scale: Scale,
     frame: Option<Frame>,
-    scaled_frame: Option<Frame>,
-    model: Model<'m>,
-    decoder: ColorCode,
-    decoded_img: Option<ColorImage>,
+    scaled_frame: ScaledFrame,
+    model: InferenceWorker,
+    /// Outputs of the model kept to write the next ones into
+    outputs: Vec<ModelOutput>,
+    /// Of the loaded model to restart it
+    model_path: String,
+    error_policy: ErrorPolicy,
+    errors: ErrorStats,
+    lag_policy: LagPolicy,
+    keep_up: KeepUpStats,
+    /// Scale before it was lowered to keep up, restored when played again
+    unscaled: Option<f32>,
+    /// Processing adapted to keep up since last asked
+    adapted: bool,
+    latency: LatencyStats,
+    router: Router,
+    decoded_img: Option<Arc<ColorImage>>,
+    overlay_style: OverlayStyle,
+    zones: Vec<Zone>,
+    annotate: bool,
+    calibration: Option<Calibration>,
+    frame_policy: FramePolicy,
+    /// Reproducible results regardless of processing speed
+    deterministic: bool,
+    /// Process the current frame again, e.g. to draw it differently while paused
+    redraw: bool,
+    /// Emit the current frame again without inference, e.g. to blend it differently while paused
+    restyle: bool,
     pub(crate) to_exit: bool,
 }
 
 /// Frame transmitted to GUI
 pub(crate) struct GUIFrame {
     pub(crate) id: u64,
-    pub(crate) buffer: ColorImage,
-    pub(crate) decoded_buffer: Option<ColorImage>,
+    /// Frames decoded since the video was played
+    pub(crate) decoded: u64,
+    /// Seconds into the video
+    pub(crate) position: f64,
+    /// Seconds since Unix epoch when the frame was processed,
+    /// or seconds into the video when deterministic
+    pub(crate) timestamp: f64,
+    pub(crate) buffer: Arc<BgrImage>,
+    pub(crate) decoded_buffer: Option<Arc<ColorImage>>,
+    /// KxHxW confidences of the first segmentation if left to the GPU to draw
+    pub(crate) seg_confidences: Option<Arc<Array3<f32>>>,
+    /// How `decoded_buffer` is blended onto `buffer`
+    pub(crate) overlay_style: OverlayStyle,
+    /// Frame with its overlay blended on, once composited
+    pub(crate) composited: Option<Arc<RgbImage>>,
+    /// Results are of this frame, others show the last overlay only
+    pub(crate) inferred: bool,
+    /// What the model saw of the frame, if previewed
+    pub(crate) model_input: Option<Arc<BgrImage>>,
+    /// Pixels per predicted class, empty without model output
+    pub(crate) class_areas: Vec<u64>,
+    /// Maximum confidences of pixels per predicted class, empty without confidences
+    pub(crate) confidences: Vec<Histogram>,
+    /// Features of the frame if a model output is routed as embedding
+    pub(crate) embedding: Option<Arc<Embedding>>,
+    /// What was found to pre-annotate the frame with
+    pub(crate) annotations: Annotations,
+    /// Results within each zone
+    pub(crate) zones: Vec<ZoneStats>,
+    /// Of the centers of boxes in the world if calibrated
+    pub(crate) positions: Vec<Position>,
+    /// When the frame was decoded
+    pub(crate) ingested: Instant,
+    /// Seconds from decoding the frame to its results, none if deterministic or redrawn
+    pub(crate) latency: Option<f64>,
+}
+
+/// Structured results of a frame
+#[derive(serde::Serialize)]
+pub(crate) struct FrameRecord<'a> {
+    pub(crate) id: u64,
+    pub(crate) timestamp: f64,
+    pub(crate) class_areas: &'a [u64],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub(crate) zones: &'a [ZoneStats],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub(crate) positions: &'a [Position],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) latency: Option<f64>,
+}
+
+impl GUIFrame {
+    /// Blend the overlay onto the frame, e.g. before handing it to the GUI thread
+    pub(crate) fn composite(&mut self) {
+        if let Some(overlay) = &self.decoded_buffer {
+            let rgb = composite(&self.buffer, Some(overlay), self.overlay_style);
+            self.composited = Some(Arc::new(rgb));
+        }
+    }
+
+    pub(crate) fn record(&self) -> FrameRecord<'_> {
+        FrameRecord {
+            id: self.id,
+            timestamp: self.timestamp,
+            class_areas: &self.class_areas,
+            zones: &self.zones,
+            positions: &self.positions,
+            latency: self.latency,
+        }
+    }
+}
+
+impl FrameRecord<'_> {
+    /// Results within a zone by name
+    pub(crate) fn zone(&self, name: &str) -> Option<&ZoneStats> {
+        self.zones.iter().find(|z| z.name == name)
+    }
+}
+
+#[cfg(test)]
+impl GUIFrame {
+    /// Frame of `buffer` without results
+    pub(crate) fn empty(id: u64, buffer: Arc<BgrImage>) -> Self {
+        Self {
+            id,
+            decoded: id,
+            position: 0.0,
+            timestamp: 0.0,
+            buffer,
+            decoded_buffer: None,
+            seg_confidences: None,
+            model_input: None,
+            overlay_style: OverlayStyle::default(),
+            composited: None,
+            inferred: true,
+            class_areas: vec![],
+            confidences: vec![],
+            embedding: None,
+            annotations: Annotations::default(),
+            zones: vec![],
+            positions: vec![],
+            ingested: Instant::now(),
+            latency: None,
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
+#[cfg(test)]
+impl<'a> FrameRecord<'a> {
+    /// Record of pixels per class only
+    pub(crate) fn of_classes(id: u64, timestamp: f64, class_areas: &'a [u64]) -> Self {
+        Self { id, timestamp, class_areas, zones: &[], positions: &[], latency: None }
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
 /// Information on current state of app
 pub(crate) struct AppInfo {
     pub(crate) model_info: Option<ModelInfo>,
+    pub(crate) video_info: Option<VideoInfo>,
+    /// Size of frames passed to the model
+    pub(crate) scaled_size: Option<[u32; 2]>,
+    pub(crate) paused: bool,
+    /// Failures of stages and how they recovered
+    pub(crate) errors: ErrorStats,
+    /// How processing adapted to live videos
+    pub(crate) keep_up: KeepUpStats,
+    /// Of results since the video was played
+    pub(crate) latency: LatencyStats,
 }
 
-impl ProcessingApp<'_> {
+/// Latest processing state for observers other than the GUI
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub(crate) struct AppStatus {
+    pub(crate) info: Option<AppInfo>,
+    /// Id of the last processed frame
+    pub(crate) frame_id: Option<u64>,
+    /// Number of frames processed so far
+    pub(crate) frames: u64,
+    /// Last control or processing error
+    pub(crate) error: Option<String>,
+    /// Number of times processing was restarted
+    pub(crate) restarts: u64,
+    /// Since when processing works on a frame
+    #[serde(skip)]
+    pub(crate) busy_since: Option<Instant>,
+}
+
+pub(crate) type SharedStatus = Arc<Mutex<AppStatus>>;
+
+impl ProcessingApp {
+    /// Frame policy in effect, deterministic processing never drops frames
+    pub(crate) fn frame_policy(&self) -> FramePolicy {
+        if self.deterministic {
+            FramePolicy::ShowEvery
+        } else {
+            self.frame_policy
+        }
+    }
+
+    fn timestamp(&self, frame: &Frame) -> f64 {
+        if self.deterministic {
+            // virtual clock of the video
+            frame.position
+        } else {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |d| d.as_secs_f64())
+        }
+    }
+
+    /// Whether processing adapted to keep up with a live video since last asked
+    pub(crate) fn take_adapted(&mut self) -> bool {
+        std::mem::take(&mut self.adapted)
+    }
+
+    /// Abort a blocking video read from another thread
+    pub(crate) fn abort_handle(&self) -> Option<AbortHandle> {
+        self.vid.abort_handle()
+    }
+
     pub(crate) fn info(&self) -> AppInfo {
         let model_info = self.model.get_info().cloned();
-        AppInfo { model_info }
+        let video_info = self.vid.info();
+        let scaled_size = video_info.as_ref().map(|v| self.scale.output_size(v.size));
+        let paused = self.vid.is_paused();
+        let (errors, keep_up, latency) = (self.errors, self.keep_up, self.latency);
+        AppInfo { model_info, video_info, scaled_size, paused, errors, keep_up, latency }
+    }
+
+    /// Stage that failed beyond its error policy, if any
+    pub(crate) fn failed_stage(&self) -> Option<&'static str> {
+        let policy = self.error_policy;
+        if self.errors.scale.failed(policy.scale) {
+            Some("scaling")
+        } else if self.errors.model.failed(policy.model) {
+            Some("inference")
+        } else {
+            None
+        }
+    }
+
+    /// Skip the frame a stage failed on, after restarting it or stopping the video
+    fn recover(&mut self, stage: Stage, recovery: Recovery) -> Option<GUIFrame> {
+        let restarted = match (recovery, stage) {
+            (Recovery::RestartStage, Stage::Scale) => {
+                let factor = self.scale.factor();
+                self.scale = Scale::default();
+                self.scale.control(factor).map(|_| ()).map_err(AppCmdError::from)
+            }
+            (Recovery::RestartStage, Stage::Model) => {
+                let path = self.model_path.clone();
+                self.model.control(ModelCmd::Load(path)).map(|_| ()).map_err(AppCmdError::from)
+            }
+            (Recovery::StopPipeline, _) => {
+                self.vid.control(VideoCmd::Stop).map(|_| ()).map_err(AppCmdError::from)
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = restarted {
+            warn!("couldn't recover {:?}: {}", stage, e);
+        }
+        None
+    }
+
+    /// Decode, scale, infer and decode outputs of the next frame
+    fn process_frame(
+        &mut self,
+        input: &(),
+        frame_span: &Span,
+    ) -> <Self as Processor>::ProcessResult {
+        let redraw = std::mem::take(&mut self.redraw);
+        let restyle = std::mem::take(&mut self.restyle);
+        debug_span!("decode").in_scope(|| self.vid.advance(input, &mut self.frame))?;
+        if let Some(frame) = &self.frame {
+            frame_span.record("id", frame.id);
+        }
+        if let Some(lag) = self.vid.lag() {
+            let (scale, adaptations) = (self.scale.factor(), self.keep_up.adaptations);
+            if let Some(scaled) = keep_up(self.lag_policy, &mut self.keep_up, lag, scale) {
+                match self.scale.control(scaled) {
+                    Ok(_) => _ = self.unscaled.get_or_insert(scale),
+                    Err(e) => warn!("couldn't scale down to keep up: {}", e),
+                }
+            }
+            self.adapted |= self.keep_up.adaptations != adaptations;
+        }
+        // others are shown with the last results
+        let infer_every = self.keep_up.infer_every.max(1) as u64;
+        let infer = redraw || self.frame.as_ref().map_or(true, |f| f.id % infer_every == 0);
+        if self.is_dirty() {
+            let scaled = if self.errors.scale.disabled {
+                self.scaled_frame = ScaledFrame::Input;
+                Ok(())
+            } else {
+                debug_span!("scale")
+                    .in_scope(|| self.scale.advance(&self.frame, &mut self.scaled_frame))
+            };
+            let (policy, stats) = (self.error_policy.scale, &mut self.errors.scale);
+            match check_stage(Stage::Scale, policy, stats, scaled)? {
+                None => {}
+                Some(Recovery::DisableStage) => self.scaled_frame = ScaledFrame::Input,
+                Some(recovery) => return Ok(self.recover(Stage::Scale, recovery)),
+            }
+        };
+        let inferred = match self.scaled_frame.get(&self.frame) {
+            Some(_) if !infer => Ok(()),
+            // nothing to decode
+            Some(_) if self.errors.model.disabled => {
+                self.outputs.clear();
+                Ok(())
+            }
+            Some(scaled_frame) => debug_span!("inference")
+                .in_scope(|| self.model.advance(&scaled_frame.img, &mut self.outputs)),
+            None => Ok(()),
+        };
+        let (policy, stats) = (self.error_policy.model, &mut self.errors.model);
+        match check_stage(Stage::Model, policy, stats, inferred)? {
+            None => {}
+            Some(Recovery::DisableStage) => self.outputs.clear(),
+            Some(recovery) => return Ok(self.recover(Stage::Model, recovery)),
+        }
+        if let Some(scaled_frame) = self.scaled_frame.get(&self.frame) {
+            if infer {
+                let _overlay = debug_span!("overlay").entered();
+                let names = self.model.get_info().map_or(&[][..], |i| &i.output_names);
+                let (w, h) = scaled_frame.img.dimensions();
+                let size = [w as usize, h as usize];
+                self.router.keep_classes = self.annotate || !self.zones.is_empty();
+                self.router.decode(names, &mut self.outputs, size, &mut self.decoded_img);
+            }
+            // frames not inferred on show the last overlay, yet have no results of their own
+            let none = Annotations::default();
+            let annotations = if infer { &self.router.annotations } else { &none };
+            let class_areas = if infer { self.router.class_areas.clone() } else { vec![] };
+            let mut zones = if infer { zone_stats(&self.zones, annotations) } else { vec![] };
+            let size = self.frame.as_ref().map(|f| [f.img.width(), f.img.height()]);
+            let mut positions = vec![];
+            if let Some((calibration, size)) = self.calibration.zip(size).filter(|_| infer) {
+                for (stats, zone) in zones.iter_mut().zip(&self.zones) {
+                    stats.area = calibration.area(&zone.points, size);
+                }
+                positions = calibration.positions(&annotations.boxes, size);
+            }
+            // color conversion is left to the GUI (ideally its GPU)
+            let timestamp = self.timestamp(scaled_frame);
+            // the same frame again isn't any later
+            let latency = (!self.deterministic && !redraw && !restyle && !self.vid.is_paused())
+                .then(|| scaled_frame.ingested.elapsed().as_secs_f64());
+            if let Some(latency) = latency {
+                self.latency.count(latency);
+            }
+            Ok(Some(GUIFrame {
+                id: scaled_frame.id,
+                decoded: scaled_frame.decoded,
+                position: scaled_frame.position,
+                timestamp,
+                buffer: scaled_frame.img.clone(),
+                decoded_buffer: self.decoded_img.clone(),
+                seg_confidences: self.router.offloaded.clone(),
+                model_input: self.model.take_input_preview().map(Arc::new),
+                overlay_style: self.overlay_style,
+                composited: None,
+                inferred: infer,
+                class_areas,
+                confidences: if infer { self.router.confidences.clone() } else { vec![] },
+                embedding: self.router.embedding.clone().filter(|_| infer),
+                annotations: annotations.clone(),
+                zones,
+                positions,
+                ingested: scaled_frame.ingested,
+                latency,
+            }))
+        } else {
+            Ok(None)
+        }
     }
 }
 
-impl Processor for ProcessingApp<'_> {
+impl Processor for ProcessingApp {
     type Command = AppCmd;
     type ControlError = AppCmdError;
     type Input = ();
@@ -91,75 +749,102 @@ impl Processor for ProcessingApp<'_> {
     fn control(&mut self, cmd: Self::Command) -> Result<&mut Self, Self::ControlError> {
         match cmd {
             AppCmd::Video(cmd) => {
+                let played = matches!(cmd, VideoCmd::Play(_));
                 self.vid.control(cmd)?;
+                if played {
+                    if let Some(scale) = self.unscaled.take() {
+                        self.scale.control(scale)?;
+                    }
+                    self.keep_up = KeepUpStats::default();
+                    self.latency =
+                        LatencyStats { budget: self.latency.budget, ..Default::default() };
+                }
             }
             AppCmd::Scale(cmd) => {
                 self.scale.control(cmd)?;
+                self.unscaled = None;
+                self.errors.scale.disabled = false;
             }
             AppCmd::Exit => self.to_exit = true,
+            // handled by the supervisor
+            AppCmd::Restart => {}
             AppCmd::Model(cmd) => {
+                let path = match &cmd {
+                    ModelCmd::Load(path) => Some(path.clone()),
+                    _ => None,
+                };
                 self.model.control(cmd)?;
+                if let Some(path) = path {
+                    self.model_path = path;
+                    self.errors.model.disabled = false;
+                }
+            }
+            AppCmd::Route(routes) => self.router.set_routes(routes),
+            AppCmd::Overlay(cmd) => {
+                self.router.control_overlay(cmd);
+                self.redraw = true;
+            }
+            AppCmd::Zones(zones) => {
+                self.zones = zones;
+                self.redraw = true;
+            }
+            AppCmd::Annotate(annotate) => self.annotate = annotate,
+            AppCmd::Calibration(calibration) => {
+                self.calibration = calibration;
+                self.redraw = true;
+            }
+            AppCmd::OverlayStyle(style) => {
+                self.overlay_style = style;
+                self.restyle = true;
+            }
+            AppCmd::FramePolicy(policy) => {
+                self.frame_policy = policy;
+                self.vid.skip_stale(self.frame_policy() == FramePolicy::ShowLatest);
             }
+            AppCmd::Deterministic(deterministic) => {
+                self.deterministic = deterministic;
+                self.vid.skip_stale(self.frame_policy() == FramePolicy::ShowLatest);
+            }
+            AppCmd::ReadPolicy(policy) => self.vid.read_policy(policy),
+            AppCmd::LatencyBudget(budget) => {
+                self.latency = LatencyStats { budget, ..Default::default() };
+            }
+            AppCmd::LagPolicy(policy) => {
+                self.lag_policy = policy;
+                self.keep_up = KeepUpStats::default();
+            }
+            AppCmd::ErrorPolicy(policy) => {
+                self.error_policy = policy;
+                for stats in [&mut self.errors.scale, &mut self.errors.model] {
+                    stats.in_a_row = 0;
+                    stats.disabled = false;
+                }
+            }
+            AppCmd::Archive(opts) => self.vid.archive(opts),
+            AppCmd::Share(opts) => self.vid.share(opts),
         };
         Ok(self)
     }
 
     fn advance(&mut self, input: &(), _out: &mut ()) -> Self::ProcessResult {
-        self.vid.advance(input, &mut self.frame)?;
-        if self.is_dirty() {
-            self.scale.advance(&self.frame, &mut self.scaled_frame)?;
-        };
-        if let Some(scaled_frame) = &self.scaled_frame {
-            let mut out = vec![];
-            self.model.advance(&scaled_frame.img, &mut out)?;
-            if !out.is_empty() {
-                let out = &out[0];
-                let shape = out.shape();
-                let shape = [shape[0], shape[1], shape[2]];
-                // todo: find way to not clone
-                let hm: Array3<f32> =
-                    Array3::from_shape_vec(shape, out.clone().into_raw_vec()).unwrap();
-
-                self.decoder.advance(&hm, &mut self.decoded_img);
-
-                // todo: send input image and color coded img
-                //return Ok(Some(GUIFrame { id: scaled_frame.id, buffer: img.unwrap() }));
-            } else {
-                self.decoded_img = None;
-            }
-
-            // todo: trait and/or processor
-            let rgba_pixels = scaled_frame
-                .img
-                .pixels()
-                .map(|p| {
-                    let cs = p.channels();
-                    eframe::epaint::Color32::from_rgb(cs[2], cs[1], cs[0])
-                })
-                .collect::<Vec<_>>();
-
-            let col_img = ColorImage {
-                size: [scaled_frame.img.width() as usize, scaled_frame.img.height() as usize],
-                pixels: rgba_pixels,
-            };
-            Ok(Some(GUIFrame {
-                id: scaled_frame.id,
-                buffer: col_img,
-                decoded_buffer: self.decoded_img.clone(),
-            }))
-        } else {
-            Ok(None)
+        let frame_span = debug_span!("frame", id = field::Empty).entered();
+        let frame = self.process_frame(input, &frame_span);
+        if let Err(e) = &frame {
+            // part of the frame's trace
+            debug!("frame failed: {}", e);
         }
+        frame
     }
 
     fn is_dirty(&self) -> bool {
-        self.vid.is_dirty() || self.scale.is_dirty()
+        self.vid.is_dirty() || self.scale.is_dirty() || self.redraw || self.restyle
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::processing::Clip;
     use infur_test_gen::{long_small_video, short_large_video};
 
     /// 640x480
@@ -171,6 +856,140 @@ mod test {
         vec![long_small_video().to_string_lossy().to_string()]
     }
 
+    #[test]
+    fn recover_stage() {
+        let policy = StagePolicy { failures: 2, recovery: Recovery::RestartStage };
+        let mut stats = StageStats::default();
+        let mut check = |failed: bool| {
+            let result = if failed { Err("failed") } else { Ok(()) };
+            check_stage(Stage::Model, policy, &mut stats, result)
+        };
+        assert!(check(true).is_err());
+        assert_eq!(check(false), Ok(None));
+        assert!(check(true).is_err());
+        assert!(check(true).is_err());
+        assert_eq!(check(true), Ok(Some(Recovery::RestartStage)));
+        // restarted stages get as many tries again
+        assert!(check(true).is_err());
+        assert_eq!((stats.failures, stats.in_a_row, stats.recoveries), (5, 1, 1));
+        assert!(stats.failed(policy), "still failing after restarting");
+
+        let policy = StagePolicy { failures: 0, recovery: Recovery::SkipFrame };
+        let mut stats = StageStats::default();
+        for _ in 0..3 {
+            let skip = check_stage(Stage::Scale, policy, &mut stats, Err("failed"));
+            assert_eq!(skip, Ok(Some(Recovery::SkipFrame)));
+        }
+        assert_eq!((stats.failures, stats.recoveries, stats.disabled), (3, 1, false));
+        assert!(stats.failed(policy));
+        check_stage(Stage::Scale, policy, &mut stats, Ok::<_, &str>(())).unwrap();
+        assert!(!stats.failed(policy), "succeeded again");
+    }
+
+    #[test]
+    fn latency_budget() {
+        let mut app = ProcessingApp::default();
+        app.control(AppCmd::LatencyBudget(Some(60.0))).unwrap();
+        app.control(AppCmd::Video(VideoCmd::Play(long_small_input()))).unwrap();
+        let frame = app.generate().unwrap().unwrap();
+        let latency = frame.latency.expect("latency should be measured");
+        assert!(latency >= 0.0 && latency < 60.0);
+        let stats = app.info().latency;
+        assert_eq!((stats.frames, stats.missed, stats.budget), (1, 0, Some(60.0)));
+
+        app.control(AppCmd::LatencyBudget(Some(0.0))).unwrap();
+        app.generate().unwrap().unwrap();
+        assert_eq!(app.info().latency.missed, 1);
+
+        app.control(AppCmd::Deterministic(true)).unwrap();
+        assert!(app.generate().unwrap().unwrap().latency.is_none());
+    }
+
+    #[test]
+    fn restore_scale_on_play() {
+        let mut app = ProcessingApp::default();
+        app.control(AppCmd::Scale(0.5)).unwrap();
+        // as if scaled down to keep up
+        app.scale.control(0.25).unwrap();
+        app.unscaled = Some(0.5);
+        app.control(AppCmd::Video(VideoCmd::Play(long_small_input()))).unwrap();
+        assert_eq!((app.scale.factor(), app.unscaled), (0.5, None));
+
+        app.scale.control(0.25).unwrap();
+        app.unscaled = Some(0.5);
+        app.control(AppCmd::Scale(0.75)).unwrap();
+        app.control(AppCmd::Video(VideoCmd::Play(long_small_input()))).unwrap();
+        assert_eq!(app.scale.factor(), 0.75, "set explicitly since");
+    }
+
+    #[test]
+    fn keep_up_with_lag() {
+        let policy = LagPolicy { max_lag: Some(1.0), adapt: Adaptation::InferenceInterval };
+        let mut stats = KeepUpStats::default();
+        assert_eq!(keep_up(policy, &mut stats, 0.5, 0.5), None);
+        assert_eq!(stats.infer_every, 1);
+        keep_up(policy, &mut stats, 1.5, 0.5);
+        assert_eq!((stats.infer_every, stats.adaptations), (2, 1));
+        // only once it grew by as much again since
+        keep_up(policy, &mut stats, 2.0, 0.5);
+        keep_up(policy, &mut stats, 1.2, 0.5);
+        assert_eq!(stats.infer_every, 2);
+        keep_up(policy, &mut stats, 2.5, 0.5);
+        assert_eq!((stats.infer_every, stats.adaptations), (4, 2));
+
+        let policy = LagPolicy { max_lag: Some(1.0), adapt: Adaptation::Scale };
+        let mut stats = KeepUpStats::default();
+        assert_eq!(keep_up(policy, &mut stats, 1.5, 0.5), Some(0.375));
+        assert_eq!(keep_up(policy, &mut stats, 9.0, MIN_SCALE), None);
+        assert_eq!(stats.lag, Some(9.0));
+
+        let mut stats = KeepUpStats::default();
+        assert_eq!(keep_up(LagPolicy::default(), &mut stats, 9.0, 0.5), None);
+        assert_eq!(stats.adaptations, 0);
+    }
+
+    #[test]
+    fn prioritize_cmds() {
+        let play = |v: &str| AppCmd::Video(VideoCmd::Play(vec![v.to_string()]));
+        let clip = Clip { start: Some(2.0), end: None };
+        let cmds = vec![
+            play("a.mp4"),
+            AppCmd::Video(VideoCmd::Clip(clip)),
+            AppCmd::Video(VideoCmd::Pause(true)),
+            AppCmd::Scale(0.5),
+            AppCmd::Video(VideoCmd::Stop),
+            AppCmd::Scale(0.25),
+            play("b.mp4"),
+        ];
+        let prioritized = prioritize(cmds.clone());
+        assert!(matches!(
+            prioritized.as_slice(),
+            [
+                AppCmd::Video(VideoCmd::Clip(c)),
+                AppCmd::Video(VideoCmd::Pause(true)),
+                AppCmd::Video(VideoCmd::Play(input)),
+                AppCmd::Scale(s),
+            ] if *c == clip && input == &["b.mp4"] && *s == 0.25
+        ));
+
+        let model = vec![
+            AppCmd::Model(ModelCmd::Providers(vec![])),
+            AppCmd::Model(ModelCmd::Load("a.onnx".to_string())),
+            AppCmd::Model(ModelCmd::Load("b.onnx".to_string())),
+        ];
+        assert!(matches!(
+            prioritize(model).as_slice(),
+            [
+                AppCmd::Model(ModelCmd::Providers(_)),
+                AppCmd::Model(ModelCmd::Load(path)),
+            ] if path == "b.onnx"
+        ));
+
+        let mut cmds = cmds;
+        cmds.insert(2, AppCmd::Exit);
+        assert!(matches!(prioritize(cmds).as_slice(), [AppCmd::Exit]));
+    }
+
     #[test]
     fn void() {
         let mut app = ProcessingApp::default();
@@ -184,7 +1003,22 @@ mod test {
         app.control(AppCmd::Video(VideoCmd::Play(short_large_input()))).unwrap();
         app.control(AppCmd::Scale(0.5)).unwrap();
         let f2 = app.generate().unwrap().expect("video should already play");
-        assert_eq!(f2.buffer.size, [1280 / 2, 720 / 2]);
+        assert_eq!(f2.buffer.dimensions(), (1280 / 2, 720 / 2));
+    }
+
+    #[test]
+    fn info() {
+        let mut app = ProcessingApp::default();
+        assert!(app.info().video_info.is_none());
+        app.control(AppCmd::Video(VideoCmd::Play(short_large_input()))).unwrap();
+        app.control(AppCmd::Scale(0.5)).unwrap();
+        app.control(AppCmd::Video(VideoCmd::Pause(true))).unwrap();
+        let info = app.info();
+        let video_info = info.video_info.expect("video should play");
+        assert_eq!(video_info.size, [1280, 720]);
+        assert!(video_info.codec.is_some());
+        assert_eq!(info.scaled_size, Some([640, 360]));
+        assert!(info.paused);
     }
 
     #[test]
@@ -192,11 +1026,11 @@ mod test {
         let mut app = ProcessingApp::default();
         app.control(AppCmd::Video(VideoCmd::Play(long_small_input()))).unwrap();
         let f1 = app.generate().unwrap().expect("video should already play");
-        assert_eq!(f1.buffer.size, [640, 480]);
+        assert_eq!(f1.buffer.dimensions(), (640, 480));
 
         app.control(AppCmd::Scale(0.5)).unwrap();
         let f2 = app.generate().unwrap().expect("video should keep playing");
-        assert_eq!(f2.buffer.size, [640 / 2, 480 / 2]);
+        assert_eq!(f2.buffer.dimensions(), (640 / 2, 480 / 2));
     }
 
     #[test]
@@ -205,15 +1039,15 @@ mod test {
         // in 640x480 out same
         app.control(AppCmd::Video(VideoCmd::Play(long_small_input()))).unwrap();
         let f1 = app.generate().unwrap().unwrap();
-        assert_eq!(f1.buffer.size, [640, 480]);
+        assert_eq!(f1.buffer.dimensions(), (640, 480));
         // in 1280x720 out same
         app.control(AppCmd::Video(VideoCmd::Play(short_large_input()))).unwrap();
         let f2 = app.generate().unwrap().unwrap();
-        assert_eq!(f2.buffer.size, [1280, 720]);
+        assert_eq!(f2.buffer.dimensions(), (1280, 720));
         // in 1280x720 out twice
         app.control(AppCmd::Scale(2.0)).unwrap();
         let f3 = app.generate().unwrap().unwrap();
-        assert_eq!(f3.buffer.size, [1280 * 2, 720 * 2]);
+        assert_eq!(f3.buffer.dimensions(), (1280 * 2, 720 * 2));
     }
 
     #[test]
@@ -221,17 +1055,37 @@ mod test {
         let mut app = ProcessingApp::default();
         app.control(AppCmd::Video(VideoCmd::Play(short_large_input()))).unwrap();
         let f1 = app.generate().unwrap().unwrap();
-        assert_eq!(f1.buffer.size, [1280, 720]);
+        assert_eq!(f1.buffer.dimensions(), (1280, 720));
         app.control(AppCmd::Video(VideoCmd::Stop)).unwrap();
         let f2 = app.generate().unwrap().unwrap();
-        assert_eq!(f1.id, f2.id);
+        assert_eq!((f1.id, f1.position), (f2.id, f2.position));
         assert!(!app.is_dirty());
 
         app.control(AppCmd::Scale(0.5)).unwrap();
         assert!(app.is_dirty());
         let f3 = app.generate().unwrap().unwrap();
         assert_eq!(f2.id, f3.id);
-        assert_eq!(f3.buffer.size, [1280 / 2, 720 / 2]);
+        assert_eq!(f3.buffer.dimensions(), (1280 / 2, 720 / 2));
+    }
+
+    #[test]
+    fn deterministic_timestamps() {
+        let mut app = ProcessingApp::default();
+        app.control(AppCmd::FramePolicy(FramePolicy::ShowLatest)).unwrap();
+        app.control(AppCmd::Deterministic(true)).unwrap();
+        assert_eq!(app.frame_policy(), FramePolicy::ShowEvery);
+        app.control(AppCmd::Video(VideoCmd::Play(long_small_input()))).unwrap();
+        let frames = (0..3)
+            .map(|_| app.generate().unwrap().map(|f| (f.id, f.timestamp)).unwrap())
+            .collect::<Vec<_>>();
+        // 10 fps
+        assert_eq!(frames, [(1, 0.0), (2, 0.1), (3, 0.2)]);
+
+        app.control(AppCmd::Video(VideoCmd::Seek(2.0))).unwrap();
+        let frame = app.generate().unwrap().unwrap();
+        assert_eq!((frame.id, frame.timestamp), (1, 2.0));
+        // the count of decoded frames goes on
+        assert_eq!((frame.decoded, frame.position), (4, 2.0));
     }
 
     #[test]
@@ -250,4 +1104,19 @@ mod test {
         let f3 = app.generate().unwrap().unwrap();
         assert_ne!(f2.id, f3.id);
     }
+
+    #[test]
+    fn restyle_paused() {
+        let mut app = ProcessingApp::default();
+        app.control(AppCmd::Video(VideoCmd::Play(long_small_input()))).unwrap();
+        app.control(AppCmd::Video(VideoCmd::Pause(true))).unwrap();
+        let f1 = app.generate().unwrap().unwrap();
+        let style = OverlayStyle { opacity: 0.5, ..Default::default() };
+        app.control(AppCmd::OverlayStyle(style)).unwrap();
+        assert!(app.is_dirty());
+        assert!(!app.redraw, "nothing to infer again");
+        let f2 = app.generate().unwrap().unwrap();
+        assert_eq!((f1.id, f2.overlay_style), (f2.id, style));
+        assert!(!app.is_dirty());
+    }
 }
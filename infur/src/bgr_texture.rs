@@ -0,0 +1,205 @@
+use std::{
+    num::{NonZeroU32, NonZeroU64},
+    sync::Arc,
+};
+
+use eframe::{
+    egui::{PaintCallback, Rect},
+    egui_wgpu::{self, RenderState},
+    wgpu,
+};
+use image_ext::BgrImage;
+
+/// Bytes per RGBA8 texel holding packed BGR bytes
+const TEXEL_BYTES: u32 = 4;
+
+/// GPU resources to draw BGR frames without converting pixels on the CPU
+///
+/// The raw BGR buffer is uploaded as RGBA8 texels (each carrying 4 bytes of consecutive
+/// BGR data) and swizzled back into RGB by the fragment shader.
+pub(crate) struct BgrRenderResources {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    linearize: bool,
+    texture: Option<(wgpu::Texture, wgpu::Extent3d)>,
+    bind_group: Option<wgpu::BindGroup>,
+    uploaded: Option<u64>,
+    padded: Vec<u8>,
+}
+
+impl BgrRenderResources {
+    fn new(device: &wgpu::Device, target_format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bgr_texture"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("bgr_texture.wgsl").into()),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bgr_texture"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(16),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bgr_texture"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("bgr_texture"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(target_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("bgr_texture"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            pipeline,
+            bind_group_layout,
+            uniform_buffer,
+            linearize: target_format.describe().srgb,
+            texture: None,
+            bind_group: None,
+            uploaded: None,
+            padded: vec![],
+        }
+    }
+
+    /// Upload image unless it was already uploaded under the same key
+    fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, key: u64, img: &BgrImage) {
+        if self.uploaded == Some(key) || img.width() == 0 || img.height() == 0 {
+            return;
+        }
+        let row_bytes = img.width() * 3;
+        let row_texels = row_bytes.div_ceil(TEXEL_BYTES);
+        let size =
+            wgpu::Extent3d { width: row_texels, height: img.height(), depth_or_array_layers: 1 };
+
+        // (re-)create texture on size change
+        if self.texture.as_ref().map_or(true, |(_, s)| *s != size) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("bgr_texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bgr_texture"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: self.uniform_buffer.as_entire_binding(),
+                    },
+                ],
+            }));
+            self.texture = Some((texture, size));
+        }
+        let params = [img.width(), img.height(), self.linearize as u32, 0];
+        let params = params.iter().flat_map(|p| p.to_ne_bytes()).collect::<Vec<_>>();
+        queue.write_buffer(&self.uniform_buffer, 0, &params);
+
+        // rows must span entire texels, thus pad them if necessary
+        let padded_row_bytes = row_texels * TEXEL_BYTES;
+        let data = if padded_row_bytes == row_bytes {
+            img.as_raw().as_slice()
+        } else {
+            self.padded.resize((padded_row_bytes * img.height()) as usize, 0);
+            for (padded_row, row) in self
+                .padded
+                .chunks_exact_mut(padded_row_bytes as usize)
+                .zip(img.as_raw().chunks_exact(row_bytes as usize))
+            {
+                padded_row[..row.len()].copy_from_slice(row);
+            }
+            self.padded.as_slice()
+        };
+        if let Some((texture, _)) = &self.texture {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_bytes),
+                    rows_per_image: NonZeroU32::new(img.height()),
+                },
+                size,
+            );
+        }
+        self.uploaded = Some(key);
+    }
+
+    fn paint<'rp>(&'rp self, rpass: &mut wgpu::RenderPass<'rp>) {
+        if let Some(bind_group) = &self.bind_group {
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_bind_group(0, bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+    }
+}
+
+/// Register GPU resources needed by `paint_callback`
+pub(crate) fn register(render_state: &RenderState) {
+    let resources = BgrRenderResources::new(&render_state.device, render_state.target_format);
+    render_state.egui_rpass.write().paint_callback_resources.insert(resources);
+}
+
+/// Paint a BGR image into rect, uploading it only if key changed
+pub(crate) fn paint_callback(rect: Rect, key: u64, img: Arc<BgrImage>) -> PaintCallback {
+    let cb = egui_wgpu::CallbackFn::new()
+        .prepare(move |device, queue, paint_callback_resources| {
+            if let Some(resources) = paint_callback_resources.get_mut::<BgrRenderResources>() {
+                resources.prepare(device, queue, key, &img);
+            }
+        })
+        .paint(move |_info, rpass, paint_callback_resources| {
+            if let Some(resources) = paint_callback_resources.get::<BgrRenderResources>() {
+                resources.paint(rpass);
+            }
+        });
+    PaintCallback { rect, callback: Arc::new(cb) }
+}
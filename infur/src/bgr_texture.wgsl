@@ -0,0 +1,62 @@
+// Draw a packed BGR24 image uploaded as RGBA8 texels (4 bytes per texel, rows padded)
+
+struct Params {
+    width: u32,
+    height: u32,
+    // 1 if the render target expects linear colors
+    linearize: u32,
+    _pad: u32,
+};
+
+@group(0) @binding(0)
+var bgr: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> params: Params;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// one triangle covering the entire viewport
+@vertex
+fn vs_main(@builtin(vertex_index) idx: u32) -> VertexOut {
+    let uv = vec2<f32>(f32((idx << 1u) & 2u), f32(idx & 2u));
+    var out: VertexOut;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn byte_at(i: i32, row: i32) -> f32 {
+    let texel = textureLoad(bgr, vec2<i32>(i / 4, row), 0);
+    let c = i % 4;
+    if (c == 0) {
+        return texel.r;
+    }
+    if (c == 1) {
+        return texel.g;
+    }
+    if (c == 2) {
+        return texel.b;
+    }
+    return texel.a;
+}
+
+fn to_linear(c: vec3<f32>) -> vec3<f32> {
+    let lo = c / 12.92;
+    let hi = pow((c + vec3<f32>(0.055)) / 1.055, vec3<f32>(2.4));
+    return select(hi, lo, c <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    let x = min(i32(in.uv.x * f32(params.width)), i32(params.width) - 1);
+    let y = min(i32(in.uv.y * f32(params.height)), i32(params.height) - 1);
+    // swizzle BGR into RGB
+    var rgb = vec3<f32>(byte_at(x * 3 + 2, y), byte_at(x * 3 + 1, y), byte_at(x * 3, y));
+    if (params.linearize != 0u) {
+        rgb = to_linear(rgb);
+    }
+    return vec4<f32>(rgb, 1.0);
+}
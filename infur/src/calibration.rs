@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::Detection;
+
+/// Maps pixels of video frames to world coordinates, e.g. meters on the ground
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Calibration {
+    /// Pixels per world unit, e.g. of a top-down view
+    PixelsPerMeter(f64),
+    /// Row-major 3x3 matrix from pixels to world coordinates, e.g. on the ground plane
+    Homography([[f64; 3]; 3]),
+}
+
+/// Where the center of a box is in the world
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub(crate) struct Position {
+    pub(crate) class: usize,
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+}
+
+impl Calibration {
+    /// Name of world units, those of a homography are whatever its world points were given in
+    pub(crate) fn unit(&self) -> &'static str {
+        match self {
+            Self::PixelsPerMeter(_) => "m",
+            Self::Homography(_) => "units",
+        }
+    }
+
+    /// World coordinates of a point relative to a frame of `size`, `None` beyond the horizon
+    pub(crate) fn to_world(&self, [x, y]: [f32; 2], [w, h]: [u32; 2]) -> Option<[f64; 2]> {
+        let (x, y) = (x as f64 * w as f64, y as f64 * h as f64);
+        match self {
+            Self::PixelsPerMeter(ppm) => Some([x / ppm, y / ppm]),
+            Self::Homography(m) => {
+                let z = m[2][0] * x + m[2][1] * y + m[2][2];
+                let project = |row: [f64; 3]| (row[0] * x + row[1] * y + row[2]) / z;
+                (z.abs() > f64::EPSILON).then(|| [project(m[0]), project(m[1])])
+            }
+        }
+    }
+
+    /// Area of a polygon relative to a frame of `size` in world units squared
+    pub(crate) fn area(&self, points: &[[f32; 2]], size: [u32; 2]) -> Option<f64> {
+        let world = points.iter().map(|&p| self.to_world(p, size)).collect::<Option<Vec<_>>>()?;
+        // shoelace formula, lines stay lines under a homography
+        let n = world.len();
+        let twice: f64 = (0..n)
+            .map(|i| {
+                let ([x1, y1], [x2, y2]) = (world[i], world[(i + 1) % n]);
+                x1 * y2 - x2 * y1
+            })
+            .sum();
+        Some(twice.abs() / 2.0)
+    }
+
+    /// World positions of the centers of boxes on a frame of `size`
+    pub(crate) fn positions(&self, boxes: &[Detection], size: [u32; 2]) -> Vec<Position> {
+        let center = |[x1, y1, x2, y2]: [f32; 4]| [(x1 + x2) / 2.0, (y1 + y2) / 2.0];
+        boxes
+            .iter()
+            .filter_map(|d| {
+                let [x, y] = self.to_world(center(d.bbox), size)?;
+                Some(Position { class: d.class, x, y })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SQUARE: [[f32; 2]; 4] = [[0.0, 0.0], [0.5, 0.0], [0.5, 0.5], [0.0, 0.5]];
+
+    #[test]
+    fn pixels_per_meter() {
+        let calibration = Calibration::PixelsPerMeter(10.0);
+        assert_eq!(calibration.unit(), "m");
+        assert_eq!(calibration.to_world([0.5, 1.0], [200, 100]), Some([10.0, 10.0]));
+        assert_eq!(calibration.area(&SQUARE, [200, 100]), Some(50.0));
+        let boxes = [Detection { class: 3, bbox: [0.0, 0.0, 1.0, 1.0] }];
+        let positions = calibration.positions(&boxes, [200, 100]);
+        assert_eq!(positions, [Position { class: 3, x: 10.0, y: 5.0 }]);
+    }
+
+    #[test]
+    fn homography() {
+        // doubles x and shifts y by 1
+        let shift = Calibration::Homography([[2.0, 0.0, 0.0], [0.0, 1.0, 1.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(shift.to_world([0.5, 0.5], [10, 10]), Some([10.0, 6.0]));
+        assert_eq!(shift.area(&SQUARE, [10, 10]), Some(50.0));
+        let horizon = Calibration::Homography([[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 1.0, -5.0]]);
+        assert_eq!(horizon.to_world([0.5, 0.5], [10, 10]), None);
+        assert_eq!(horizon.area(&SQUARE, [10, 10]), None);
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// Merges and names classes of a model, e.g. 21 VOC classes into other, person and vehicle
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct ClassMap {
+    /// Class of each model class by its index, those beyond keep theirs
+    pub(crate) ids: Vec<usize>,
+    /// Names of classes after mapping by index
+    pub(crate) names: Vec<String>,
+}
+
+impl ClassMap {
+    /// Class after mapping a model's class
+    pub(crate) fn map(&self, class: usize) -> usize {
+        self.ids.get(class).copied().unwrap_or(class)
+    }
+
+    /// Number of classes after mapping `k` classes of a model
+    pub(crate) fn len(&self, k: usize) -> usize {
+        (0..k).map(|class| self.map(class) + 1).max().unwrap_or(0)
+    }
+}
+
+/// Name of a class if any, else its index
+pub(crate) fn label(names: &[String], class: usize) -> String {
+    names.get(class).cloned().unwrap_or_else(|| class.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_classes() {
+        let map = ClassMap { ids: vec![0, 2, 1, 2], names: vec![] };
+        assert_eq!((0..6).map(|k| map.map(k)).collect::<Vec<_>>(), [0, 2, 1, 2, 4, 5]);
+        assert_eq!(map.len(4), 3);
+        assert_eq!(map.len(6), 6);
+        assert_eq!(map.len(0), 0);
+        assert_eq!(ClassMap::default().len(21), 21);
+    }
+
+    #[test]
+    fn label_by_name() {
+        let names = ["other".to_string(), "person".to_string()];
+        assert_eq!(label(&names, 1), "person");
+        assert_eq!(label(&names, 2), "2");
+    }
+}
@@ -0,0 +1,274 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::{
+    gui::ProcConfig, predict_onnx::ExecutionProvider, processing::ArchiveOpts, shm::ShmOpts,
+    sink::SinkSpec,
+};
+
+/// Command line arguments
+#[derive(Parser, Debug, Default)]
+#[command(version, about)]
+pub(crate) struct Args {
+    /// Video input (file, URL, ...), in headless mode also directories and glob patterns
+    #[arg(short, long = "video", value_name = "INPUT")]
+    pub(crate) videos: Vec<String>,
+    /// ONNX model to load
+    #[arg(short, long, value_name = "PATH")]
+    pub(crate) model: Option<String>,
+    /// Execution provider to run the model on, repeat in order of preference
+    #[arg(long = "provider", value_name = "PROVIDER", value_enum)]
+    pub(crate) providers: Vec<ExecutionProvider>,
+    /// Scale frames by factor before inference
+    #[arg(short, long)]
+    pub(crate) scale: Option<f32>,
+    /// Start with video paused
+    #[arg(long)]
+    pub(crate) paused: bool,
+    /// Seek videos to start at this many seconds
+    #[arg(long, value_name = "SECS")]
+    pub(crate) start: Option<f64>,
+    /// Stop videos at this many seconds
+    #[arg(long, value_name = "SECS")]
+    pub(crate) end: Option<f64>,
+    /// Copy live inputs to files in this directory while they are processed
+    #[arg(long, value_name = "DIR")]
+    pub(crate) archive: Option<PathBuf>,
+    /// Share decoded frames with other local processes by this file, e.g. in /dev/shm
+    #[arg(long, value_name = "PATH")]
+    pub(crate) shm: Option<PathBuf>,
+    /// Process every frame and time results by the video, e.g. for reproducible tests
+    #[arg(long)]
+    pub(crate) deterministic: bool,
+    /// Check the environment (ffmpeg, onnxruntime, ...) and print a report
+    #[arg(long)]
+    pub(crate) doctor: bool,
+    /// Run the model on images (files, directories or glob patterns), write overlays and results
+    /// to the output directory and exit
+    #[arg(long = "image", value_name = "INPUT")]
+    pub(crate) images: Vec<String>,
+    /// Process all inputs without GUI
+    #[arg(long)]
+    pub(crate) headless: bool,
+    /// Without GUI, read JSON commands from stdin and write results and events to stdout
+    #[arg(long)]
+    pub(crate) stdio: bool,
+    /// Number of inputs processed concurrently in headless mode
+    #[arg(long, default_value_t = 1)]
+    pub(crate) jobs: usize,
+    /// Directory to write headless and image results to
+    #[arg(long, value_name = "DIR", default_value = "results")]
+    pub(crate) out: PathBuf,
+    /// TOML file with startup configuration [default: infur.toml if it exists]
+    #[arg(long, value_name = "PATH")]
+    pub(crate) config: Option<PathBuf>,
+    /// Write per-frame results to stdout, jsonl:PATH or csv:PATH, may be repeated
+    #[arg(long = "sink", value_name = "SPEC")]
+    pub(crate) sinks: Vec<SinkSpec>,
+    /// Restart processing if it's stuck for this many seconds, 0 disables
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    pub(crate) watchdog: u64,
+    /// Record all commands with their timing to a file
+    #[arg(long, value_name = "PATH")]
+    pub(crate) record: Option<PathBuf>,
+    /// Replay commands recorded with --record
+    #[arg(long, value_name = "PATH")]
+    pub(crate) replay: Option<PathBuf>,
+    /// Serve HTTP control API on address, e.g. 127.0.0.1:8080
+    #[cfg(feature = "http")]
+    #[arg(long, value_name = "ADDR")]
+    pub(crate) http: Option<String>,
+    /// Publish per-frame results to MQTT broker at HOST[:PORT]
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_name = "ADDR")]
+    pub(crate) mqtt: Option<String>,
+    /// MQTT topic to publish results to
+    #[cfg(feature = "mqtt")]
+    #[arg(long, default_value_t = crate::mqtt_sink::default_topic())]
+    pub(crate) mqtt_topic: String,
+    /// MQTT quality of service
+    #[cfg(feature = "mqtt")]
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=2))]
+    pub(crate) mqtt_qos: u8,
+    /// Record spans of processing stages to PATH, e.g. for inferno-flamegraph
+    #[cfg(feature = "flame")]
+    #[arg(long, value_name = "PATH")]
+    pub(crate) flame: Option<PathBuf>,
+    /// Export spans of processing to an OTLP/gRPC endpoint, e.g. http://localhost:4317
+    #[cfg(feature = "otel")]
+    #[arg(long, value_name = "URL")]
+    pub(crate) otlp: Option<String>,
+    /// Raw ffmpeg arguments after `--`, passed as one input (following `-i`)
+    #[arg(last = true, value_name = "FFMPEG_ARGS")]
+    pub(crate) ffmpeg_args: Vec<String>,
+}
+
+impl Args {
+    /// Ffmpeg arguments of the video to play first
+    pub(crate) fn video_input(&self) -> Option<Vec<String>> {
+        if !self.ffmpeg_args.is_empty() {
+            Some(self.ffmpeg_args.clone())
+        } else {
+            self.videos.first().map(|v| vec![v.clone()])
+        }
+    }
+
+    /// Ffmpeg arguments of every video, each played in its own pipeline
+    pub(crate) fn pipeline_inputs(&self) -> Vec<Vec<String>> {
+        let passthrough = Some(self.ffmpeg_args.clone()).filter(|a| !a.is_empty());
+        passthrough.into_iter().chain(self.videos.iter().map(|v| vec![v.clone()])).collect()
+    }
+
+    /// Config of each pipeline
+    ///
+    /// Without inputs given, pipelines are restored as in `configs`. Otherwise, there is one
+    /// per input with all but the video input as in the first config.
+    pub(crate) fn pipeline_configs(&self, mut configs: Vec<ProcConfig>) -> Vec<ProcConfig> {
+        if configs.is_empty() {
+            configs.push(ProcConfig::default());
+        }
+        let inputs = self.pipeline_inputs();
+        if inputs.is_empty() {
+            configs.iter_mut().for_each(|config| self.apply_to(config));
+            return configs;
+        }
+        let mut config = configs.swap_remove(0);
+        self.apply_to(&mut config);
+        std::iter::once(config.clone())
+            .chain(inputs[1..].iter().map(|video_input| ProcConfig {
+                video_input: video_input.clone(),
+                ..config.clone()
+            }))
+            .collect()
+    }
+
+    /// Sinks of a config file followed by those given as arguments
+    pub(crate) fn sink_specs(&self, config: Option<&ProcConfig>) -> crate::Result<Vec<SinkSpec>> {
+        let mut specs = config.map(|c| c.sinks.clone()).unwrap_or_default();
+        specs.extend(self.sinks.iter().cloned());
+        #[cfg(feature = "mqtt")]
+        if let Some(addr) = &self.mqtt {
+            let opts = crate::mqtt_sink::MqttOpts::new(addr, &self.mqtt_topic, self.mqtt_qos)?;
+            specs.push(SinkSpec::Mqtt(opts));
+        }
+        Ok(specs)
+    }
+
+    /// Archive settings of a config file, into the directory given as argument if any
+    pub(crate) fn archive_opts(&self, config: Option<ArchiveOpts>) -> Option<ArchiveOpts> {
+        match &self.archive {
+            Some(dir) => Some(ArchiveOpts { dir: dir.clone(), ..config.unwrap_or_default() }),
+            None => config,
+        }
+    }
+
+    /// Override config by arguments that were given
+    pub(crate) fn apply_to(&self, config: &mut ProcConfig) {
+        if let Some(video_input) = self.video_input() {
+            config.video_input = video_input;
+        }
+        if let Some(model) = &self.model {
+            config.model_input = model.clone();
+        }
+        if !self.providers.is_empty() {
+            config.providers = self.providers.clone();
+        }
+        if let Some(scale) = self.scale {
+            config.scale = scale;
+        }
+        if self.paused {
+            config.paused = true;
+        }
+        if self.start.is_some() {
+            config.clip.start = self.start;
+        }
+        if self.end.is_some() {
+            config.clip.end = self.end;
+        }
+        config.archive = self.archive_opts(config.archive.take());
+        if let Some(path) = &self.shm {
+            config.shm = Some(ShmOpts { path: path.clone() });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processing::Clip;
+    use clap::CommandFactory;
+
+    #[test]
+    fn verify_args() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn ffmpeg_passthrough() {
+        let args = Args::try_parse_from([
+            "infur", "--model", "m.onnx", "-v", "a.mp4", "--", "-re", "b.mp4", "-t", "5",
+        ])
+        .unwrap();
+        assert_eq!(args.model.as_deref(), Some("m.onnx"));
+        assert_eq!(args.videos, ["a.mp4"]);
+        assert_eq!(args.video_input().unwrap(), ["-re", "b.mp4", "-t", "5"]);
+    }
+
+    #[test]
+    fn override_config() {
+        let args =
+            Args::try_parse_from(["infur", "--video", "a.mp4", "--scale", "0.25", "--end", "9.5"])
+                .unwrap();
+        let mut config = ProcConfig {
+            model_input: "m.onnx".to_string(),
+            clip: Clip { start: Some(1.0), end: None },
+            ..Default::default()
+        };
+        args.apply_to(&mut config);
+        assert_eq!(config.video_input, ["a.mp4"]);
+        assert_eq!(config.scale, 0.25);
+        assert_eq!(config.model_input, "m.onnx");
+        assert!(!config.paused);
+        assert_eq!(config.clip, Clip { start: Some(1.0), end: Some(9.5) });
+    }
+
+    #[test]
+    fn one_config_per_input() {
+        let args =
+            Args::try_parse_from(["infur", "-v", "a.mp4", "-v", "b.mp4", "--", "-re", "c.mp4"])
+                .unwrap();
+        let config = ProcConfig { model_input: "m.onnx".to_string(), ..Default::default() };
+        let configs = args.pipeline_configs(vec![config]);
+        let inputs = configs.iter().map(|c| c.video_input.clone()).collect::<Vec<_>>();
+        assert_eq!(inputs, [vec!["-re", "c.mp4"], vec!["a.mp4"], vec!["b.mp4"]]);
+        assert!(configs.iter().all(|c| c.model_input == "m.onnx"));
+
+        let configs = Args::default().pipeline_configs(vec![]);
+        assert_eq!(configs.len(), 1);
+    }
+
+    #[test]
+    fn restore_pipelines() {
+        let args = Args::try_parse_from(["infur", "--scale", "0.25"]).unwrap();
+        let configs = ["a.mp4", "b.mp4"]
+            .map(|input| ProcConfig { video_input: vec![input.to_string()], ..Default::default() });
+        let configs = args.pipeline_configs(configs.to_vec());
+        let inputs = configs.iter().map(|c| c.video_input.clone()).collect::<Vec<_>>();
+        assert_eq!(inputs, [vec!["a.mp4"], vec!["b.mp4"]]);
+        assert!(configs.iter().all(|c| c.scale == 0.25));
+    }
+
+    #[test]
+    fn sinks_after_config() {
+        let args =
+            Args::try_parse_from(["infur", "--sink", "csv:a.csv", "--sink", "stdout"]).unwrap();
+        let config = ProcConfig { sinks: vec![SinkSpec::Stdout], ..Default::default() };
+        let specs = args.sink_specs(Some(&config)).unwrap();
+        assert_eq!(
+            specs,
+            [SinkSpec::Stdout, SinkSpec::Csv { path: "a.csv".into() }, SinkSpec::Stdout]
+        );
+        assert!(Args::try_parse_from(["infur", "--sink", "a.csv"]).is_err());
+    }
+}
@@ -0,0 +1,495 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+};
+
+use eframe::epaint::ColorImage;
+use ff_video::{FFMpegEncoder, VideoProcError};
+use image_ext::{BgrImage, ImageError, RgbImage};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{
+    annotations::{AnnotationFormat, AnnotationWriter, Annotations},
+    app::GUIFrame,
+    preview::{composite, OverlayStyle},
+};
+
+/// Frame rate of clips if it can't be told by timestamps
+const FALLBACK_FPS: f64 = 10.0;
+
+/// Error flushing frames
+#[derive(Error, Debug)]
+pub(crate) enum ClipError {
+    #[error(transparent)]
+    Video(#[from] VideoProcError),
+    #[error(transparent)]
+    Image(#[from] ImageError),
+    #[error("couldn't create {path:?}")]
+    Create {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("couldn't annotate frames in {path:?}")]
+    Annotate {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Receives frames flushed from a history
+pub(crate) trait FrameSink: Send {
+    /// Write the next frame and what the model found in it, all are of the same size and `fps`
+    fn write(
+        &mut self,
+        frame: &RgbImage,
+        annotations: &Annotations,
+        fps: f64,
+    ) -> Result<(), ClipError>;
+
+    /// Finish writing and describe where to
+    fn finish(self: Box<Self>) -> Result<String, ClipError>;
+}
+
+/// Video file encoded by ffmpeg
+pub(crate) struct ClipSink {
+    path: PathBuf,
+    encoder: Option<FFMpegEncoder>,
+}
+
+impl ClipSink {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self { path, encoder: None }
+    }
+}
+
+impl FrameSink for ClipSink {
+    fn write(&mut self, frame: &RgbImage, _: &Annotations, fps: f64) -> Result<(), ClipError> {
+        let encoder = match &mut self.encoder {
+            Some(encoder) => encoder,
+            None => {
+                create_dir_of(&self.path)?;
+                let (w, h) = frame.dimensions();
+                self.encoder.insert(FFMpegEncoder::create(&self.path, w, h, fps)?)
+            }
+        };
+        Ok(encoder.write_rgb(frame.as_raw())?)
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, ClipError> {
+        let encoder = self.encoder.ok_or_else(|| VideoProcError::Other("no frames".into()))?;
+        Ok(encoder.finish()?.to_string_lossy().to_string())
+    }
+}
+
+/// Numbered PNG images in a directory, annotated if a format is given
+pub(crate) struct ImagesSink {
+    dir: PathBuf,
+    written: usize,
+    annotations: Option<AnnotationWriter>,
+}
+
+impl ImagesSink {
+    pub(crate) fn new(dir: PathBuf, annotations: Option<AnnotationFormat>) -> Self {
+        let annotations = annotations.map(|format| AnnotationWriter::new(format, dir.clone()));
+        Self { dir, written: 0, annotations }
+    }
+}
+
+impl FrameSink for ImagesSink {
+    fn write(
+        &mut self,
+        frame: &RgbImage,
+        annotations: &Annotations,
+        _fps: f64,
+    ) -> Result<(), ClipError> {
+        if self.written == 0 {
+            fs::create_dir_all(&self.dir)
+                .map_err(|source| ClipError::Create { path: self.dir.clone(), source })?;
+        }
+        self.written += 1;
+        let name = format!("{:06}.png", self.written);
+        frame.save(self.dir.join(&name))?;
+        if let Some(writer) = &mut self.annotations {
+            writer
+                .write(&name, [frame.width(), frame.height()], annotations)
+                .map_err(|source| ClipError::Annotate { path: self.dir.clone(), source })?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<String, ClipError> {
+        if let Some(writer) = self.annotations {
+            writer
+                .finish()
+                .map_err(|source| ClipError::Annotate { path: self.dir.clone(), source })?;
+        }
+        Ok(self.dir.to_string_lossy().to_string())
+    }
+}
+
+fn create_dir_of(path: &Path) -> Result<(), ClipError> {
+    match path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        Some(dir) => {
+            fs::create_dir_all(dir).map_err(|source| ClipError::Create { path: dir.into(), source })
+        }
+        None => Ok(()),
+    }
+}
+
+/// How exported frames are stored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ClipFormat {
+    #[default]
+    Mp4,
+    Gif,
+    /// Directory of PNG images
+    Png,
+}
+
+/// Where to export clips to
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct ClipOpts {
+    pub(crate) dir: PathBuf,
+    #[serde(default)]
+    pub(crate) format: ClipFormat,
+    /// Write what the model found next to each image for a labeling tool, only of `png`
+    #[serde(default)]
+    pub(crate) annotations: Option<AnnotationFormat>,
+}
+
+impl Default for ClipOpts {
+    fn default() -> Self {
+        Self { dir: PathBuf::from("clips"), format: ClipFormat::default(), annotations: None }
+    }
+}
+
+impl ClipOpts {
+    /// Path of a clip named after what it shows, e.g. a rule and the frame it fired at
+    pub(crate) fn path(&self, name: &str, frame_id: u64) -> PathBuf {
+        let name = name.replace(|c: char| !c.is_alphanumeric() && c != '-', "_");
+        let path = self.dir.join(format!("{}-{}", name, frame_id));
+        match self.format {
+            ClipFormat::Mp4 => path.with_extension("mp4"),
+            ClipFormat::Gif => path.with_extension("gif"),
+            ClipFormat::Png => path,
+        }
+    }
+
+    /// Sink for a clip named as by `path`
+    pub(crate) fn sink(&self, name: &str, frame_id: u64) -> Box<dyn FrameSink> {
+        let path = self.path(name, frame_id);
+        match self.format {
+            ClipFormat::Mp4 | ClipFormat::Gif => Box::new(ClipSink::new(path)),
+            ClipFormat::Png => Box::new(ImagesSink::new(path, self.annotations)),
+        }
+    }
+}
+
+/// Which and how many frames a history keeps
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct HistoryOpts {
+    /// Seconds of frames kept before a flush, 0 keeps none
+    pub(crate) secs: f64,
+    /// Seconds of frames added after a flush
+    pub(crate) post_secs: f64,
+    /// Frames kept at most, bounding memory at high frame rates
+    pub(crate) max_frames: usize,
+    /// Keep frames without the model's overlay
+    pub(crate) raw: bool,
+}
+
+impl Default for HistoryOpts {
+    fn default() -> Self {
+        Self { secs: 5.0, post_secs: 0.0, max_frames: 150, raw: false }
+    }
+}
+
+/// Processed frame with its overlay
+#[derive(Clone)]
+struct Kept {
+    timestamp: f64,
+    buffer: Arc<BgrImage>,
+    overlay: Option<Arc<ColorImage>>,
+    style: OverlayStyle,
+    annotations: Annotations,
+}
+
+/// Flush waiting for frames after the event
+struct Pending {
+    until: f64,
+    frames: Vec<Kept>,
+    sink: Box<dyn FrameSink>,
+}
+
+#[derive(Default)]
+struct Frames {
+    recent: VecDeque<Kept>,
+    pending: Vec<Pending>,
+}
+
+/// Rolling buffer of a pipeline's recent frames to flush to sinks, e.g. around events
+#[derive(Default)]
+pub(crate) struct FrameHistory {
+    opts: Mutex<HistoryOpts>,
+    frames: Mutex<Frames>,
+}
+
+impl FrameHistory {
+    pub(crate) fn new(opts: HistoryOpts) -> Self {
+        Self { opts: Mutex::new(opts), frames: Mutex::default() }
+    }
+
+    pub(crate) fn opts(&self) -> HistoryOpts {
+        *self.opts.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Keep other frames from now on, frames kept already are trimmed with the next one
+    pub(crate) fn set_opts(&self, opts: HistoryOpts) {
+        *self.opts.lock().unwrap_or_else(PoisonError::into_inner) = opts;
+    }
+
+    /// Keep a frame and forget those too old
+    pub(crate) fn push(&self, frame: &GUIFrame) {
+        let opts = self.opts();
+        let kept = Kept {
+            timestamp: frame.timestamp,
+            buffer: frame.buffer.clone(),
+            overlay: if opts.raw { None } else { frame.decoded_buffer.clone() },
+            style: frame.overlay_style,
+            annotations: frame.annotations.clone(),
+        };
+        let mut frames = self.frames.lock().unwrap_or_else(PoisonError::into_inner);
+        // another video may start over
+        let restart = frames.recent.back().map_or(false, |f| f.timestamp > kept.timestamp);
+        if restart {
+            frames.recent.clear();
+        }
+        let (due, pending): (Vec<_>, _) = frames.pending.drain(..).partition(|p| {
+            restart || p.until < kept.timestamp || p.frames.len() >= opts.max_frames
+        });
+        frames.pending = pending;
+        for pending in frames.pending.iter_mut() {
+            pending.frames.push(kept.clone());
+        }
+        for pending in due {
+            write_all(pending.frames, pending.sink);
+        }
+
+        let recent = &mut frames.recent;
+        while recent.len() >= opts.max_frames
+            || recent.front().map_or(false, |f| kept.timestamp - f.timestamp > opts.secs)
+        {
+            recent.pop_front();
+        }
+        if opts.secs > 0.0 && opts.max_frames > 0 {
+            recent.push_back(kept);
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.frames.lock().unwrap_or_else(PoisonError::into_inner).recent.len()
+    }
+
+    /// Write the frames kept so far and those of the next `post_secs` to `sink` in the background
+    pub(crate) fn flush(&self, sink: Box<dyn FrameSink>) {
+        let post_secs = self.opts().post_secs;
+        let mut frames = self.frames.lock().unwrap_or_else(PoisonError::into_inner);
+        let kept = Vec::from(frames.recent.clone());
+        match kept.last() {
+            Some(last) if post_secs > 0.0 => {
+                let until = last.timestamp + post_secs;
+                frames.pending.push(Pending { until, frames: kept, sink });
+            }
+            _ => write_all(kept, sink),
+        }
+    }
+
+    /// Export frames as a clip, see `flush`
+    pub(crate) fn export(&self, path: PathBuf) {
+        self.flush(Box::new(ClipSink::new(path)));
+    }
+
+    /// Write flushes still waiting for frames after their event, e.g. when a video ends or pauses
+    pub(crate) fn finish_pending(&self) {
+        let mut frames = self.frames.lock().unwrap_or_else(PoisonError::into_inner);
+        for pending in frames.pending.drain(..) {
+            write_all(pending.frames, pending.sink);
+        }
+    }
+}
+
+impl Drop for FrameHistory {
+    /// Write pending flushes before returning, since the process may exit right after
+    fn drop(&mut self) {
+        let frames = self.frames.get_mut().unwrap_or_else(PoisonError::into_inner);
+        for pending in frames.pending.drain(..) {
+            write_logged(pending.frames, pending.sink);
+        }
+    }
+}
+
+/// Composite frames of the same size as the last one into `sink` on another thread
+fn write_all(frames: Vec<Kept>, sink: Box<dyn FrameSink>) {
+    let spawned = thread::Builder::new()
+        .name("ClipExport".to_string())
+        .spawn(move || write_logged(frames, sink));
+    if let Err(e) = spawned {
+        warn!("couldn't export frames: {}", e);
+    }
+}
+
+fn write_logged(frames: Vec<Kept>, sink: Box<dyn FrameSink>) {
+    let n = frames.len();
+    match write_frames(&frames, sink) {
+        Ok(to) => info!("exported {} frames to {}", n, to),
+        Err(e) => warn!("couldn't export {} frames: {}", n, e),
+    }
+}
+
+fn write_frames(frames: &[Kept], mut sink: Box<dyn FrameSink>) -> Result<String, ClipError> {
+    if let Some(last) = frames.last() {
+        let size = last.buffer.dimensions();
+        let fps = estimate_fps(frames);
+        for frame in frames.iter().filter(|f| f.buffer.dimensions() == size) {
+            let composited = composite(&frame.buffer, frame.overlay.as_deref(), frame.style);
+            sink.write(&composited, &frame.annotations, fps)?;
+        }
+    }
+    sink.finish()
+}
+
+/// Frames per second by timestamps
+fn estimate_fps(frames: &[Kept]) -> f64 {
+    match (frames.first(), frames.last()) {
+        (Some(first), Some(last)) if last.timestamp > first.timestamp => {
+            (frames.len() - 1) as f64 / (last.timestamp - first.timestamp)
+        }
+        _ => FALLBACK_FPS,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc::{channel, Sender};
+
+    fn frame(id: u64, timestamp: f64) -> GUIFrame {
+        GUIFrame { timestamp, ..GUIFrame::empty(id, Arc::new(BgrImage::new(4, 2))) }
+    }
+
+    /// Sends the number of frames written when finished
+    struct CountSink(usize, Sender<usize>);
+
+    impl FrameSink for CountSink {
+        fn write(&mut self, _: &RgbImage, _: &Annotations, _: f64) -> Result<(), ClipError> {
+            self.0 += 1;
+            Ok(())
+        }
+
+        fn finish(self: Box<Self>) -> Result<String, ClipError> {
+            self.1.send(self.0).unwrap();
+            Ok(String::default())
+        }
+    }
+
+    #[test]
+    fn keep_recent_seconds() {
+        let history = FrameHistory::default();
+        for i in 0..20 {
+            history.push(&frame(i, i as f64));
+        }
+        assert_eq!(history.len(), 6);
+        // starting over forgets frames of the previous video
+        history.push(&frame(1, 0.0));
+        assert_eq!(history.len(), 1);
+        history.set_opts(HistoryOpts { max_frames: 3, ..Default::default() });
+        for i in 0..5 {
+            history.push(&frame(i, 0.0));
+        }
+        assert_eq!(history.len(), 3);
+        history.set_opts(HistoryOpts { secs: 0.0, ..Default::default() });
+        history.push(&frame(9, 0.0));
+        assert_eq!(history.len(), 0);
+    }
+
+    #[test]
+    fn flush_pre_and_post() {
+        let history =
+            FrameHistory::new(HistoryOpts { secs: 2.0, post_secs: 1.0, ..Default::default() });
+        for i in 0..5 {
+            history.push(&frame(i, i as f64));
+        }
+        let (tx, rx) = channel();
+        history.flush(Box::new(CountSink(0, tx)));
+        history.push(&frame(5, 5.0));
+        assert!(rx.try_recv().is_err(), "flush should wait for later frames");
+        // the first frame past the post-roll completes it
+        history.push(&frame(6, 6.0));
+        // 3 before, 1 within a second after
+        assert_eq!(rx.recv().unwrap(), 4);
+    }
+
+    #[test]
+    fn finish_post_roll_early() {
+        let history =
+            FrameHistory::new(HistoryOpts { secs: 2.0, post_secs: 5.0, ..Default::default() });
+        for i in 0..3 {
+            history.push(&frame(i, i as f64));
+        }
+        let (tx, rx) = channel();
+        history.flush(Box::new(CountSink(0, tx.clone())));
+        history.push(&frame(3, 3.0));
+        // e.g. paused or at its end
+        history.finish_pending();
+        assert_eq!(rx.recv().unwrap(), 4);
+
+        history.flush(Box::new(CountSink(0, tx)));
+        drop(history);
+        assert_eq!(rx.try_recv().unwrap(), 3, "written before dropping it");
+    }
+
+    #[test]
+    fn fps_by_timestamps() {
+        let kept = |t: f64| Kept {
+            timestamp: t,
+            buffer: Arc::default(),
+            overlay: None,
+            style: OverlayStyle::default(),
+            annotations: Annotations::default(),
+        };
+        assert_eq!(estimate_fps(&[kept(1.0), kept(1.5), kept(2.0)]), 2.0);
+        assert_eq!(estimate_fps(&[kept(1.0)]), FALLBACK_FPS);
+    }
+
+    #[test]
+    fn clip_paths() {
+        let opts = ClipOpts { dir: "out".into(), format: ClipFormat::Gif, annotations: None };
+        assert_eq!(opts.path("a b/c", 7), Path::new("out/a_b_c-7.gif"));
+        let opts = ClipOpts { format: ClipFormat::Png, ..opts };
+        assert_eq!(opts.path("r", 7), Path::new("out/r-7"));
+    }
+
+    #[test]
+    fn export_clip() {
+        let frames = (0..3).map(|i| Kept {
+            timestamp: i as f64 / 10.0,
+            buffer: Arc::new(BgrImage::new(4, 2)),
+            overlay: None,
+            style: OverlayStyle::default(),
+            annotations: Annotations::default(),
+        });
+        let path = std::env::temp_dir().join(format!("infur_clip_{}.mp4", std::process::id()));
+        write_frames(&frames.collect::<Vec<_>>(), Box::new(ClipSink::new(path.clone()))).unwrap();
+        assert!(fs::metadata(&path).unwrap().len() > 0);
+        fs::remove_file(&path).unwrap();
+    }
+}
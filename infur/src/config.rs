@@ -0,0 +1,279 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use toml::{value::Table, Value};
+
+use crate::gui::ProcConfig;
+
+/// Configuration file loaded at startup if present
+pub(crate) const DEFAULT_CONFIG: &str = "infur.toml";
+
+/// Version of state written, older ones are migrated when read
+pub(crate) const STATE_VERSION: i64 = 2;
+
+/// Setup of all pipelines to restore after a restart
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct AppState {
+    pub(crate) version: i64,
+    /// Index of the pipeline shown
+    #[serde(default)]
+    pub(crate) active: usize,
+    pub(crate) pipelines: Vec<ProcConfig>,
+}
+
+impl From<ProcConfig> for AppState {
+    fn from(config: ProcConfig) -> Self {
+        Self { version: STATE_VERSION, active: 0, pipelines: vec![config] }
+    }
+}
+
+impl AppState {
+    /// Config of the first pipeline, which sinks and rules are taken from
+    pub(crate) fn first(&self) -> Option<&ProcConfig> {
+        self.pipelines.first()
+    }
+}
+
+/// Bring a document up to `STATE_VERSION`, step by step
+///
+/// Version 1 has no `version` and is the config of a single pipeline.
+fn migrate(mut doc: Value) -> Result<Value, i64> {
+    loop {
+        doc = match doc.get("version").and_then(Value::as_integer).unwrap_or(1) {
+            1 => Value::Table(Table::from_iter([
+                ("version".to_string(), Value::Integer(2)),
+                ("pipelines".to_string(), Value::Array(vec![doc])),
+            ])),
+            STATE_VERSION => return Ok(doc),
+            version => return Err(version),
+        };
+    }
+}
+
+/// Error reading or writing a configuration file
+#[derive(Error, Debug)]
+pub(crate) enum ConfigError {
+    #[error("couldn't access config {path:?}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("couldn't parse config {path:?}")]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+    #[error("config {path:?} is of version {version}, up to {STATE_VERSION} is supported")]
+    Version { path: PathBuf, version: i64 },
+    #[error(transparent)]
+    Serialize(#[from] toml::ser::Error),
+}
+
+/// Parse a configuration of any version, missing fields of pipelines are defaulted
+pub(crate) fn load(path: &Path) -> Result<AppState, ConfigError> {
+    let io_err = |source| ConfigError::Io { path: path.to_path_buf(), source };
+    let parse_err = |source| ConfigError::Parse { path: path.to_path_buf(), source };
+    let text = fs::read_to_string(path).map_err(io_err)?;
+    let doc = toml::from_str(&text).map_err(parse_err)?;
+    let doc =
+        migrate(doc).map_err(|version| ConfigError::Version { path: path.into(), version })?;
+    doc.try_into().map_err(parse_err)
+}
+
+/// Of a JSON value, none of nulls
+fn from_json(json: serde_json::Value) -> Option<Value> {
+    use serde_json::Value as Json;
+    Some(match json {
+        Json::Null => return None,
+        Json::Bool(b) => Value::Boolean(b),
+        Json::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => {
+                let float = n.as_f64()?;
+                // written as read rather than widened, e.g. 0.3 of an f32
+                let narrow = float as f32;
+                let float = match narrow as f64 == float {
+                    true => narrow.to_string().parse().unwrap_or(float),
+                    false => float,
+                };
+                Value::Float(float)
+            }
+        },
+        Json::String(s) => Value::String(s),
+        Json::Array(items) => Value::Array(items.into_iter().filter_map(from_json).collect()),
+        Json::Object(fields) => {
+            Value::Table(fields.into_iter().filter_map(|(k, v)| Some((k, from_json(v)?))).collect())
+        }
+    })
+}
+
+/// State as TOML document
+///
+/// TOML needs values ahead of tables and serializes no enums with data, e.g. `{ gamma = 0.5 }`.
+/// Going through JSON, both hold whatever the order of fields.
+fn to_toml(state: &AppState) -> Result<Value, ConfigError> {
+    let json = serde_json::to_value(state).map_err(serde::ser::Error::custom)?;
+    from_json(json).ok_or_else(|| serde::ser::Error::custom("empty state"))
+}
+
+/// Whether states are saved alike
+pub(crate) fn same(a: &AppState, b: &AppState) -> bool {
+    matches!((to_toml(a), to_toml(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Write entire state of all pipelines
+pub(crate) fn save(state: &AppState, path: &Path) -> Result<(), ConfigError> {
+    let text = toml::to_string_pretty(&to_toml(state)?)?;
+    fs::write(path, text).map_err(|source| ConfigError::Io { path: path.to_path_buf(), source })
+}
+
+/// State stored by the GUI, also of a single pipeline's config as stored before versions
+#[cfg(feature = "persistence")]
+pub(crate) fn restore(storage: &dyn eframe::Storage) -> Option<AppState> {
+    match eframe::get_value::<AppState>(storage, eframe::APP_KEY) {
+        Some(state) if state.version <= STATE_VERSION => Some(state),
+        Some(_) => None,
+        None => eframe::get_value::<ProcConfig>(storage, eframe::APP_KEY).map(AppState::from),
+    }
+}
+
+/// Config file to use, either given explicitly or the default
+pub(crate) fn config_path(path: Option<&Path>) -> PathBuf {
+    path.map_or_else(|| PathBuf::from(DEFAULT_CONFIG), Path::to_path_buf)
+}
+
+/// Load config for startup
+///
+/// A file given explicitly must exist, the default file is optional.
+pub(crate) fn load_startup(path: Option<&Path>) -> Result<Option<AppState>, ConfigError> {
+    let file = config_path(path);
+    if path.is_none() && !file.is_file() {
+        return Ok(None);
+    }
+    load(&file).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sink::SinkSpec;
+
+    #[test]
+    fn partial_config() {
+        let config: ProcConfig = toml::from_str("model_input = \"m.onnx\"\nscale = 0.3").unwrap();
+        assert_eq!(config.model_input, "m.onnx");
+        assert_eq!(config.scale, 0.3);
+        assert!(config.video_input.is_empty());
+    }
+
+    #[test]
+    fn sinks_config() {
+        let text = "[[sinks]]\nkind = \"stdout\"\n[[sinks]]\nkind = \"csv\"\npath = \"a.csv\"";
+        let config: ProcConfig = toml::from_str(text).unwrap();
+        assert_eq!(config.sinks, [SinkSpec::Stdout, SinkSpec::Csv { path: "a.csv".into() }]);
+    }
+
+    #[test]
+    fn save_load() {
+        let path = std::env::temp_dir().join(format!("infur_config_{}.toml", std::process::id()));
+        let config = ProcConfig {
+            video_input: vec!["-re".to_string(), "a.mp4".to_string()],
+            paused: true,
+            ..Default::default()
+        };
+        let state = AppState { active: 1, ..AppState::from(config.clone()) };
+        save(&AppState { pipelines: vec![config.clone(), config], ..state }, &path).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.version, STATE_VERSION);
+        assert_eq!(loaded.active, 1);
+        assert_eq!(loaded.pipelines.len(), 2);
+        assert_eq!(loaded.pipelines[1].video_input, ["-re", "a.mp4"]);
+        assert!(loaded.pipelines[1].paused);
+    }
+
+    #[test]
+    fn save_full_state() {
+        let text = r#"
+            video_input = ["-i", "rtsp://cam/stream"]
+            scale = 0.3
+            paused = true
+            clip = { start = 1.5, end = 9.0 }
+            read_policy = { skips = 2, reopens = 1 }
+            lag_policy = { max_lag = 2.0, adapt = "Scale" }
+            latency_budget = 0.25
+            archive = { dir = "archive", format = "mkv", segment_secs = 60.0, keep_files = 3 }
+            shm = { path = "/dev/shm/infur" }
+            model_input = "m.onnx"
+            providers = ["Cuda", "Cpu"]
+            preview_model_input = true
+            alpha_curve = { gamma = 0.5 }
+            hatch_margin = 0.1
+            class_map = { ids = [0, 1, 1], names = ["other", "vehicle"] }
+            gpu_colorize = true
+            overlay_style = { opacity = 0.7, blend = "multiply" }
+            frame_policy = "ShowLatest"
+            sinks = [{ kind = "stdout" }, { kind = "csv", path = "out.csv" }]
+            history = { secs = 3.0, post_secs = 1.0, max_frames = 90, raw = true }
+            zones = [{ name = "spot", points = [[0.1, 0.1], [0.5, 0.1], [0.5, 0.5]] }]
+            calibration = { pixels_per_meter = 20.0 }
+
+            [error_policy]
+            scale = { failures = 3, recovery = "SkipFrame" }
+            model = { failures = 1, recovery = "StopPipeline" }
+
+            [input_formats."m.onnx"]
+            dim_seq = "NCHW"
+            color_seq = "RGB"
+            dtype = "Float32"
+
+            [output_routes."m.onnx"]
+            out = "segmentation"
+            aux = "embedding"
+
+            [[rules]]
+            name = "busy"
+            sustained = 2.0
+            when = [{ kind = "class_area", class = 1, min = 0.25 }]
+            actions = [{ kind = "log" }, { kind = "clip", dir = "clips", format = "gif" }]
+        "#;
+        let config: ProcConfig = toml::from_str(text).unwrap();
+        let state = AppState {
+            active: 1,
+            pipelines: vec![config.clone(), config],
+            ..AppState::from(ProcConfig::default())
+        };
+        let path = std::env::temp_dir().join(format!("infur_full_{}.toml", std::process::id()));
+        save(&state, &path).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(to_toml(&loaded).unwrap(), to_toml(&state).unwrap());
+        assert_eq!(loaded.pipelines[1].scale, 0.3);
+        assert!(loaded.pipelines[1].calibration.is_some());
+    }
+
+    #[test]
+    fn migrate_v1() {
+        let doc = toml::from_str("model_input = \"m.onnx\"").unwrap();
+        let state: AppState = migrate(doc).unwrap().try_into().unwrap();
+        assert_eq!(state.version, 2);
+        assert_eq!(state.active, 0);
+        assert_eq!(state.first().unwrap().model_input, "m.onnx");
+
+        let doc = toml::from_str("version = 2\n[[pipelines]]\nscale = 0.3").unwrap();
+        let state: AppState = migrate(doc).unwrap().try_into().unwrap();
+        assert_eq!(state.first().unwrap().scale, 0.3);
+        assert_eq!(migrate(toml::from_str("version = 9").unwrap()).unwrap_err(), 9);
+    }
+
+    #[test]
+    fn missing_explicit_config() {
+        assert!(load_startup(Some(Path::new("does/not/exist.toml"))).is_err());
+    }
+}
@@ -1,12 +1,17 @@
-use crate::app::Processor;
+use std::sync::Arc;
+
+use crate::{
+    annotations::Detection, app::Processor, class_map::ClassMap, predict_onnx::ModelOutput,
+    processing::writable,
+};
 use eframe::epaint::{Color32, ColorImage};
-use onnxruntime::ndarray::Array3;
+use ndarray::{Array2, Array3, ArrayBase, ArrayView3, Axis, Dimension, Ix2, Ix3, IxDyn, RawData};
 
 /// 20 RGB high-contrast BGR/RGB triplets
 ///
 /// adapted from: <http://www.color-hex.com/color-palette/23381>
 /// and: <http://www.color-hex.com/color-palette/52402>
-const COLORS_PALETTE: [(u8, u8, u8); 20] = [
+pub(crate) const COLORS_PALETTE: [(u8, u8, u8); 20] = [
     (75, 180, 60),
     (75, 25, 230),
     (25, 225, 255),
@@ -29,21 +34,377 @@ const COLORS_PALETTE: [(u8, u8, u8); 20] = [
     (128, 128, 128),
 ];
 
-fn color_code(klass: usize, alpha: f32) -> Color32 {
+/// Boxes with a lower score aren't drawn
+const BOX_MIN_SCORE: f32 = 0.5;
+/// Line width of boxes in pixels
+const BOX_LINE: usize = 2;
+/// Width of diagonal stripes of runner-up classes in pixels
+pub(crate) const HATCH_WIDTH: usize = 2;
+/// Rows of confidences per job when decoding in parallel
+#[cfg(feature = "rayon")]
+const CHUNK_ROWS: usize = 16;
+
+/// Equally wide bins of confidences from 0 to 1
+pub(crate) const CONFIDENCE_BINS: usize = 20;
+
+/// Number of pixels per bin of confidence
+pub(crate) type Histogram = [u64; CONFIDENCE_BINS];
+
+/// Bin of a confidence, those outside 0-1 count to the first or last one
+fn confidence_bin(confidence: f32) -> usize {
+    ((confidence * CONFIDENCE_BINS as f32).max(0.0) as usize).min(CONFIDENCE_BINS - 1)
+}
+
+pub(crate) fn color_code(klass: usize, alpha: f32) -> Color32 {
     // todo: pre-transform COLORS into linear space
     let (r, g, b) = COLORS_PALETTE[klass % COLORS_PALETTE.len()];
     Color32::from_rgba_unmultiplied(r, g, b, (alpha * 255.0f32) as u8)
 }
 
+/// Array of `D` dimensions after removing leading ones, e.g. 1x1xHxW to HxW
+pub(crate) fn squeeze<S: RawData, D: Dimension>(
+    mut array: ArrayBase<S, IxDyn>,
+) -> Option<ArrayBase<S, D>> {
+    while D::NDIM.map_or(false, |n| array.ndim() > n) && array.shape()[0] == 1 {
+        array = array.remove_axis(Axis(0));
+    }
+    array.into_dimensionality::<D>().ok()
+}
+
+/// Get or re-create an output image of `w`x`h`
+fn image_of(out: &mut Option<Arc<ColorImage>>, w: usize, h: usize) -> &mut ColorImage {
+    let new = || ColorImage::new([w, h], Color32::TRANSPARENT);
+    let img = out.get_or_insert_with(|| Arc::new(new()));
+    if img.width() != w || img.height() != h {
+        *img = Arc::new(new());
+    }
+    writable(img, new)
+}
+
+/// Get or re-create a class map of `h`x`w`
+fn classes_of(out: &mut Option<Arc<Array2<u16>>>, h: usize, w: usize) -> &mut Array2<u16> {
+    let new = || Array2::zeros((h, w));
+    let classes = out.get_or_insert_with(|| Arc::new(new()));
+    if classes.dim() != (h, w) {
+        *classes = Arc::new(new());
+    }
+    writable(classes, new)
+}
+
+/// Segmentation of an image
+#[derive(Debug)]
+pub(crate) enum SegMap {
+    /// KxHxW confidences per class
+    Confidences(Array3<f32>),
+    /// HxW class index per pixel
+    Classes(Array2<u16>),
+}
+
+impl SegMap {
+    /// Map of a model's output, `None` if its shape doesn't fit
+    ///
+    /// Class indices may have a leading dimension of 1, e.g. 1xHxW.
+    pub(crate) fn from_output(output: ModelOutput) -> Option<Self> {
+        match output {
+            ModelOutput::Float(confidences) => {
+                confidences.into_dimensionality::<Ix3>().ok().map(Self::Confidences)
+            }
+            ModelOutput::ClassIndex(classes) => squeeze::<_, Ix2>(classes).map(Self::Classes),
+        }
+    }
+
+    /// Model output to write the next one into
+    pub(crate) fn into_output(self) -> ModelOutput {
+        match self {
+            Self::Confidences(c) => ModelOutput::Float(c.into_dyn()),
+            Self::Classes(c) => ModelOutput::ClassIndex(c.into_dyn()),
+        }
+    }
+
+    /// Height and width
+    fn size(&self) -> (usize, usize) {
+        match self {
+            Self::Confidences(c) => (c.shape()[1], c.shape()[2]),
+            Self::Classes(c) => (c.shape()[0], c.shape()[1]),
+        }
+    }
+}
+
+/// How a pixel's confidence in its class maps to the alpha of its color
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum AlphaCurve {
+    /// As confident as opaque
+    #[default]
+    Linear,
+    /// Confidence to the power of gamma, below 1 shows less confident pixels more
+    Gamma(f32),
+    /// Opaque from the threshold on, transparent below
+    Step(f32),
+}
+
+impl AlphaCurve {
+    /// Each curve with a typical parameter
+    pub(crate) const ALL: [Self; 3] = [Self::Linear, Self::Gamma(0.5), Self::Step(0.5)];
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Self::Linear => "linear",
+            Self::Gamma(_) => "gamma",
+            Self::Step(_) => "step",
+        }
+    }
+
+    pub(crate) fn alpha(&self, confidence: f32) -> f32 {
+        match *self {
+            Self::Linear => confidence,
+            Self::Gamma(gamma) => confidence.max(0.0).powf(gamma),
+            Self::Step(threshold) => {
+                if confidence >= threshold {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Commands that control how segmentations are drawn
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub(crate) enum OverlayCmd {
+    /// Draw only this class, all if none
+    Isolate(Option<usize>),
+    /// Shade confidences by this curve
+    AlphaCurve(AlphaCurve),
+    /// Hatch pixels by their runner-up class if within this margin of the best, never if none
+    HatchMargin(Option<f32>),
+    /// Merge and name classes before decoding
+    ClassMap(ClassMap),
+    /// Leave drawing confidences to the GPU, only decode their classes
+    Offload(bool),
+}
+
+/// Pixels per class and histograms of their confidences
+type ClassStats = (Vec<u64>, Vec<Histogram>);
+
+/// Add up stats of two parts of an image
+#[cfg(feature = "rayon")]
+fn merge_stats(
+    (mut areas, mut hists): ClassStats,
+    (more_areas, more_hists): ClassStats,
+) -> ClassStats {
+    areas.iter_mut().zip(more_areas).for_each(|(a, b)| *a += b);
+    for (hist, more) in hists.iter_mut().zip(more_hists) {
+        hist.iter_mut().zip(more).for_each(|(a, b)| *a += b);
+    }
+    (areas, hists)
+}
+
+/// Decides the class and color of each pixel of confidences
+struct PixelDecoder<'a> {
+    map: &'a ClassMap,
+    /// Number of classes after mapping
+    k: usize,
+    isolated: Option<usize>,
+    curve: AlphaCurve,
+    hatch_margin: Option<f32>,
+}
+
+impl PixelDecoder<'_> {
+    /// Decode KxRxW confidences of rows from `y` into classes, and colors if drawn
+    fn rows(
+        &self,
+        inp: ArrayView3<f32>,
+        y: usize,
+        classes: &mut [u16],
+        pixels: Option<&mut [Color32]>,
+    ) -> ClassStats {
+        let (k, _, w) = inp.dim();
+        let mut areas = vec![0; self.k];
+        let mut confidences = vec![Histogram::default(); self.k];
+        let mut merged = vec![0f32; self.k];
+        let mut pixels = pixels.map(|p| p.iter_mut());
+        let inp_flat = inp.exact_chunks([k, 1, 1]);
+        classes.iter_mut().zip(inp_flat).enumerate().for_each(|(p, (class, klasses))| {
+            merged.fill(0.0);
+            klasses.iter().enumerate().for_each(|(i, &c)| merged[self.map.map(i)] += c);
+            let (mut k_max, mut k_second) = (0, 0);
+            let (mut c_max, mut c_second) = (0f32, 0f32);
+            merged.iter().enumerate().for_each(|(i, &confidence)| {
+                if confidence > c_max {
+                    (k_second, c_second) = (k_max, c_max);
+                    (k_max, c_max) = (i, confidence);
+                } else if confidence > c_second {
+                    (k_second, c_second) = (i, confidence);
+                }
+            });
+            areas[k_max] += 1;
+            *class = k_max as u16;
+            confidences[k_max][confidence_bin(c_max)] += 1;
+            let col = match pixels.as_mut().and_then(Iterator::next) {
+                Some(col) => col,
+                None => return,
+            };
+            let ambiguous =
+                self.hatch_margin.map_or(false, |m| k_second != k_max && c_max - c_second < m);
+            let stripe = (p % w + y + p / w) / HATCH_WIDTH % 2 == 1;
+            let shown_class = if ambiguous && stripe { k_second } else { k_max };
+            *col = if self.isolated.map_or(true, |k| k == shown_class) {
+                color_code(shown_class, self.curve.alpha(c_max))
+            } else {
+                Color32::TRANSPARENT
+            };
+        });
+        (areas, confidences)
+    }
+
+    /// Decode KxHxW confidences into HxW classes, and colors if drawn, in parallel chunks of rows
+    #[cfg(feature = "rayon")]
+    fn decode(
+        &self,
+        inp: &Array3<f32>,
+        classes: &mut [u16],
+        pixels: Option<&mut [Color32]>,
+    ) -> ClassStats {
+        use rayon::prelude::*;
+        let w = inp.shape()[2].max(1);
+        let job = |(i, classes): (usize, &mut [u16]), pixels| {
+            let y = i * CHUNK_ROWS;
+            let rows = inp.slice(ndarray::s![.., y..y + classes.len() / w, ..]);
+            self.rows(rows, y, classes, pixels)
+        };
+        let empty = || (vec![0; self.k], vec![Histogram::default(); self.k]);
+        let chunks = classes.par_chunks_mut(CHUNK_ROWS * w).enumerate();
+        match pixels {
+            Some(pixels) => chunks
+                .zip(pixels.par_chunks_mut(CHUNK_ROWS * w))
+                .map(|(chunk, pixels)| job(chunk, Some(pixels)))
+                .reduce(empty, merge_stats),
+            None => chunks.map(|chunk| job(chunk, None)).reduce(empty, merge_stats),
+        }
+    }
+
+    /// Decode KxHxW confidences into HxW classes, and colors if drawn
+    #[cfg(not(feature = "rayon"))]
+    fn decode(
+        &self,
+        inp: &Array3<f32>,
+        classes: &mut [u16],
+        pixels: Option<&mut [Color32]>,
+    ) -> ClassStats {
+        self.rows(inp.view(), 0, classes, pixels)
+    }
+}
+
 #[derive(Default)]
-pub(crate) struct ColorCode;
+pub(crate) struct ColorCode {
+    /// Number of pixels per class (argmax) of the last input
+    pub(crate) class_areas: Vec<u64>,
+    /// Maximum confidences per class (argmax) of the last input, empty for class indices
+    pub(crate) confidences: Vec<Histogram>,
+    /// Class (argmax) per pixel of the last input
+    pub(crate) classes: Option<Arc<Array2<u16>>>,
+    /// Only class drawn, if any
+    isolated: Option<usize>,
+    alpha_curve: AlphaCurve,
+    /// Hatch pixels by their runner-up class if that's at most this less confident
+    hatch_margin: Option<f32>,
+    /// Confidences of merged classes add up
+    class_map: ClassMap,
+    /// Don't draw confidences
+    offload: bool,
+}
 
 impl Processor for ColorCode {
+    type Command = OverlayCmd;
+    type ControlError = ();
+    type Input = SegMap;
+    type Output = Option<Arc<ColorImage>>;
+    type ProcessResult = ();
+
+    fn control(&mut self, cmd: Self::Command) -> Result<&mut Self, Self::ControlError> {
+        match cmd {
+            OverlayCmd::Isolate(klass) => self.isolated = klass,
+            OverlayCmd::AlphaCurve(curve) => self.alpha_curve = curve,
+            OverlayCmd::HatchMargin(margin) => self.hatch_margin = margin,
+            OverlayCmd::ClassMap(map) => self.class_map = map,
+            OverlayCmd::Offload(offload) => self.offload = offload,
+        }
+        Ok(self)
+    }
+
+    fn advance(&mut self, inp: &Self::Input, out: &mut Self::Output) -> Self::ProcessResult {
+        let (h, w) = inp.size();
+        let classes = classes_of(&mut self.classes, h, w);
+
+        let areas = &mut self.class_areas;
+        areas.clear();
+        let confidences = &mut self.confidences;
+        confidences.clear();
+        let map = &self.class_map;
+        match inp {
+            SegMap::Confidences(inp) => {
+                let decoder = PixelDecoder {
+                    map,
+                    k: map.len(inp.shape()[0]),
+                    isolated: self.isolated,
+                    curve: self.alpha_curve,
+                    hatch_margin: self.hatch_margin,
+                };
+                if self.offload {
+                    *out = None;
+                }
+                let pixels = (!self.offload).then(|| image_of(out, w, h).pixels.as_mut_slice());
+                // created in standard layout
+                let classes = classes.as_slice_mut().expect("contiguous classes");
+                (*areas, *confidences) = decoder.decode(inp, classes, pixels);
+            }
+            // already decided, thus fully confident
+            SegMap::Classes(inp) => {
+                // beyond the labels, or saturated from negative indices, is background
+                let labels = match map.names.len() {
+                    0 => u16::MAX as usize,
+                    n => n,
+                };
+                let mapped = |k: u16| Some(map.map(k as usize)).filter(|&k| k < labels);
+                let in_range = inp.iter().filter(|&&k| mapped(k).is_some()).max();
+                let k = in_range.map_or(0, |&k| k as usize + 1);
+                areas.resize(map.len(k).clamp(1, labels), 0);
+                let img = image_of(out, w, h);
+                let pixels = img.pixels.iter_mut().zip(classes.iter_mut());
+                pixels.zip(inp.iter()).for_each(|((col, class), &klass)| {
+                    let klass = mapped(klass).unwrap_or(0);
+                    areas[klass] += 1;
+                    *class = klass as u16;
+                    let shown = self.isolated.map_or(true, |k| k == klass);
+                    *col = if shown { color_code(klass, 1.0) } else { Color32::TRANSPARENT };
+                });
+            }
+        }
+    }
+
+    fn is_dirty(&self) -> bool {
+        false
+    }
+}
+
+/// Shades a single channel map, e.g. depth, from its minimum to its maximum
+#[derive(Default)]
+pub(crate) struct HeatMap;
+
+/// Black over red to yellow
+fn heat_color(value: f32) -> Color32 {
+    let [r, g] = [(2.0 * value).min(1.0), (2.0 * value - 1.0).max(0.0)];
+    Color32::from_rgba_unmultiplied((r * 255.0) as u8, (g * 255.0) as u8, 0, (value * 255.0) as u8)
+}
+
+impl Processor for HeatMap {
     type Command = ();
     type ControlError = ();
-    /// KxHxW confidences
-    type Input = Array3<f32>;
-    type Output = Option<ColorImage>;
+    /// HxW values
+    type Input = Array2<f32>;
+    type Output = Option<Arc<ColorImage>>;
     type ProcessResult = ();
 
     fn control(&mut self, _cmd: Self::Command) -> Result<&mut Self, Self::ControlError> {
@@ -51,31 +412,78 @@ impl Processor for ColorCode {
     }
 
     fn advance(&mut self, inp: &Self::Input, out: &mut Self::Output) -> Self::ProcessResult {
-        let shape = inp.shape();
-        let (k, h, w) = (shape[0], shape[1], shape[2]);
+        let (h, w) = inp.dim();
+        let img = image_of(out, w, h);
+        let (min, max) = inp.iter().fold((f32::MAX, f32::MIN), |(l, h), &v| (l.min(v), h.max(v)));
+        let range = if max > min { max - min } else { 1.0 };
+        img.pixels.iter_mut().zip(inp.iter()).for_each(|(col, &v)| {
+            *col = heat_color((v - min) / range);
+        });
+    }
 
-        // get or re-create output image
-        let img = if let Some(ref mut img) = out {
-            if img.width() != w || img.height() != h {
-                *img = ColorImage::new([w, h], Color32::BLACK);
-            }
-            img
-        } else {
-            out.get_or_insert_with(|| ColorImage::new([w, h], Color32::BLACK))
-        };
+    fn is_dirty(&self) -> bool {
+        false
+    }
+}
 
-        let inp_flat = inp.exact_chunks([k, 1, 1]);
-        img.pixels.iter_mut().zip(inp_flat).for_each(|(col, klasses)| {
-            let mut k_max = 0;
-            let mut c_max = 0f32;
-            klasses.iter().enumerate().for_each(|(i, confidence)| {
-                if confidence > &c_max {
-                    k_max = i;
-                    c_max = *confidence;
+/// Outlines boxes of detections
+#[derive(Default)]
+pub(crate) struct Boxes {
+    /// Boxes drawn of the last input
+    pub(crate) detections: Vec<Detection>,
+    class_map: ClassMap,
+}
+
+impl Processor for Boxes {
+    type Command = ClassMap;
+    type ControlError = ();
+    /// Nx4+ boxes of `x1, y1, x2, y2[, score[, class]]` and the `[width, height]` to draw on
+    ///
+    /// Coordinates of at most 1 are relative to the size.
+    type Input = (Array2<f32>, [usize; 2]);
+    type Output = Option<Arc<ColorImage>>;
+    type ProcessResult = ();
+
+    fn control(&mut self, cmd: Self::Command) -> Result<&mut Self, Self::ControlError> {
+        self.class_map = cmd;
+        Ok(self)
+    }
+
+    fn advance(&mut self, inp: &Self::Input, out: &mut Self::Output) -> Self::ProcessResult {
+        let (boxes, [w, h]) = inp;
+        let (w, h) = (*w, *h);
+        let img = image_of(out, w, h);
+        img.pixels.fill(Color32::TRANSPARENT);
+        self.detections.clear();
+        if boxes.ncols() < 4 || w == 0 || h == 0 {
+            return;
+        }
+        let relative = boxes.columns().into_iter().take(4).all(|c| c.iter().all(|&v| v <= 1.0));
+        let (sx, sy) = if relative { (w as f32, h as f32) } else { (1.0, 1.0) };
+        for row in boxes.rows() {
+            if row.get(4).map_or(false, |&score| score < BOX_MIN_SCORE) {
+                continue;
+            }
+            let class = self.class_map.map(row.get(5).map_or(0, |&k| k.max(0.0) as usize));
+            let color = color_code(class, 1.0);
+            let x = |v: f32| ((v * sx).max(0.0) as usize).min(w - 1);
+            let y = |v: f32| ((v * sy).max(0.0) as usize).min(h - 1);
+            let (x1, y1, x2, y2) = (x(row[0]), y(row[1]), x(row[2]), y(row[3]));
+            let (fw, fh) = (w as f32, h as f32);
+            let bbox = [x1 as f32 / fw, y1 as f32 / fh, (x2 + 1) as f32 / fw, (y2 + 1) as f32 / fh];
+            self.detections.push(Detection { class, bbox });
+            for py in y1..=y2 {
+                for px in x1..=x2 {
+                    let edge = px < x1 + BOX_LINE
+                        || px + BOX_LINE > x2
+                        || py < y1 + BOX_LINE
+                        || py + BOX_LINE > y2;
+                    if edge {
+                        img.pixels[py * w + px] = color;
+                    }
                 }
-            });
-            *col = color_code(k_max, c_max);
-        });
+            }
+        }
     }
 
     fn is_dirty(&self) -> bool {
@@ -86,7 +494,7 @@ impl Processor for ColorCode {
 #[cfg(test)]
 mod test {
 
-    use onnxruntime::ndarray::Array1;
+    use ndarray::Array1;
 
     use super::*;
 
@@ -100,18 +508,199 @@ mod test {
     fn decode_0to1() {
         let hm = <Array1<f32>>::linspace(0., 1., 22 * 24 * 32).into_shape([22, 24, 32]).unwrap();
         let mut img = None;
-        let mut decoder = ColorCode;
-        decoder.advance(&hm, &mut img);
+        let mut decoder = ColorCode::default();
+        decoder.advance(&SegMap::Confidences(hm), &mut img);
 
         let img = img.unwrap();
         assert_eq!(img.width(), 32);
         assert_eq!(img.height(), 24);
         let mut conf = 0;
-        for p in img.pixels {
+        for &p in img.pixels.iter() {
             assert_eq!(p, color_code(21, p.a() as f32 / 255f32));
             assert!(conf <= p.a(), "expected monotically rising confidence/alpha");
             conf = p.a();
         }
         assert_eq!(conf, 255);
     }
+
+    #[test]
+    fn class_areas() {
+        let mut hm = Array3::<f32>::zeros([3, 4, 5]);
+        hm.slice_mut(ndarray::s![1, .., 0..2]).fill(1.0);
+        hm.slice_mut(ndarray::s![2, .., 2..]).fill(1.0);
+        let mut img = None;
+        let mut decoder = ColorCode::default();
+        decoder.advance(&SegMap::Confidences(hm), &mut img);
+        assert_eq!(decoder.class_areas, [0, 4 * 2, 4 * 3]);
+        let classes = decoder.classes.unwrap();
+        assert_eq!((classes[[0, 1]], classes[[3, 4]]), (1, 2));
+    }
+
+    #[test]
+    fn confidence_histograms() {
+        let mut hm = Array3::<f32>::zeros([2, 1, 4]);
+        hm.slice_mut(ndarray::s![0, 0, ..]).assign(&ndarray::arr1(&[0.3, 0.99, 2.0, 0.0]));
+        hm[[1, 0, 3]] = 0.5;
+        let mut decoder = ColorCode::default();
+        decoder.advance(&SegMap::Confidences(hm), &mut None);
+        let bins = |k: usize| {
+            let hist = decoder.confidences[k].iter().enumerate();
+            hist.filter(|(_, &n)| n > 0).map(|(i, &n)| (i, n)).collect::<Vec<_>>()
+        };
+        assert_eq!(bins(0), [(6, 1), (19, 2)], "larger confidences should count to the last bin");
+        assert_eq!(bins(1), [(10, 1)]);
+    }
+
+    #[test]
+    fn alpha_curves() {
+        assert_eq!(AlphaCurve::Linear.alpha(0.25), 0.25);
+        assert_eq!(AlphaCurve::Gamma(0.5).alpha(0.25), 0.5);
+        assert_eq!(AlphaCurve::Gamma(2.0).alpha(-1.0), 0.0);
+        assert_eq!(AlphaCurve::Step(0.5).alpha(0.49), 0.0);
+        assert_eq!(AlphaCurve::Step(0.5).alpha(0.5), 1.0);
+
+        let hm = Array3::from_shape_vec([1, 1, 2], vec![0.4, 0.6]).unwrap();
+        let mut img = None;
+        let mut decoder = ColorCode::default();
+        decoder.control(OverlayCmd::AlphaCurve(AlphaCurve::Step(0.5))).unwrap();
+        decoder.advance(&SegMap::Confidences(hm), &mut img);
+        assert_eq!(img.unwrap().pixels, [color_code(0, 0.0), color_code(0, 1.0)]);
+    }
+
+    #[test]
+    fn hatch_runner_ups() {
+        let hm = Array3::from_shape_vec([2, 1, 4], vec![0.9, 0.55, 0.5, 0.1, 0.1, 0.45, 0.5, 0.9])
+            .unwrap();
+        let seg = SegMap::Confidences(hm);
+        let mut img = None;
+        let mut decoder = ColorCode::default();
+        decoder.advance(&seg, &mut img);
+        assert_eq!(img.as_deref().unwrap().pixels[2], color_code(0, 0.5), "ties are argmax");
+
+        decoder.control(OverlayCmd::HatchMargin(Some(0.2))).unwrap();
+        decoder.advance(&seg, &mut img);
+        let hatched = &img.as_deref().unwrap().pixels;
+        assert_eq!(hatched[0], color_code(0, 0.9));
+        assert_eq!(hatched[1], color_code(0, 0.55), "should be between stripes");
+        assert_eq!(hatched[2], color_code(1, 0.5), "runner-up should be on a stripe");
+        assert_eq!(hatched[3], color_code(1, 0.9), "confident pixels aren't hatched");
+        assert_eq!(decoder.class_areas, [3, 1], "areas should be of the best class");
+    }
+
+    #[test]
+    fn decode_tall_maps() {
+        let mut hm = Array3::<f32>::from_elem([2, 40, 3], 0.45);
+        hm.slice_mut(ndarray::s![0, .., ..]).fill(0.5);
+        let mut img = None;
+        let mut decoder = ColorCode::default();
+        decoder.control(OverlayCmd::HatchMargin(Some(0.1))).unwrap();
+        decoder.advance(&SegMap::Confidences(hm), &mut img);
+        assert_eq!(decoder.class_areas, [40 * 3, 0]);
+        assert_eq!(decoder.confidences[0][10], 40 * 3);
+        let pixels = &img.unwrap().pixels;
+        assert_eq!(pixels[17 * 3], color_code(0, 0.5));
+        assert_eq!(pixels[18 * 3], color_code(1, 0.5), "stripes should continue across rows");
+    }
+
+    #[test]
+    fn merge_classes() {
+        let hm = Array3::from_shape_vec([3, 1, 2], vec![0.4, 0.2, 0.3, 0.1, 0.3, 0.7]).unwrap();
+        let map = ClassMap { ids: vec![0, 1, 1], names: vec![] };
+        let mut img = None;
+        let mut decoder = ColorCode::default();
+        decoder.control(OverlayCmd::ClassMap(map.clone())).unwrap();
+        decoder.advance(&SegMap::Confidences(hm), &mut img);
+        assert_eq!(decoder.class_areas, [0, 2], "confidences of merged classes should add up");
+        assert_eq!(img.unwrap().pixels[0], color_code(1, 0.6));
+
+        let classes = Array2::from_shape_vec([1, 3], vec![0u16, 2, 3]).unwrap();
+        decoder.advance(&SegMap::Classes(classes), &mut None);
+        assert_eq!(decoder.class_areas, [1, 1, 0, 1], "unmapped classes should stay");
+        assert_eq!(decoder.classes.as_deref().unwrap().row(0).to_vec(), [0, 1, 3]);
+
+        let classes = Array2::from_shape_vec([1, 3], vec![u16::MAX, 2, 3]).unwrap();
+        decoder.advance(&SegMap::Classes(classes), &mut None);
+        assert_eq!(decoder.class_areas, [1, 1, 0, 1], "saturated classes should be background");
+        let names = ["a", "b", "c"].map(String::from).to_vec();
+        decoder.control(OverlayCmd::ClassMap(ClassMap { names, ..map.clone() })).unwrap();
+        let classes = Array2::from_shape_vec([1, 3], vec![0u16, 2, 3]).unwrap();
+        decoder.advance(&SegMap::Classes(classes), &mut None);
+        assert_eq!(decoder.class_areas, [2, 1], "classes beyond labels should be background");
+
+        let boxes = Array2::from_shape_vec([1, 6], vec![0.0, 0.0, 0.5, 0.5, 0.9, 2.0]).unwrap();
+        let mut boxes_decoder = Boxes::default();
+        boxes_decoder.control(map).unwrap();
+        boxes_decoder.advance(&(boxes, [4, 4]), &mut None);
+        assert_eq!(boxes_decoder.detections[0].class, 1);
+    }
+
+    #[test]
+    fn offload_drawing() {
+        let hm = Array3::from_shape_vec([2, 1, 2], vec![0.9, 0.2, 0.1, 0.8]).unwrap();
+        let mut img = None;
+        let mut decoder = ColorCode::default();
+        decoder.advance(&SegMap::Confidences(hm.clone()), &mut img);
+        assert!(img.is_some());
+        decoder.control(OverlayCmd::Offload(true)).unwrap();
+        decoder.advance(&SegMap::Confidences(hm), &mut img);
+        assert!(img.is_none(), "confidences should be left undrawn");
+        assert_eq!(decoder.class_areas, [1, 1]);
+        assert_eq!(decoder.classes.as_deref().unwrap().row(0).to_vec(), [0, 1]);
+    }
+
+    #[test]
+    fn class_index_map() {
+        let classes = Array3::<u16>::from_shape_fn([1, 4, 5], |(_, _, x)| (x / 2) as u16);
+        let seg = SegMap::from_output(ModelOutput::ClassIndex(classes.into_dyn())).unwrap();
+        let mut img = None;
+        let mut decoder = ColorCode::default();
+        decoder.advance(&seg, &mut img);
+        assert_eq!(decoder.class_areas, [4 * 2, 4 * 2, 4]);
+        let shown = img.as_deref().unwrap();
+        assert_eq!((shown.width(), shown.height()), (5, 4));
+        assert_eq!(shown.pixels[4], color_code(2, 1.0));
+
+        decoder.control(OverlayCmd::Isolate(Some(1))).unwrap();
+        decoder.advance(&seg, &mut img);
+        assert_eq!(decoder.class_areas, [4 * 2, 4 * 2, 4], "areas should include hidden classes");
+        let isolated = img.unwrap();
+        assert_eq!(isolated.pixels[4], Color32::TRANSPARENT);
+        assert_eq!(isolated.pixels[2], color_code(1, 1.0));
+    }
+
+    #[test]
+    fn heat_from_min_to_max() {
+        let depth = Array2::from_shape_vec([1, 3], vec![2.0, 4.0, 3.0]).unwrap();
+        let mut img = None;
+        HeatMap.advance(&depth, &mut img);
+        let pixels = &img.unwrap().pixels;
+        assert_eq!(pixels[0], heat_color(0.0));
+        assert_eq!(pixels[1], heat_color(1.0));
+        assert_eq!(pixels[2], heat_color(0.5));
+    }
+
+    #[test]
+    fn outline_boxes() {
+        let boxes = Array2::from_shape_vec(
+            [2, 6],
+            vec![0.0, 0.0, 0.5, 0.5, 0.9, 3.0, 0.5, 0.5, 1.0, 1.0, 0.1, 1.0],
+        )
+        .unwrap();
+        let mut img = None;
+        let mut decoder = Boxes::default();
+        decoder.advance(&(boxes, [20, 10]), &mut img);
+        let img = img.unwrap();
+        assert_eq!(img.pixels[0], color_code(3, 1.0), "top left should be outlined");
+        assert_eq!(img.pixels[2 * 20 + 2], Color32::TRANSPARENT, "inside should be clear");
+        assert_eq!(img.pixels[9 * 20 + 19], Color32::TRANSPARENT, "low scores shouldn't be drawn");
+        assert_eq!(decoder.detections, [Detection { class: 3, bbox: [0.0, 0.0, 0.55, 0.6] }]);
+    }
+
+    #[test]
+    fn reject_other_shapes() {
+        let flat = ModelOutput::Float(Array2::<f32>::zeros([4, 5]).into_dyn());
+        assert!(SegMap::from_output(flat).is_none());
+        let batched = ModelOutput::ClassIndex(Array3::<u16>::zeros([2, 4, 5]).into_dyn());
+        assert!(SegMap::from_output(batched).is_none());
+    }
 }
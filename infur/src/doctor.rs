@@ -0,0 +1,129 @@
+use std::{ffi::CStr, os::raw::c_char, sync::Arc};
+
+use ff_video::Capabilities;
+use ort::sys;
+
+/// Outcome of checking one part of the environment
+#[derive(Debug)]
+pub(crate) struct Check {
+    pub(crate) name: &'static str,
+    /// What was found or why it's a problem
+    pub(crate) outcome: Result<String, String>,
+}
+
+impl Check {
+    fn new(name: &'static str, outcome: Result<String, String>) -> Self {
+        Self { name, outcome }
+    }
+}
+
+fn ffmpeg_capabilities() -> Result<Arc<Capabilities>, String> {
+    ff_video::capabilities(&ff_video::ffmpeg_path())
+        .map_err(|e| format!("couldn't run ffmpeg ({}), is it on PATH or set by FFMPEG?", e))
+}
+
+fn ffmpeg_version() -> Result<String, String> {
+    ffmpeg_capabilities()?
+        .version
+        .clone()
+        .ok_or_else(|| "couldn't parse ffmpeg version".to_string())
+}
+
+fn hwaccels() -> Result<String, String> {
+    match &ffmpeg_capabilities()?.hwaccels {
+        methods if methods.is_empty() => Err("no hardware acceleration available".to_string()),
+        methods => Ok(methods.join(", ")),
+    }
+}
+
+pub(crate) fn to_string(s: *const c_char) -> String {
+    // SAFETY: strings from onnxruntime are valid and nul terminated
+    unsafe { CStr::from_ptr(s) }.to_string_lossy().to_string()
+}
+
+pub(crate) fn ort_version() -> Result<String, String> {
+    // SAFETY: the api base is static and its version string outlives the process
+    unsafe {
+        let base = sys::OrtGetApiBase();
+        let get_version = (*base).GetVersionString.ok_or("no version string")?;
+        Ok(to_string(get_version()))
+    }
+}
+
+/// Execution providers the linked onnxruntime was built with
+pub(crate) fn ort_provider_names() -> Result<Vec<String>, String> {
+    // SAFETY: the api is static, providers are read and released as documented
+    unsafe {
+        let base = sys::OrtGetApiBase();
+        let api = (*base).GetApi.ok_or("no api")?(sys::ORT_API_VERSION);
+        if api.is_null() {
+            return Err(format!("api version {} isn't supported", sys::ORT_API_VERSION));
+        }
+        let get = (*api).GetAvailableProviders.ok_or("can't list providers")?;
+        let release = (*api).ReleaseAvailableProviders.ok_or("can't list providers")?;
+        let (mut providers, mut len) = (std::ptr::null_mut(), 0);
+        let status = get(&mut providers, &mut len);
+        if !status.is_null() {
+            let msg = (*api).GetErrorMessage.map_or_else(String::new, |m| to_string(m(status)));
+            if let Some(release_status) = (*api).ReleaseStatus {
+                release_status(status);
+            }
+            return Err(format!("couldn't list providers: {}", msg));
+        }
+        let names = (0..len as usize).map(|i| to_string(*providers.add(i))).collect();
+        release(providers, len);
+        Ok(names)
+    }
+}
+
+fn ort_providers() -> Result<String, String> {
+    ort_provider_names().map(|names| names.join(", "))
+}
+
+/// Checks cheap enough to run at every startup
+pub(crate) fn light_checks() -> Vec<Check> {
+    vec![Check::new("ffmpeg", ffmpeg_version()), Check::new("onnxruntime", ort_version())]
+}
+
+/// All checks
+pub(crate) fn full_checks() -> Vec<Check> {
+    let mut checks = light_checks();
+    checks.push(Check::new("hardware acceleration", hwaccels()));
+    checks.push(Check::new("execution providers", ort_providers()));
+    checks
+}
+
+/// Readable report with one line per check
+pub(crate) fn report(checks: &[Check]) -> String {
+    checks
+        .iter()
+        .map(|c| match &c.outcome {
+            Ok(found) => format!("[ok]   {}: {}", c.name, found),
+            Err(problem) => format!("[fail] {}: {}", c.name, problem),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Describe failed checks
+pub(crate) fn problems(checks: &[Check]) -> Vec<String> {
+    checks
+        .iter()
+        .filter_map(|c| c.outcome.as_ref().err().map(|problem| format!("{}: {}", c.name, problem)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report_problems() {
+        let checks = [
+            Check::new("ffmpeg", Ok("5.1.2".to_string())),
+            Check::new("onnxruntime", Err("missing".to_string())),
+        ];
+        assert_eq!(report(&checks), "[ok]   ffmpeg: 5.1.2\n[fail] onnxruntime: missing");
+        assert_eq!(problems(&checks), ["onnxruntime: missing"]);
+    }
+}
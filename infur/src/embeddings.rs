@@ -0,0 +1,270 @@
+use std::{
+    collections::VecDeque,
+    mem,
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+};
+
+use ndarray::{s, Array1, Array3, ArrayView1, Axis, Ix1, Ix3};
+use tracing::warn;
+
+use crate::{app::GUIFrame, decode_predict::squeeze, predict_onnx::ModelOutput};
+
+/// Cells per side spatial embeddings are pooled to at most
+const GRID: usize = 8;
+/// Frames kept in the index, older ones are forgotten
+const INDEX_CAP: usize = 1000;
+/// Bytes of embeddings kept in the index, e.g. of about 250 frames of 1024 features per cell
+const INDEX_BYTES: usize = 64 << 20;
+
+/// Feature vectors of a frame on a coarse grid, each of unit length
+#[derive(Clone, Debug)]
+pub(crate) struct Embedding {
+    /// HxWxC
+    cells: Array3<f32>,
+}
+
+fn normalized(mut v: Array1<f32>) -> Array1<f32> {
+    let norm = v.dot(&v).sqrt();
+    if norm > 0.0 {
+        v /= norm;
+    }
+    v
+}
+
+/// Cell range of a relative span over `n` cells, at least one
+fn cell_span(from: f32, to: f32, n: usize) -> (usize, usize) {
+    let start = ((from.clamp(0.0, 1.0) * n as f32) as usize).min(n - 1);
+    let end = ((to.clamp(0.0, 1.0) * n as f32).ceil() as usize).clamp(start + 1, n);
+    (start, end)
+}
+
+impl Embedding {
+    /// Embedding of a model output of C features per frame or CxHxW per location
+    pub(crate) fn from_output(output: &ModelOutput) -> Option<Self> {
+        let features = match output {
+            ModelOutput::Float(features) => features.view(),
+            ModelOutput::ClassIndex(_) => return None,
+        };
+        let features = match features.ndim() {
+            3 => features.into_dimensionality::<Ix3>().ok()?,
+            _ => squeeze::<_, Ix1>(features)?.insert_axis(Axis(1)).insert_axis(Axis(2)),
+        };
+        let (c, h, w) = features.dim();
+        if c == 0 || h == 0 || w == 0 {
+            return None;
+        }
+        let (gh, gw) = (h.min(GRID), w.min(GRID));
+        let mut cells = Array3::zeros([gh, gw, c]);
+        for y in 0..gh {
+            for x in 0..gw {
+                let cell = features.slice(s![
+                    ..,
+                    y * h / gh..(y + 1) * h / gh,
+                    x * w / gw..(x + 1) * w / gw
+                ]);
+                let pooled =
+                    cell.lanes(Axis(0)).into_iter().fold(Array1::zeros(c), |sum, l| sum + l);
+                cells.slice_mut(s![y, x, ..]).assign(&normalized(pooled));
+            }
+        }
+        Some(Self { cells })
+    }
+
+    /// Mean feature of the cells covering a relative region `[x0, y0, x1, y1]`
+    pub(crate) fn region(&self, region: [f32; 4]) -> Array1<f32> {
+        let (gh, gw, _) = self.cells.dim();
+        let (x0, x1) = cell_span(region[0], region[2], gw);
+        let (y0, y1) = cell_span(region[1], region[3], gh);
+        let cells = self.cells.slice(s![y0..y1, x0..x1, ..]);
+        let sum = cells.lanes(Axis(2)).into_iter().fold(Array1::zeros(cells.dim().2), |s, l| s + l);
+        normalized(sum)
+    }
+
+    fn bytes(&self) -> usize {
+        self.cells.len() * mem::size_of::<f32>()
+    }
+
+    /// Highest cosine similarity of any cell to a feature of unit length
+    fn best_match(&self, feature: ArrayView1<'_, f32>) -> f32 {
+        if self.cells.dim().2 != feature.len() {
+            return f32::MIN;
+        }
+        self.cells
+            .lanes(Axis(2))
+            .into_iter()
+            .map(|cell| cell.dot(&feature))
+            .fold(f32::MIN, f32::max)
+    }
+}
+
+/// Frame similar to a searched region
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Match {
+    pub(crate) frame_id: u64,
+    pub(crate) timestamp: f64,
+    /// Cosine similarity, 1 for the same direction
+    pub(crate) similarity: f32,
+}
+
+#[derive(Clone)]
+struct Indexed {
+    frame_id: u64,
+    timestamp: f64,
+    embedding: Arc<Embedding>,
+}
+
+#[derive(Default)]
+struct Frames {
+    indexed: VecDeque<Indexed>,
+    /// Of all embeddings indexed
+    bytes: usize,
+}
+
+/// Embeddings of a pipeline's recent frames to search for similar ones
+#[derive(Default)]
+pub(crate) struct EmbeddingIndex {
+    frames: Mutex<Frames>,
+    /// Matches of the last search in the background, until taken
+    found: Mutex<Option<Vec<Match>>>,
+}
+
+impl EmbeddingIndex {
+    /// Keep the embedding of a frame if it has one
+    pub(crate) fn push(&self, frame: &GUIFrame) {
+        let embedding = match &frame.embedding {
+            Some(embedding) => embedding.clone(),
+            None => return,
+        };
+        let mut frames = self.frames.lock().unwrap_or_else(PoisonError::into_inner);
+        // another video may start over
+        if frames.indexed.back().map_or(false, |f| f.timestamp > frame.timestamp) {
+            *frames = Frames::default();
+        }
+        frames.bytes += embedding.bytes();
+        while frames.indexed.len() >= INDEX_CAP || frames.bytes > INDEX_BYTES {
+            match frames.indexed.pop_front() {
+                Some(old) => frames.bytes -= old.embedding.bytes(),
+                None => break,
+            }
+        }
+        let indexed = Indexed { frame_id: frame.id, timestamp: frame.timestamp, embedding };
+        frames.indexed.push_back(indexed);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.frames.lock().unwrap_or_else(PoisonError::into_inner).indexed.len()
+    }
+
+    /// Up to `n` other frames most similar to a relative region of frame `frame_id`
+    ///
+    /// Regions are compared to any location of other frames, none is found if the frame
+    /// wasn't indexed.
+    pub(crate) fn search(&self, frame_id: u64, region: [f32; 4], n: usize) -> Vec<Match> {
+        // don't hold up indexing while comparing
+        let frames = self.frames.lock().unwrap_or_else(PoisonError::into_inner).indexed.clone();
+        let feature = match frames.iter().find(|f| f.frame_id == frame_id) {
+            Some(f) => f.embedding.region(region),
+            None => return vec![],
+        };
+        let mut matches = frames
+            .iter()
+            .filter(|f| f.frame_id != frame_id)
+            .map(|f| Match {
+                frame_id: f.frame_id,
+                timestamp: f.timestamp,
+                similarity: f.embedding.best_match(feature.view()),
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+        matches.truncate(n);
+        matches
+    }
+
+    /// Search as by `search` on another thread, calling `done` once its matches can be taken
+    pub(crate) fn search_in_background(
+        self: &Arc<Self>,
+        frame_id: u64,
+        region: [f32; 4],
+        n: usize,
+        done: impl FnOnce() + Send + 'static,
+    ) {
+        let index = self.clone();
+        let spawned = thread::Builder::new().name("EmbeddingSearch".to_string()).spawn(move || {
+            let found = index.search(frame_id, region, n);
+            *index.found.lock().unwrap_or_else(PoisonError::into_inner) = Some(found);
+            done();
+        });
+        if let Err(e) = spawned {
+            warn!("couldn't search similar frames: {}", e);
+        }
+    }
+
+    /// Matches of the last search in the background if it finished since last taken
+    pub(crate) fn take_found(&self) -> Option<Vec<Match>> {
+        self.found.lock().unwrap_or_else(PoisonError::into_inner).take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ndarray::{Array1, ArrayD};
+
+    fn frame(id: u64, features: ArrayD<f32>) -> GUIFrame {
+        let embedding = Embedding::from_output(&ModelOutput::Float(features)).map(Arc::new);
+        GUIFrame { timestamp: id as f64, embedding, ..GUIFrame::empty(id, Arc::default()) }
+    }
+
+    /// 2 features on 2x2 locations, the top left one differs
+    fn spatial(top_left: [f32; 2]) -> ArrayD<f32> {
+        let mut features = Array3::<f32>::zeros([2, 2, 2]);
+        features.slice_mut(s![0, .., ..]).fill(1.0);
+        features[[0, 0, 0]] = top_left[0];
+        features[[1, 0, 0]] = top_left[1];
+        features.into_dyn()
+    }
+
+    #[test]
+    fn pool_to_grid() {
+        let big = Array3::<f32>::ones([3, 20, 10]).into_dyn();
+        let embedding = Embedding::from_output(&ModelOutput::Float(big)).unwrap();
+        assert_eq!(embedding.cells.dim(), (GRID, GRID, 3));
+        let flat = Array1::<f32>::from(vec![3.0, 4.0]).insert_axis(Axis(0)).into_dyn();
+        let embedding = Embedding::from_output(&ModelOutput::Float(flat)).unwrap();
+        assert_eq!(embedding.cells.as_slice().unwrap(), [0.6, 0.8]);
+    }
+
+    #[test]
+    fn find_similar_regions() {
+        let index = EmbeddingIndex::default();
+        index.push(&frame(1, spatial([0.0, 1.0])));
+        index.push(&frame(2, spatial([1.0, 0.0])));
+        index.push(&frame(3, Array3::<f32>::from_elem([2, 2, 2], 0.5).into_dyn()));
+        assert_eq!(index.len(), 3);
+        // the top left of frame 1 is unlike any location of frame 2
+        let found = index.search(1, [0.0, 0.0, 0.4, 0.4], 2);
+        assert_eq!(found.iter().map(|m| m.frame_id).collect::<Vec<_>>(), [3, 2]);
+        assert!(found[0].similarity > 0.7 && found[1].similarity < 0.01);
+        assert!(index.search(9, [0.0, 0.0, 1.0, 1.0], 2).is_empty());
+
+        let index = Arc::new(index);
+        let (tx, rx) = std::sync::mpsc::channel();
+        index.search_in_background(1, [0.0, 0.0, 0.4, 0.4], 2, move || tx.send(()).unwrap());
+        rx.recv().unwrap();
+        assert_eq!(index.take_found(), Some(found));
+        assert_eq!(index.take_found(), None);
+    }
+
+    #[test]
+    fn cap_by_bytes() {
+        let index = EmbeddingIndex::default();
+        // 64 cells of 4096 features make 1 MiB
+        let big = || Array3::<f32>::ones([4096, GRID, GRID]).into_dyn();
+        for id in 0..(INDEX_BYTES >> 20) as u64 + 2 {
+            index.push(&frame(id, big()));
+        }
+        assert_eq!(index.len(), INDEX_BYTES >> 20);
+        assert_eq!(index.frames.lock().unwrap().bytes, INDEX_BYTES);
+    }
+}
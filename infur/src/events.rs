@@ -0,0 +1,125 @@
+use std::sync::{Condvar, Mutex, PoisonError};
+
+use thiserror::Error;
+
+use crate::app::{AppCmdError, AppInfo, AppProcError, GUIFrame};
+
+/// What processing reports to the GUI, in the order it happened
+///
+/// Frames themselves are handed over by a `FrameSlot`, such that only the latest is kept.
+pub(crate) enum ProcEvent {
+    /// A frame was processed and put into the slot
+    FrameReady,
+    /// State after commands changed it
+    Info(AppInfo),
+    /// Processing goes on despite an issue
+    Progress(ProcIssue),
+    /// Processing gave up and waits to be restarted
+    FatalError(String),
+    /// Stats refreshed while playing, dropped if the GUI lags behind
+    Metrics(AppInfo),
+}
+
+/// Issue that doesn't stop processing
+#[derive(Error, Debug)]
+pub(crate) enum ProcIssue {
+    #[error(transparent)]
+    Cmd(#[from] AppCmdError),
+    #[error(transparent)]
+    Frame(#[from] AppProcError),
+    #[error("processing restarted after: {0}")]
+    Restarted(String),
+}
+
+#[derive(Default)]
+struct SlotState {
+    frame: Option<GUIFrame>,
+    closed: bool,
+}
+
+/// Latest processed frame until the GUI takes it
+#[derive(Default)]
+pub(crate) struct FrameSlot {
+    state: Mutex<SlotState>,
+    taken: Condvar,
+}
+
+impl FrameSlot {
+    /// Put a frame, replacing one not taken yet unless to `wait` until it was
+    ///
+    /// False if the slot was closed.
+    pub(crate) fn put(&self, frame: GUIFrame, wait: bool) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        while wait && state.frame.is_some() && !state.closed {
+            state = self.taken.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+        if state.closed {
+            return false;
+        }
+        state.frame = Some(frame);
+        true
+    }
+
+    pub(crate) fn take(&self) -> Option<GUIFrame> {
+        let frame = self.state.lock().unwrap_or_else(PoisonError::into_inner).frame.take();
+        self.taken.notify_all();
+        frame
+    }
+
+    /// Stop accepting frames and release anyone waiting to put one
+    pub(crate) fn close(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.closed = true;
+        state.frame = None;
+        self.taken.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, thread};
+
+    use image_ext::BgrImage;
+
+    use super::*;
+
+    fn frame(id: u64) -> GUIFrame {
+        GUIFrame::empty(id, Arc::new(BgrImage::new(2, 2)))
+    }
+
+    #[test]
+    fn keep_latest() {
+        let slot = FrameSlot::default();
+        assert!(slot.put(frame(1), false));
+        assert!(slot.put(frame(2), false));
+        assert_eq!(slot.take().map(|f| f.id), Some(2));
+        assert!(slot.take().is_none());
+    }
+
+    #[test]
+    fn wait_until_taken() {
+        let slot = Arc::new(FrameSlot::default());
+        assert!(slot.put(frame(1), true));
+        let producer = {
+            let slot = slot.clone();
+            thread::spawn(move || slot.put(frame(2), true))
+        };
+        // not replaced while waiting
+        assert_eq!(slot.take().map(|f| f.id), Some(1));
+        assert!(producer.join().unwrap());
+        assert_eq!(slot.take().map(|f| f.id), Some(2));
+    }
+
+    #[test]
+    fn release_on_close() {
+        let slot = Arc::new(FrameSlot::default());
+        assert!(slot.put(frame(1), true));
+        let producer = {
+            let slot = slot.clone();
+            thread::spawn(move || slot.put(frame(2), true))
+        };
+        slot.close();
+        assert!(!producer.join().unwrap());
+        assert!(slot.take().is_none());
+    }
+}
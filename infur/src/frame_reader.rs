@@ -0,0 +1,305 @@
+use std::{
+    mem,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use ff_video::{AbortHandle, FrameUpdate, ProgressHandle, Stream, VideoProcError, VideoResult};
+use image_ext::{BgrImage, ImageBuffer};
+use tracing::warn;
+
+use crate::video_source::VideoSource;
+
+/// Buffers and flags shared with the reading thread
+#[derive(Default)]
+struct Slots {
+    /// Most recently decoded frame that wasn't taken yet, and when it was decoded
+    latest: Option<(u64, Instant, BgrImage)>,
+    /// Buffer handed back to decode into
+    spare: Option<BgrImage>,
+    /// Output stream if it changed since the latest frame was taken
+    output: Option<Stream>,
+    /// Why reading stopped
+    error: Option<VideoProcError>,
+    ended: bool,
+    paused: bool,
+    /// Keep reading while paused to not fall behind a live source
+    live: bool,
+    /// Overwrite frames not taken yet instead of waiting for them to be taken
+    skip_stale: bool,
+    /// Failed reads in a row to skip instead of ending, if the source resyncs
+    max_skips: u32,
+    resyncs: bool,
+    closing: bool,
+}
+
+impl Slots {
+    fn may_read(&self) -> bool {
+        if self.paused {
+            // only the latest frame is kept until unpaused
+            self.live
+        } else {
+            self.skip_stale || self.latest.is_none()
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    slots: Mutex<Slots>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn wait_while(&self, condition: impl FnMut(&mut Slots) -> bool) -> MutexGuard<'_, Slots> {
+        self.changed.wait_while(self.slots.lock().unwrap(), condition).unwrap()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Slots)) {
+        f(&mut self.slots.lock().unwrap());
+        self.changed.notify_all();
+    }
+}
+
+fn empty_image(stream: &Stream) -> BgrImage {
+    ImageBuffer::new(stream.width, stream.height)
+}
+
+/// Decodes a video on its own thread ahead of processing
+///
+/// Frames are passed between three buffers: one being decoded into, the latest decoded one
+/// and the one being processed. When skipping stale frames, a slow consumer always gets the
+/// most recent frame instead of letting the video pipe back up.
+pub(crate) struct FrameReader {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<Box<dyn VideoSource>>>,
+    abort: Option<AbortHandle>,
+    progress: Option<ProgressHandle>,
+    pub(crate) video_output: Stream,
+    pub(crate) video_input: Option<(String, Stream)>,
+    /// Of the source's backend
+    pub(crate) version: Option<String>,
+    /// Failed frames can be skipped, otherwise the source must be reopened
+    pub(crate) resyncs: bool,
+    /// When the frame last taken was decoded
+    decoded_at: Option<Instant>,
+}
+
+impl FrameReader {
+    /// Start reading a video, `live` ones are read while paused as well
+    pub(crate) fn spawn(
+        vid: Box<dyn VideoSource>,
+        paused: bool,
+        skip_stale: bool,
+        live: bool,
+    ) -> VideoResult<Self> {
+        let resyncs = vid.resyncs();
+        let shared = Arc::new(Shared::default());
+        {
+            let mut slots = shared.slots.lock().unwrap();
+            slots.paused = paused;
+            slots.skip_stale = skip_stale;
+            slots.live = live;
+            slots.resyncs = resyncs;
+        }
+        let abort = vid.abort_handle();
+        let progress = vid.progress_handle();
+        let info = vid.info();
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("VideoReader".to_string())
+            .spawn(move || read_ahead(vid, &thread_shared))
+            .map_err(|source| VideoProcError::IO {
+                msg: "couldn't spawn video reader thread".to_string(),
+                source,
+            })?;
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+            abort,
+            progress,
+            video_output: info.output,
+            video_input: info.input,
+            version: info.version,
+            resyncs,
+            decoded_at: None,
+        })
+    }
+
+    pub(crate) fn abort_handle(&self) -> Option<AbortHandle> {
+        self.abort.clone()
+    }
+
+    /// Latest decoding progress reported by the source
+    pub(crate) fn progress(&self) -> Option<FrameUpdate> {
+        self.progress.as_ref()?.latest()
+    }
+
+    /// When the frame last taken was decoded, as late as it got into processing
+    pub(crate) fn decoded_at(&self) -> Option<Instant> {
+        self.decoded_at
+    }
+
+    pub(crate) fn empty_image(&self) -> BgrImage {
+        empty_image(&self.video_output)
+    }
+
+    /// Stop decoding until unpaused, a frame that was already decoded is kept
+    ///
+    /// Live videos are still decoded so that unpausing continues with a recent frame
+    /// instead of a backlog of stale ones.
+    pub(crate) fn pause(&self, paused: bool) {
+        self.shared.update(|s| s.paused = paused);
+    }
+
+    pub(crate) fn skip_stale(&self, skip_stale: bool) {
+        self.shared.update(|s| s.skip_stale = skip_stale);
+    }
+
+    /// Skip up to this many failed reads in a row instead of ending, if the source resyncs
+    pub(crate) fn max_skips(&self, max_skips: u32) {
+        self.shared.update(|s| s.max_skips = max_skips);
+    }
+
+    /// Swap the next frame into `image` and return its frame id, blocks until decoded
+    ///
+    /// Frames are of the size of `video_output` as of the frame taken.
+    pub(crate) fn read_frame(&mut self, image: &mut BgrImage) -> VideoResult<u64> {
+        let mut slots = self.shared.wait_while(|s| s.latest.is_none() && !s.ended);
+        let frame = slots.latest.take();
+        match frame {
+            Some((id, decoded_at, latest)) => {
+                if let Some(output) = slots.output.take() {
+                    self.video_output = output;
+                }
+                self.decoded_at = Some(decoded_at);
+                slots.spare = Some(mem::replace(image, latest));
+                drop(slots);
+                self.shared.changed.notify_all();
+                Ok(id)
+            }
+            None => Err(slots
+                .error
+                .take()
+                .unwrap_or_else(|| VideoProcError::Other("video reader stopped".to_string()))),
+        }
+    }
+
+    /// Stop reading after the current frame and close the video gracefully
+    pub(crate) fn close(mut self) -> VideoResult<()> {
+        self.shared.update(|s| s.closing = true);
+        let vid = match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(vid)) => vid,
+            _ => return Err(VideoProcError::Other("error joining video reader".to_string())),
+        };
+        vid.close()
+    }
+}
+
+impl Drop for FrameReader {
+    fn drop(&mut self) {
+        // the thread drops the video after its current read
+        self.shared.update(|s| s.closing = true);
+    }
+}
+
+/// Whether reading failed because the video ended
+fn is_end(e: &VideoProcError) -> bool {
+    matches!(e, VideoProcError::FinishedNormally { .. })
+}
+
+/// Decode frames into the latest slot until closing or the video ends
+fn read_ahead(mut vid: Box<dyn VideoSource>, shared: &Shared) -> Box<dyn VideoSource> {
+    let mut output = vid.info().output;
+    let mut image = empty_image(&output);
+    let mut skipped = 0;
+    loop {
+        let closing = shared.wait_while(|s| !s.closing && !s.may_read()).closing;
+        if closing {
+            break;
+        }
+        let read = vid.read_frame(&mut image);
+        let mut slots = shared.slots.lock().unwrap();
+        match read {
+            Ok(id) => {
+                let current = vid.info().output;
+                if current != output {
+                    output = current;
+                    slots.output = Some(output.clone());
+                }
+                // recycle the stale or a spare buffer if it fits
+                let next = match slots.latest.replace((id, Instant::now(), image)) {
+                    Some((_, _, stale)) => Some(stale),
+                    None => slots.spare.take(),
+                };
+                image = next
+                    .filter(|i| i.dimensions() == (output.width, output.height))
+                    .unwrap_or_else(|| empty_image(&output));
+                skipped = 0;
+            }
+            Err(e) if !is_end(&e) && slots.resyncs && skipped < slots.max_skips => {
+                skipped += 1;
+                warn!("skipping frame ({}/{}): {}", skipped, slots.max_skips, e);
+            }
+            Err(e) => {
+                slots.error = Some(e);
+                slots.ended = true;
+            }
+        }
+        let ended = slots.ended;
+        drop(slots);
+        shared.changed.notify_all();
+        if ended {
+            break;
+        }
+    }
+    vid
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processing::Clip;
+    use ff_video::FFMpegDecoder;
+    use infur_test_gen::long_small_video;
+    use std::time::Duration;
+
+    fn spawn(paused: bool, skip_stale: bool, live: bool) -> FrameReader {
+        let input = [long_small_video().to_string_lossy().to_string()];
+        let vid = FFMpegDecoder::open(&input, Clip::default()).unwrap();
+        FrameReader::spawn(Box::new(vid), paused, skip_stale, live).unwrap()
+    }
+
+    #[test]
+    fn every_frame() {
+        let mut reader = spawn(false, false, false);
+        let mut img = reader.empty_image();
+        let ids = (0..5).map(|_| reader.read_frame(&mut img).unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
+        assert_eq!(img.dimensions(), (640, 480));
+        reader.close().unwrap();
+    }
+
+    #[test]
+    fn skip_stale_frames() {
+        let mut reader = spawn(false, true, false);
+        let mut img = reader.empty_image();
+        let first = reader.read_frame(&mut img).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        let latest = reader.read_frame(&mut img).unwrap();
+        assert!(latest > first + 1, "{latest} should skip frames after {first}");
+        reader.close().unwrap();
+    }
+
+    #[test]
+    fn paused_live_keeps_up() {
+        let mut reader = spawn(true, false, true);
+        thread::sleep(Duration::from_millis(200));
+        reader.pause(false);
+        let mut img = reader.empty_image();
+        let first = reader.read_frame(&mut img).unwrap();
+        assert!(first > 1, "frame {first} should be recent");
+        reader.close().unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+//! Regression tests of the full pipeline against stored per-frame results
+//!
+//! Synthetic videos are segmented by the FCN fixture and the pixels per class of each frame are
+//! compared to `infur/golden/NAME.json` within a tolerance. `INFUR_UPDATE_GOLDEN=1` records them
+//! instead, e.g. after intended changes.
+use std::{fs, path::PathBuf};
+
+use ff_video::VideoProcError;
+use infur_test_gen::{fcn_resnet50_12_int8_onnx, moving_objects_video, synth_video};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::{AppCmd, AppProcError, ProcessingApp, Processor},
+    predict_onnx::ModelCmd,
+    processing::{Clip, VideoCmd},
+};
+
+/// Share of a frame's pixels that may be classified differently, e.g. on other CPUs
+const TOLERANCE: f64 = 0.02;
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct GoldenFrame {
+    id: u64,
+    class_areas: Vec<u64>,
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("golden").join(format!("{name}.json"))
+}
+
+/// Process every frame of a video as the headless mode would
+fn run_pipeline(input: PathBuf, scale: f32, clip: Clip) -> Vec<GoldenFrame> {
+    let mut app = ProcessingApp::default();
+    app.control(AppCmd::Deterministic(true)).unwrap();
+    app.control(AppCmd::Scale(scale)).unwrap();
+    let model = fcn_resnet50_12_int8_onnx().to_string_lossy().to_string();
+    app.control(AppCmd::Model(ModelCmd::Load(model))).unwrap();
+    app.control(AppCmd::Video(VideoCmd::Clip(clip))).unwrap();
+    let input = input.to_string_lossy().to_string();
+    app.control(AppCmd::Video(VideoCmd::Play(vec![input]))).unwrap();
+    let mut frames = vec![];
+    while app.is_dirty() {
+        match app.generate() {
+            Ok(Some(frame)) => {
+                frames.push(GoldenFrame { id: frame.id, class_areas: frame.class_areas })
+            }
+            Ok(None) => {}
+            Err(AppProcError::Video(VideoProcError::FinishedNormally { .. })) => break,
+            Err(e) => panic!("pipeline failed: {e}"),
+        }
+    }
+    frames
+}
+
+/// Why frames differ from golden ones beyond the tolerance, if they do
+fn mismatch(golden: &[GoldenFrame], frames: &[GoldenFrame]) -> Option<String> {
+    if golden.len() != frames.len() {
+        return Some(format!("{} frames instead of {}", frames.len(), golden.len()));
+    }
+    for (expected, frame) in golden.iter().zip(frames) {
+        if expected.id != frame.id || expected.class_areas.len() != frame.class_areas.len() {
+            return Some(format!("frame {} doesn't match frame {}", frame.id, expected.id));
+        }
+        let pixels = expected.class_areas.iter().sum::<u64>().max(1);
+        let areas = expected.class_areas.iter().zip(&frame.class_areas);
+        // each misclassified pixel is missing in one class and too many in another
+        let differing = areas.map(|(a, b)| a.abs_diff(*b)).sum::<u64>() / 2;
+        if differing as f64 > TOLERANCE * pixels as f64 {
+            return Some(format!(
+                "frame {}: {} of {} pixels classified differently, {:?} instead of {:?}",
+                frame.id, differing, pixels, frame.class_areas, expected.class_areas
+            ));
+        }
+    }
+    None
+}
+
+/// Compare to the golden results of `name`, or record them if asked to
+fn check_golden(name: &str, frames: Vec<GoldenFrame>) {
+    assert!(!frames.is_empty(), "{name}: no frames processed");
+    assert!(frames.iter().all(|f| !f.class_areas.is_empty()), "{name}: frames without results");
+    let path = golden_path(name);
+    let update = std::env::var("INFUR_UPDATE_GOLDEN").as_deref() == Ok("1");
+    if update {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, serde_json::to_string_pretty(&frames).unwrap()).unwrap();
+        return;
+    }
+    assert!(path.is_file(), "{name}: no {path:?}, record it with INFUR_UPDATE_GOLDEN=1");
+    let golden: Vec<GoldenFrame> = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+    if let Some(mismatch) = mismatch(&golden, &frames) {
+        panic!("{name} regressed against {path:?}: {mismatch}");
+    }
+}
+
+#[test]
+fn within_tolerance() {
+    let golden = [GoldenFrame { id: 1, class_areas: vec![90, 10] }];
+    let frame = |class_areas| [GoldenFrame { id: 1, class_areas }];
+    assert_eq!(mismatch(&golden, &frame(vec![89, 11])), None);
+    assert!(mismatch(&golden, &frame(vec![80, 20])).is_some());
+    assert!(mismatch(&golden, &frame(vec![90, 10, 0])).is_some());
+    assert!(mismatch(&golden, &[]).is_some());
+}
+
+#[test]
+fn golden_test_pattern() {
+    let video = synth_video(160, 120, 5, 2).unwrap();
+    check_golden("test_pattern_160x120", run_pipeline(video, 1.0, Clip::default()));
+}
+
+#[test]
+fn golden_moving_objects_scaled() {
+    let (video, _) = moving_objects_video().unwrap();
+    let clip = Clip { start: None, end: Some(2.0) };
+    check_golden("moving_objects_half", run_pipeline(video, 0.5, clip));
+}
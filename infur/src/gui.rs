@@ -1,43 +1,98 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
 use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use crate::app::{AppCmd, AppCmdError, AppInfo, AppProcError, GUIFrame};
-use crate::predict_onnx::ModelCmd;
-use crate::processing::VideoCmd;
+use crate::app::{
+    AppCmd, AppCmdError, AppInfo, AppProcError, ErrorPolicy, FramePolicy, GUIFrame, LagPolicy,
+};
+use crate::bgr_texture;
+use crate::calibration::Calibration;
+use crate::class_map::{label, ClassMap};
+use crate::clips::{ClipOpts, FrameHistory, HistoryOpts};
+use crate::config::{self, AppState, STATE_VERSION};
+use crate::decode_predict::{color_code, AlphaCurve, Histogram, OverlayCmd, CONFIDENCE_BINS};
+use crate::embeddings::{EmbeddingIndex, Match};
+use crate::events::{FrameSlot, ProcEvent, ProcIssue};
+use crate::predict_onnx::{
+    ColorSeq, DimSeq, ExecutionProvider, InputDType, InputFormat, InputNorm, ModelCmd,
+};
+use crate::preview::{BlendMode, OverlayStyle};
+use crate::processing::{ArchiveOpts, Clip, ReadPolicy, VideoCmd};
+use crate::reload::{self, ConfigWatch};
+#[cfg(feature = "sqlite")]
+use crate::results_db::{Direction, Hit, ResultsDb};
+use crate::routing::{default_decoder, OutputDecoder, OutputRoutes};
+use crate::rules::{Rule, RuleEvent, Rules};
+#[cfg(feature = "gpu_colorize")]
+use crate::seg_texture::{self, SegStyle};
+use crate::shm::ShmOpts;
+#[cfg(feature = "sqlite")]
+use crate::sink::SinkError;
+use crate::sink::SinkSpec;
+use crate::zones::{Zone, ZoneStats};
 use eframe::{
-    egui::{self, CentralPanel, RichText, SidePanel, Slider, TextureFilter, TextureHandle},
-    epaint::FontId,
+    egui::{
+        self,
+        plot::{Bar, BarChart, Plot},
+        Align2, CentralPanel, ComboBox, RichText, SidePanel, Slider, TextEdit, TextureFilter,
+        TextureHandle,
+    },
+    epaint::{ColorImage, FontId, Shape, Stroke},
 };
+use image_ext::{to_color_image, BgrImage};
+#[cfg(feature = "gpu_colorize")]
+use ndarray::Array3;
 
-/// Result from processing a frame
-pub(crate) type FrameResult = std::result::Result<GUIFrame, AppProcError>;
-
-/// Result from processing commands
-pub(crate) type CtrlResult = std::result::Result<AppInfo, AppCmdError>;
+/// Model input either as egui texture or raw image painted by GPU
+pub(crate) enum MainTexture {
+    Handle(TextureHandle),
+    Bgr { key: u64, img: Arc<BgrImage> },
+}
 
 /// GUI textures of model in-/output
 pub(crate) struct TextureFrame {
     pub(crate) id: u64,
-    pub(crate) handle: TextureHandle,
-    pub(crate) decoded_handle: Option<TextureHandle>,
+    /// Frames decoded since the video was played
+    pub(crate) decoded: u64,
+    /// Seconds into the video
+    pub(crate) position: f64,
+    pub(crate) timestamp: f64,
+    pub(crate) size: [usize; 2],
+    /// Frame with its overlay blended on, if any
+    pub(crate) main: MainTexture,
+    /// What the model saw, if previewed
+    pub(crate) model_input_handle: Option<TextureHandle>,
+    /// Confidences to colorize on top and their upload key
+    #[cfg(feature = "gpu_colorize")]
+    pub(crate) seg: Option<(u64, Arc<Array3<f32>>)>,
 }
 
 /// Count frames and time between set points
 pub(crate) struct FrameCounter {
     pub(crate) recvd_id: Option<u64>,
     pub(crate) shown_id: u64,
+    pub(crate) uploaded_id: u64,
     pub(crate) since: Instant,
     pub(crate) elapsed_since: Duration,
     pub(crate) shown_since: u64,
+    pub(crate) uploaded_since: u64,
     pub(crate) recvd_since: Option<u64>,
 }
 
 impl FrameCounter {
     // set start of new time strip (call per measurement)
-    pub(crate) fn set_on(&mut self, now: Instant, shown_id: u64, recvd_id: Option<u64>) {
+    pub(crate) fn set_on(
+        &mut self,
+        now: Instant,
+        shown_id: u64,
+        uploaded_id: u64,
+        recvd_id: Option<u64>,
+    ) {
         // deltas
         self.shown_since = shown_id - self.shown_id;
+        self.uploaded_since = uploaded_id - self.uploaded_id;
         self.recvd_since = match (self.recvd_id, recvd_id) {
             (None, _) => None,
             (_, None) => None,
@@ -48,6 +103,7 @@ impl FrameCounter {
         // new 0
         self.recvd_id = recvd_id;
         self.shown_id = shown_id;
+        self.uploaded_id = uploaded_id;
         self.since = now;
     }
 
@@ -71,7 +127,7 @@ impl FrameCounter {
 
     // frames dropped (not shown) or skipped (also not shown)
     pub(crate) fn dropped_since(&self) -> i64 {
-        self.recvd_since.unwrap_or_default() as i64 - self.shown_since as i64
+        self.recvd_since.unwrap_or_default() as i64 - self.uploaded_since as i64
     }
 }
 
@@ -80,132 +136,455 @@ impl Default for FrameCounter {
         Self {
             recvd_id: None,
             shown_id: 0,
+            uploaded_id: 0,
             since: Instant::now(),
             elapsed_since: Duration::ZERO,
             shown_since: 0,
+            uploaded_since: 0,
             recvd_since: None,
         }
     }
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
 pub(crate) struct ProcConfig {
     pub(crate) video_input: Vec<String>,
     pub(crate) scale: f32,
     pub(crate) paused: bool,
+    /// Part of the video to play
+    pub(crate) clip: Clip,
+    /// Failed reads of the video to retry before closing it
+    pub(crate) read_policy: ReadPolicy,
+    /// How scaling and inference recover from failing on frames
+    pub(crate) error_policy: ErrorPolicy,
+    /// How far processing may fall behind live videos before adapting
+    pub(crate) lag_policy: LagPolicy,
+    /// Seconds results of a frame may take, frames over it are counted
+    pub(crate) latency_budget: Option<f64>,
+    /// Where live inputs are copied to while played
+    pub(crate) archive: Option<ArchiveOpts>,
+    /// Where decoded frames are shared with other processes
+    pub(crate) shm: Option<ShmOpts>,
     pub(crate) model_input: String,
+    /// Execution providers to try in order, CPU is the last resort
+    pub(crate) providers: Vec<ExecutionProvider>,
+    /// Show what the model sees of each frame after pre-processing
+    pub(crate) preview_model_input: bool,
+    /// Overrides of guessed image input formats by model path
+    pub(crate) input_formats: BTreeMap<String, InputFormat>,
+    /// Decoders of outputs by model path
+    pub(crate) output_routes: BTreeMap<String, OutputRoutes>,
+    /// How segmentations are shaded by confidence
+    pub(crate) alpha_curve: AlphaCurve,
+    /// Hatch pixels by their runner-up class if within this margin of the best
+    pub(crate) hatch_margin: Option<f32>,
+    /// Merge and name classes of the model
+    pub(crate) class_map: ClassMap,
+    /// Colorize segmentations of confidences by a shader if built with `gpu_colorize`
+    pub(crate) gpu_colorize: bool,
+    /// How model outputs are blended onto frames
+    pub(crate) overlay_style: OverlayStyle,
+    pub(crate) frame_policy: FramePolicy,
+    /// Where results are written to besides the GUI
+    pub(crate) sinks: Vec<SinkSpec>,
+    /// Rules evaluated on results
+    pub(crate) rules: Vec<Rule>,
+    /// Recent frames kept for clips
+    pub(crate) history: HistoryOpts,
+    /// Areas to tell results within
+    pub(crate) zones: Vec<Zone>,
+    /// Of video frames to report zones and boxes in world coordinates
+    pub(crate) calibration: Option<Calibration>,
 }
 
 impl Default for ProcConfig {
     fn default() -> Self {
-        Self { video_input: vec![], scale: 0.5, paused: false, model_input: String::default() }
+        Self {
+            video_input: vec![],
+            scale: 0.5,
+            paused: false,
+            clip: Clip::default(),
+            read_policy: ReadPolicy::default(),
+            error_policy: ErrorPolicy::default(),
+            lag_policy: LagPolicy::default(),
+            latency_budget: None,
+            archive: None,
+            shm: None,
+            model_input: String::default(),
+            providers: vec![ExecutionProvider::Cpu],
+            preview_model_input: false,
+            input_formats: BTreeMap::new(),
+            output_routes: BTreeMap::new(),
+            alpha_curve: AlphaCurve::default(),
+            hatch_margin: None,
+            class_map: ClassMap::default(),
+            gpu_colorize: false,
+            overlay_style: OverlayStyle::default(),
+            frame_policy: FramePolicy::default(),
+            sinks: vec![],
+            rules: vec![],
+            history: HistoryOpts::default(),
+            zones: vec![],
+            calibration: None,
+        }
     }
 }
 
 #[derive(Default, Clone)]
 pub(crate) struct ProcStatus {
     pub(crate) video: String,
+    pub(crate) stream: String,
+    /// Frame stats of ffmpeg
+    pub(crate) decoder: String,
+    /// Failures of stages and their recoveries
+    pub(crate) errors: String,
+    /// Lag behind a live video and adaptations to it
+    pub(crate) keep_up: String,
+    /// Of results against the budget
+    pub(crate) latency: String,
     pub(crate) scale: String,
     pub(crate) model: String,
+    /// Image input format of the loaded model
+    pub(crate) input_format: Option<InputFormat>,
+    /// Output names of the loaded model
+    pub(crate) output_names: Vec<String>,
+    /// Outcome of exporting a clip
+    pub(crate) clip: String,
+    pub(crate) config: String,
 }
 
-pub(crate) struct InFur {
+/// Connection to one processing pipeline
+pub(crate) struct PipelineChannels {
+    pub(crate) ctrl_tx: Sender<AppCmd>,
+    pub(crate) event_rx: Receiver<ProcEvent>,
+    /// Latest processed frame
+    pub(crate) frames: Arc<FrameSlot>,
+    /// Recent frames to export clips from
+    pub(crate) history: Arc<FrameHistory>,
+    /// Embeddings of recent frames to search
+    pub(crate) embeddings: Arc<EmbeddingIndex>,
+}
+
+/// Combo box to override a guessed setting, `None` keeps the guess
+fn override_ui<T: Copy + PartialEq + std::fmt::Debug>(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut Option<T>,
+    guess: Option<T>,
+    options: &[T],
+) -> bool {
+    let prev = *value;
+    let selected = match (*value, guess) {
+        (Some(v), _) => format!("{:?}", v),
+        (None, Some(g)) => format!("{:?} (guessed)", g),
+        (None, None) => "guess".to_string(),
+    };
+    ComboBox::from_label(label).selected_text(selected).show_ui(ui, |ui| {
+        ui.selectable_value(value, None, "guess");
+        for &option in options {
+            ui.selectable_value(value, Some(option), format!("{:?}", option));
+        }
+    });
+    *value != prev
+}
+
+/// Seconds from user input, empty for none or the previous value if invalid
+fn parse_secs(input: &str, prev: Option<f64>) -> Option<f64> {
+    match input.trim() {
+        "" => None,
+        t => t.parse::<f64>().ok().filter(|t| *t >= 0.0).or(prev),
+    }
+}
+
+/// Controls and latest results of one pipeline
+pub(crate) struct StreamView {
+    pub(crate) index: usize,
     pub(crate) ctrl_tx: Sender<AppCmd>,
-    pub(crate) frame_rx: Receiver<FrameResult>,
+    pub(crate) event_rx: Receiver<ProcEvent>,
+    pub(crate) frames: Arc<FrameSlot>,
+    /// Another frame became ready after one was shown in the same update
+    pub(crate) frame_pending: bool,
+    /// Failure of the last frame, if it failed
     pub(crate) proc_result: Option<AppProcError>,
-    pub(crate) ctrl_rx: Receiver<CtrlResult>,
     pub(crate) main_texture: Option<TextureFrame>,
     pub(crate) config: ProcConfig,
-    pub(crate) closing: bool,
-    pub(crate) allow_closing: bool,
     pub(crate) error_history: VecDeque<String>,
     pub(crate) counter: FrameCounter,
     pub(crate) show_count: u64,
+    pub(crate) upload_count: u64,
+    /// Seconds from decoding the last frame to showing it
+    pub(crate) shown_latency: Option<f64>,
     pub(crate) proc_status: ProcStatus,
+    /// Processing hung up
+    pub(crate) disconnected: bool,
+    /// Processing gave up and waits to be restarted
+    pub(crate) fatal: Option<String>,
+    /// Start and end of the clip being edited
+    pub(crate) clip_input: [String; 2],
+    pub(crate) history: Arc<FrameHistory>,
+    pub(crate) embeddings: Arc<EmbeddingIndex>,
+    /// Frames most similar to the last clicked region, searched in the background
+    pub(crate) similar: Vec<Match>,
+    /// Maximum confidences per class of the last frame
+    pub(crate) confidences: Vec<Histogram>,
+    /// Only class drawn while its number key is held
+    pub(crate) isolated: Option<usize>,
+    /// Results within each zone of the last frame
+    pub(crate) zone_stats: Vec<ZoneStats>,
+    /// Zone being drawn by clicking its corners
+    pub(crate) zone_draft: Option<Zone>,
+    /// Segmentations can be colorized by a shader
+    #[cfg(feature = "gpu_colorize")]
+    pub(crate) seg_painting: bool,
 }
 
-impl InFur {
-    pub(crate) fn new(
-        config: ProcConfig,
-        ctrl_tx: Sender<AppCmd>,
-        frame_rx: Receiver<FrameResult>,
-        ctrl_rx: Receiver<CtrlResult>,
-    ) -> Self {
-        let mut app = Self {
-            ctrl_tx,
-            frame_rx,
-            ctrl_rx,
+impl StreamView {
+    pub(crate) fn new(index: usize, config: ProcConfig, channels: PipelineChannels) -> Self {
+        let clip_input = [config.clip.start, config.clip.end]
+            .map(|t| t.map(|t| t.to_string()).unwrap_or_default());
+        let mut view = Self {
+            index,
+            ctrl_tx: channels.ctrl_tx,
+            event_rx: channels.event_rx,
+            frames: channels.frames,
+            frame_pending: false,
             proc_result: None,
             main_texture: None,
             config,
-            closing: false,
-            allow_closing: false,
             error_history: VecDeque::with_capacity(3),
             counter: FrameCounter::default(),
             show_count: 0,
+            upload_count: 0,
+            shown_latency: None,
             proc_status: ProcStatus::default(),
+            disconnected: false,
+            fatal: None,
+            clip_input,
+            history: channels.history,
+            embeddings: channels.embeddings,
+            similar: vec![],
+            confidences: vec![],
+            isolated: None,
+            zone_stats: vec![],
+            zone_draft: None,
+            #[cfg(feature = "gpu_colorize")]
+            seg_painting: false,
         };
+        view.history.set_opts(view.config.history);
         // send initial config
-        app.send(AppCmd::Scale(app.config.scale));
-        app.send(AppCmd::Video(VideoCmd::Play(
-            app.config.video_input.iter().cloned().filter(|s| !s.is_empty()).collect(),
+        view.send(AppCmd::Scale(view.config.scale));
+        view.send(AppCmd::FramePolicy(view.config.frame_policy));
+        view.send(AppCmd::ReadPolicy(view.config.read_policy));
+        view.send(AppCmd::ErrorPolicy(view.config.error_policy));
+        view.send(AppCmd::LagPolicy(view.config.lag_policy));
+        view.send(AppCmd::LatencyBudget(view.config.latency_budget));
+        view.send(AppCmd::Archive(view.config.archive.clone()));
+        view.send(AppCmd::Share(view.shm_opts()));
+        view.send(AppCmd::Overlay(OverlayCmd::AlphaCurve(view.config.alpha_curve)));
+        view.send(AppCmd::Overlay(OverlayCmd::HatchMargin(view.config.hatch_margin)));
+        view.send(AppCmd::Overlay(OverlayCmd::ClassMap(view.config.class_map.clone())));
+        view.send(AppCmd::OverlayStyle(view.config.overlay_style));
+        view.send(AppCmd::Zones(view.config.zones.clone()));
+        view.send(AppCmd::Calibration(view.config.calibration));
+        view.send(AppCmd::Video(VideoCmd::Clip(view.config.clip)));
+        view.send(AppCmd::Video(VideoCmd::Play(
+            view.config.video_input.iter().cloned().filter(|s| !s.is_empty()).collect(),
         )));
-        app.send(AppCmd::Video(VideoCmd::Pause(app.config.paused)));
-        app.send(AppCmd::Model(ModelCmd::Load(app.config.model_input.clone())));
-        app
+        view.send(AppCmd::Video(VideoCmd::Pause(view.config.paused)));
+        view.send(AppCmd::Model(ModelCmd::Providers(view.config.providers.clone())));
+        view.send(AppCmd::Model(ModelCmd::PreviewInput(view.config.preview_model_input)));
+        view.load_model();
+        view
+    }
+
+    /// (Re-)load the configured model with its input format and output routes
+    fn load_model(&mut self) {
+        let format = self.config.input_formats.get(&self.config.model_input).copied();
+        let routes = self.config.output_routes.get(&self.config.model_input).cloned();
+        self.send(AppCmd::Route(routes.unwrap_or_default()));
+        self.send(AppCmd::Model(ModelCmd::InputFormat(format.unwrap_or_default())));
+        self.send(AppCmd::Model(ModelCmd::Load(self.config.model_input.clone())));
+    }
+
+    /// Take over changes of a config file
+    fn reload(&mut self, old: &ProcConfig, new: &ProcConfig) {
+        for cmd in reload::reload(&mut self.config, old, new) {
+            let cmd = match cmd {
+                AppCmd::Share(_) => AppCmd::Share(self.shm_opts()),
+                cmd => cmd,
+            };
+            self.send(cmd);
+        }
+        self.history.set_opts(self.config.history);
+        self.clip_input = [self.config.clip.start, self.config.clip.end]
+            .map(|t| t.map(|t| t.to_string()).unwrap_or_default());
+    }
+
+    /// Where this pipeline shares its frames, apart from those of others
+    fn shm_opts(&self) -> Option<ShmOpts> {
+        self.config.shm.as_ref().map(|opts| opts.for_pipeline(self.index))
     }
 
     pub(crate) fn send(&mut self, cmd: AppCmd) {
         self.error_history.truncate(2);
         _ = self.ctrl_tx.send(cmd).map_err(|e| self.error_history.push_front(e.to_string()));
     }
-}
 
-impl eframe::App for InFur {
-    fn update(&mut self, ctx: &eframe::egui::Context, frame_: &mut eframe::Frame) {
-        // update texture from new frame or close if disconnected
-        // this limits UI updates if no frames are sent to ca. 30fps
-        let mut new_frame = false;
-        match self.frame_rx.recv_timeout(Duration::from_millis(30)) {
-            Ok(Ok(frame)) => {
-                let decoded_handle = frame.decoded_buffer.map(|decoded_img| {
-                    ctx.load_texture("decoded_texture", decoded_img, TextureFilter::Linear)
-                });
+    /// Tab title after the video input's file name
+    fn title(&self) -> String {
+        let input = self.config.video_input.iter().rev().find(|i| !i.is_empty());
+        match input.and_then(|i| std::path::Path::new(i).file_name()) {
+            Some(name) => format!("{}: {}", self.index + 1, name.to_string_lossy()),
+            None => format!("Stream {}", self.index + 1),
+        }
+    }
 
-                let tex = TextureFrame {
-                    id: frame.id,
-                    handle: ctx.load_texture("main_texture", frame.buffer, TextureFilter::Linear),
-                    decoded_handle,
-                };
-                new_frame = true;
-                self.main_texture = Some(tex);
-                self.proc_result = None;
-            }
-            Ok(Err(e)) => {
-                self.proc_result = Some(e);
-            }
-            Err(RecvTimeoutError::Timeout) => {}
-            Err(RecvTimeoutError::Disconnected) => {
-                self.allow_closing = true;
-                frame_.close();
+    /// Take events and the latest frame from processing, upload textures only if visible
+    ///
+    /// At most one frame is shown per update, later events wait for the next one.
+    fn receive(&mut self, ctx: &egui::Context, paint_bgr: bool, visible: bool, wait: Duration) {
+        let wait = if self.frame_pending { Duration::ZERO } else { wait };
+        let mut shown = false;
+        let mut event = self.event_rx.recv_timeout(wait);
+        loop {
+            match event {
+                Ok(ProcEvent::FrameReady) if shown => {
+                    self.frame_pending = true;
+                    ctx.request_repaint();
+                    break;
+                }
+                Ok(ProcEvent::FrameReady) => {
+                    // a frame was processed after any failure before
+                    self.proc_result = None;
+                    match self.frames.take() {
+                        Some(frame) => {
+                            self.show_frame(ctx, frame, paint_bgr, visible);
+                            shown = true;
+                        }
+                        None => self.show_frame_status(false),
+                    }
+                }
+                Ok(ProcEvent::Info(info) | ProcEvent::Metrics(info)) => self.show_info(info),
+                Ok(ProcEvent::Progress(ProcIssue::Frame(e))) => {
+                    self.proc_result = Some(e);
+                    self.show_frame_status(false);
+                }
+                Ok(ProcEvent::Progress(ProcIssue::Cmd(e))) => match e {
+                    AppCmdError::Video(e) => self.proc_status.video = e.to_string(),
+                    AppCmdError::Scale(e) => self.proc_status.scale = e.to_string(),
+                    AppCmdError::Model(e) => self.proc_status.model = e.to_string(),
+                },
+                Ok(ProcEvent::Progress(issue @ ProcIssue::Restarted(_))) => {
+                    self.error_history.truncate(2);
+                    self.error_history.push_front(issue.to_string());
+                }
+                Ok(ProcEvent::FatalError(e)) => {
+                    self.fatal = Some(format!("processing gave up after: {}", e))
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    if !self.disconnected {
+                        self.error_history.push_front("lost processing control".to_string());
+                    }
+                    self.disconnected = true;
+                    break;
+                }
             }
+            event = self.event_rx.try_recv().map_err(|e| match e {
+                TryRecvError::Empty => RecvTimeoutError::Timeout,
+                TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+            });
         }
-        if self.closing && !self.allow_closing {
-            self.send(AppCmd::Video(VideoCmd::Stop));
-            self.send(AppCmd::Exit);
+        // its event may have been dropped while lagging behind, or left for this update
+        if !shown {
+            if let Some(frame) = self.frames.take() {
+                self.show_frame(ctx, frame, paint_bgr, visible);
+            }
+            self.frame_pending = false;
         }
 
         // advance and reset counters every second
         self.show_count += 1;
         let now = std::time::Instant::now();
         if self.counter.elapsed(now) > Duration::from_secs(1) {
-            self.counter.set_on(now, self.show_count, self.main_texture.as_ref().map(|t| t.id));
+            self.counter.set_on(
+                now,
+                self.show_count,
+                self.upload_count,
+                // unlike ids, not reset by seeks
+                self.main_texture.as_ref().map(|t| t.decoded),
+            );
+        }
+    }
+
+    /// Keep results of a frame, upload textures only if visible
+    fn show_frame(&mut self, ctx: &egui::Context, frame: GUIFrame, paint_bgr: bool, visible: bool) {
+        // others keep showing the last results
+        if frame.inferred {
+            self.confidences = frame.confidences;
+            self.zone_stats = frame.zones;
         }
+        self.proc_result = None;
+        self.shown_latency = Some(frame.ingested.elapsed().as_secs_f64());
+        if !visible {
+            // keep the id for stats only
+            self.main_texture = self.main_texture.take().map(|tex| TextureFrame {
+                id: frame.id,
+                decoded: frame.decoded,
+                position: frame.position,
+                timestamp: frame.timestamp,
+                ..tex
+            });
+            self.show_frame_status(false);
+            return;
+        }
+        let model_input_handle = frame.model_input.map(|img| {
+            let img = to_color_image(&img);
+            // keep pixels sharp to judge resampling
+            ctx.load_texture("model_input_texture", img, TextureFilter::Nearest)
+        });
 
-        // stringify last frame's statuses
+        self.upload_count += 1;
+        let size = [frame.buffer.width() as usize, frame.buffer.height() as usize];
+        let main = match frame.composited {
+            // egui can only draw over, so other blend modes are mixed by processing
+            Some(rgb) => {
+                let img = ColorImage::from_rgb(size, rgb.as_raw());
+                MainTexture::Handle(ctx.load_texture("main_texture", img, TextureFilter::Linear))
+            }
+            None if paint_bgr => {
+                // unique among streams
+                let key = (self.index as u64) << 48 | self.upload_count;
+                MainTexture::Bgr { key, img: frame.buffer }
+            }
+            None => {
+                let img = to_color_image(&frame.buffer);
+                MainTexture::Handle(ctx.load_texture("main_texture", img, TextureFilter::Linear))
+            }
+        };
+        let tex = TextureFrame {
+            id: frame.id,
+            decoded: frame.decoded,
+            position: frame.position,
+            timestamp: frame.timestamp,
+            size,
+            main,
+            model_input_handle,
+            #[cfg(feature = "gpu_colorize")]
+            seg: frame.seg_confidences.map(|c| ((self.index as u64) << 48 | self.upload_count, c)),
+        };
+        self.main_texture = Some(tex);
+        self.show_frame_status(true);
+    }
+
+    /// Stringify statuses of the last frame or its failure
+    fn show_frame_status(&mut self, new_frame: bool) {
         match (new_frame, &self.main_texture, &self.proc_result) {
             (true, _, Some(AppProcError::Video(e))) => self.proc_status.video = e.to_string(),
-            (true, Some(tex), _) => self.proc_status.video = tex.id.to_string(),
+            (true, Some(tex), _) => {
+                self.proc_status.video = format!("{} at {:.2} s", tex.decoded, tex.position)
+            }
             _ => {}
         };
         match &self.proc_result {
@@ -220,127 +599,915 @@ impl eframe::App for InFur {
             }
             _ => {}
         }
+    }
 
-        // stringify control errors or app infos, may override frame status
-        match self.ctrl_rx.try_recv() {
-            Ok(info) => match info {
-                Ok(info) => {
-                    if let Some(model_info) = info.model_info {
-                        self.proc_status.model = format!(
-                            "Model loaded: {} -> {}",
-                            model_info.input_names.join(","),
-                            model_info.output_names.join(",")
-                        );
-                    }
-                }
-                Err(AppCmdError::Video(e)) => {
-                    self.proc_status.video = e.to_string();
+    /// Reflect the actual state of processing
+    fn show_info(&mut self, info: AppInfo) {
+        self.config.paused = info.paused;
+        self.proc_status.stream = match (&info.video_info, info.scaled_size) {
+            (Some(v), Some([sw, sh])) => format!(
+                "{} {}x{} @ {} fps -> {}x{}",
+                v.codec.as_deref().unwrap_or("?"),
+                v.size[0],
+                v.size[1],
+                v.fps.map_or_else(|| "?".to_string(), |fps| format!("{:.1}", fps)),
+                sw,
+                sh,
+            ),
+            _ => String::default(),
+        };
+        let decoder = info
+            .video_info
+            .as_ref()
+            .and_then(|v| Some((v.decoder.as_ref()?, v.ffmpeg.as_deref().unwrap_or("?"))));
+        self.proc_status.decoder = match decoder {
+            Some((d, ffmpeg)) => format!(
+                "decoded: {} @ {} fps, {}x{}, {} kbit/s\ndup/drop by ffmpeg {}: {}/{}",
+                d.frames,
+                d.fps.map_or_else(|| "?".to_string(), |fps| format!("{:.1}", fps)),
+                d.speed.map_or_else(|| "?".to_string(), |speed| format!("{:.2}", speed)),
+                match d.speed {
+                    Some(speed) if speed < 1.0 => " (behind)",
+                    _ => "",
+                },
+                d.bitrate.map_or_else(|| "?".to_string(), |bitrate| format!("{:.0}", bitrate)),
+                ffmpeg,
+                d.dup,
+                d.drop,
+            ),
+            None => String::default(),
+        };
+        let stages = [("scale", info.errors.scale), ("model", info.errors.model)];
+        self.proc_status.errors = stages
+            .iter()
+            .filter(|(_, s)| s.failures > 0)
+            .map(|(stage, s)| {
+                let disabled = if s.disabled { ", disabled" } else { "" };
+                format!("{stage} failed: {} recovered: {}{disabled}", s.failures, s.recoveries)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let latency = info.latency;
+        self.proc_status.latency = match (latency.last, latency.budget) {
+            (Some(last), Some(budget)) => format!(
+                "latency processed: {:.0} ms (max {:.0})\nover {:.0} ms budget: {}/{} frames",
+                last * 1e3,
+                latency.max * 1e3,
+                budget * 1e3,
+                latency.missed,
+                latency.frames
+            ),
+            (Some(last), None) => {
+                format!("latency processed: {:.0} ms (max {:.0})", last * 1e3, latency.max * 1e3)
+            }
+            (None, _) => String::default(),
+        };
+        let keep_up = info.keep_up;
+        self.proc_status.keep_up = match keep_up.lag {
+            Some(lag) if keep_up.adaptations > 0 => format!(
+                "behind live: {:.1} s\nadapted {} times to infer every {} frame(s) at scale {:.2}",
+                lag, keep_up.adaptations, keep_up.infer_every, keep_up.scale
+            ),
+            Some(lag) => format!("behind live: {:.1} s", lag),
+            None => String::default(),
+        };
+        if let Some(model_info) = info.model_info {
+            self.proc_status.model = format!(
+                "Model loaded: {} -> {}\nrunning on: {:?}",
+                model_info.input_names.join(","),
+                model_info.output_names.join(","),
+                model_info.execution_provider,
+            );
+            self.proc_status.input_format = Some(model_info.input_format);
+            self.proc_status.output_names = model_info.output_names;
+        }
+    }
+
+    /// Controls of this pipeline
+    fn options_ui(&mut self, ui: &mut egui::Ui) {
+        // video input
+        ui.label(RichText::new("Video").font(FontId::proportional(30.0)));
+        // (un-)pause video
+        if ui.checkbox(&mut self.config.paused, "Pause").changed {
+            self.send(AppCmd::Video(VideoCmd::Pause(self.config.paused)))
+        };
+        // frame delivery if GUI lags behind
+        let policy = self.config.frame_policy;
+        ui.radio_value(
+            &mut self.config.frame_policy,
+            FramePolicy::ShowEvery,
+            "show every frame (may lag)",
+        );
+        ui.radio_value(
+            &mut self.config.frame_policy,
+            FramePolicy::ShowLatest,
+            "always show latest (drop)",
+        );
+        if policy != self.config.frame_policy {
+            self.send(AppCmd::FramePolicy(self.config.frame_policy));
+        }
+        // (re-)play video
+        if self.config.video_input.is_empty() {
+            self.config.video_input.push(String::default());
+        }
+        let mut vid_input_changed = false;
+        for inp in self.config.video_input.iter_mut() {
+            let textbox = ui.text_edit_singleline(inp);
+            vid_input_changed = vid_input_changed || textbox.lost_focus();
+        }
+        if vid_input_changed {
+            self.send(AppCmd::Video(VideoCmd::Play(
+                self.config.video_input.iter().cloned().filter(|s| !s.is_empty()).collect(),
+            )));
+        }
+        // play only part of the video
+        let mut clip_changed = false;
+        ui.horizontal(|ui| {
+            for (inp, hint) in self.clip_input.iter_mut().zip(["start (s)", "end (s)"]) {
+                let textbox = TextEdit::singleline(inp).hint_text(hint).desired_width(60.0);
+                clip_changed = clip_changed || ui.add(textbox).lost_focus();
+            }
+        });
+        if clip_changed {
+            let start = parse_secs(&self.clip_input[0], self.config.clip.start);
+            let end = parse_secs(&self.clip_input[1], self.config.clip.end);
+            let clip = Clip { start, end };
+            self.clip_input = [start, end].map(|t| t.map(|t| t.to_string()).unwrap_or_default());
+            if clip != self.config.clip {
+                self.config.clip = clip;
+                self.send(AppCmd::Video(VideoCmd::Clip(clip)));
+            }
+        }
+        ui.label(&self.proc_status.stream);
+        ui.label(&self.proc_status.video);
+
+        ui.label(RichText::new("Inference").font(FontId::proportional(30.0)));
+        let scale = Slider::new(&mut self.config.scale, 0.1f32..=1.0)
+            .step_by(0.01f64)
+            .text("scale")
+            .clamp_to_range(true);
+        let scale_response = ui.add(scale);
+        if scale_response.changed {
+            self.send(AppCmd::Scale(self.config.scale));
+        };
+        if !self.proc_status.scale.is_empty() {
+            ui.label(&self.proc_status.model);
+        }
+
+        // (re-)load model
+        let model_input = ui.text_edit_singleline(&mut self.config.model_input);
+        if model_input.lost_focus() {
+            self.load_model();
+        }
+        // override what's guessed about the model's image input
+        let guess = self.proc_status.input_format.unwrap_or_default();
+        let model = &self.config.model_input;
+        let mut format = self.config.input_formats.get(model).copied().unwrap_or_default();
+        let mut format_changed = false;
+        ui.collapsing("input format", |ui| {
+            let layouts = [DimSeq::NCHW, DimSeq::NHWC];
+            let colors = [ColorSeq::RGB, ColorSeq::BGR];
+            let dtypes = [InputDType::Uint8, InputDType::Float32];
+            let norms = [InputNorm::Unit, InputNorm::ImageNet];
+            format_changed |=
+                override_ui(ui, "layout", &mut format.dim_seq, guess.dim_seq, &layouts);
+            format_changed |=
+                override_ui(ui, "color order", &mut format.color_seq, guess.color_seq, &colors);
+            format_changed |= override_ui(ui, "dtype", &mut format.dtype, guess.dtype, &dtypes);
+            format_changed |=
+                override_ui(ui, "normalization", &mut format.norm, guess.norm, &norms);
+        });
+        let preview = ui.checkbox(&mut self.config.preview_model_input, "show model input");
+        if preview.changed() {
+            self.send(AppCmd::Model(ModelCmd::PreviewInput(self.config.preview_model_input)));
+        }
+        if format_changed {
+            // only persist actual overrides
+            if format == InputFormat::default() {
+                self.config.input_formats.remove(&self.config.model_input);
+            } else {
+                self.config.input_formats.insert(self.config.model_input.clone(), format);
+            }
+            self.send(AppCmd::Model(ModelCmd::InputFormat(format)));
+        }
+        // visualize outputs by name
+        let model = &self.config.model_input;
+        let mut routes = self.config.output_routes.get(model).cloned().unwrap_or_default();
+        let mut routes_changed = false;
+        ui.collapsing("outputs", |ui| {
+            for (i, name) in self.proc_status.output_names.iter().enumerate() {
+                let mut route = routes.get(name).copied();
+                let guess = Some(default_decoder(i));
+                if override_ui(ui, name, &mut route, guess, &OutputDecoder::ALL) {
+                    match route {
+                        Some(route) => routes.insert(name.clone(), route),
+                        None => routes.remove(name),
+                    };
+                    routes_changed = true;
                 }
-                Err(AppCmdError::Scale(e)) => {
-                    self.proc_status.scale = e.to_string();
+            }
+        });
+        if routes_changed {
+            // only persist actual overrides
+            if routes.is_empty() {
+                self.config.output_routes.remove(&self.config.model_input);
+            } else {
+                self.config.output_routes.insert(self.config.model_input.clone(), routes.clone());
+            }
+            self.send(AppCmd::Route(routes));
+        }
+        // shade segmentations by confidence
+        let mut curve = self.config.alpha_curve;
+        ComboBox::from_label("confidence to alpha").selected_text(curve.name()).show_ui(ui, |ui| {
+            for option in AlphaCurve::ALL {
+                let selected = curve.name() == option.name();
+                if ui.selectable_label(selected, option.name()).clicked() && !selected {
+                    curve = option;
                 }
-                Err(AppCmdError::Model(e)) => {
-                    self.proc_status.model = e.to_string();
+            }
+        });
+        match &mut curve {
+            AlphaCurve::Linear => {}
+            AlphaCurve::Gamma(gamma) => {
+                ui.add(Slider::new(gamma, 0.1..=4.0).text("gamma"));
+            }
+            AlphaCurve::Step(threshold) => {
+                ui.add(Slider::new(threshold, 0.0..=1.0).text("threshold"));
+            }
+        }
+        if curve != self.config.alpha_curve {
+            self.config.alpha_curve = curve;
+            self.send(AppCmd::Overlay(OverlayCmd::AlphaCurve(curve)));
+        }
+        // reveal where the model is torn between classes
+        let mut hatch = self.config.hatch_margin.is_some();
+        let mut margin = self.config.hatch_margin.unwrap_or(DEFAULT_HATCH_MARGIN);
+        ui.checkbox(&mut hatch, "hatch runner-up classes");
+        if hatch {
+            ui.add(Slider::new(&mut margin, 0.0..=0.5).text("within margin"));
+        }
+        let hatch_margin = hatch.then_some(margin);
+        if hatch_margin != self.config.hatch_margin {
+            self.config.hatch_margin = hatch_margin;
+            self.send(AppCmd::Overlay(OverlayCmd::HatchMargin(hatch_margin)));
+        }
+        #[cfg(feature = "gpu_colorize")]
+        if self.seg_painting {
+            let gpu = ui.checkbox(&mut self.config.gpu_colorize, "colorize on GPU");
+            if gpu.changed() {
+                self.send(AppCmd::Overlay(OverlayCmd::Offload(self.config.gpu_colorize)));
+            }
+        }
+        // blend overlays onto frames
+        let mut style = self.config.overlay_style;
+        ui.add(Slider::new(&mut style.opacity, 0.0..=1.0).text("overlay opacity"));
+        ComboBox::from_label("blend mode").selected_text(format!("{:?}", style.blend)).show_ui(
+            ui,
+            |ui| {
+                for mode in BlendMode::ALL {
+                    ui.selectable_value(&mut style.blend, mode, format!("{:?}", mode));
                 }
             },
-            Err(TryRecvError::Disconnected) => {
-                self.error_history.push_front("lost processing control".to_string());
-                frame_.close();
-            }
-            Err(_) => {}
+        );
+        if style != self.config.overlay_style {
+            self.config.overlay_style = style;
+            self.send(AppCmd::OverlayStyle(style));
         }
+        // preferred execution provider, falls back to the next ones
+        let preferred = self.config.providers.first().copied().unwrap_or(ExecutionProvider::Cpu);
+        let mut selected = preferred;
+        ComboBox::from_label("execution provider")
+            .selected_text(format!("{:?}", selected))
+            .show_ui(ui, |ui| {
+                for provider in ExecutionProvider::ALL {
+                    ui.selectable_value(&mut selected, provider, format!("{:?}", provider));
+                }
+            });
+        if selected != preferred {
+            self.config.providers = ExecutionProvider::chain(&[selected]);
+            self.send(AppCmd::Model(ModelCmd::Providers(self.config.providers.clone())));
+        }
+        ui.label(&self.proc_status.model);
 
-        SidePanel::left("Options").show(ctx, |ui| {
-            ui.spacing_mut().item_spacing.y = 10.0;
-            // video input
-            ui.label(RichText::new("Video").font(FontId::proportional(30.0)));
-            // (un-)pause video
-            if ui.checkbox(&mut self.config.paused, "Pause").changed {
-                self.send(AppCmd::Video(VideoCmd::Pause(self.config.paused)))
+        // frame stats
+        ui.label(RichText::new("Stats").font(FontId::proportional(30.0)));
+        let frame_stats = format!(
+            "fps UI: {:>3.1}\nprocessed: {:>3.1}\nskipped after decoding: {}",
+            self.counter.shown_fps(),
+            self.counter.recvd_fps(),
+            self.counter.dropped_since()
+        );
+        ui.label(frame_stats);
+        ui.label(&self.proc_status.decoder);
+        if let Some(shown) = self.shown_latency {
+            ui.label(format!("latency shown: {:.0} ms\n{}", shown * 1e3, self.proc_status.latency));
+        }
+        if !self.proc_status.keep_up.is_empty() {
+            ui.label(&self.proc_status.keep_up);
+        }
+        if !self.proc_status.errors.is_empty() {
+            ui.label(&self.proc_status.errors);
+        }
+        if !self.confidences.is_empty() {
+            let names = &self.config.class_map.names;
+            ui.collapsing("confidences", |ui| confidence_plot(ui, &self.confidences, names));
+        }
+        ui.collapsing("zones", |ui| self.zones_ui(ui));
+
+        // save what was just shown
+        let history = &mut self.config.history;
+        let mut history_changed = false;
+        ui.collapsing("recent frames", |ui| {
+            let secs = Slider::new(&mut history.secs, 0.0..=30.0).text("seconds before");
+            history_changed |= ui.add(secs).changed();
+            let post_secs = Slider::new(&mut history.post_secs, 0.0..=30.0).text("seconds after");
+            history_changed |= ui.add(post_secs).changed();
+            history_changed |= ui.checkbox(&mut history.raw, "without overlay").changed();
+        });
+        if history_changed {
+            self.history.set_opts(self.config.history);
+        }
+        let kept = self.history.len();
+        if ui.add_enabled(kept > 0, egui::Button::new("Save what I just saw")).clicked() {
+            let shown_id = self.main_texture.as_ref().map_or(0, |t| t.id);
+            let path = ClipOpts::default().path("recent", shown_id);
+            self.history.export(path.clone());
+            self.proc_status.clip = format!("exporting to {}", path.to_string_lossy());
+        }
+        ui.label(&self.proc_status.clip);
+
+        if let Some(found) = self.embeddings.take_found() {
+            self.similar = found;
+        }
+        if self.embeddings.len() > 0 {
+            ui.collapsing("similar frames", |ui| {
+                if self.similar.is_empty() {
+                    ui.label("click a region of the frame to search");
+                }
+                for m in self.similar.iter() {
+                    ui.label(format!(
+                        "frame {} at {:.1}s: {:.2}",
+                        m.frame_id, m.timestamp, m.similarity
+                    ));
+                }
+            });
+        }
+    }
+
+    /// Draw only the class whose number key is held, unless typing
+    fn isolate_by_keys(&mut self, ctx: &egui::Context) {
+        let isolated = if ctx.wants_keyboard_input() {
+            None
+        } else {
+            let input = ctx.input();
+            CLASS_KEYS.iter().position(|&key| input.key_down(key))
+        };
+        if isolated != self.isolated {
+            self.isolated = isolated;
+            self.send(AppCmd::Overlay(OverlayCmd::Isolate(isolated)));
+        }
+    }
+
+    /// Last frame and its model output
+    fn frame_ui(&mut self, ui: &mut egui::Ui) {
+        let tex_frame = match &self.main_texture {
+            Some(tex_frame) => tex_frame,
+            None => return,
+        };
+        // occupy max width with constant aspect ratio
+        let max_width = ui.available_width();
+        let [w, h] = tex_frame.size;
+        let w_scale = max_width / w as f32;
+        let (w, h) = (w as f32 * w_scale, h as f32 * w_scale);
+        let response = match &tex_frame.main {
+            MainTexture::Handle(handle) => {
+                ui.add(egui::Image::new(handle, [w, h]).sense(egui::Sense::click()))
+            }
+            MainTexture::Bgr { key, img } => {
+                let (rect, response) =
+                    ui.allocate_exact_size(egui::vec2(w, h), egui::Sense::click());
+                ui.painter().add(bgr_texture::paint_callback(rect, *key, img.clone()));
+                response
+            }
+        };
+        #[cfg(feature = "gpu_colorize")]
+        if let Some((key, confidences)) = &tex_frame.seg {
+            let style = SegStyle {
+                alpha_curve: self.config.alpha_curve,
+                isolated: self.isolated,
+                hatch_margin: self.config.hatch_margin,
+                class_map: self.config.class_map.clone(),
+                overlay: self.config.overlay_style,
             };
-            // (re-)play video
-            if self.config.video_input.is_empty() {
-                self.config.video_input.push(String::default());
+            let callback =
+                seg_texture::paint_callback(response.rect, *key, confidences.clone(), style);
+            ui.painter().add(callback);
+        }
+        // small and at most at its actual size
+        if let Some(ref handle) = tex_frame.model_input_handle {
+            let [iw, ih] = handle.size().map(|s| s as f32);
+            let scale = (max_width / 4.0 / iw).min(1.0);
+            ui.label("model input");
+            ui.image(handle, [iw * scale, ih * scale]);
+        }
+        // outline zones, the one being drawn open
+        let rect = response.rect;
+        let on_frame =
+            |[x, y]: [f32; 2]| rect.min + egui::vec2(x * rect.width(), y * rect.height());
+        let painter = ui.painter();
+        for zone in &self.config.zones {
+            let points = zone.points.iter().copied().map(on_frame).collect::<Vec<_>>();
+            if let Some(&first) = points.first() {
+                let font = FontId::proportional(ZONE_FONT_SIZE);
+                painter.text(first, Align2::LEFT_BOTTOM, &zone.name, font, ZONE_COLOR);
             }
-            let mut vid_input_changed = false;
-            for inp in self.config.video_input.iter_mut() {
-                let textbox = ui.text_edit_singleline(inp);
-                vid_input_changed = vid_input_changed || textbox.lost_focus();
+            painter.add(Shape::closed_line(points, Stroke::new(2.0, ZONE_COLOR)));
+        }
+        if let Some(draft) = &self.zone_draft {
+            let points = draft.points.iter().copied().map(on_frame).collect();
+            painter.add(Shape::line(points, Stroke::new(2.0, ZONE_COLOR)));
+        }
+        if let Some(pos) = response.interact_pointer_pos().filter(|_| response.clicked()) {
+            let rel = (pos - rect.min) / rect.size();
+            match &mut self.zone_draft {
+                Some(draft) => draft.points.push([rel.x, rel.y]),
+                None => {
+                    let r = SEARCH_RADIUS;
+                    let region = [rel.x - r, rel.y - r, rel.x + r, rel.y + r];
+                    let ctx = ui.ctx().clone();
+                    let done = move || ctx.request_repaint();
+                    let (id, n) = (tex_frame.id, SIMILAR_SHOWN);
+                    self.embeddings.search_in_background(id, region, n, done);
+                }
             }
-            if vid_input_changed {
-                self.send(AppCmd::Video(VideoCmd::Play(
-                    self.config.video_input.iter().cloned().filter(|s| !s.is_empty()).collect(),
-                )));
+        }
+    }
+
+    /// Zones with their results, drawn by clicking their corners on the frame
+    fn zones_ui(&mut self, ui: &mut egui::Ui) {
+        let mut removed = None;
+        for (i, zone) in self.config.zones.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let stats = self.zone_stats.iter().find(|s| s.name == zone.name);
+                let unit = self.config.calibration.map_or("", |c| c.unit());
+                let summary = stats.map(|s| zone_summary(s, &self.config.class_map.names, unit));
+                ui.label(format!("{}: {}", zone.name, summary.unwrap_or_default()));
+                if ui.small_button("remove").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        let mut changed = false;
+        if let Some(i) = removed {
+            self.config.zones.remove(i);
+            changed = true;
+        }
+        match self.zone_draft.take() {
+            Some(mut draft) => {
+                ui.label("click corners on the frame");
+                ui.text_edit_singleline(&mut draft.name);
+                let complete = draft.points.len() >= 3 && !draft.name.is_empty();
+                let (done, cancel) = ui
+                    .horizontal(|ui| {
+                        (ui.add_enabled(complete, egui::Button::new("done")), ui.button("cancel"))
+                    })
+                    .inner;
+                if done.clicked() {
+                    self.config.zones.push(draft);
+                    changed = true;
+                } else if !cancel.clicked() {
+                    self.zone_draft = Some(draft);
+                }
             }
-            ui.label(&self.proc_status.video);
-
-            ui.label(RichText::new("Inference").font(FontId::proportional(30.0)));
-            let scale = Slider::new(&mut self.config.scale, 0.1f32..=1.0)
-                .step_by(0.01f64)
-                .text("scale")
-                .clamp_to_range(true);
-            let scale_response = ui.add(scale);
-            if scale_response.changed {
-                self.send(AppCmd::Scale(self.config.scale));
-            };
-            if !self.proc_status.scale.is_empty() {
-                ui.label(&self.proc_status.model);
+            None => {
+                if ui.button("draw zone").clicked() {
+                    let name = format!("zone {}", self.config.zones.len() + 1);
+                    self.zone_draft = Some(Zone { name, points: vec![] });
+                }
             }
+        }
+        if changed {
+            self.send(AppCmd::Zones(self.config.zones.clone()));
+        }
+    }
+}
 
-            // (re-)load model
-            let model_input = ui.text_edit_singleline(&mut self.config.model_input);
-            if model_input.lost_focus() {
-                self.send(AppCmd::Model(ModelCmd::Load(self.config.model_input.clone())));
+impl Drop for StreamView {
+    /// Release processing waiting to hand over a frame
+    fn drop(&mut self) {
+        self.frames.close();
+    }
+}
+
+/// Margin of confidences to hatch runner-up classes within when enabled
+const DEFAULT_HATCH_MARGIN: f32 = 0.1;
+
+/// Color of zone outlines and names
+const ZONE_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 220, 0);
+/// Font size of zone names
+const ZONE_FONT_SIZE: f32 = 14.0;
+
+/// Area in `unit`, shares of classes but the background and numbers of boxes by class
+fn zone_summary(stats: &ZoneStats, names: &[String], unit: &str) -> String {
+    let total = stats.class_areas.iter().sum::<u64>().max(1) as f64;
+    let areas = stats.class_areas.iter().enumerate().skip(1).filter(|(_, &a)| a > 0);
+    let shares =
+        areas.map(|(k, &a)| format!("{} {:.0}%", label(names, k), a as f64 / total * 100.0));
+    let detections = stats.detections.iter().enumerate().filter(|(_, &n)| n > 0);
+    let counts = detections.map(|(k, &n)| format!("{n}x {}", label(names, k)));
+    let area = stats.area.map(|a| format!("{a:.1} {unit}²"));
+    area.into_iter().chain(shares).chain(counts).collect::<Vec<_>>().join(", ")
+}
+
+/// Pixels per maximum confidence, stacked by class in its color
+fn confidence_plot(ui: &mut egui::Ui, confidences: &[Histogram], names: &[String]) {
+    let width = 1.0 / CONFIDENCE_BINS as f64;
+    let mut charts: Vec<BarChart> = vec![];
+    for (k, hist) in confidences.iter().enumerate().filter(|(_, h)| h.iter().any(|&n| n > 0)) {
+        let bars =
+            hist.iter().enumerate().map(|(i, &n)| Bar::new((i as f64 + 0.5) * width, n as f64));
+        let chart = BarChart::new(bars.collect())
+            .width(width)
+            .color(color_code(k, 1.0))
+            .name(label(names, k))
+            .stack_on(&charts.iter().collect::<Vec<_>>());
+        charts.push(chart);
+    }
+    Plot::new("confidences")
+        .height(120.0)
+        .include_x(0.0)
+        .include_x(1.0)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .show(ui, |plot_ui| charts.into_iter().for_each(|c| plot_ui.bar_chart(c)));
+}
+
+/// Keys isolating the class of their number
+const CLASS_KEYS: [egui::Key; 10] = [
+    egui::Key::Num0,
+    egui::Key::Num1,
+    egui::Key::Num2,
+    egui::Key::Num3,
+    egui::Key::Num4,
+    egui::Key::Num5,
+    egui::Key::Num6,
+    egui::Key::Num7,
+    egui::Key::Num8,
+    egui::Key::Num9,
+];
+
+/// Half the relative size of a clicked region to search similar frames by
+const SEARCH_RADIUS: f32 = 1.0 / 16.0;
+/// Most similar frames listed
+const SIMILAR_SHOWN: usize = 5;
+
+/// Events of rules listed to seek to
+#[cfg(feature = "sqlite")]
+const EVENTS_SHOWN: usize = 20;
+
+/// Query of stored results to seek to frames of a class or rule
+#[cfg(feature = "sqlite")]
+pub(crate) struct ResultsSearch {
+    db: ResultsDb,
+    /// Search events of rules instead of classes
+    by_event: bool,
+    class: usize,
+    /// Share of the frame the class covers at least
+    min_share: f64,
+    /// Rule whose events to search, any if `None`
+    rule: Option<String>,
+    found: String,
+}
+
+#[cfg(feature = "sqlite")]
+impl ResultsSearch {
+    fn new(db: ResultsDb) -> Self {
+        Self { db, by_event: false, class: 1, min_share: 0.0, rule: None, found: String::new() }
+    }
+
+    fn find(&self, timestamp: f64, direction: Direction) -> Result<Option<Hit>, SinkError> {
+        if self.by_event {
+            self.db.find_event(self.rule.as_deref(), timestamp, direction)
+        } else {
+            self.db.find_class(self.class, self.min_share, timestamp, direction)
+        }
+    }
+
+    /// Navigate from the shown frame by stored results, returns seconds to seek to
+    fn ui(&mut self, ui: &mut egui::Ui, shown: Option<&TextureFrame>) -> Option<f64> {
+        ui.label(RichText::new("Results").font(FontId::proportional(30.0)));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.by_event, false, "class");
+            ui.radio_value(&mut self.by_event, true, "event");
+        });
+        if self.by_event {
+            let rules = self.db.rules().unwrap_or_default();
+            let selected = self.rule.clone().unwrap_or_else(|| "any".to_string());
+            ComboBox::from_label("rule").selected_text(selected).show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.rule, None, "any");
+                for rule in rules {
+                    ui.selectable_value(&mut self.rule, Some(rule.clone()), rule);
+                }
+            });
+        } else {
+            ui.add(egui::DragValue::new(&mut self.class).prefix("class "));
+            ui.add(Slider::new(&mut self.min_share, 0.0..=1.0).text("min. share"));
+        }
+
+        let timestamp = shown.map_or(f64::MIN, |t| t.timestamp);
+        let mut seek = None;
+        ui.horizontal(|ui| {
+            for (label, direction) in [("previous", Direction::Previous), ("next", Direction::Next)]
+            {
+                if ui.button(label).clicked() {
+                    self.found = match self.find(timestamp, direction) {
+                        Ok(Some(hit)) => {
+                            seek = Some(hit.timestamp);
+                            format!("frame {} at {:.1}s", hit.frame_id, hit.timestamp)
+                        }
+                        Ok(None) => "none found".to_string(),
+                        Err(e) => e.to_string(),
+                    };
+                }
             }
-            ui.label(&self.proc_status.model);
+        });
+        ui.label(&self.found);
+        ui.collapsing("events", |ui| match self.db.events(EVENTS_SHOWN) {
+            Ok(events) => {
+                for event in events {
+                    let label = format!("{} at {:.1}s", event.rule, event.timestamp);
+                    if ui.button(label).clicked() {
+                        seek = Some(event.timestamp);
+                    }
+                }
+            }
+            Err(e) => {
+                ui.label(e.to_string());
+            }
+        });
+        seek
+    }
+}
 
-            // frame stats
-            ui.label(RichText::new("Stats").font(FontId::proportional(30.0)));
-            let frame_stats = format!(
-                "fps UI: {:>3.1}\nprocessed: {:>3.1}\ndrops/skips: {}",
-                self.counter.shown_fps(),
-                self.counter.recvd_fps(),
-                self.counter.dropped_since()
-            );
-            ui.label(frame_stats);
+/// How long events of rules are shown
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+/// GUI with one tab per pipeline
+///
+/// Each pipeline loads its own model session, since sessions can't be shared between threads.
+pub(crate) struct InFur {
+    pub(crate) streams: Vec<StreamView>,
+    /// Index of the shown stream
+    pub(crate) active: usize,
+    pub(crate) closing: bool,
+    pub(crate) allow_closing: bool,
+    pub(crate) paint_bgr: bool,
+    pub(crate) config_path: PathBuf,
+    /// Problems of the environment found at startup
+    pub(crate) env_problems: Vec<String>,
+    /// Rules watching the first pipeline
+    pub(crate) rules: Option<Arc<Rules>>,
+    /// Events of rules and since when they're shown
+    pub(crate) toasts: VecDeque<(Instant, RuleEvent)>,
+    /// Results of the first pipeline stored to query
+    #[cfg(feature = "sqlite")]
+    pub(crate) results: Option<ResultsSearch>,
+    /// Config file whose changes are applied
+    pub(crate) reload: Option<ConfigWatch>,
+}
+
+impl InFur {
+    pub(crate) fn new(pipelines: Vec<(ProcConfig, PipelineChannels)>) -> Self {
+        let streams = pipelines
+            .into_iter()
+            .enumerate()
+            .map(|(i, (config, channels))| StreamView::new(i, config, channels))
+            .collect();
+        Self {
+            streams,
+            active: 0,
+            closing: false,
+            allow_closing: false,
+            paint_bgr: false,
+            config_path: PathBuf::from(config::DEFAULT_CONFIG),
+            env_problems: vec![],
+            rules: None,
+            toasts: VecDeque::new(),
+            #[cfg(feature = "sqlite")]
+            results: None,
+            reload: None,
+        }
+    }
+
+    /// Paint frames with a custom shader instead of uploading egui textures
+    pub(crate) fn with_bgr_painting(
+        mut self,
+        render_state: &eframe::egui_wgpu::RenderState,
+    ) -> Self {
+        bgr_texture::register(render_state);
+        self.paint_bgr = true;
+        #[cfg(feature = "gpu_colorize")]
+        {
+            seg_texture::register(render_state);
+            for stream in self.streams.iter_mut() {
+                stream.seg_painting = true;
+                stream.send(AppCmd::Overlay(OverlayCmd::Offload(stream.config.gpu_colorize)));
+            }
+        }
+        self
+    }
+
+    /// Show problems of the environment
+    pub(crate) fn with_env_problems(mut self, env_problems: Vec<String>) -> Self {
+        self.env_problems = env_problems;
+        self
+    }
+
+    /// Toggle rules and notify when they fire
+    pub(crate) fn with_rules(mut self, rules: Arc<Rules>) -> Self {
+        self.rules = Some(rules);
+        self
+    }
+
+    /// Query results stored in a database
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn with_results_db(mut self, path: &std::path::Path) -> Self {
+        match ResultsDb::open(path) {
+            Ok(db) => self.results = Some(ResultsSearch::new(db)),
+            Err(e) => self.env_problems.push(e.to_string()),
+        }
+        self
+    }
+
+    /// Show the pipeline of this index if there is one
+    pub(crate) fn with_active(mut self, active: usize) -> Self {
+        self.active = active.min(self.streams.len().saturating_sub(1));
+        self
+    }
+
+    /// Setup of all pipelines to restore
+    fn state(&self) -> AppState {
+        AppState {
+            version: STATE_VERSION,
+            active: self.active,
+            pipelines: self.streams.iter().map(|s| s.config.clone()).collect(),
+        }
+    }
+
+    /// Apply changes of a config file while running
+    pub(crate) fn with_reload(mut self, watch: Option<ConfigWatch>) -> Self {
+        self.reload = watch;
+        self
+    }
+
+    /// Where to save the current config to
+    pub(crate) fn with_config_path(mut self, config_path: PathBuf) -> Self {
+        self.config_path = config_path;
+        self
+    }
+
+    /// Stop and exit all pipelines
+    fn exit_all(&mut self) {
+        for stream in self.streams.iter_mut() {
+            // we could not close the video to exit faster, but
+            // would end with an ffmpeg error
+            stream.send(AppCmd::Video(VideoCmd::Stop));
+            stream.send(AppCmd::Exit);
+        }
+    }
+}
+
+impl eframe::App for InFur {
+    fn update(&mut self, ctx: &eframe::egui::Context, frame_: &mut eframe::Frame) {
+        // update textures from new frames or close if all disconnected
+        // this limits UI updates if no frames are sent to ca. 30fps
+        for stream in self.streams.iter_mut() {
+            let visible = stream.index == self.active;
+            let wait = if visible { Duration::from_millis(30) } else { Duration::ZERO };
+            stream.receive(ctx, self.paint_bgr, visible, wait);
+        }
+        if let Some(changes) = self.reload.as_mut().and_then(ConfigWatch::poll) {
+            for (stream, (old, new)) in self.streams.iter_mut().zip(changes) {
+                stream.reload(&old, &new);
+            }
+        }
+        if self.streams.iter().all(|s| s.disconnected) {
+            self.allow_closing = true;
+            frame_.close();
+        }
+        if self.closing && !self.allow_closing {
+            self.exit_all();
+        }
+
+        if self.streams.len() > 1 {
+            egui::TopBottomPanel::top("Streams").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for stream in self.streams.iter() {
+                        ui.selectable_value(&mut self.active, stream.index, stream.title());
+                    }
+                });
+            });
+        }
+
+        let mut save = false;
+        let stream = &mut self.streams[self.active];
+        stream.isolate_by_keys(ctx);
+        SidePanel::left("Options").show(ctx, |ui| {
+            ui.spacing_mut().item_spacing.y = 10.0;
+            stream.options_ui(ui);
+            // write current state of all pipelines for next startup
+            save = ui.button("Save config").clicked();
+            ui.label(&stream.proc_status.config);
+
+            // rules watch the first pipeline
+            if let Some(rules) = self.rules.as_ref().filter(|_| stream.index == 0) {
+                let mut specs = rules.rules();
+                if !specs.is_empty() {
+                    ui.label(RichText::new("Rules").font(FontId::proportional(30.0)));
+                }
+                for (i, rule) in specs.iter_mut().enumerate() {
+                    if ui.checkbox(&mut rule.enabled, &rule.name).changed() {
+                        rules.set_enabled(i, rule.enabled);
+                        stream.config.rules = rules.rules();
+                    }
+                }
+            }
+            #[cfg(feature = "sqlite")]
+            if let Some(results) = self.results.as_mut().filter(|_| stream.index == 0) {
+                if let Some(secs) = results.ui(ui, stream.main_texture.as_ref()) {
+                    stream.send(AppCmd::Video(VideoCmd::Seek(secs)));
+                }
+            }
 
             // rather fatal errors or final messages
             ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
-                for (i, err) in self.error_history.iter().cloned().enumerate() {
+                for problem in self.env_problems.iter() {
+                    ui.colored_label(egui::Color32::YELLOW, problem);
+                }
+                for (i, err) in stream.error_history.iter().cloned().enumerate() {
                     let col = egui::Color32::RED.linear_multiply(1.0 - (i as f32 / 4.0));
                     ui.colored_label(col, err);
                 }
+                if let Some(fatal) = &stream.fatal {
+                    if ui.button("Restart processing").clicked() {
+                        stream.fatal = None;
+                        stream.send(AppCmd::Restart);
+                    } else {
+                        ui.colored_label(egui::Color32::RED, fatal);
+                    }
+                }
             });
         });
 
         // show last_texture
-        if let Some(tex_frame) = &self.main_texture {
-            CentralPanel::default().show(ctx, |ui| {
-                // occupy max width with constant aspect ratio
-                let max_width = ui.available_width();
-                let [w, h] = tex_frame.handle.size();
-                let w_scale = max_width / w as f32;
-                let (w, h) = (w as f32 * w_scale, h as f32 * w_scale);
-                ui.image(&tex_frame.handle, [w, h]);
-                // prop decoded image underneath
-                // todo: blend somehow?
-                if let Some(ref handle) = tex_frame.decoded_handle {
-                    ui.image(handle, [w, h]);
-                };
-            });
+        if stream.main_texture.is_some() {
+            CentralPanel::default().show(ctx, |ui| stream.frame_ui(ui));
         };
 
+        if save {
+            let state = self.state();
+            let saved = match config::save(&state, &self.config_path) {
+                Ok(()) => {
+                    // not taken for changes by someone else
+                    if let Some(watch) = &mut self.reload {
+                        watch.saved(&self.config_path, state);
+                    }
+                    format!("saved to {}", self.config_path.to_string_lossy())
+                }
+                Err(e) => e.to_string(),
+            };
+            self.streams[self.active].proc_status.config = saved;
+        }
+
+        // notify of fired rules for a while
+        if let Some(rules) = &self.rules {
+            let now = Instant::now();
+            self.toasts.extend(rules.take_toasts().into_iter().map(|event| (now, event)));
+        }
+        self.toasts.retain(|(since, _)| since.elapsed() < TOAST_DURATION);
+        if !self.toasts.is_empty() {
+            egui::Area::new("Toasts").anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0]).show(
+                ctx,
+                |ui| {
+                    for (_, event) in self.toasts.iter() {
+                        egui::Frame::popup(ui.style()).show(ui, |ui| {
+                            ui.label(format!("{} (frame {})", event.rule, event.frame_id))
+                        });
+                    }
+                },
+            );
+        }
+
         ctx.request_repaint();
     }
 
     fn on_close_event(&mut self) -> bool {
-        self.error_history.push_front("exiting...".to_string());
+        for stream in self.streams.iter_mut() {
+            stream.error_history.push_front("exiting...".to_string());
+        }
         // send exit once
         if !self.closing {
-            // we could not close the video to exit faster, but
-            // would end with an ffmpeg error
-            self.send(AppCmd::Video(VideoCmd::Stop));
-            self.send(AppCmd::Exit);
+            self.exit_all();
         }
         self.closing = true;
         self.allow_closing
@@ -348,6 +1515,6 @@ impl eframe::App for InFur {
 
     #[cfg(feature = "persistence")]
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
-        eframe::set_value(storage, eframe::APP_KEY, &self.config);
+        eframe::set_value(storage, eframe::APP_KEY, &self.state());
     }
 }
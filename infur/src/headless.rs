@@ -0,0 +1,481 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use ff_video::{redact, VideoProcError};
+use stable_eyre::eyre::{eyre, Report};
+use tracing::{info, warn};
+
+use crate::{
+    app::{AppCmd, AppProcError, ErrorPolicy, LagPolicy, ProcessingApp, Processor, SharedStatus},
+    calibration::Calibration,
+    class_map::ClassMap,
+    cli::Args,
+    clips::{FrameHistory, HistoryOpts},
+    config::AppState,
+    decode_predict::{AlphaCurve, OverlayCmd},
+    gui::ProcConfig,
+    predict_onnx::{ExecutionProvider, InputFormat, ModelCmd},
+    preview::{OverlayStyle, Preview},
+    processing::{ArchiveOpts, Clip, ReadPolicy, VideoCmd},
+    reload::{reload, ConfigWatch},
+    routing::OutputRoutes,
+    rules::{Rule, Rules},
+    shm::ShmOpts,
+    signals,
+    sink::{self, JsonlSink, ResultSink, SinkSpec},
+    zones::Zone,
+    Result,
+};
+
+/// Options to process a batch of inputs without GUI
+pub(crate) struct BatchOpts {
+    /// Videos, directories of videos or glob patterns
+    pub(crate) inputs: Vec<String>,
+    /// Raw ffmpeg arguments processed as one more input
+    pub(crate) ffmpeg_args: Vec<String>,
+    pub(crate) model: String,
+    /// Execution providers to try in order
+    pub(crate) providers: Vec<ExecutionProvider>,
+    /// Overrides of the model's guessed image input format
+    pub(crate) input_format: InputFormat,
+    /// Decoders of the model's outputs, the first segmentation gives results
+    pub(crate) output_routes: OutputRoutes,
+    /// How segmentations are shaded by confidence
+    pub(crate) alpha_curve: AlphaCurve,
+    /// Hatch pixels by their runner-up class if within this margin of the best
+    pub(crate) hatch_margin: Option<f32>,
+    /// Merge and name classes of the model
+    pub(crate) class_map: ClassMap,
+    /// How overlays are blended onto previews and clips
+    pub(crate) overlay_style: OverlayStyle,
+    pub(crate) scale: f32,
+    /// Part of each input to process
+    pub(crate) clip: Clip,
+    /// Failed reads of each input to retry before giving up on it
+    pub(crate) read_policy: ReadPolicy,
+    /// How scaling and inference recover from failing on frames
+    pub(crate) error_policy: ErrorPolicy,
+    /// How far processing may fall behind live inputs before adapting
+    pub(crate) lag_policy: LagPolicy,
+    /// Seconds results of a frame may take, frames over it are counted
+    pub(crate) latency_budget: Option<f64>,
+    /// Where live inputs are copied to while processed
+    pub(crate) archive: Option<ArchiveOpts>,
+    /// Where decoded frames are shared, by each job of its own
+    pub(crate) shm: Option<ShmOpts>,
+    /// Process every frame and time results by the video
+    pub(crate) deterministic: bool,
+    /// Number of inputs processed concurrently
+    pub(crate) jobs: usize,
+    /// Directory for one results file per input and the summary
+    pub(crate) out_dir: PathBuf,
+    /// Progress over all inputs
+    pub(crate) status: SharedStatus,
+    /// Frames of all inputs for preview clients
+    pub(crate) preview: Arc<Preview>,
+    /// Where results of all inputs are written to as well
+    pub(crate) sinks: Vec<SinkSpec>,
+    /// Rules evaluated on the results of each input
+    pub(crate) rules: Vec<Rule>,
+    /// Recent frames kept per input for clips of rules
+    pub(crate) history: HistoryOpts,
+    /// Areas to tell results within
+    pub(crate) zones: Vec<Zone>,
+    /// Of video frames to report zones and boxes in world coordinates
+    pub(crate) calibration: Option<Calibration>,
+    /// Config file to apply changes of while processing, by all jobs
+    pub(crate) watch: Option<Mutex<BatchWatch>>,
+    /// Finish early, e.g. on a signal
+    pub(crate) stop: Arc<AtomicBool>,
+}
+
+impl BatchOpts {
+    /// Options from arguments, falling back to a config file's settings
+    pub(crate) fn new(args: &Args, config: Option<ProcConfig>) -> Result<Self> {
+        // unlike the GUI, process at full scale by default
+        let scale = args.scale.or_else(|| config.as_ref().map(|c| c.scale)).unwrap_or(1.0);
+        let sinks = args.sink_specs(config.as_ref())?;
+        let config = config.unwrap_or_default();
+        let clip =
+            Clip { start: args.start.or(config.clip.start), end: args.end.or(config.clip.end) };
+        let ffmpeg_args = match (args.videos.is_empty(), args.ffmpeg_args.is_empty()) {
+            (true, true) => config.video_input,
+            _ => args.ffmpeg_args.clone(),
+        };
+        let model = args.model.clone().unwrap_or(config.model_input);
+        let input_format = config.input_formats.get(&model).copied().unwrap_or_default();
+        let output_routes = config.output_routes.get(&model).cloned().unwrap_or_default();
+        Ok(Self {
+            inputs: args.videos.clone(),
+            ffmpeg_args,
+            model,
+            providers: if args.providers.is_empty() {
+                config.providers
+            } else {
+                args.providers.clone()
+            },
+            input_format,
+            output_routes,
+            alpha_curve: config.alpha_curve,
+            hatch_margin: config.hatch_margin,
+            class_map: config.class_map,
+            overlay_style: config.overlay_style,
+            scale,
+            clip,
+            read_policy: config.read_policy,
+            error_policy: config.error_policy,
+            lag_policy: config.lag_policy,
+            latency_budget: config.latency_budget,
+            archive: args.archive_opts(config.archive),
+            shm: args.shm.clone().map(|path| ShmOpts { path }).or(config.shm),
+            deterministic: args.deterministic,
+            jobs: args.jobs,
+            out_dir: args.out.clone(),
+            status: SharedStatus::default(),
+            preview: Arc::new(Preview::default()),
+            sinks,
+            rules: config.rules,
+            history: config.history,
+            zones: config.zones,
+            calibration: config.calibration,
+            watch: None,
+            stop: Arc::default(),
+        })
+    }
+}
+
+/// Config file watched for all inputs and the latest settings of its first pipeline
+pub(crate) struct BatchWatch {
+    watch: ConfigWatch,
+    /// As loaded when the batch started, what options were made of
+    initial: ProcConfig,
+    latest: ProcConfig,
+    /// Times the file was reloaded
+    version: u64,
+}
+
+/// Settings an app was configured with last, by the `BatchWatch` version they're of
+type Applied = (u64, ProcConfig);
+
+impl BatchWatch {
+    pub(crate) fn new(path: &Path, state: AppState) -> Self {
+        let initial = state.first().cloned().unwrap_or_default();
+        let latest = initial.clone();
+        Self { watch: ConfigWatch::new(path, state), initial, latest, version: 0 }
+    }
+
+    /// Commands taking the `job`-th app from what it `applied`, initial settings if none, to the
+    /// latest
+    ///
+    /// Which input is played and whether it's paused is left to the batch.
+    fn changes(&mut self, job: usize, applied: &mut Option<Applied>) -> Vec<AppCmd> {
+        if let Some((_, new)) = self.watch.poll().and_then(|changes| changes.into_iter().next()) {
+            self.latest = new;
+            self.version += 1;
+        }
+        let (version, config) = applied.get_or_insert_with(|| (0, self.initial.clone()));
+        if *version == self.version {
+            return vec![];
+        }
+        let old = config.clone();
+        let cmds = reload(config, &old, &self.latest);
+        *version = self.version;
+        cmds.into_iter()
+            .filter(|cmd| !matches!(cmd, AppCmd::Video(VideoCmd::Play(_) | VideoCmd::Pause(_))))
+            .map(|cmd| match cmd {
+                // apart from other jobs, as when started
+                AppCmd::Share(_) => {
+                    AppCmd::Share(config.shm.as_ref().map(|shm| shm.for_pipeline(job)))
+                }
+                cmd => cmd,
+            })
+            .collect()
+    }
+}
+
+/// Outcome of processing one input
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct InputSummary {
+    input: String,
+    results: PathBuf,
+    frames: u64,
+    secs: f64,
+    error: Option<String>,
+}
+
+/// Resolve directories and glob patterns into files, anything else is kept as is
+pub(crate) fn expand_inputs(inputs: &[String]) -> Result<Vec<String>> {
+    let mut expanded = vec![];
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let mut files = fs::read_dir(path)?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_file())
+                .collect::<Vec<_>>();
+            files.sort();
+            expanded.extend(files.iter().map(|p| p.to_string_lossy().to_string()));
+        } else if input.contains(['*', '?', '[']) {
+            let mut files = glob::glob(input)?.collect::<std::result::Result<Vec<_>, _>>()?;
+            files.sort();
+            expanded.extend(files.iter().map(|p| p.to_string_lossy().to_string()));
+        } else {
+            // may still be an URL or other ffmpeg input
+            expanded.push(input.clone());
+        }
+    }
+    Ok(expanded)
+}
+
+/// Unique results file named after each input's first argument
+pub(crate) fn results_paths(inputs: &[Vec<String>], out_dir: &Path) -> Vec<PathBuf> {
+    let mut taken = HashSet::new();
+    inputs
+        .iter()
+        .map(|input| {
+            let stem = input
+                .first()
+                .and_then(|i| Path::new(i).file_stem())
+                .map_or_else(|| "input".to_string(), |s| s.to_string_lossy().to_string());
+            let mut name = format!("{stem}.jsonl");
+            let mut n = 1;
+            while !taken.insert(name.clone()) {
+                name = format!("{stem}_{n}.jsonl");
+                n += 1;
+            }
+            out_dir.join(name)
+        })
+        .collect()
+}
+
+/// App set up by the options for the `job`-th of concurrent ones, yet without an input to play
+pub(crate) fn configured_app(opts: &BatchOpts, job: usize) -> Result<ProcessingApp> {
+    let mut app = ProcessingApp::default();
+    app.control(AppCmd::Deterministic(opts.deterministic))?;
+    app.control(AppCmd::Scale(opts.scale))?;
+    app.control(AppCmd::Model(ModelCmd::Providers(opts.providers.clone())))?;
+    app.control(AppCmd::Model(ModelCmd::InputFormat(opts.input_format)))?;
+    app.control(AppCmd::Route(opts.output_routes.clone()))?;
+    app.control(AppCmd::Overlay(OverlayCmd::AlphaCurve(opts.alpha_curve)))?;
+    app.control(AppCmd::Overlay(OverlayCmd::HatchMargin(opts.hatch_margin)))?;
+    app.control(AppCmd::Overlay(OverlayCmd::ClassMap(opts.class_map.clone())))?;
+    app.control(AppCmd::OverlayStyle(opts.overlay_style))?;
+    app.control(AppCmd::Zones(opts.zones.clone()))?;
+    app.control(AppCmd::Annotate(opts.rules.iter().any(Rule::annotates)))?;
+    app.control(AppCmd::Calibration(opts.calibration))?;
+    app.control(AppCmd::Model(ModelCmd::Load(opts.model.clone())))?;
+    app.control(AppCmd::Video(VideoCmd::Clip(opts.clip)))?;
+    app.control(AppCmd::ReadPolicy(opts.read_policy))?;
+    app.control(AppCmd::ErrorPolicy(opts.error_policy))?;
+    app.control(AppCmd::LagPolicy(opts.lag_policy))?;
+    app.control(AppCmd::LatencyBudget(opts.latency_budget))?;
+    app.control(AppCmd::Archive(opts.archive.clone()))?;
+    app.control(AppCmd::Share(opts.shm.as_ref().map(|shm| shm.for_pipeline(job))))?;
+    Ok(app)
+}
+
+/// Play one input until it finishes and write per-frame results
+fn process_input(
+    input: &[String],
+    job: usize,
+    opts: &BatchOpts,
+    results: &Path,
+    shared_sinks: &Mutex<Vec<Box<dyn ResultSink>>>,
+) -> Result<u64> {
+    let mut app = configured_app(opts, job)?;
+    app.control(AppCmd::Video(VideoCmd::Play(input.to_vec())))?;
+
+    let mut results = JsonlSink::create(results)?;
+    // clips waiting for frames are written when it's dropped
+    let history = Arc::new(FrameHistory::new(opts.history));
+    let rules = Rules::open(&opts.rules, Some(history.clone()))?;
+    // changes of the config file before this input are applied as well
+    let mut applied = None;
+    let mut frames = 0;
+    let mut last_error = None;
+    while app.is_dirty() {
+        if opts.stop.load(Ordering::Relaxed) {
+            // close the video gracefully and keep results so far
+            for cmd in signals::stop_cmds() {
+                app.control(cmd)?;
+            }
+            results.flush()?;
+            return Ok(frames);
+        }
+        let cmds = opts.watch.as_ref().map(|w| w.lock().unwrap().changes(job, &mut applied));
+        for cmd in cmds.unwrap_or_default() {
+            if let Err(e) = app.control(cmd) {
+                warn!("couldn't apply reloaded config: {}", e);
+            }
+        }
+        match app.generate() {
+            Ok(Some(frame)) => {
+                let record = frame.record();
+                results.write(&record)?;
+                frames += 1;
+                history.push(&frame);
+                if let Err(e) = rules.evaluate(&record) {
+                    warn!("couldn't act on rules for frame {}: {}", frame.id, e);
+                }
+                for sink in shared_sinks.lock().unwrap().iter_mut() {
+                    if let Err(e) = sink.write(&record) {
+                        warn!("couldn't write results of frame {}: {}", frame.id, e);
+                    }
+                }
+                {
+                    let mut status = opts.status.lock().unwrap();
+                    status.frame_id = Some(frame.id);
+                    status.frames += 1;
+                }
+                opts.preview.publish(&frame);
+            }
+            Ok(None) => {}
+            Err(AppProcError::Video(VideoProcError::FinishedNormally { .. })) => break,
+            // recovered from by the error policy
+            Err(e @ (AppProcError::Scale(_) | AppProcError::Model(_))) => {
+                warn!("frame failed: {}", e);
+                last_error = Some(e.to_string());
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    results.flush()?;
+    // e.g. a broken model's results are empty
+    if let Some(stage) = app.failed_stage() {
+        let cause = last_error.map_or_else(String::new, |e| format!(": {e}"));
+        return Err(eyre!("{} failed beyond its error policy{}", stage, cause));
+    }
+    Ok(frames)
+}
+
+/// Process all inputs sequentially or by a pool of jobs and write a summary
+pub(crate) fn run_batch(opts: BatchOpts) -> Result<()> {
+    let mut inputs = expand_inputs(&opts.inputs)?.into_iter().map(|i| vec![i]).collect::<Vec<_>>();
+    if !opts.ffmpeg_args.is_empty() {
+        inputs.push(opts.ffmpeg_args.clone());
+    }
+    if inputs.is_empty() {
+        return Err(eyre!("no inputs to process"));
+    }
+    fs::create_dir_all(&opts.out_dir)?;
+    let results = results_paths(&inputs, &opts.out_dir);
+    let queue = Mutex::new(inputs.into_iter().zip(results).enumerate().collect::<VecDeque<_>>());
+    let summaries = Mutex::new(vec![]);
+    let shared_sinks = Mutex::new(sink::open_all(&opts.sinks)?);
+
+    thread::scope(|s| {
+        let (opts, queue, summaries, shared_sinks) = (&opts, &queue, &summaries, &shared_sinks);
+        for n in 0..opts.jobs.max(1) {
+            thread::Builder::new().name(format!("Batch{n}")).spawn_scoped(s, move || loop {
+                if opts.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                // don't hold the lock while processing
+                let next = queue.lock().unwrap().pop_front();
+                let (i, (input, results)) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let args = input;
+                let input = redact(&args.join(" "));
+                info!("processing {}", input);
+                let start = Instant::now();
+                let outcome = process_input(&args, n, opts, &results, shared_sinks);
+                let secs = start.elapsed().as_secs_f64();
+                let summary = match outcome {
+                    Ok(frames) => InputSummary { input, results, frames, secs, error: None },
+                    Err(e) => {
+                        warn!("failed processing {}: {}", input, e);
+                        let error = Some(e.to_string());
+                        InputSummary { input, results, frames: 0, secs, error }
+                    }
+                };
+                summaries.lock().unwrap().push((i, summary));
+            })?;
+        }
+        Ok::<_, Report>(())
+    })?;
+
+    for sink in shared_sinks.into_inner().unwrap().iter_mut() {
+        if let Err(e) = sink.flush() {
+            warn!("couldn't flush results: {}", e);
+        }
+    }
+    let mut summaries = summaries.into_inner().unwrap();
+    summaries.sort_by_key(|(i, _)| *i);
+    let summaries = summaries.into_iter().map(|(_, s)| s).collect::<Vec<_>>();
+    for s in summaries.iter() {
+        match &s.error {
+            None => info!("{}: {} frames in {:.1}s -> {:?}", s.input, s.frames, s.secs, s.results),
+            Some(e) => info!("{}: failed with {}", s.input, e),
+        }
+    }
+    let summary_file = File::create(opts.out_dir.join("summary.json"))?;
+    serde_json::to_writer_pretty(BufWriter::new(summary_file), &summaries)?;
+
+    if opts.stop.load(Ordering::Relaxed) {
+        return Err(eyre!("stopped after {} inputs", summaries.len()));
+    }
+    let failed = summaries.iter().filter(|s| s.error.is_some()).count();
+    if failed > 0 {
+        return Err(eyre!("{} of {} inputs failed", failed, summaries.len()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unique_results_paths() {
+        let inputs = ["a/video.mp4", "b/video.mp4", "rtsp://host/cam"].map(|i| vec![i.to_string()]);
+        let paths = results_paths(&inputs, Path::new("out"));
+        assert_eq!(
+            paths,
+            ["out/video.jsonl", "out/video_1.jsonl", "out/cam.jsonl"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn reload_for_later_inputs() {
+        let state = AppState::from(ProcConfig { scale: 0.5, ..Default::default() });
+        let mut watch = BatchWatch::new(Path::new("infur.toml"), state);
+        let mut first = None;
+        assert!(watch.changes(0, &mut first).is_empty());
+
+        // as if the file was reloaded
+        watch.latest.scale = 0.25;
+        watch.version += 1;
+        // the running input and one started later both take the change once
+        let mut later = None;
+        for applied in [&mut first, &mut later] {
+            let cmds = watch.changes(0, applied);
+            assert!(matches!(cmds[..], [AppCmd::Scale(s)] if s == 0.25));
+            assert!(watch.changes(0, applied).is_empty());
+        }
+    }
+
+    #[test]
+    fn reload_shm_per_job() {
+        let state = AppState::from(ProcConfig::default());
+        let mut watch = BatchWatch::new(Path::new("infur.toml"), state);
+        watch.latest.shm = Some(ShmOpts { path: PathBuf::from("frames") });
+        watch.version += 1;
+        let paths = [0, 1].map(|job| match &watch.changes(job, &mut None)[..] {
+            [AppCmd::Share(Some(shm))] => shm.path.clone(),
+            cmds => panic!("should share frames, got {cmds:?}"),
+        });
+        assert_eq!(paths, [PathBuf::from("frames"), PathBuf::from("frames.1")]);
+    }
+}
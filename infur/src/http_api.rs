@@ -0,0 +1,355 @@
+use std::{
+    io::{Read, Write},
+    mem,
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde::de::DeserializeOwned;
+use stable_eyre::eyre::eyre;
+use thiserror::Error;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use tracing::{debug, info, warn};
+use tungstenite::{handshake::derive_accept_key, protocol::Role, Message, WebSocket};
+
+use crate::{
+    app::{AppCmd, SharedStatus},
+    predict_onnx::ModelCmd,
+    preview::{Preview, PreviewFrame, Subscription, Update},
+    processing::VideoCmd,
+    Result,
+};
+
+/// Multipart boundary between MJPEG frames
+const BOUNDARY: &str = "infurframe";
+
+/// Streams write at least this often, to notice clients that left while nothing is published
+const KEEPALIVE: Duration = Duration::from_secs(2);
+
+/// Error handling an API request
+#[derive(Error, Debug)]
+enum ApiError {
+    #[error("no route for {0}")]
+    NotFound(String),
+    #[error("invalid body: {0}")]
+    Body(#[from] serde_json::Error),
+    #[error("couldn't read body: {0}")]
+    Read(#[from] std::io::Error),
+    #[error("processing is not running anymore")]
+    Unavailable,
+    #[error("missing websocket key")]
+    NoWebSocketKey,
+}
+
+impl ApiError {
+    fn status_code(&self) -> u16 {
+        match self {
+            Self::NotFound(_) => 404,
+            Self::Body(_) | Self::Read(_) | Self::NoWebSocketKey => 400,
+            Self::Unavailable => 503,
+        }
+    }
+}
+
+/// What a request asks for
+#[derive(Debug)]
+enum Route {
+    /// Current state as JSON
+    Status,
+    /// Relay command to processing
+    Cmd(AppCmd),
+    /// Stream composited frames as MJPEG
+    Mjpeg,
+    /// Stream per-frame results as JSON over a websocket
+    Results,
+}
+
+fn parse<T: DeserializeOwned>(body: &str) -> std::result::Result<T, ApiError> {
+    Ok(serde_json::from_str(body)?)
+}
+
+/// Map method, url and JSON body to a route
+///
+/// * `GET /status`
+/// * `GET /preview.mjpeg` streams composited frames
+/// * `GET /results` streams per-frame results (websocket)
+/// * `POST /video/play` with ffmpeg arguments, e.g. `["video.mp4"]`
+/// * `POST /video/pause` with `true` or `false`
+/// * `POST /video/stop`
+/// * `POST /video/clip` with seconds, e.g. `{"start": 10, "end": 20}`, `{}` plays all
+/// * `POST /video/seek` with seconds to restart the current video at, e.g. `42.5`
+/// * `POST /model` with a path, empty unloads the model
+/// * `POST /model/providers` with execution providers to try, e.g. `["Cuda", "Cpu"]`
+/// * `POST /scale` with a factor, e.g. `0.5`
+fn route(method: &Method, url: &str, body: &str) -> std::result::Result<Route, ApiError> {
+    let path = url.split('?').next().unwrap_or_default().trim_end_matches('/');
+    let route = match (method, path) {
+        (Method::Get, "/status") => Route::Status,
+        (Method::Get, "/preview.mjpeg") => Route::Mjpeg,
+        (Method::Get, "/results") => Route::Results,
+        (Method::Post, "/video/play") => Route::Cmd(AppCmd::Video(VideoCmd::Play(parse(body)?))),
+        (Method::Post, "/video/pause") => Route::Cmd(AppCmd::Video(VideoCmd::Pause(parse(body)?))),
+        (Method::Post, "/video/stop") => Route::Cmd(AppCmd::Video(VideoCmd::Stop)),
+        (Method::Post, "/video/clip") => Route::Cmd(AppCmd::Video(VideoCmd::Clip(parse(body)?))),
+        (Method::Post, "/video/seek") => Route::Cmd(AppCmd::Video(VideoCmd::Seek(parse(body)?))),
+        (Method::Post, "/model") => Route::Cmd(AppCmd::Model(ModelCmd::Load(parse(body)?))),
+        (Method::Post, "/model/providers") => {
+            Route::Cmd(AppCmd::Model(ModelCmd::Providers(parse(body)?)))
+        }
+        (Method::Post, "/scale") => Route::Cmd(AppCmd::Scale(parse(body)?)),
+        _ => return Err(ApiError::NotFound(format!("{} {}", method, path))),
+    };
+    Ok(route)
+}
+
+fn header(key: &str, value: &str) -> Header {
+    Header::from_bytes(key.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn json_response(code: u16, json: String) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(json)
+        .with_status_code(code)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+/// Multipart body of JPEG frames until the preview closes
+struct MjpegStream {
+    subscription: Subscription,
+    /// Sent again while idle
+    last: Option<PreviewFrame>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for MjpegStream {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.buf.len() {
+            let frame = match self.subscription.next_frame(KEEPALIVE) {
+                Update::Frame(frame) => Some(self.last.insert(frame).clone()),
+                Update::Idle => self.last.clone(),
+                Update::Closed => return Ok(0),
+            };
+            self.buf.clear();
+            self.pos = 0;
+            match frame {
+                Some(frame) => {
+                    write!(
+                        self.buf,
+                        "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                        frame.jpeg.len()
+                    )?;
+                    self.buf.extend_from_slice(&frame.jpeg);
+                    self.buf.extend_from_slice(b"\r\n");
+                }
+                // ignored as preamble before the first part
+                None => self.buf.extend_from_slice(b"\r\n"),
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Stream MJPEG until the client disconnects or the preview closes
+fn stream_mjpeg(request: Request, preview: &Arc<Preview>) {
+    let subscription = preview.subscribe();
+    let stream = MjpegStream { subscription, last: None, buf: vec![], pos: 0 };
+    let content_type = format!("multipart/x-mixed-replace; boundary={BOUNDARY}");
+    let response = Response::new(
+        StatusCode(200),
+        vec![header("Content-Type", &content_type)],
+        stream,
+        None,
+        None,
+    );
+    if let Err(e) = request.respond(response) {
+        debug!("MJPEG client left: {}", e);
+    }
+}
+
+/// Upgrade to websocket and send results until the client disconnects or the preview closes
+fn stream_results(request: Request, preview: &Arc<Preview>) {
+    let key = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+        .map(|h| h.value.to_string());
+    let key = match key {
+        Some(key) => key,
+        None => {
+            respond_error(request, ApiError::NoWebSocketKey);
+            return;
+        }
+    };
+    let response = Response::new_empty(StatusCode(101))
+        .with_header(header("Upgrade", "websocket"))
+        .with_header(header("Connection", "Upgrade"))
+        .with_header(header("Sec-WebSocket-Accept", &derive_accept_key(key.as_bytes())));
+    let stream = request.upgrade("websocket", response);
+    let mut ws = WebSocket::from_raw_socket(stream, Role::Server, None);
+    let mut subscription = preview.subscribe();
+    loop {
+        let message = match subscription.next_frame(KEEPALIVE) {
+            Update::Frame(frame) => Message::Text(frame.json.to_string()),
+            Update::Idle => Message::Ping(vec![]),
+            Update::Closed => {
+                _ = ws.close(None).and_then(|_| ws.write_pending());
+                return;
+            }
+        };
+        if let Err(e) = ws.write_message(message) {
+            debug!("results client left: {}", e);
+            return;
+        }
+    }
+}
+
+/// Shared by all requests
+#[derive(Clone)]
+pub(crate) struct ApiContext {
+    /// Relay commands if processing is controllable
+    pub(crate) ctrl_tx: Option<Sender<AppCmd>>,
+    pub(crate) status: SharedStatus,
+    pub(crate) preview: Arc<Preview>,
+}
+
+/// Threads serving streams, those finished are joined when another one starts
+type Streams = Arc<Mutex<Vec<JoinHandle<()>>>>;
+
+fn spawn_stream(streams: &Streams, name: &str, stream: impl FnOnce() + Send + 'static) {
+    let mut streams = streams.lock().unwrap();
+    let (finished, running): (Vec<_>, _) =
+        mem::take(&mut *streams).into_iter().partition(|s| s.is_finished());
+    *streams = running;
+    for finished in finished {
+        _ = finished.join();
+    }
+    match thread::Builder::new().name(name.to_string()).spawn(stream) {
+        Ok(handle) => streams.push(handle),
+        Err(e) => warn!("couldn't serve {}: {}", name, e),
+    }
+}
+
+fn respond(request: Request, response: Response<std::io::Cursor<Vec<u8>>>) {
+    if let Err(e) = request.respond(response) {
+        warn!("couldn't respond to API request: {}", e);
+    }
+}
+
+fn respond_error(request: Request, e: ApiError) {
+    debug!("failed API request {} {}: {}", request.method(), request.url(), e);
+    let msg = serde_json::to_string(&e.to_string()).unwrap_or_default();
+    respond(request, json_response(e.status_code(), msg));
+}
+
+/// Respond to JSON requests, streams are served by their own thread
+fn respond_to(mut request: Request, ctx: &ApiContext, streams: &Streams) {
+    let mut body = String::new();
+    let routed = match request.as_reader().read_to_string(&mut body) {
+        Ok(_) => route(request.method(), request.url(), &body),
+        Err(e) => Err(e.into()),
+    };
+    let response = match routed {
+        Ok(Route::Status) => {
+            let status = ctx.status.lock().unwrap().clone();
+            serde_json::to_string(&status).map(|json| json_response(200, json)).map_err(Into::into)
+        }
+        Ok(Route::Cmd(cmd)) => {
+            debug!("relaying API command: {:?}", cmd);
+            match &ctx.ctrl_tx {
+                Some(ctrl_tx) if ctrl_tx.send(cmd).is_ok() => {
+                    // results of commands are reflected by status eventually
+                    Ok(json_response(202, "null".to_string()))
+                }
+                _ => Err(ApiError::Unavailable),
+            }
+        }
+        Ok(Route::Mjpeg) => {
+            let preview = ctx.preview.clone();
+            spawn_stream(streams, "MJPEG", move || stream_mjpeg(request, &preview));
+            return;
+        }
+        Ok(Route::Results) => {
+            let preview = ctx.preview.clone();
+            spawn_stream(streams, "Results", move || stream_results(request, &preview));
+            return;
+        }
+        Err(e) => Err(e),
+    };
+    match response {
+        Ok(response) => respond(request, response),
+        Err(e) => respond_error(request, e),
+    }
+}
+
+/// Serves the API until dropped
+pub(crate) struct ApiServer {
+    server: Arc<Server>,
+    thread: Option<JoinHandle<()>>,
+    streams: Streams,
+    preview: Arc<Preview>,
+}
+
+impl Drop for ApiServer {
+    /// Stop taking requests, end all streams and wait for their threads
+    fn drop(&mut self) {
+        self.server.unblock();
+        self.preview.close();
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+        for stream in mem::take(&mut *self.streams.lock().unwrap()) {
+            _ = stream.join();
+        }
+    }
+}
+
+/// Serve API on addr until the returned server is dropped
+pub(crate) fn spawn_server(addr: &str, ctx: ApiContext) -> Result<ApiServer> {
+    let server = Server::http(addr).map_err(|e| eyre!("couldn't serve API on {}: {}", addr, e))?;
+    let server = Arc::new(server);
+    info!("serving API on http://{}", addr);
+    let streams = Streams::default();
+    let preview = ctx.preview.clone();
+    let thread = {
+        let (server, streams) = (server.clone(), streams.clone());
+        thread::Builder::new().name("API".to_string()).spawn(move || {
+            for request in server.incoming_requests() {
+                respond_to(request, &ctx, &streams);
+            }
+        })?
+    };
+    Ok(ApiServer { server, thread: Some(thread), streams, preview })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn route_commands() {
+        let r = route(&Method::Post, "/video/play/", r#"["a.mp4", "-t", "5"]"#).unwrap();
+        assert!(matches!(r, Route::Cmd(AppCmd::Video(VideoCmd::Play(args))) if args.len() == 3));
+        let r = route(&Method::Post, "/scale", "0.5").unwrap();
+        assert!(matches!(r, Route::Cmd(AppCmd::Scale(s)) if s == 0.5));
+        let r = route(&Method::Post, "/video/clip", r#"{"start": 10}"#).unwrap();
+        assert!(matches!(
+            r,
+            Route::Cmd(AppCmd::Video(VideoCmd::Clip(c))) if c.start == Some(10.0) && c.end.is_none()
+        ));
+        let r = route(&Method::Post, "/model/providers", r#"["Cuda", "Cpu"]"#).unwrap();
+        assert!(matches!(r, Route::Cmd(AppCmd::Model(ModelCmd::Providers(p))) if p.len() == 2));
+        let r = route(&Method::Get, "/status?pretty", "").unwrap();
+        assert!(matches!(r, Route::Status));
+    }
+
+    #[test]
+    fn route_errors() {
+        assert!(matches!(route(&Method::Get, "/scale", ""), Err(ApiError::NotFound(_))));
+        let e = route(&Method::Post, "/video/pause", "maybe").unwrap_err();
+        assert_eq!(e.status_code(), 400);
+    }
+}
@@ -0,0 +1,89 @@
+use std::{fs, path::Path};
+
+use ff_video::VideoProcError;
+use image_ext::ImageFormat;
+use stable_eyre::eyre::eyre;
+use tracing::{info, warn};
+
+use crate::{
+    app::{AppCmd, AppProcError, GUIFrame, ProcessingApp, Processor},
+    headless::{configured_app, expand_inputs, results_paths, BatchOpts},
+    preview::composite,
+    processing::VideoCmd,
+    Result,
+};
+
+/// Whether a file of a directory is taken as image
+fn is_image(path: &str) -> bool {
+    ImageFormat::from_path(path).is_ok()
+}
+
+/// Run the model on one image, decoded by ffmpeg as a video of one frame
+fn process_image(app: &mut ProcessingApp, input: &str) -> Result<GUIFrame> {
+    app.control(AppCmd::Video(VideoCmd::Play(vec![input.to_string()])))?;
+    let mut last = None;
+    while app.is_dirty() {
+        match app.generate() {
+            Ok(Some(frame)) => last = Some(frame),
+            Ok(None) => {}
+            Err(AppProcError::Video(VideoProcError::FinishedNormally { .. })) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    last.ok_or_else(|| eyre!("no frame decoded"))
+}
+
+/// Write the overlay as PNG and results as JSON next to each other
+fn write_results(frame: &GUIFrame, png: &Path) -> Result<()> {
+    let rgb = composite(&frame.buffer, frame.decoded_buffer.as_deref(), frame.overlay_style);
+    rgb.save_with_format(png, ImageFormat::Png)?;
+    let json = fs::File::create(png.with_extension("json"))?;
+    serde_json::to_writer_pretty(json, &frame.record())?;
+    Ok(())
+}
+
+/// Process images, files of directories only if they are named like images
+pub(crate) fn run_images(opts: &BatchOpts, inputs: &[String]) -> Result<()> {
+    let mut images = vec![];
+    for input in inputs {
+        let dir = Path::new(input).is_dir();
+        let expanded = expand_inputs(std::slice::from_ref(input))?;
+        images.extend(expanded.into_iter().filter(|i| !dir || is_image(i)).map(|i| vec![i]));
+    }
+    if images.is_empty() {
+        return Err(eyre!("no images to process"));
+    }
+    fs::create_dir_all(&opts.out_dir)?;
+    let pngs = results_paths(&images, &opts.out_dir).into_iter().map(|p| p.with_extension("png"));
+    // the model is loaded once for all images
+    let mut app = configured_app(opts, 0)?;
+    // wait on results of each frame
+    app.control(AppCmd::Deterministic(true))?;
+    let mut failed = 0;
+    for (input, png) in images.iter().map(|i| &i[0]).zip(pngs) {
+        match process_image(&mut app, input).and_then(|frame| write_results(&frame, &png)) {
+            Ok(()) => info!("{} -> {:?}", input, png),
+            Err(e) => {
+                warn!("failed processing {}: {}", input, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(eyre!("{} of {} images failed", failed, images.len()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn image_files() {
+        assert!(is_image("a/b.png"));
+        assert!(is_image("b.JPG"));
+        assert!(!is_image("c.mp4"));
+        assert!(!is_image("notes"));
+    }
+}
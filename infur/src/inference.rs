@@ -0,0 +1,196 @@
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::{self, JoinHandle},
+};
+
+use image_ext::BgrImage;
+use tracing::debug;
+
+use crate::{
+    app::Processor,
+    predict_onnx::{Model, ModelCmd, ModelCmdError, ModelInfo, ModelOutput, ModelProcError},
+};
+
+type Outputs = Vec<ModelOutput>;
+
+/// Request to the thread owning the model
+enum Request {
+    Control(ModelCmd),
+    /// Infer on an image, which is passed back with the outputs to reuse both
+    Forward(BgrImage, Outputs),
+}
+
+/// Outcome of a command and the model's info thereafter
+type ControlReply = (Result<(), ModelCmdError>, Option<ModelInfo>);
+/// Image and outputs passed back, what the model saw if previewed, and the outcome
+type ForwardReply = (BgrImage, Outputs, Option<BgrImage>, Result<(), ModelProcError>);
+
+struct Connection {
+    tx: Sender<Request>,
+    control_rx: Receiver<ControlReply>,
+    forward_rx: Receiver<ForwardReply>,
+    thread: JoinHandle<()>,
+}
+
+/// Run requests until the worker hangs up
+fn serve(
+    requests: Receiver<Request>,
+    control_tx: Sender<ControlReply>,
+    forward_tx: Sender<ForwardReply>,
+) {
+    let mut model = Model::default();
+    for request in requests {
+        let sent = match request {
+            Request::Control(cmd) => {
+                let result = model.control(cmd).map(|_| ());
+                control_tx.send((result, model.get_info().cloned())).is_ok()
+            }
+            Request::Forward(img, mut out) => {
+                let result = model.advance(&img, &mut out);
+                forward_tx.send((img, out, model.take_input_preview(), result)).is_ok()
+            }
+        };
+        if !sent {
+            break;
+        }
+    }
+    debug!("inference stopped");
+}
+
+/// Model whose session lives on a thread of its own
+///
+/// Sessions are created, run and dropped there, so callers needn't care which thread they're on.
+/// The thread starts with the first command and again after it stopped, e.g. by a panic.
+#[derive(Default)]
+pub(crate) struct InferenceWorker {
+    conn: Option<Connection>,
+    /// Info of the model as of the last command
+    info: Option<ModelInfo>,
+    /// Image buffer to send with the next frame
+    spare: Option<BgrImage>,
+    /// What the model saw of the last frame, if previewed
+    input_preview: Option<BgrImage>,
+}
+
+impl InferenceWorker {
+    pub(crate) fn get_info(&self) -> Option<&ModelInfo> {
+        self.info.as_ref()
+    }
+
+    /// What the model saw of the last frame, see [`ModelCmd::PreviewInput`]
+    pub(crate) fn take_input_preview(&mut self) -> Option<BgrImage> {
+        self.input_preview.take()
+    }
+
+    fn connect(&mut self) -> Result<&Connection, ModelCmdError> {
+        if self.conn.is_none() {
+            let (tx, requests) = channel();
+            let (control_tx, control_rx) = channel();
+            let (forward_tx, forward_rx) = channel();
+            let thread = thread::Builder::new()
+                .name("Inference".to_string())
+                .spawn(move || serve(requests, control_tx, forward_tx))?;
+            self.conn = Some(Connection { tx, control_rx, forward_rx, thread });
+        }
+        self.conn.as_ref().ok_or(ModelCmdError::WorkerStopped)
+    }
+
+    /// Forget a stopped thread and what it had loaded
+    fn disconnect(&mut self) {
+        self.conn = None;
+        self.info = None;
+    }
+}
+
+impl Processor for InferenceWorker {
+    type Command = ModelCmd;
+    type ControlError = ModelCmdError;
+    type Input = BgrImage;
+    type Output = Outputs;
+    type ProcessResult = Result<(), ModelProcError>;
+
+    fn control(&mut self, cmd: Self::Command) -> Result<&mut Self, Self::ControlError> {
+        let conn = self.connect()?;
+        let reply =
+            conn.tx.send(Request::Control(cmd)).ok().and_then(|_| conn.control_rx.recv().ok());
+        match reply {
+            Some((result, info)) => {
+                self.info = info;
+                result?;
+                Ok(self)
+            }
+            None => {
+                self.disconnect();
+                Err(ModelCmdError::WorkerStopped)
+            }
+        }
+    }
+
+    fn advance(&mut self, img: &Self::Input, out: &mut Self::Output) -> Self::ProcessResult {
+        let conn = match &self.conn {
+            Some(conn) => conn,
+            // nothing loaded yet
+            None => {
+                out.clear();
+                return Ok(());
+            }
+        };
+        let mut input = match self.spare.take() {
+            Some(spare) if spare.dimensions() == img.dimensions() => spare,
+            _ => BgrImage::new(img.width(), img.height()),
+        };
+        input.copy_from_slice(img.as_raw());
+        let request = Request::Forward(input, std::mem::take(out));
+        let reply = conn.tx.send(request).ok().and_then(|_| conn.forward_rx.recv().ok());
+        match reply {
+            Some((input, outputs, preview, result)) => {
+                self.spare = Some(input);
+                self.input_preview = preview;
+                *out = outputs;
+                result
+            }
+            None => {
+                self.disconnect();
+                Err(ModelProcError::WorkerStopped)
+            }
+        }
+    }
+
+    fn is_dirty(&self) -> bool {
+        false
+    }
+}
+
+impl Drop for InferenceWorker {
+    /// Wait until the session is dropped
+    fn drop(&mut self) {
+        if let Some(Connection { tx, thread, .. }) = self.conn.take() {
+            drop(tx);
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use infur_test_gen::fcn_resnet50_12_int8_onnx;
+
+    #[test]
+    fn infer_on_worker() {
+        let mut worker = InferenceWorker::default();
+        let mut out = vec![];
+        worker.advance(&BgrImage::new(4, 4), &mut out).unwrap();
+        assert!(out.is_empty(), "nothing should be inferred without a model");
+
+        let model = fcn_resnet50_12_int8_onnx().to_string_lossy().to_string();
+        worker.control(ModelCmd::Load(model)).unwrap();
+        assert!(worker.get_info().is_some());
+        for _ in 0..2 {
+            worker.advance(&BgrImage::new(320, 240), &mut out).unwrap();
+            assert_eq!(out[0].shape(), [21, 240, 320]);
+        }
+        worker.control(ModelCmd::Load(String::default())).unwrap();
+        assert!(worker.get_info().is_none());
+    }
+}
@@ -1,138 +1,485 @@
+mod annotations;
 mod app;
+mod bgr_texture;
+mod calibration;
+mod class_map;
+mod cli;
+mod clips;
+mod config;
 mod decode_predict;
+mod doctor;
+mod embeddings;
+mod events;
+mod frame_reader;
+#[cfg(test)]
+mod golden;
 mod gui;
+mod headless;
+#[cfg(feature = "http")]
+mod http_api;
+mod images;
+mod inference;
+mod model_cache;
+#[cfg(feature = "mqtt")]
+mod mqtt_sink;
+#[cfg(feature = "otel")]
+mod otel;
 mod predict_onnx;
+mod preview;
 mod processing;
+mod reload;
+#[cfg(feature = "sqlite")]
+mod results_db;
+mod routing;
+mod rules;
+#[cfg(feature = "gpu_colorize")]
+mod seg_texture;
+mod session;
+mod shm;
+mod signals;
+mod sink;
+mod stdio;
+mod supervisor;
+mod synthetic_source;
+mod video_source;
+#[cfg(feature = "webhook")]
+mod webhook_sink;
+mod zones;
 
-use std::sync::mpsc::{Receiver, SyncSender, TryRecvError};
+use std::{
+    sync::{
+        atomic::Ordering,
+        mpsc::{Receiver, SyncSender, TryRecvError},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
 
-use app::{AppCmd, ProcessingApp, Processor};
-use gui::{CtrlResult, FrameResult};
+use app::{prioritize, AppCmd, FramePolicy, ProcessingApp, Processor, SharedStatus};
+use clap::Parser;
+use clips::FrameHistory;
+use embeddings::EmbeddingIndex;
+use events::{FrameSlot, ProcEvent, ProcIssue};
+use ff_video::AbortHandle;
+use preview::Preview;
+use reload::ConfigWatch;
+use session::CmdRecorder;
 use stable_eyre::eyre::{eyre, Report};
-use tracing::{debug, warn};
-use tracing_subscriber::{fmt, EnvFilter};
+use supervisor::{ProcShared, Supervisor};
+use tracing::{debug, debug_span, warn};
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
 /// Result with user facing error
 type Result<T> = std::result::Result<T, Report>;
 
-fn init_logs() -> Result<()> {
+/// Writes or exports collected traces when dropped
+#[derive(Default)]
+struct TraceGuard {
+    #[cfg(feature = "flame")]
+    _flame: Option<tracing_flame::FlushGuard<std::io::BufWriter<std::fs::File>>>,
+    #[cfg(feature = "otel")]
+    _otel: Option<otel::OtelGuard>,
+}
+
+#[cfg_attr(not(any(feature = "flame", feature = "otel")), allow(unused_variables, unused_mut))]
+fn init_logs(args: &cli::Args) -> Result<TraceGuard> {
     stable_eyre::install()?;
     let format = fmt::format().with_thread_names(true).with_target(false).compact();
     let filter = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info")).unwrap();
-    tracing_subscriber::fmt::fmt().with_env_filter(filter).event_format(format).init();
-    Ok(())
+    // stdout carries events in stdio mode
+    let writer = if args.stdio {
+        fmt::writer::BoxMakeWriter::new(std::io::stderr)
+    } else {
+        fmt::writer::BoxMakeWriter::new(std::io::stdout)
+    };
+    // the filter applies to logs only, traces record all spans
+    let logs = fmt::layer().event_format(format).with_writer(writer).with_filter(filter);
+    let registry = tracing_subscriber::registry().with(logs);
+    let mut guard = TraceGuard::default();
+    #[cfg(feature = "flame")]
+    let registry = registry.with(match &args.flame {
+        Some(path) => {
+            let (flame, flush) = tracing_flame::FlameLayer::with_file(path)?;
+            guard._flame = Some(flush);
+            Some(flame)
+        }
+        None => None,
+    });
+    #[cfg(feature = "otel")]
+    let registry = registry.with(match &args.otlp {
+        Some(endpoint) => {
+            let (otel, export) = otel::layer(endpoint)?;
+            guard._otel = Some(export);
+            Some(otel)
+        }
+        None => None,
+    });
+    registry.init();
+    Ok(guard)
 }
 
-/// Channel events from and processing results to GUI
+/// How often stats of a playing video are refreshed
+const INFO_INTERVAL: Duration = Duration::from_secs(1);
+/// Events queued up before processing waits on the GUI, refreshed stats are dropped instead
+const EVENTS_CAP: usize = 8;
+
+/// Channel commands from and events to GUI, frames are put into `frames`
+///
+/// While generating a frame, `busy_since` of the shared status is set for the watchdog.
+/// Any current video can be aborted via `abort`.
 fn proc_loop(
     ctrl_rx: Receiver<AppCmd>,
-    frame_tx: SyncSender<FrameResult>,
-    app_tx: SyncSender<CtrlResult>,
+    event_tx: SyncSender<ProcEvent>,
+    frames: Arc<FrameSlot>,
+    shared: Arc<ProcShared>,
+    abort: Arc<Mutex<Option<AbortHandle>>>,
 ) -> Result<()> {
-    fn send_app_info(app: &ProcessingApp, app_tx: &SyncSender<CtrlResult>) {
+    fn send_app_info(app: &ProcessingApp, event_tx: &SyncSender<ProcEvent>, status: &SharedStatus) {
         let app_info = app.info();
         debug!("sending updated app info {:?}", &app_info);
-        let _ = app_tx.send(Ok(app_info));
+        status.lock().unwrap().info = Some(app_info.clone());
+        let _ = event_tx.send(ProcEvent::Info(app_info));
+    }
+    /// Refresh stats that change while playing, skipped if the GUI lags behind
+    fn refresh_app_info(
+        app: &ProcessingApp,
+        event_tx: &SyncSender<ProcEvent>,
+        status: &SharedStatus,
+    ) {
+        let app_info = app.info();
+        status.lock().unwrap().info = Some(app_info.clone());
+        let _ = event_tx.try_send(ProcEvent::Metrics(app_info));
     }
+    let status = &shared.status;
+    let set_busy = |busy: bool| status.lock().unwrap().busy_since = busy.then(Instant::now);
 
-    // instantiate app in processing thread,
-    // since ort session can't be moved/sent
+    // instantiate app in processing thread, a restarted worker starts afresh
     let mut app = ProcessingApp::default();
+    let mut info_since = Instant::now();
 
     loop {
         // todo: exit on closed channel?
         let mut state_change = false;
         loop {
-            let cmd = if !app.is_dirty() {
+            let mut cmds = vec![];
+            if !app.is_dirty() {
                 // video is not playing, block
                 debug!("blocking on new command");
+                // don't hold results or clips back, e.g. in a transaction others wait on
+                shared.history.finish_pending();
+                for sink in shared.sinks.lock().unwrap_or_else(PoisonError::into_inner).iter_mut() {
+                    if let Err(e) = sink.flush() {
+                        warn!("couldn't flush results: {}", e);
+                    }
+                }
                 if state_change {
-                    send_app_info(&app, &app_tx);
+                    send_app_info(&app, &event_tx, status);
                     state_change = false;
                 };
+                set_busy(false);
                 match ctrl_rx.recv() {
-                    Ok(c) => Some(c),
+                    Ok(c) => cmds.push(c),
                     // unfixable (hung-up)
                     Err(e) => return Err(eyre!(e)),
                 }
-            } else {
-                // video is playing, don't block
+            }
+            // take all that queued up, don't block
+            loop {
                 match ctrl_rx.try_recv() {
-                    Ok(c) => Some(c),
+                    Ok(c) => cmds.push(c),
                     Err(TryRecvError::Empty) => break,
                     // unfixable (hung-up)
                     Err(e) => return Err(eyre!(e)),
                 }
-            };
-            if let Some(cmd) = cmd {
+            }
+            if cmds.is_empty() {
+                break;
+            }
+            // commands aren't timed, e.g. loading a model may take a while on some providers
+            for cmd in prioritize(cmds) {
+                let _span = debug_span!("command", ?cmd).entered();
                 debug!("relaying command: {:?}", cmd);
                 if let Err(e) = app.control(cmd) {
                     // Control Error
-                    let _ = app_tx.send(Err(e));
+                    debug!("command failed: {}", e);
+                    status.lock().unwrap().error = Some(e.to_string());
+                    let _ = event_tx.send(ProcEvent::Progress(ProcIssue::Cmd(e)));
                 } else {
                     state_change = true;
                 }
-            };
-            if app.to_exit {
-                return Ok(());
-            };
+                if app.to_exit {
+                    return Ok(());
+                };
+            }
+            *abort.lock().unwrap() = app.abort_handle();
         }
 
         if state_change {
-            send_app_info(&app, &app_tx);
+            send_app_info(&app, &event_tx, status);
+            info_since = Instant::now();
+        } else if info_since.elapsed() > INFO_INTERVAL {
+            refresh_app_info(&app, &event_tx, status);
+            info_since = Instant::now();
         }
 
-        match app.generate() {
+        set_busy(true);
+        let mut generated = app.generate();
+        // reported right away, unlike stats refreshed at intervals
+        if app.take_adapted() {
+            send_app_info(&app, &event_tx, status);
+            info_since = Instant::now();
+        }
+        {
+            let mut status = status.lock().unwrap();
+            match &generated {
+                Ok(Some(frame)) => {
+                    status.frame_id = Some(frame.id);
+                    status.frames += 1;
+                }
+                Ok(None) => {}
+                Err(e) => status.error = Some(e.to_string()),
+            }
+        }
+        if let Ok(Some(frame)) = &mut generated {
+            // leave only uploading it to the GUI thread
+            frame.composite();
+            shared.preview.publish(frame);
+            shared.history.push(frame);
+            shared.embeddings.push(frame);
+            let record = frame.record();
+            for sink in shared.sinks.lock().unwrap_or_else(PoisonError::into_inner).iter_mut() {
+                if let Err(e) = sink.write(&record) {
+                    warn!("couldn't write results of frame {}: {}", frame.id, e);
+                }
+            }
+        }
+        // waiting on the GUI isn't hanging
+        set_busy(false);
+        match generated {
+            // block on GUI backpressure, else replace a frame the GUI didn't show yet
             Ok(Some(frame)) => {
-                // block on GUI backpressure
-                let _ = frame_tx.send(Ok(frame));
+                let wait = app.frame_policy() == FramePolicy::ShowEvery;
+                if !frames.put(frame, wait) {
+                    debug!("GUI closed its frames");
+                } else if wait {
+                    let _ = event_tx.send(ProcEvent::FrameReady);
+                } else {
+                    // the GUI takes the latest frame anyway once it catches up
+                    let _ = event_tx.try_send(ProcEvent::FrameReady);
+                }
             }
+            // or skipped by the error policy
             Ok(None) => {
-                warn!("Nothing to process yet")
+                debug!("Nothing to process yet")
             }
 
             Err(e) => {
-                let _ = frame_tx.send(Err(e));
+                let _ = event_tx.send(ProcEvent::Progress(ProcIssue::Frame(e)));
             }
         };
     }
 }
 
 fn main() -> Result<()> {
-    init_logs()?;
-    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = cli::Args::parse();
+    let _traces = init_logs(&args)?;
+    if args.doctor {
+        let checks = doctor::full_checks();
+        println!("{}", doctor::report(&checks));
+        let problems = doctor::problems(&checks);
+        if !problems.is_empty() {
+            return Err(eyre!("{} of {} checks failed", problems.len(), checks.len()));
+        }
+        return Ok(());
+    }
+    let file_state = config::load_startup(args.config.as_deref())?;
+    let file_config = file_state.as_ref().and_then(|s| s.first().cloned());
+    // only a file given explicitly is watched for changes
+    let watched = args.config.clone().zip(file_state.clone());
+    if !args.images.is_empty() {
+        let opts = headless::BatchOpts::new(&args, file_config)?;
+        return images::run_images(&opts, &args.images);
+    }
+    if args.stdio {
+        let opts = headless::BatchOpts::new(&args, file_config)?;
+        let stop = opts.stop.clone();
+        signals::on_stop(move || stop.store(true, Ordering::Relaxed))?;
+        return stdio::run(&opts);
+    }
+    if args.headless {
+        let mut opts = headless::BatchOpts::new(&args, file_config)?;
+        opts.watch =
+            watched.map(|(path, state)| Mutex::new(headless::BatchWatch::new(&path, state)));
+        let stop = opts.stop.clone();
+        signals::on_stop(move || stop.store(true, Ordering::Relaxed))?;
+        #[cfg(feature = "http")]
+        let _api = match &args.http {
+            Some(addr) => {
+                let ctx = http_api::ApiContext {
+                    ctrl_tx: None,
+                    status: opts.status.clone(),
+                    preview: opts.preview.clone(),
+                };
+                Some(http_api::spawn_server(addr, ctx)?)
+            }
+            None => None,
+        };
+        return headless::run_batch(opts);
+    }
+    let config_path = config::config_path(args.config.as_deref());
 
-    let (frame_tx, frame_rx) = std::sync::mpsc::sync_channel(2);
-    let (ctrl_tx, ctrl_rx) = std::sync::mpsc::channel();
-    let (ctrl_result_tx, ctrl_result_rx) = std::sync::mpsc::sync_channel(2);
+    let status = SharedStatus::default();
+    let preview = Arc::new(Preview::default());
+    let history_opts = file_config.as_ref().map(|c| c.history).unwrap_or_default();
+    let history = Arc::new(FrameHistory::new(history_opts));
+    // rules watch the first pipeline like sinks
+    let rule_specs = file_config.as_ref().map(|c| c.rules.clone()).unwrap_or_default();
+    let rules = rules::Rules::open(&rule_specs, Some(history.clone()))?;
+    let annotate = rule_specs.iter().any(rules::Rule::annotates);
+    let sink_specs = args.sink_specs(file_config.as_ref())?;
+    let mut sinks = sink::open_all(&sink_specs)?;
+    sinks.push(Box::new(rules::RuleSink(rules.clone())));
+    let mut sinks = Some(sinks);
+    let mut recorder = args.record.as_deref().map(CmdRecorder::create).transpose()?;
 
-    debug!("spawning Proc thread");
-    let infur_thread = std::thread::Builder::new()
-        .name("Proc".to_string())
-        .spawn(move || proc_loop(ctrl_rx, frame_tx, ctrl_result_tx))?;
+    // one pipeline per input or as saved, the first one is controlled and observed from outside
+    let file_configs = file_state.as_ref().map(|s| args.pipeline_configs(s.pipelines.clone()));
+    let n_pipelines = match &file_configs {
+        Some(configs) => configs.len(),
+        None => args.pipeline_inputs().len().max(1),
+    };
+    let proc_threads = Arc::new(Mutex::new(Vec::with_capacity(n_pipelines)));
+    let signal_txs = Arc::new(Mutex::new(Vec::with_capacity(n_pipelines)));
+    let mut spawn_pipeline = {
+        let (status, preview, history) = (status.clone(), preview.clone(), history.clone());
+        let (proc_threads, signal_txs) = (proc_threads.clone(), signal_txs.clone());
+        let timeout = Duration::from_secs(args.watchdog);
+        let deterministic = args.deterministic;
+        move |id: usize| -> Result<gui::PipelineChannels> {
+            let (event_tx, event_rx) = std::sync::mpsc::sync_channel(EVENTS_CAP);
+            let (ctrl_tx, ctrl_rx) = std::sync::mpsc::channel();
+            let frames = Arc::new(FrameSlot::default());
+            let (status, preview, history) = match id {
+                0 => (status.clone(), preview.clone(), history.clone()),
+                _ => (SharedStatus::default(), Arc::default(), Arc::default()),
+            };
+            let sinks = sinks.take().unwrap_or_default();
+            let embeddings = Arc::new(EmbeddingIndex::default());
+            let shared = Arc::new(ProcShared {
+                status,
+                preview,
+                history: history.clone(),
+                embeddings: embeddings.clone(),
+                sinks: sinks.into(),
+            });
+            let supervisor = Supervisor {
+                id,
+                event_tx,
+                frames: frames.clone(),
+                shared,
+                recorder: recorder.take(),
+                timeout,
+            };
 
+            debug!("spawning Supervisor{} thread", id);
+            let thread = std::thread::Builder::new()
+                .name(format!("Supervisor{}", id))
+                .spawn(move || supervisor.run(ctrl_rx))?;
+            proc_threads.lock().unwrap().push(thread);
+            if deterministic {
+                let _ = ctrl_tx.send(AppCmd::Deterministic(true));
+            }
+            if annotate && id == 0 {
+                let _ = ctrl_tx.send(AppCmd::Annotate(true));
+            }
+            signal_txs.lock().unwrap().push(ctrl_tx.clone());
+            Ok(gui::PipelineChannels { ctrl_tx, event_rx, frames, history, embeddings })
+        }
+    };
+    let mut pipelines = (0..n_pipelines).map(&mut spawn_pipeline).collect::<Result<Vec<_>>>()?;
+    let ctrl_tx = pipelines[0].ctrl_tx.clone();
+
+    if let Some(replay) = &args.replay {
+        session::spawn_replay(session::load(replay)?, ctrl_tx.clone())?;
+    }
+
+    #[cfg(feature = "http")]
+    let _api = match &args.http {
+        Some(addr) => {
+            let ctx = http_api::ApiContext { ctrl_tx: Some(ctrl_tx.clone()), status, preview };
+            Some(http_api::spawn_server(addr, ctx)?)
+        }
+        None => None,
+    };
+
+    let env_problems = doctor::problems(&doctor::light_checks());
+    for problem in env_problems.iter() {
+        warn!("{}", problem);
+    }
+
+    drop(ctrl_tx);
+
+    // stop all pipelines, the GUI closes once they hung up
+    signals::on_stop(move || {
+        for ctrl_tx in signal_txs.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+            for cmd in signals::stop_cmds() {
+                let _ = ctrl_tx.send(cmd);
+            }
+        }
+    })?;
+
+    #[cfg(feature = "sqlite")]
+    let results_db = sink::results_db(&sink_specs);
     debug!("starting InFur GUI");
     let window_opts = eframe::NativeOptions { vsync: true, ..Default::default() };
-    let ctrl_tx_gui = ctrl_tx;
     eframe::run_native(
         "InFur",
         window_opts,
         Box::new(|cc| {
-            let mut config = match cc.storage {
+            // config file takes precedence over stored state, still override from args
+            let (mut configs, active) = match (file_configs, cc.storage) {
+                (Some(configs), _) => (configs, file_state.map_or(0, |s| s.active)),
                 #[cfg(feature = "persistence")]
-                Some(storage) => eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default(),
-                _ => gui::ProcConfig::default(),
+                (None, Some(storage)) => match config::restore(storage) {
+                    Some(state) => (args.pipeline_configs(state.pipelines), state.active),
+                    None => (args.pipeline_configs(vec![]), 0),
+                },
+                _ => (args.pipeline_configs(vec![]), 0),
+            };
+            // stored state may be of more pipelines than started, or fewer
+            while pipelines.len() < configs.len() {
+                match spawn_pipeline(pipelines.len()) {
+                    Ok(pipeline) => pipelines.push(pipeline),
+                    Err(e) => {
+                        warn!("couldn't restore pipeline {}: {}", pipelines.len(), e);
+                        break;
+                    }
+                }
+            }
+            let first = configs[0].clone();
+            configs.resize(pipelines.len(), first);
+            let app_gui = gui::InFur::new(configs.into_iter().zip(pipelines).collect())
+                .with_active(active)
+                .with_reload(watched.map(|(path, state)| ConfigWatch::new(&path, state)))
+                .with_config_path(config_path)
+                .with_env_problems(env_problems)
+                .with_rules(rules);
+            #[cfg(feature = "sqlite")]
+            let app_gui = match results_db {
+                Some(path) => app_gui.with_results_db(&path),
+                None => app_gui,
             };
-            // still override video from args
-            if !args.is_empty() {
-                config.video_input = args;
+            match &cc.wgpu_render_state {
+                Some(render_state) => Box::new(app_gui.with_bgr_painting(render_state)),
+                None => Box::new(app_gui),
             }
-            let app_gui = gui::InFur::new(config, ctrl_tx_gui, frame_rx, ctrl_result_rx);
-            Box::new(app_gui)
         }),
     );
 
     // ensure exit code
-    infur_thread.join().unwrap().unwrap();
+    let proc_threads = std::mem::take(&mut *proc_threads.lock().unwrap());
+    for thread in proc_threads {
+        thread.join().unwrap().unwrap();
+    }
     Ok(())
 }
@@ -0,0 +1,165 @@
+use std::{
+    env, fs, io,
+    os::raw::c_char,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+use ort::{sys, GraphOptimizationLevel, LoggingLevel};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::info;
+
+use crate::doctor;
+
+/// Overrides where optimized models are cached, empty disables caching
+const CACHE_DIR_VAR: &str = "INFUR_CACHE_DIR";
+
+#[derive(Error, Debug)]
+pub(crate) enum ModelCacheError {
+    #[error("model cache disabled")]
+    Disabled,
+    #[error("model cache: {0}")]
+    Io(#[from] io::Error),
+    #[error("optimizing model for cache: {0}")]
+    Ort(String),
+}
+
+/// Directory for optimized models, `None` if disabled
+fn cache_dir() -> Option<PathBuf> {
+    match env::var_os(CACHE_DIR_VAR) {
+        Some(dir) if dir.is_empty() => None,
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => {
+            #[cfg(windows)]
+            let base = env::var_os("LOCALAPPDATA").map(PathBuf::from);
+            #[cfg(not(windows))]
+            let base = env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")));
+            base.map(|base| base.join(env!("CARGO_PKG_NAME")).join("models"))
+        }
+    }
+}
+
+/// Hex encoded SHA256 of a file
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// File name of an optimized model, unique per model content and onnxruntime version
+fn cache_name(model: &Path, hash: &str, ort_version: &str) -> String {
+    let stem = model.file_stem().map_or_else(|| "model".into(), |s| s.to_string_lossy());
+    format!("{}-{}-ort{}-basic.onnx", stem, &hash[..16.min(hash.len())], ort_version)
+}
+
+/// Path to the model with basic optimizations, optimized and cached on first use
+///
+/// Basic optimizations hold for any execution provider. Extended ones depend on the provider and
+/// are still applied by sessions loading it.
+pub(crate) fn optimized(model: &Path) -> Result<PathBuf, ModelCacheError> {
+    let dir = cache_dir().ok_or(ModelCacheError::Disabled)?;
+    let ort_version = doctor::ort_version().map_err(ModelCacheError::Ort)?;
+    let cached = dir.join(cache_name(model, &sha256_file(model)?, &ort_version));
+    if cached.is_file() {
+        return Ok(cached);
+    }
+    fs::create_dir_all(&dir)?;
+    // write aside so that an interrupted optimization isn't picked up
+    let part = cached.with_extension("onnx.part");
+    info!("optimizing {:?} into {:?}", model, cached);
+    // SAFETY: all pointers are created, checked and released within
+    unsafe { write_optimized(model, &part) }.map_err(ModelCacheError::Ort)?;
+    fs::rename(&part, &cached)?;
+    Ok(cached)
+}
+
+/// Path as expected by onnxruntime
+#[cfg(not(windows))]
+fn ort_path(path: &Path) -> Result<Vec<c_char>, String> {
+    use std::os::unix::ffi::OsStrExt;
+    let bytes = path.as_os_str().as_bytes();
+    if bytes.contains(&0) {
+        return Err(format!("invalid path {:?}", path));
+    }
+    Ok(bytes.iter().chain(Some(&0)).map(|&b| b as c_char).collect())
+}
+
+#[cfg(windows)]
+fn ort_path(path: &Path) -> Result<Vec<u16>, String> {
+    use std::os::windows::ffi::OsStrExt;
+    Ok(path.as_os_str().encode_wide().chain(Some(0)).collect())
+}
+
+/// Message of a failed status, which is released
+unsafe fn check(api: *const sys::OrtApi, status: *mut sys::OrtStatus) -> Result<(), String> {
+    if status.is_null() {
+        return Ok(());
+    }
+    let msg = (*api).GetErrorMessage.map_or_else(String::new, |m| doctor::to_string(m(status)));
+    if let Some(release_status) = (*api).ReleaseStatus {
+        release_status(status);
+    }
+    Err(msg)
+}
+
+/// Load a model once to have onnxruntime write its optimized graph
+unsafe fn write_optimized(model: &Path, optimized: &Path) -> Result<(), String> {
+    let base = sys::OrtGetApiBase();
+    let api = (*base).GetApi.ok_or("no api")?(sys::ORT_API_VERSION);
+    if api.is_null() {
+        return Err(format!("api version {} isn't supported", sys::ORT_API_VERSION));
+    }
+    let missing = |f: &str| format!("api lacks {}", f);
+    let create_env = (*api).CreateEnv.ok_or_else(|| missing("CreateEnv"))?;
+    let create_options =
+        (*api).CreateSessionOptions.ok_or_else(|| missing("CreateSessionOptions"))?;
+    let set_level = (*api)
+        .SetSessionGraphOptimizationLevel
+        .ok_or_else(|| missing("SetSessionGraphOptimizationLevel"))?;
+    let set_path =
+        (*api).SetOptimizedModelFilePath.ok_or_else(|| missing("SetOptimizedModelFilePath"))?;
+    let create_session = (*api).CreateSession.ok_or_else(|| missing("CreateSession"))?;
+    let release_env = (*api).ReleaseEnv.ok_or_else(|| missing("ReleaseEnv"))?;
+    let release_options =
+        (*api).ReleaseSessionOptions.ok_or_else(|| missing("ReleaseSessionOptions"))?;
+    let release_session = (*api).ReleaseSession.ok_or_else(|| missing("ReleaseSession"))?;
+
+    let (model_path, optimized_path) = (ort_path(model)?, ort_path(optimized)?);
+    let log_id = b"infur-model-cache\0";
+
+    // the environment is shared with other sessions of this process
+    let mut env = ptr::null_mut();
+    let level = LoggingLevel::Warning.into();
+    check(api, create_env(level, log_id.as_ptr() as *const c_char, &mut env))?;
+    let mut options = ptr::null_mut();
+    let mut result = check(api, create_options(&mut options));
+    if result.is_ok() {
+        let mut session = ptr::null_mut();
+        result = check(api, set_level(options, GraphOptimizationLevel::Level1.into()))
+            .and_then(|_| check(api, set_path(options, optimized_path.as_ptr())))
+            .and_then(|_| {
+                check(api, create_session(env, model_path.as_ptr(), options, &mut session))
+            });
+        if !session.is_null() {
+            release_session(session);
+        }
+        release_options(options);
+    }
+    release_env(env);
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn name_by_content_and_version() {
+        let name = cache_name(Path::new("models/fcn.onnx"), "0123456789abcdef0123", "1.13.1");
+        assert_eq!(name, "fcn-0123456789abcdef-ort1.13.1-basic.onnx");
+    }
+}
@@ -0,0 +1,126 @@
+use std::{thread, time::Duration};
+
+use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+use serde::{Deserialize, Serialize};
+use stable_eyre::eyre::eyre;
+use tracing::{debug, info, warn};
+
+use crate::{
+    app::FrameRecord,
+    sink::{ResultSink, SinkError},
+    Result,
+};
+
+/// Capacity of requests queued for the broker before frames are dropped
+const QUEUE_CAP: usize = 16;
+
+/// Broker address and what to publish where
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct MqttOpts {
+    pub(crate) host: String,
+    #[serde(default = "default_port")]
+    pub(crate) port: u16,
+    #[serde(default = "default_topic")]
+    pub(crate) topic: String,
+    #[serde(default)]
+    pub(crate) qos: u8,
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+pub(crate) fn default_topic() -> String {
+    "infur/frames".to_string()
+}
+
+impl MqttOpts {
+    /// Options from a `host[:port]` address, port defaults to 1883
+    pub(crate) fn new(addr: &str, topic: &str, qos: u8) -> Result<Self> {
+        let (host, port) = match addr.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse()?),
+            None => (addr, default_port()),
+        };
+        if host.is_empty() {
+            return Err(eyre!("missing MQTT host in {:?}", addr));
+        }
+        Ok(Self { host: host.to_string(), port, topic: topic.to_string(), qos })
+    }
+}
+
+fn to_qos(qos: u8) -> QoS {
+    match qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+/// Publishes per-frame results to an MQTT broker
+///
+/// Publishing never blocks processing, frames are dropped while the broker lags behind.
+#[derive(Clone, Debug)]
+pub(crate) struct MqttSink {
+    client: Client,
+    topic: String,
+    qos: QoS,
+}
+
+impl MqttSink {
+    /// Connect in the background, reconnecting whenever the connection is lost
+    pub(crate) fn connect(opts: &MqttOpts) -> std::result::Result<Self, SinkError> {
+        let client_id = format!("infur-{}", std::process::id());
+        let mut mqtt_opts = MqttOptions::new(client_id, opts.host.clone(), opts.port);
+        mqtt_opts.set_keep_alive(Duration::from_secs(10));
+        let (client, mut connection) = Client::new(mqtt_opts, QUEUE_CAP);
+
+        let addr = format!("{}:{}", opts.host, opts.port);
+        thread::Builder::new().name("MQTT".to_string()).spawn(move || {
+            for notification in connection.iter() {
+                match notification {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => info!("connected to MQTT {}", addr),
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("MQTT connection to {} failed: {}", addr, e);
+                        thread::sleep(Duration::from_secs(1));
+                    }
+                }
+            }
+        })?;
+        Ok(Self { client, topic: opts.topic.clone(), qos: to_qos(opts.qos) })
+    }
+
+    /// Publish any message as JSON, dropped while the broker lags behind
+    pub(crate) fn publish<T: Serialize>(&self, msg: &T) -> std::result::Result<(), SinkError> {
+        let payload = serde_json::to_vec(msg)?;
+        if let Err(e) = self.client.try_publish(&self.topic, self.qos, false, payload) {
+            debug!("dropped MQTT message: {}", e);
+        }
+        Ok(())
+    }
+}
+
+impl ResultSink for MqttSink {
+    fn write(&mut self, record: &FrameRecord) -> std::result::Result<(), SinkError> {
+        let payload = serde_json::to_vec(record)?;
+        if let Err(e) = self.client.try_publish(&self.topic, self.qos, false, payload) {
+            debug!("dropped MQTT message of frame {}: {}", record.id, e);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn broker_addr() {
+        let opts = MqttOpts::new("broker.local", "infur/frames", 1).unwrap();
+        assert_eq!((opts.host.as_str(), opts.port), ("broker.local", 1883));
+        let opts = MqttOpts::new("10.0.0.2:1884", "t", 0).unwrap();
+        assert_eq!((opts.host.as_str(), opts.port), ("10.0.0.2", 1884));
+        assert!(MqttOpts::new(":1883", "t", 0).is_err());
+        assert!(MqttOpts::new("host:port", "t", 0).is_err());
+    }
+}
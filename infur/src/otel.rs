@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use opentelemetry::{
+    sdk::{
+        resource::{EnvResourceDetector, ResourceDetector},
+        trace, Resource,
+    },
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use tokio::runtime::Runtime;
+use tracing::Subscriber;
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+/// Time to read resource attributes from the environment
+const DETECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Exports spans in the background, pending ones are flushed when dropped
+pub(crate) struct OtelGuard {
+    runtime: Runtime,
+}
+
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        let _entered = self.runtime.enter();
+        opentelemetry::global::shutdown_tracer_provider();
+    }
+}
+
+/// Layer exporting spans to an OTLP/gRPC endpoint, e.g. `http://localhost:4317`
+///
+/// Instances are told apart by `OTEL_RESOURCE_ATTRIBUTES`, e.g. `service.instance.id=cam-3`.
+pub(crate) fn layer<S>(endpoint: &str) -> crate::Result<(impl Layer<S>, OtelGuard)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("Otel")
+        .enable_all()
+        .build()?;
+    let entered = runtime.enter();
+    // the environment overrides defaults
+    let env = EnvResourceDetector::new().detect(DETECT_TIMEOUT);
+    let resource = Resource::new([
+        KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+        KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        KeyValue::new("process.pid", std::process::id() as i64),
+    ])
+    .merge(&env);
+    let exporter = opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint);
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(trace::config().with_resource(resource))
+        .install_batch(opentelemetry::runtime::Tokio)?;
+    drop(entered);
+    Ok((tracing_opentelemetry::layer().with_tracer(tracer), OtelGuard { runtime }))
+}
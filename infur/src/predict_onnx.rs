@@ -1,33 +1,36 @@
-use std::marker::PhantomData;
-
-use image_ext::BgrImage;
-use once_cell::sync::Lazy;
-use onnxruntime::{
-    environment::Environment,
-    ndarray,
-    ndarray::{arr1, Array4, ArrayD, ArrayView4, Axis, IxDyn},
-    session::{Input, Session},
-    tensor::OrtOwnedTensor,
-    GraphOptimizationLevel, LoggingLevel, OrtError, TensorElementDataType,
-    TypeToTensorElementDataType,
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use image_ext::{Bgr, BgrImage, ChannelLayout, F32Image, StridedView};
+use ndarray::{ArrayD, ArrayView4, Axis, CowArray, IxDyn, ShapeBuilder};
+use ort::{
+    session::Input, tensor::IntoTensorElementDataType, Environment, GraphOptimizationLevel,
+    LoggingLevel, OrtError, Session, SessionBuilder, TensorElementDataType, Value,
 };
 use thiserror::Error;
+use tracing::warn;
 
-use crate::app::Processor;
+use crate::{
+    app::Processor,
+    model_cache::{self, ModelCacheError},
+};
 
-// ONNX global environment to provide 'static to any session
-static ENVIRONMENT: Lazy<Environment> = Lazy::new(|| {
+/// ONNX environment shared by all sessions, ort keeps only one per process
+fn environment() -> Result<Arc<Environment>, OrtError> {
     #[cfg(debug_assertions)]
     const LOGGING_LEVEL: LoggingLevel = LoggingLevel::Verbose;
     #[cfg(not(debug_assertions))]
     const LOGGING_LEVEL: LoggingLevel = LoggingLevel::Warning;
 
-    Environment::builder()
+    Ok(Environment::builder()
         .with_name(env!("CARGO_PKG_NAME"))
         .with_log_level(LOGGING_LEVEL)
-        .build()
-        .unwrap()
-});
+        .build()?
+        .into_arc())
+}
 
 /// Error processing model
 #[derive(Error, Debug)]
@@ -36,6 +39,10 @@ pub(crate) enum ModelProcError {
     ShapeError(#[from] ndarray::ShapeError),
     #[error("scaling to 0-sized output")]
     RuntimeError(#[from] OrtError),
+    #[error("output of type {0:?} isn't supported")]
+    OutputType(TensorElementDataType),
+    #[error("inference worker stopped")]
+    WorkerStopped,
 }
 
 /// Error loading model
@@ -45,6 +52,50 @@ pub(crate) enum ModelCmdError {
     OrtError(#[from] OrtError),
     #[error(transparent)]
     RuntimeError(#[from] ModelInputFormatError),
+    #[error("no execution provider could run the model: {0}")]
+    NoProvider(String),
+    #[error("couldn't start inference worker")]
+    Spawn(#[from] std::io::Error),
+    #[error("inference worker stopped")]
+    WorkerStopped,
+}
+
+/// Hardware backend of onnxruntime to run models on
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Deserialize, serde::Serialize,
+)]
+pub(crate) enum ExecutionProvider {
+    TensorRt,
+    Cuda,
+    DirectMl,
+    CoreMl,
+    Cpu,
+}
+
+impl ExecutionProvider {
+    pub(crate) const ALL: [Self; 5] =
+        [Self::TensorRt, Self::Cuda, Self::DirectMl, Self::CoreMl, Self::Cpu];
+
+    /// Provider with default options
+    fn to_ort(self) -> ort::ExecutionProvider {
+        match self {
+            Self::TensorRt => ort::ExecutionProvider::TensorRT(Default::default()),
+            Self::Cuda => ort::ExecutionProvider::CUDA(Default::default()),
+            Self::DirectMl => ort::ExecutionProvider::DirectML(Default::default()),
+            Self::CoreMl => ort::ExecutionProvider::CoreML(Default::default()),
+            Self::Cpu => ort::ExecutionProvider::CPU(Default::default()),
+        }
+    }
+
+    /// Providers to try in order, falling back to the CPU
+    pub(crate) fn chain(preferred: &[Self]) -> Vec<Self> {
+        let mut chain = preferred.to_vec();
+        chain.dedup();
+        if !chain.contains(&Self::Cpu) {
+            chain.push(Self::Cpu);
+        }
+        chain
+    }
 }
 
 #[derive(Error, Debug)]
@@ -53,23 +104,93 @@ pub(crate) enum ModelInputFormatError {
     Infer(String),
 }
 
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub(crate) struct ModelInfo {
     pub(crate) input_names: Vec<String>,
     pub(crate) input0_dtype: String,
     pub(crate) output_names: Vec<String>,
+    /// Provider the session was created with
+    pub(crate) execution_provider: ExecutionProvider,
+    /// Image input format in use, as guessed or overridden
+    pub(crate) input_format: InputFormat,
+}
+
+/// Output of a model without batch dimension
+#[derive(Clone, Debug)]
+pub(crate) enum ModelOutput {
+    /// Floats, e.g. confidences per class
+    Float(ArrayD<f32>),
+    /// Integers as class indices, saturated to `u16::MAX` and thus out of range
+    ClassIndex(ArrayD<u16>),
+}
+
+/// Empty, e.g. to take an output's place
+impl Default for ModelOutput {
+    fn default() -> Self {
+        Self::Float(ArrayD::zeros(IxDyn(&[0])))
+    }
+}
+
+impl ModelOutput {
+    pub(crate) fn shape(&self) -> &[usize] {
+        match self {
+            Self::Float(a) => a.shape(),
+            Self::ClassIndex(a) => a.shape(),
+        }
+    }
+
+    /// Buffer to write floats into, empty if of class indices
+    fn into_floats(self) -> ArrayD<f32> {
+        match self {
+            Self::Float(a) => a,
+            Self::ClassIndex(_) => ArrayD::zeros(IxDyn(&[0])),
+        }
+    }
+
+    /// Buffer to write class indices into, empty if of floats
+    fn into_indices(self) -> ArrayD<u16> {
+        match self {
+            Self::Float(_) => ArrayD::zeros(IxDyn(&[0])),
+            Self::ClassIndex(a) => a,
+        }
+    }
+}
+
+/// First item of a batched tensor, written into `kept` if that has as many elements
+fn unbatch<T: IntoTensorElementDataType + Debug + Clone, U>(
+    value: &Value,
+    kept: ArrayD<U>,
+    convert: impl Fn(T) -> U,
+) -> Result<ArrayD<U>, OrtError> {
+    let tensor = value.try_extract::<T>()?;
+    let batch = tensor.view();
+    let first = batch.index_axis(Axis(0), 0);
+    // decoders may have reshaped it, e.g. squeezed 1xHxW into HxW
+    Ok(match kept.into_shape(first.shape()) {
+        Ok(mut kept) => {
+            kept.zip_mut_with(&first, |k, v| *k = convert(v.clone()));
+            kept
+        }
+        Err(_) => first.mapv(convert),
+    })
+}
+
+/// Integer as class index, saturated to `u16::MAX`
+fn class_index<T: TryInto<u16>>(i: T) -> u16 {
+    i.try_into().unwrap_or(u16::MAX)
 }
 
 #[derive(Debug)]
-struct ImageSession<'s> {
-    session: Session<'s>,
+struct ImageSession {
+    session: Session,
     img_proc: ImgPreProc,
     model_info: ModelInfo,
+    /// Kept buffer for f32 inputs
+    input_f32: F32Image,
 }
 
 /// ONNX session with pre-processing u8 images.
-impl<'s> ImageSession<'s> {
+impl ImageSession {
     /// Construct an `ImageSession` by inferring some required image input meta data.
     ///
     /// The basic assumption is that images are passed as batches at position 0.
@@ -77,85 +198,200 @@ impl<'s> ImageSession<'s> {
     /// #Arguments
     ///
     /// * `session` - ONNX session with desired runtime behavior
-    /// * `color_seq` - Order of color channels in the color dimension
-    /// * `norm_float` - Whether `f32` input should not be scaled from 0-1 but around another mean and std deviation
+    /// * `execution_provider` - Provider the session runs on
+    /// * `format` - Image input format, unset parts are inferred
     fn try_from_session(
-        session: Session<'s>,
-        color_seq: ColorSeq,
-        norm_float: Option<ColorNorm<f32>>,
+        session: Session,
+        execution_provider: ExecutionProvider,
+        format: InputFormat,
     ) -> Result<Self, ModelInputFormatError> {
-        let (dim_seq, color_range) = infer_img_pre_proc(&session.inputs[0], norm_float)?;
-        let img_proc = ImgPreProc { dim_seq, color_seq, color_range };
+        let input_format = resolve_input_format(&session.inputs[0], format)?;
+        let img_proc = ImgPreProc::from(&input_format);
         let input_names = session.inputs.iter().map(|i| i.name.clone()).collect();
         let input0_dtype = format!("{:?}", session.inputs[0].input_type);
         let output_names = session.outputs.iter().map(|o| o.name.clone()).collect();
-        let model_info = ModelInfo { input_names, input0_dtype, output_names };
-        Ok(Self { session, img_proc, model_info })
+        let model_info =
+            ModelInfo { input_names, input0_dtype, output_names, execution_provider, input_format };
+        let input_f32 = F32Image::new(0, 0, img_proc.layout());
+        Ok(Self { session, img_proc, model_info, input_f32 })
     }
 
-    /// Forward pass an NHWC(BGR) image batch
-    fn forward<T: Clone + std::fmt::Debug + onnxruntime::TypeToTensorElementDataType>(
+    /// Feed images in another format, the current one is kept if it's invalid
+    fn set_input_format(&mut self, format: InputFormat) -> Result<(), ModelInputFormatError> {
+        let input_format = resolve_input_format(&self.session.inputs[0], format)?;
+        self.img_proc = ImgPreProc::from(&input_format);
+        self.model_info.input_format = input_format;
+        self.input_f32 = F32Image::new(0, 0, self.img_proc.layout());
+        Ok(())
+    }
+
+    /// Forward pass a BGR image as batch of one, writes outputs without batch dimension to `out`
+    ///
+    /// Outputs are written into the buffers of `out` if they fit. What the model sees is written
+    /// to `preview` if given.
+    fn forward(
         &mut self,
-        mut img_tensor: ArrayView4<'_, u8>,
-    ) -> Result<Vec<OrtOwnedTensor<T, IxDyn>>, ModelProcError> {
+        img: &StridedView<'_>,
+        preview: Option<&mut BgrImage>,
+        out: &mut Vec<ModelOutput>,
+    ) -> Result<(), ModelProcError> {
         let pre = &self.img_proc;
+        let allocator = self.session.allocator();
 
-        match pre.color_seq {
-            ColorSeq::BGR => {}
-            ColorSeq::RGB => {
-                img_tensor.invert_axis(Axis(3));
-            }
-        };
-        let (col_axis, img_tensor) = match pre.dim_seq {
-            DimSeq::NHWC => (Axis(3), img_tensor),
-            DimSeq::NCHW => (Axis(1), img_tensor.permuted_axes([0, 3, 1, 2])),
-        };
-
-        // todo: can at least keep an input vec around
-        let model_tensors: Vec<OrtOwnedTensor<T, _>> = match &pre.color_range {
+        let outputs = match &pre.color_range {
             ColorRange::Uint8 => {
-                // todo: why .run() doesn't accept a view?
-                // if it actually requires contiguity, to_owned may not provide that
-                // and we may get BGR flipped if RGB is required (negative stride ignored) or a segfault..
-                let owned_img = img_tensor.to_owned();
-                self.session.run(vec![owned_img])?
+                let mut img_tensor = tensor_view(img)?;
+                match pre.color_seq {
+                    ColorSeq::BGR => {}
+                    ColorSeq::RGB => {
+                        img_tensor.invert_axis(Axis(3));
+                    }
+                };
+                let img_tensor = match pre.dim_seq {
+                    DimSeq::NHWC => img_tensor,
+                    DimSeq::NCHW => img_tensor.permuted_axes([0, 3, 1, 2]),
+                };
+                if let Some(preview) = preview {
+                    *preview = input_preview(pre, img_tensor.view());
+                }
+                // ort reads tensors as c contiguous, which also copies flipped colors in order
+                let input = img_tensor.as_standard_layout().into_dyn();
+                self.session.run(vec![Value::from_array(allocator, &input)?])?
             }
             ColorRange::Float32(norm) => {
-                // instead of mapv, we have to recollect to ensure c contiguity
-                // given potential prior permutations, otherwise onnxruntime segfaults
-                let mut img_tensor_float = Array4::from_shape_vec(
-                    img_tensor.raw_dim(),
-                    img_tensor.iter().cloned().map(|v| f32::from(v) * 1f32 / 255f32).collect(),
-                )?;
+                // write into the kept buffer in the model's layout, thus c contiguous
+                let input = &mut self.input_f32;
+                input.write_bgr(img, matches!(pre.color_seq, ColorSeq::RGB));
                 if let Some(norm) = norm {
-                    let mean = arr1(&norm.mean);
-                    let std1 = 1.0f32 / arr1(&norm.std);
-                    for mut lane in img_tensor_float.lanes_mut(col_axis) {
-                        lane -= &mean;
-                        lane *= &std1;
-                    }
+                    input.normalize(norm.mean, norm.std);
                 };
-                self.session.run(vec![img_tensor_float])?
+                let [a, b, c] = input.shape();
+                let img_tensor_float = ArrayView4::from_shape([1, a, b, c], input.as_slice())?;
+                if let Some(preview) = preview {
+                    *preview = input_preview(pre, img_tensor_float);
+                }
+                let input = CowArray::from(img_tensor_float.into_dyn());
+                self.session.run(vec![Value::from_array(allocator, &input)?])?
             }
         };
-        Ok(model_tensors)
+        // integer outputs are taken as class index maps
+        out.resize_with(outputs.len(), ModelOutput::default);
+        let outputs = outputs.iter().zip(self.session.outputs.iter());
+        for ((value, output), kept) in outputs.zip(out.iter_mut()) {
+            let kept_output = std::mem::take(kept);
+            *kept = match output.output_type {
+                TensorElementDataType::Float32 => {
+                    ModelOutput::Float(unbatch(value, kept_output.into_floats(), |v: f32| v)?)
+                }
+                TensorElementDataType::Uint8 => ModelOutput::ClassIndex(unbatch(
+                    value,
+                    kept_output.into_indices(),
+                    class_index::<u8>,
+                )?),
+                TensorElementDataType::Uint16 => {
+                    ModelOutput::ClassIndex(unbatch(value, kept_output.into_indices(), |i: u16| i)?)
+                }
+                TensorElementDataType::Int32 => ModelOutput::ClassIndex(unbatch(
+                    value,
+                    kept_output.into_indices(),
+                    class_index::<i32>,
+                )?),
+                TensorElementDataType::Int64 => ModelOutput::ClassIndex(unbatch(
+                    value,
+                    kept_output.into_indices(),
+                    class_index::<i64>,
+                )?),
+                dtype => return Err(ModelProcError::OutputType(dtype)),
+            };
+        }
+        Ok(())
     }
 }
 
+/// Image input of a model as BGR bytes, un-normalized if it's normalized
+fn input_preview<T: Copy + Into<f32>>(pre: &ImgPreProc, input: ArrayView4<'_, T>) -> BgrImage {
+    let nhwc = match pre.dim_seq {
+        DimSeq::NHWC => input,
+        DimSeq::NCHW => input.permuted_axes([0, 2, 3, 1]),
+    };
+    let (_, height, width, _) = nhwc.dim();
+    let (range, mean, std) = match &pre.color_range {
+        ColorRange::Uint8 => (1.0, [0.0; 3], [1.0; 3]),
+        ColorRange::Float32(None) => (255.0, [0.0; 3], [1.0; 3]),
+        ColorRange::Float32(Some(norm)) => (255.0, norm.mean, norm.std),
+    };
+    BgrImage::from_fn(width as u32, height as u32, |x, y| {
+        let channel = |c: usize| {
+            let value: f32 = nhwc[[0, y as usize, x as usize, c]].into();
+            ((value * std[c] + mean[c]) * range).round().clamp(0.0, 255.0) as u8
+        };
+        let [first, second, third] = [channel(0), channel(1), channel(2)];
+        match pre.color_seq {
+            ColorSeq::BGR => Bgr([first, second, third]),
+            ColorSeq::RGB => Bgr([third, second, first]),
+        }
+    })
+}
+
 /// ONNX model session
-pub(crate) struct Model<'s, T = f32> {
-    img_session: Option<ImageSession<'s>>,
-    _marker: PhantomData<T>,
+pub(crate) struct Model {
+    img_session: Option<ImageSession>,
+    /// Path of the loaded model
+    path: String,
+    /// Preferred execution providers
+    providers: Vec<ExecutionProvider>,
+    /// Overrides of the guessed image input format
+    input_format: InputFormat,
+    /// Keep what the model sees of each image
+    preview_input: bool,
+    input_preview: Option<BgrImage>,
 }
 
-impl<T> Default for Model<'_, T> {
+impl Default for Model {
     fn default() -> Self {
-        Self { img_session: None, _marker: PhantomData }
+        Self {
+            img_session: None,
+            path: String::default(),
+            providers: vec![ExecutionProvider::Cpu],
+            input_format: InputFormat::default(),
+            preview_input: false,
+            input_preview: None,
+        }
+    }
+}
+
+/// Session on the first provider of `chain` that works
+fn new_session(
+    path: &Path,
+    optimization: GraphOptimizationLevel,
+    chain: &[ExecutionProvider],
+) -> Result<(Session, ExecutionProvider), ModelCmdError> {
+    let environment = environment()?;
+    let mut failures = vec![];
+    for &provider in chain {
+        let ort_provider = provider.to_ort();
+        if !ort_provider.is_available() {
+            failures.push(format!("{:?} isn't available in onnxruntime", provider));
+            continue;
+        }
+        let session = SessionBuilder::new(&environment)
+            .and_then(|b| b.with_optimization_level(optimization))
+            .and_then(|b| b.with_intra_threads(3))
+            .and_then(|b| b.with_execution_providers([ort_provider]))
+            .and_then(|b| b.with_model_from_file(path));
+        match session {
+            Ok(session) => return Ok((session, provider)),
+            Err(e) => failures.push(format!("{:?}: {}", provider, e)),
+        }
+    }
+    for failure in failures.iter() {
+        warn!("{}", failure);
     }
+    Err(ModelCmdError::NoProvider(failures.join(", ")))
 }
 
 /// Order of color channels of a model's image input
-#[derive(Debug, Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 #[allow(clippy::upper_case_acronyms)]
 pub(crate) enum ColorSeq {
     RGB,
@@ -197,7 +433,7 @@ pub(crate) enum ColorRange {
 }
 
 /// Order of semantic dimensions of a model's image input
-#[derive(Debug, Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 #[allow(clippy::upper_case_acronyms)]
 pub(crate) enum DimSeq {
     /// Batch + TorchVision convention e.g.
@@ -206,6 +442,32 @@ pub(crate) enum DimSeq {
     NCHW,
 }
 
+/// Element type of a model's image input
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub(crate) enum InputDType {
+    Uint8,
+    Float32,
+}
+
+/// Normalization of a model's `Float32` image input
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub(crate) enum InputNorm {
+    /// Scaled to 0-1
+    Unit,
+    /// Around mean and std deviation of ImageNet as torchvision's pre-trained models
+    ImageNet,
+}
+
+/// Format of a model's image input, unset parts are guessed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct InputFormat {
+    pub(crate) dim_seq: Option<DimSeq>,
+    pub(crate) color_seq: Option<ColorSeq>,
+    pub(crate) dtype: Option<InputDType>,
+    pub(crate) norm: Option<InputNorm>,
+}
+
 /// Specification of a model's image input
 #[derive(Debug, Clone)]
 pub(crate) struct ImgPreProc {
@@ -214,16 +476,40 @@ pub(crate) struct ImgPreProc {
     color_range: ColorRange,
 }
 
-/// Determine partially model's image input requirements heuristically
+impl ImgPreProc {
+    /// Layout of `Float32` inputs
+    fn layout(&self) -> ChannelLayout {
+        match self.dim_seq {
+            DimSeq::NHWC => ChannelLayout::Interleaved,
+            DimSeq::NCHW => ChannelLayout::Planar,
+        }
+    }
+}
+
+impl From<&InputFormat> for ImgPreProc {
+    /// Pre-processing of a resolved format
+    fn from(format: &InputFormat) -> Self {
+        let color_seq = format.color_seq.unwrap_or(ColorSeq::BGR);
+        let color_range = match format.dtype {
+            Some(InputDType::Float32) => ColorRange::Float32(match format.norm {
+                Some(InputNorm::ImageNet) => Some(match color_seq {
+                    ColorSeq::RGB => ColorNorm::new_torchvision_rgb(),
+                    ColorSeq::BGR => ColorNorm::new_torchvision_rgb().flip(),
+                }),
+                _ => None,
+            }),
+            _ => ColorRange::Uint8,
+        };
+        Self { dim_seq: format.dim_seq.unwrap_or(DimSeq::NHWC), color_seq, color_range }
+    }
+}
+
+/// Determine the order of a model's image input dimensions heuristically
 ///
 /// # Arguments
 ///
 /// * `input` - The session model's description of an image input tensor
-/// * `norm_float` - Normalization to apply if and only if input is Float32 (ignored otherwise)
-fn infer_img_pre_proc(
-    input: &Input,
-    norm_float: Option<ColorNorm<f32>>,
-) -> Result<(DimSeq, ColorRange), ModelInputFormatError> {
+fn infer_dim_seq(input: &Input) -> Result<DimSeq, ModelInputFormatError> {
     // find first dim with length 3
     let col_dim =
         input.dimensions.iter().position(|d| d.as_ref() == Some(&3)).ok_or_else(|| {
@@ -239,76 +525,92 @@ fn infer_img_pre_proc(
         )));
     };
 
-    let dim_seq = match col_dim {
-        1 => DimSeq::NCHW,
-        3 => DimSeq::NHWC,
-        p => {
-            return Err(ModelInputFormatError::Infer(format!(
-                "color dimension only at NCHW or NHWC but not in position {} supported",
-                p
-            )));
-        }
-    };
+    match col_dim {
+        1 => Ok(DimSeq::NCHW),
+        3 => Ok(DimSeq::NHWC),
+        p => Err(ModelInputFormatError::Infer(format!(
+            "color dimension only at NCHW or NHWC but not in position {} supported",
+            p
+        ))),
+    }
+}
 
-    let color_range = match &input.input_type {
-        TensorElementDataType::Float => ColorRange::Float32(norm_float),
-        TensorElementDataType::Uint8 => ColorRange::Uint8,
-        dtype => {
-            return Err(ModelInputFormatError::Infer(format!(
-                "only Float (f32) and Uint8 (u8) input supported, got {:?}",
-                dtype
-            )));
-        }
-    };
+fn infer_dtype(input: &Input) -> Result<InputDType, ModelInputFormatError> {
+    match &input.input_type {
+        TensorElementDataType::Float => Ok(InputDType::Float32),
+        TensorElementDataType::Uint8 => Ok(InputDType::Uint8),
+        dtype => Err(ModelInputFormatError::Infer(format!(
+            "only Float (f32) and Uint8 (u8) input supported, got {:?}",
+            dtype
+        ))),
+    }
+}
 
-    Ok((dim_seq, color_range))
+/// Complete `format` by guessing what isn't set from the model's image input
+fn resolve_input_format(
+    input: &Input,
+    format: InputFormat,
+) -> Result<InputFormat, ModelInputFormatError> {
+    let dim_seq = match format.dim_seq {
+        Some(dim_seq) => dim_seq,
+        None => infer_dim_seq(input)?,
+    };
+    let dtype = match format.dtype {
+        Some(dtype) => dtype,
+        None => infer_dtype(input)?,
+    };
+    // our conventions: float models are trained on RGB images and normalized like torchvision's
+    let color_seq = format.color_seq.unwrap_or(match dtype {
+        InputDType::Float32 => ColorSeq::RGB,
+        InputDType::Uint8 => ColorSeq::BGR,
+    });
+    let norm = match dtype {
+        InputDType::Float32 => Some(format.norm.unwrap_or(InputNorm::ImageNet)),
+        InputDType::Uint8 => None,
+    };
+    Ok(InputFormat { dim_seq: Some(dim_seq), color_seq: Some(color_seq), dtype: Some(dtype), norm })
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub(crate) enum ModelCmd {
     Load(String),
+    /// Execution providers by preference, reloads a loaded model
+    Providers(Vec<ExecutionProvider>),
+    /// Override the guessed image input format of models
+    InputFormat(InputFormat),
+    /// Keep what models see of each image, e.g. to check pre-processing
+    PreviewInput(bool),
 }
 
-impl<'s, 'session, T: TypeToTensorElementDataType + std::fmt::Debug + Clone> Processor
-    for Model<'session, T>
-where
-    's: 'session,
-{
+impl Processor for Model {
     type Command = ModelCmd;
     type ControlError = ModelCmdError;
     type Input = BgrImage;
-    type Output = Vec<ArrayD<T>>;
+    type Output = Vec<ModelOutput>;
     type ProcessResult = Result<(), ModelProcError>;
 
     fn control(&mut self, cmd: Self::Command) -> Result<&mut Self, Self::ControlError> {
         match cmd {
-            // todo: could use a more advanced fork to control intra vs. inter threads
-            // e.g.: https://github.com/VOICEVOX/onnruntime-rs
-            // discussion to migrate to official org:  https://github.com/nbigaouette/onnxruntime-rs/issues/112
-            ModelCmd::Load(string_path) if !string_path.is_empty() => {
-                let session = ENVIRONMENT
-                    .new_session_builder()?
-                    .with_optimization_level(GraphOptimizationLevel::Extended)?
-                    .with_number_threads(3)?
-                    .with_model_from_file(string_path)?;
-
-                // todo: control col_seq properly instead of hardcoding our conventions
-                let col_seq =
-                    if matches!(session.inputs[0].input_type, TensorElementDataType::Float) {
-                        ColorSeq::RGB
-                    } else {
-                        ColorSeq::BGR
-                    };
-                // todo: control color_norm properly instead of hardcoding our conventions
-                let norm_float = match col_seq {
-                    ColorSeq::RGB => ColorNorm::new_torchvision_rgb(),
-                    ColorSeq::BGR => ColorNorm::new_torchvision_rgb().flip(),
-                };
-                self.img_session =
-                    Some(ImageSession::try_from_session(session, col_seq, Some(norm_float))?);
+            ModelCmd::Load(path) => {
+                self.path = path;
+                self.load()?;
+            }
+            ModelCmd::Providers(providers) => {
+                let changed = providers != self.providers;
+                self.providers = providers;
+                if changed && self.img_session.is_some() {
+                    self.load()?;
+                }
+            }
+            ModelCmd::InputFormat(format) => {
+                if let Some(session) = self.img_session.as_mut() {
+                    session.set_input_format(format)?;
+                }
+                self.input_format = format;
             }
-            ModelCmd::Load(_) => {
-                self.img_session = None;
+            ModelCmd::PreviewInput(preview) => {
+                self.preview_input = preview;
+                self.input_preview = None;
             }
         }
         Ok(self)
@@ -316,18 +618,15 @@ where
 
     fn advance(&mut self, img: &Self::Input, out: &mut Self::Output) -> Self::ProcessResult {
         if let Some(ref mut session) = self.img_session {
-            let img_shape = [1, img.height() as _, img.width() as _, 3];
-            let img_tensor = ArrayView4::from_shape(img_shape, img)?;
-
-            // todo: to return a Deref ArrayViewD with &session from &self, we'd need
-            // maybe some Rc<Session> or GATs: https://github.com/rust-lang/rust/pull/96709
-            // set cloned output without batch dim
-            let model_tensors = session.forward(img_tensor)?;
+            // todo: bind outputs to let ort write into `out` directly
+            let preview = if self.preview_input {
+                Some(self.input_preview.get_or_insert_with(|| BgrImage::new(0, 0)))
+            } else {
+                None
+            };
+            session.forward(&StridedView::from(img), preview, out)?;
+        } else {
             out.clear();
-            // strip batch dim and clone
-            for t in model_tensors {
-                out.push(t.index_axis(ndarray::Axis(0), 0).into_owned());
-            }
         }
 
         Ok(())
@@ -338,10 +637,46 @@ where
     }
 }
 
-impl<T> Model<'_, T> {
+/// NHWC batch of one image without copying, rows may be apart
+fn tensor_view<'a>(img: &StridedView<'a>) -> Result<ArrayView4<'a, u8>, ndarray::ShapeError> {
+    let [row, col, chan] = img.strides();
+    let shape = [1, img.height() as usize, img.width() as usize, 3];
+    let strides = [row * img.height() as usize, row, col, chan];
+    ArrayView4::from_shape(shape.strides(strides), img.as_slice())
+}
+
+impl Model {
     pub(crate) fn get_info(&self) -> Option<&ModelInfo> {
         self.img_session.as_ref().map(|s| &s.model_info)
     }
+
+    /// What the model saw of the last image, if previewed
+    pub(crate) fn take_input_preview(&mut self) -> Option<BgrImage> {
+        self.input_preview.take()
+    }
+
+    /// (Re-)create a session for the current path, unloads if empty
+    fn load(&mut self) -> Result<(), ModelCmdError> {
+        self.img_session = None;
+        if self.path.is_empty() {
+            return Ok(());
+        }
+        // load a cached optimized model if possible, extended optimizations fit the provider
+        let path = match model_cache::optimized(Path::new(&self.path)) {
+            Ok(path) => path,
+            Err(e) => {
+                if !matches!(e, ModelCacheError::Disabled) {
+                    warn!("{}", e);
+                }
+                PathBuf::from(&self.path)
+            }
+        };
+        let chain = ExecutionProvider::chain(&self.providers);
+        let (session, provider) = new_session(&path, GraphOptimizationLevel::Level2, &chain)?;
+        self.img_session =
+            Some(ImageSession::try_from_session(session, provider, self.input_format)?);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -353,9 +688,27 @@ mod test {
         fcn_resnet50_12_int8_onnx().to_string_lossy().to_string()
     }
 
+    #[test]
+    fn crop_tensor() {
+        let img = BgrImage::from_fn(4, 3, |x, y| image_ext::Bgr([x as u8, y as u8, 0]));
+        let crop = StridedView::crop(&img, 1, 1, 3, 2).unwrap();
+        let tensor = tensor_view(&crop).unwrap();
+        assert_eq!(tensor.shape(), [1, 2, 3, 3]);
+        assert_eq!(tensor[[0, 1, 2, 0]], 3);
+        assert_eq!(tensor[[0, 1, 2, 1]], 2);
+    }
+
+    #[test]
+    fn provider_chain_ends_on_cpu() {
+        use ExecutionProvider::*;
+        assert_eq!(ExecutionProvider::chain(&[TensorRt, Cuda]), [TensorRt, Cuda, Cpu]);
+        assert_eq!(ExecutionProvider::chain(&[Cpu, Cuda]), [Cpu, Cuda]);
+        assert_eq!(ExecutionProvider::chain(&[]), [Cpu]);
+    }
+
     #[test]
     fn load_seg_model() {
-        let mut m = Model::<f32>::default();
+        let mut m = Model::default();
         m.control(ModelCmd::Load(fcn_seg_int8())).unwrap();
         let session = m.img_session.unwrap();
         eprintln!("model {} session {:?}", fcn_seg_int8(), session);
@@ -367,9 +720,75 @@ mod test {
         }
     }
 
+    #[test]
+    fn fall_back_to_cpu() {
+        let mut m = Model::default();
+        m.control(ModelCmd::Providers(vec![ExecutionProvider::TensorRt])).unwrap();
+        m.control(ModelCmd::Load(fcn_seg_int8())).unwrap();
+        assert_eq!(m.get_info().unwrap().execution_provider, ExecutionProvider::Cpu);
+    }
+
+    #[test]
+    fn override_input_format() {
+        let mut m = Model::default();
+        m.control(ModelCmd::Load(fcn_seg_int8())).unwrap();
+        let guessed = m.get_info().unwrap().input_format;
+        assert!(guessed.dim_seq.is_some() && guessed.color_seq.is_some());
+
+        let format = InputFormat { color_seq: Some(ColorSeq::BGR), ..Default::default() };
+        m.control(ModelCmd::InputFormat(format)).unwrap();
+        let overridden = m.get_info().unwrap().input_format;
+        assert_eq!(overridden, InputFormat { color_seq: Some(ColorSeq::BGR), ..guessed });
+    }
+
+    #[test]
+    fn pre_proc_of_format() {
+        let format = InputFormat {
+            dim_seq: Some(DimSeq::NCHW),
+            color_seq: Some(ColorSeq::BGR),
+            dtype: Some(InputDType::Float32),
+            norm: Some(InputNorm::ImageNet),
+        };
+        let pre = ImgPreProc::from(&format);
+        assert!(matches!(pre.layout(), ChannelLayout::Planar));
+        let norm = match pre.color_range {
+            ColorRange::Float32(Some(norm)) => norm,
+            range => panic!("ImageNet should normalize floats, got {:?}", range),
+        };
+        assert_eq!(norm.mean[0], 0.406, "norm should be in BGR order");
+        let unit = InputFormat { norm: Some(InputNorm::Unit), ..format };
+        assert!(matches!(ImgPreProc::from(&unit).color_range, ColorRange::Float32(None)));
+    }
+
+    #[test]
+    fn preview_what_model_sees() {
+        let img = BgrImage::from_fn(4, 3, |x, y| Bgr([x as u8 * 60, y as u8 * 100, 7]));
+        let format = InputFormat {
+            dim_seq: Some(DimSeq::NCHW),
+            color_seq: Some(ColorSeq::RGB),
+            dtype: Some(InputDType::Float32),
+            norm: Some(InputNorm::ImageNet),
+        };
+        let pre = ImgPreProc::from(&format);
+        let norm = match &pre.color_range {
+            ColorRange::Float32(Some(norm)) => norm.clone(),
+            range => panic!("ImageNet should normalize floats, got {:?}", range),
+        };
+        let mut input = F32Image::new(0, 0, pre.layout());
+        input.write_bgr(&StridedView::from(&img), true);
+        input.normalize(norm.mean, norm.std);
+        let [a, b, c] = input.shape();
+        let tensor = ArrayView4::from_shape([1, a, b, c], input.as_slice()).unwrap();
+        assert_eq!(input_preview(&pre, tensor), img);
+
+        let bytes = ImgPreProc::from(&InputFormat::default());
+        let view = StridedView::from(&img);
+        assert_eq!(input_preview(&bytes, tensor_view(&view).unwrap()), img);
+    }
+
     #[test]
     fn infer_seg_model() {
-        let mut m = Model::<f32>::default();
+        let mut m = Model::default();
         m.control(ModelCmd::Load(fcn_seg_int8())).unwrap();
         let img = BgrImage::new(320, 240);
         let mut tensors = vec![];
@@ -378,5 +797,13 @@ mod test {
         assert_eq!(tensors.len(), 2, "this segmentation model should return two tensors");
         assert_eq!(tensors[0].shape(), [21, 240, 320], "out should be 21 classes upscaled");
         assert_eq!(tensors[1].shape(), [21, 240, 320], "aux should be 21 classes upscaled");
+
+        let buffer = |tensors: &[ModelOutput]| match &tensors[0] {
+            ModelOutput::Float(a) => a.as_ptr(),
+            ModelOutput::ClassIndex(_) => panic!("out should be floats"),
+        };
+        let kept = buffer(&tensors);
+        m.advance(&img, &mut tensors).unwrap();
+        assert_eq!(buffer(&tensors), kept, "outputs should be written into kept buffers");
     }
 }
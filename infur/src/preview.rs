@@ -0,0 +1,294 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+use eframe::epaint::ColorImage;
+use image_ext::{codecs::jpeg::JpegEncoder, BgrImage, ColorType, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::app::GUIFrame;
+
+/// Quality of JPEG encoded previews
+const JPEG_QUALITY: u8 = 80;
+
+/// How an overlay is mixed with the frame underneath
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum BlendMode {
+    /// Overlay colors cover the frame
+    #[default]
+    Normal,
+    /// Darken the frame by overlay colors, keeping its texture
+    Multiply,
+    /// Lighten the frame by overlay colors, keeping its texture
+    Screen,
+}
+
+impl BlendMode {
+    pub(crate) const ALL: [Self; 3] = [Self::Normal, Self::Multiply, Self::Screen];
+}
+
+/// How overlays are composited onto frames
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub(crate) struct OverlayStyle {
+    /// Factor of the overlay's alpha, from 0 (hidden) to 1
+    pub(crate) opacity: f32,
+    pub(crate) blend: BlendMode,
+}
+
+impl Default for OverlayStyle {
+    fn default() -> Self {
+        Self { opacity: 1.0, blend: BlendMode::Normal }
+    }
+}
+
+impl OverlayStyle {
+    /// Mix a base channel with a premultiplied overlay channel of alpha `a`, all within 0..=1
+    fn mix(&self, base: f32, c: f32, a: f32) -> f32 {
+        let (c, a) = (c * self.opacity, a * self.opacity);
+        match self.blend {
+            BlendMode::Normal => c + base * (1.0 - a),
+            BlendMode::Multiply => base * (1.0 - a + c),
+            BlendMode::Screen => base + c * (1.0 - base),
+        }
+    }
+}
+
+/// Premultiplied RGBA within 0..=1 of an overlay at `(x, y)` pixel centers, interpolated linearly
+fn sample(o: &ColorImage, x: f32, y: f32) -> [f32; 4] {
+    let (x, y) = (x.clamp(0.0, (o.width() - 1) as f32), y.clamp(0.0, (o.height() - 1) as f32));
+    let (x0, y0) = (x as usize, y as usize);
+    let (x1, y1) = ((x0 + 1).min(o.width() - 1), (y0 + 1).min(o.height() - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let rgba = |x, y| o[(x, y)].to_array().map(|c| c as f32 / 255.0);
+    let lerp = |a: [f32; 4], b: [f32; 4], f: f32| [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * f);
+    lerp(lerp(rgba(x0, y0), rgba(x1, y0), fx), lerp(rgba(x0, y1), rgba(x1, y1), fx), fy)
+}
+
+/// Blend a premultiplied overlay onto an image, stretching the overlay smoothly if necessary
+pub(crate) fn composite(
+    img: &BgrImage,
+    overlay: Option<&ColorImage>,
+    style: OverlayStyle,
+) -> RgbImage {
+    let (w, h) = img.dimensions();
+    RgbImage::from_fn(w, h, |x, y| {
+        let [b, g, r] = img.get_pixel(x, y).0;
+        match overlay {
+            Some(o) if o.width() > 0 && o.height() > 0 => {
+                let ox = (x as f32 + 0.5) * o.width() as f32 / w as f32 - 0.5;
+                let oy = (y as f32 + 0.5) * o.height() as f32 / h as f32 - 0.5;
+                let [cr, cg, cb, a] = sample(o, ox, oy);
+                let mix = |c: f32, base: u8| {
+                    let mixed = style.mix(base as f32 / 255.0, c, a);
+                    (mixed * 255.0).clamp(0.0, 255.0) as u8
+                };
+                Rgb([mix(cr, r), mix(cg, g), mix(cb, b)])
+            }
+            _ => Rgb([r, g, b]),
+        }
+    })
+}
+
+/// Encoded preview of the last published frame
+#[derive(Clone, Default)]
+pub(crate) struct PreviewFrame {
+    pub(crate) seq: u64,
+    pub(crate) jpeg: Arc<Vec<u8>>,
+    pub(crate) json: Arc<String>,
+}
+
+/// Latest composited frame and its results for any number of preview clients
+///
+/// Frames are only encoded while clients are subscribed.
+#[derive(Default)]
+pub(crate) struct Preview {
+    latest: Mutex<PreviewFrame>,
+    published: Condvar,
+    clients: AtomicUsize,
+    /// No more frames are published, e.g. when shutting down
+    closed: AtomicBool,
+}
+
+/// What a subscriber waited for
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub(crate) enum Update {
+    Frame(PreviewFrame),
+    /// Nothing new was published in time
+    Idle,
+    Closed,
+}
+
+/// Keeps preview encoding on while alive
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub(crate) struct Subscription {
+    preview: Arc<Preview>,
+    seq: u64,
+}
+
+impl Preview {
+    pub(crate) fn publish(&self, frame: &GUIFrame) {
+        if self.clients.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let rgb = match &frame.composited {
+            Some(rgb) => rgb.clone(),
+            None => Arc::new(composite(
+                &frame.buffer,
+                frame.decoded_buffer.as_deref(),
+                frame.overlay_style,
+            )),
+        };
+        let mut jpeg = vec![];
+        let encoded = JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode(
+            rgb.as_raw(),
+            rgb.width(),
+            rgb.height(),
+            ColorType::Rgb8,
+        );
+        if let Err(e) = encoded {
+            warn!("couldn't encode preview: {}", e);
+            return;
+        }
+        let json = serde_json::to_string(&frame.record()).unwrap_or_default();
+
+        let mut latest = self.latest.lock().unwrap();
+        *latest = PreviewFrame { seq: latest.seq + 1, jpeg: Arc::new(jpeg), json: Arc::new(json) };
+        self.published.notify_all();
+    }
+
+    /// Wake all subscribers to end their streams
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn close(&self) {
+        let _latest = self.latest.lock().unwrap();
+        self.closed.store(true, Ordering::Relaxed);
+        self.published.notify_all();
+    }
+
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    pub(crate) fn subscribe(self: &Arc<Self>) -> Subscription {
+        self.clients.fetch_add(1, Ordering::Relaxed);
+        let seq = self.latest.lock().unwrap().seq;
+        Subscription { preview: self.clone(), seq }
+    }
+}
+
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+impl Subscription {
+    /// Block until a frame newer than the last one returned is published, at most `timeout`
+    pub(crate) fn next_frame(&mut self, timeout: Duration) -> Update {
+        let preview = &self.preview;
+        let latest = preview.latest.lock().unwrap();
+        let (latest, _) = preview
+            .published
+            .wait_timeout_while(latest, timeout, |f| {
+                f.seq <= self.seq && !preview.closed.load(Ordering::Relaxed)
+            })
+            .unwrap();
+        if preview.closed.load(Ordering::Relaxed) {
+            Update::Closed
+        } else if latest.seq > self.seq {
+            self.seq = latest.seq;
+            Update::Frame(latest.clone())
+        } else {
+            Update::Idle
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.preview.clients.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use eframe::epaint::Color32;
+
+    fn frame(id: u64) -> GUIFrame {
+        let buffer = Arc::new(BgrImage::from_pixel(4, 2, image_ext::Bgr([10, 20, 30])));
+        let overlay = ColorImage::new([2, 1], Color32::from_rgba_premultiplied(0, 0, 100, 128));
+        GUIFrame {
+            decoded_buffer: Some(Arc::new(overlay)),
+            class_areas: vec![0, 2],
+            ..GUIFrame::empty(id, buffer)
+        }
+    }
+
+    #[test]
+    fn composite_over() {
+        let f = frame(0);
+        let rgb = composite(&f.buffer, None, f.overlay_style);
+        assert_eq!(rgb.get_pixel(3, 1), &Rgb([30, 20, 10]));
+        let rgb = composite(&f.buffer, f.decoded_buffer.as_deref(), f.overlay_style);
+        let inv_a = 1.0f32 - 128.0 / 255.0;
+        let expected_b = (100.0 + inv_a * 10.0) as u8;
+        assert_eq!(
+            rgb.get_pixel(3, 1),
+            &Rgb([(inv_a * 30.0) as u8, (inv_a * 20.0) as u8, expected_b])
+        );
+    }
+
+    #[test]
+    fn blend_modes() {
+        let img = BgrImage::from_pixel(2, 2, image_ext::Bgr([0, 100, 200]));
+        let style = |opacity, blend| OverlayStyle { opacity, blend };
+        let composite_with = |c: Color32, style| {
+            let overlay = ColorImage::new([1, 1], c);
+            *composite(&img, Some(&overlay), style).get_pixel(1, 1)
+        };
+        let original = Rgb([200, 100, 0]);
+        assert_eq!(composite_with(Color32::WHITE, style(1.0, BlendMode::Multiply)), original);
+        assert_eq!(composite_with(Color32::BLACK, style(1.0, BlendMode::Screen)), original);
+        assert_eq!(composite_with(Color32::RED, style(0.0, BlendMode::Normal)), original);
+        let half_red = composite_with(Color32::RED, style(0.5, BlendMode::Normal));
+        assert_eq!(half_red, Rgb([227, 50, 0]));
+        let multiplied = composite_with(Color32::RED, style(1.0, BlendMode::Multiply));
+        assert_eq!(multiplied, Rgb([200, 0, 0]));
+        let screened = composite_with(Color32::RED, style(1.0, BlendMode::Screen));
+        assert_eq!(screened, Rgb([255, 100, 0]));
+    }
+
+    #[test]
+    fn stretch_smoothly() {
+        let img = BgrImage::new(4, 1);
+        let mut overlay = ColorImage::new([2, 1], Color32::TRANSPARENT);
+        overlay[(1, 0)] = Color32::WHITE;
+        let rgb = composite(&img, Some(&overlay), OverlayStyle::default());
+        let reds = rgb.pixels().map(|p| p.0[0]).collect::<Vec<_>>();
+        assert_eq!(reds, [0, 63, 191, 255]);
+    }
+
+    #[test]
+    fn publish_only_when_subscribed() {
+        let preview = Arc::new(Preview::default());
+        preview.publish(&frame(1));
+        assert_eq!(preview.latest.lock().unwrap().seq, 0);
+
+        let mut sub = preview.subscribe();
+        let wait = Duration::from_millis(10);
+        assert!(matches!(sub.next_frame(wait), Update::Idle));
+        preview.publish(&frame(2));
+        let f = match sub.next_frame(wait) {
+            Update::Frame(f) => f,
+            _ => panic!("published frame should be next"),
+        };
+        assert_eq!(f.seq, 1);
+        assert!(!f.jpeg.is_empty());
+        assert_eq!(f.json.as_str(), r#"{"id":2,"timestamp":0.0,"class_areas":[0,2]}"#);
+
+        preview.close();
+        assert!(matches!(sub.next_frame(Duration::MAX), Update::Closed));
+        drop(sub);
+        preview.publish(&frame(3));
+        assert_eq!(preview.latest.lock().unwrap().seq, 1);
+    }
+}
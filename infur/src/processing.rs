@@ -1,14 +1,47 @@
-use std::{error::Error as StdError, fmt::Display, num::NonZeroU32, ops::Deref};
+use std::{
+    error::Error as StdError, fmt::Display, mem, ops::Deref, path::PathBuf, sync::Arc,
+    time::Instant,
+};
 
 use fast_image_resize as fr;
-use ff_video::{FFMpegDecoder, FFMpegDecoderBuilder, FFVideoError, VideoProcError, VideoResult};
-use image_ext::BgrImage;
+use ff_video::{
+    redact, AbortHandle, FFMpegArchiver, FFVideoError, FrameUpdate, Retention, Rotation,
+    VideoProcError, VideoResult,
+};
+use image_ext::{AsFrImageView, AsFrImageViewMut, BgrImage, FrViewError};
 use thiserror::Error;
+use tracing::{debug, warn};
+
+#[cfg(feature = "shm")]
+use crate::shm::ShmWriter;
+use crate::{frame_reader::FrameReader, shm::ShmOpts, video_source};
 
 /// Frame produced and processed
 pub(crate) struct Frame {
+    /// Id by the source, restarts with seeks
     pub(crate) id: u64,
-    pub(crate) img: BgrImage,
+    /// Frames decoded since the video was played, including seeks and skipped frames
+    pub(crate) decoded: u64,
+    /// Seconds into the video, stays while paused and continues from seeks
+    pub(crate) position: f64,
+    /// Shared with consumers without copying, only written to once not shared anymore
+    pub(crate) img: Arc<BgrImage>,
+    /// When the frame was decoded, to tell the latency of its results
+    pub(crate) ingested: Instant,
+}
+
+impl Frame {
+    pub(crate) fn new(id: u64, img: Arc<BgrImage>) -> Self {
+        Self { id, decoded: id, position: 0.0, img, ingested: Instant::now() }
+    }
+}
+
+/// Buffer to overwrite entirely, a new one if the current one is still shared
+pub(crate) fn writable<T>(buffer: &mut Arc<T>, new: impl FnOnce() -> T) -> &mut T {
+    if Arc::get_mut(buffer).is_none() {
+        *buffer = Arc::new(new());
+    }
+    Arc::get_mut(buffer).expect("buffer was just created")
 }
 
 impl PartialEq for Frame {
@@ -60,7 +93,7 @@ pub(crate) trait Processor {
 }
 
 /// Commands that control VideoPlayer
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub(crate) enum VideoCmd {
     /// Start or restart playing video from this ffmpeg input
     Play(Vec<String>),
@@ -68,20 +101,350 @@ pub(crate) enum VideoCmd {
     Pause(bool),
     /// Stop whenever
     Stop,
+    /// Play only part of this and following videos, restarts the current one
+    Clip(Clip),
+    /// Restart the current video at this many seconds, up to the clip's end
+    Seek(f64),
+}
+
+/// Segment of a video in seconds, open ends play from the beginning or to the end
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct Clip {
+    pub(crate) start: Option<f64>,
+    pub(crate) end: Option<f64>,
+}
+
+/// How often reading frames may fail in a row before the video is closed
+///
+/// The end of a video is never retried. By default, any failure closes the video.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct ReadPolicy {
+    /// Frames to skip and read on after, reopens instead if the source can't resync
+    pub(crate) skips: u32,
+    /// Times to reopen the input where it failed, once skips are exhausted
+    pub(crate) reopens: u32,
+}
+
+impl ReadPolicy {
+    /// Reopens in a row, including skips of sources that can't resync, e.g. ffmpeg's byte pipe
+    fn max_reopens(&self, resyncs: bool) -> u32 {
+        if resyncs {
+            self.reopens
+        } else {
+            self.reopens + self.skips
+        }
+    }
+}
+
+/// Where live inputs are copied to as they are while played, to analyze them again later
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub(crate) struct ArchiveOpts {
+    /// Each input is archived in a subdirectory named after it
+    pub(crate) dir: PathBuf,
+    /// Container of files, `mkv` holds most codecs
+    pub(crate) format: String,
+    /// Seconds per file
+    pub(crate) segment_secs: Option<f64>,
+    /// Bytes per file
+    pub(crate) segment_bytes: Option<u64>,
+    /// Most files kept per input, older ones are removed
+    pub(crate) keep_files: Option<usize>,
+    /// Most bytes of all files kept per input, older ones are removed
+    pub(crate) keep_bytes: Option<u64>,
+}
+
+impl Default for ArchiveOpts {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("archive"),
+            format: "mkv".to_string(),
+            segment_secs: Some(600.0),
+            segment_bytes: None,
+            keep_files: None,
+            keep_bytes: None,
+        }
+    }
+}
+
+impl ArchiveOpts {
+    /// Directory of an input's files
+    fn input_dir(&self, input: &[String]) -> PathBuf {
+        let name = input.last().map(String::as_str).unwrap_or_default();
+        let name = name.split_once("://").map_or(name, |(_, rest)| rest);
+        // credentials of the authority must not end up on disk nor in logs
+        let authority = name.find(['/', '?', '#']).map_or(name, |end| &name[..end]);
+        let name = authority.rfind('@').map_or(name, |at| &name[at + 1..]);
+        let name = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect::<String>();
+        self.dir.join(name.trim_matches('_'))
+    }
+}
+
+/// Stream details of a playing video
+#[derive(Clone, Debug, serde::Serialize)]
+pub(crate) struct VideoInfo {
+    /// Ffmpeg input arguments, without credentials
+    pub(crate) input: Vec<String>,
+    /// Input as reported by ffmpeg
+    pub(crate) url: Option<String>,
+    /// Decoded size
+    pub(crate) size: [u32; 2],
+    pub(crate) fps: Option<f32>,
+    pub(crate) codec: Option<String>,
+    /// Progress of decoding, if reported yet
+    pub(crate) decoder: Option<DecoderStats>,
+    /// Version of ffmpeg decoding, if known
+    pub(crate) ffmpeg: Option<String>,
+}
+
+/// Frame statistics reported by ffmpeg, independent of drops after decoding
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize)]
+pub(crate) struct DecoderStats {
+    /// Frames output so far
+    pub(crate) frames: u64,
+    pub(crate) fps: Option<f32>,
+    /// Of decoding relative to real time, below 1 a live input falls behind
+    pub(crate) speed: Option<f32>,
+    /// Of the decoded output in kbit/s
+    pub(crate) bitrate: Option<f64>,
+    /// Frames duplicated to match the output rate
+    pub(crate) dup: u32,
+    /// Frames dropped by ffmpeg
+    pub(crate) drop: u32,
+}
+
+impl From<FrameUpdate> for DecoderStats {
+    fn from(update: FrameUpdate) -> Self {
+        Self {
+            frames: update.frame,
+            fps: update.fps,
+            speed: update.speed,
+            bitrate: update.bitrate,
+            dup: update.dup.unwrap_or_default(),
+            drop: update.drop.unwrap_or_default(),
+        }
+    }
+}
+
+/// Whether an input keeps producing frames in real time, e.g. network streams or cameras
+pub(crate) fn is_live(input: &[String]) -> bool {
+    const SCHEMES: [&str; 8] = ["rtsp", "rtsps", "rtmp", "rtmps", "rtp", "udp", "srt", "tcp"];
+    const DEVICES: [&str; 7] =
+        ["v4l2", "video4linux2", "avfoundation", "dshow", "x11grab", "gdigrab", "decklink"];
+    let streamed = input.iter().any(|arg| match arg.split_once("://") {
+        Some((scheme, _)) => SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()),
+        None => false,
+    });
+    let captured = input.windows(2).any(|w| w[0] == "-f" && DEVICES.contains(&w[1].as_str()));
+    streamed || captured
 }
 
 /// Writes video frames at command
 #[derive(Default)]
 pub(crate) struct VideoPlayer {
-    vid: Option<FFMpegDecoder>,
+    vid: Option<FrameReader>,
     input: Vec<String>,
     paused: bool,
+    clip: Clip,
+    /// Seconds into the video the current one started at
+    start: f64,
+    /// Skip frames decoded while the last one was still processed
+    skip_stale: bool,
+    read_policy: ReadPolicy,
+    /// Frames read from the current decoder
+    frames_read: u64,
+    /// Frames decoded by earlier decoders of the same video, e.g. before seeking
+    decoded_before: u64,
+    /// Times reopened since the last frame read
+    reopened: u32,
+    archive: Option<ArchiveOpts>,
+    /// Copies the current input if it's live and archived
+    archiver: Option<FFMpegArchiver>,
+    /// When the first frame of a live input was read, and its position
+    live_since: Option<(Instant, f64)>,
+    /// Seconds the last frame of a live input was read later than its position tells
+    lag: Option<f64>,
+    shm: Option<ShmOpts>,
+    /// Shares decoded frames if `shm` is set
+    #[cfg(feature = "shm")]
+    shm_writer: Option<ShmWriter>,
 }
 
 impl VideoPlayer {
     fn close_video(&mut self) -> VideoResult<()> {
         self.vid.take().map_or(Ok(()), |vid| vid.close())
     }
+
+    /// (Re-)start the current input
+    fn play(&mut self) -> Result<(), FFVideoError> {
+        self.play_from(self.clip.start)
+    }
+
+    /// (Re-)start the current input at `start` seconds
+    fn play_from(&mut self, start: Option<f64>) -> Result<(), FFVideoError> {
+        self.close_video()?;
+        self.decoded_before += mem::take(&mut self.frames_read);
+        self.start = start.unwrap_or(0.0);
+        self.live_since = None;
+        self.lag = None;
+        let vid = video_source::open(&self.input, Clip { start, end: self.clip.end })?;
+        let live = is_live(&self.input);
+        let reader = FrameReader::spawn(vid, self.paused, self.skip_stale, live)?;
+        reader.max_skips(self.read_policy.skips);
+        self.vid = Some(reader);
+        Ok(())
+    }
+
+    /// Restart the current input after the last frame read, live ones where they are now
+    fn reopen(&mut self) -> Result<(), FFVideoError> {
+        let fps = self.fps();
+        if let Some(Err(e)) = self.vid.take().map(FrameReader::close) {
+            debug!("failed video closed with: {}", e);
+        }
+        let start = match fps {
+            Some(fps) if !is_live(&self.input) => {
+                Some(self.start + self.frames_read as f64 / fps as f64)
+            }
+            _ => self.clip.start,
+        };
+        self.play_from(start)?;
+        // archiving may have ended with the input before
+        if self.archiver.is_none() {
+            self.restart_archive();
+        }
+        Ok(())
+    }
+
+    /// (Re-)start archiving the current input if it's live
+    fn restart_archive(&mut self) {
+        if let Some(archiver) = self.archiver.take() {
+            archiver.stop();
+        }
+        let opts = match &self.archive {
+            Some(opts) if self.vid.is_some() && is_live(&self.input) => opts,
+            _ => return,
+        };
+        let dir = opts.input_dir(&self.input);
+        let rotation = Rotation { secs: opts.segment_secs, bytes: opts.segment_bytes };
+        let retention = Retention { files: opts.keep_files, bytes: opts.keep_bytes };
+        match FFMpegArchiver::start(&self.input, &dir, &opts.format, rotation, retention) {
+            Ok(archiver) => self.archiver = Some(archiver),
+            Err(e) => warn!("couldn't archive into {}: {}", dir.display(), e),
+        }
+    }
+
+    /// Forget the archiver if it ended on its own, e.g. because its copy of the input did
+    fn check_archive(&mut self) {
+        if self.archiver.as_ref().map_or(false, |a| !a.is_running()) {
+            if let Some(archiver) = self.archiver.take() {
+                warn!("archiving into {} ended before the input", archiver.dir.display());
+                archiver.stop();
+            }
+        }
+    }
+
+    /// Copy live inputs to files while they are played, or stop doing so
+    pub(crate) fn archive(&mut self, opts: Option<ArchiveOpts>) {
+        if opts != self.archive {
+            self.archive = opts;
+            self.restart_archive();
+        }
+    }
+
+    /// Share decoded frames with other local processes, or stop doing so
+    pub(crate) fn share(&mut self, opts: Option<ShmOpts>) {
+        if opts == self.shm {
+            return;
+        }
+        self.shm = opts;
+        #[cfg(feature = "shm")]
+        {
+            self.shm_writer = self.shm.as_ref().and_then(|opts| {
+                ShmWriter::create(&opts.path)
+                    .map_err(|e| warn!("couldn't share frames at {}: {}", opts.path.display(), e))
+                    .ok()
+            });
+        }
+        #[cfg(not(feature = "shm"))]
+        if let Some(opts) = &self.shm {
+            warn!("built without `shm`, frames aren't shared at {}", opts.path.display());
+        }
+    }
+
+    /// Retry failed reads instead of closing the video right away
+    pub(crate) fn read_policy(&mut self, policy: ReadPolicy) {
+        self.read_policy = policy;
+        if let Some(vid) = &self.vid {
+            vid.max_skips(policy.skips);
+        }
+    }
+
+    /// Let processing catch up with live videos instead of lagging behind
+    pub(crate) fn skip_stale(&mut self, skip_stale: bool) {
+        self.skip_stale = skip_stale;
+        if let Some(vid) = &self.vid {
+            vid.skip_stale(skip_stale);
+        }
+    }
+
+    /// Details of the playing video
+    pub(crate) fn info(&self) -> Option<VideoInfo> {
+        let vid = self.vid.as_ref()?;
+        let output = &vid.video_output;
+        let (url, input) = match &vid.video_input {
+            Some((url, stream)) => (Some(url.clone()), Some(stream)),
+            None => (None, None),
+        };
+        Some(VideoInfo {
+            input: self.input.iter().map(|arg| redact(arg)).collect(),
+            url,
+            size: [output.width, output.height],
+            fps: self.fps(),
+            codec: input.and_then(|s| s.codec.clone()),
+            decoder: vid.progress().map(DecoderStats::from),
+            ffmpeg: vid.version.clone(),
+        })
+    }
+
+    /// Frame rate of the input, otherwise of the output
+    pub(crate) fn fps(&self) -> Option<f32> {
+        let vid = self.vid.as_ref()?;
+        vid.video_input.as_ref().and_then(|(_, s)| s.fps).or(vid.video_output.fps)
+    }
+
+    /// Seconds into the video of a frame read from the current source
+    fn position(&self, id: u64) -> f64 {
+        let fps = self.fps().unwrap_or(1.0) as f64;
+        self.start + id.saturating_sub(1) as f64 / fps
+    }
+
+    /// How far processing fell behind a live input, none for others
+    pub(crate) fn lag(&self) -> Option<f64> {
+        self.lag
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Abort reading the current video from elsewhere
+    pub(crate) fn abort_handle(&self) -> Option<AbortHandle> {
+        self.vid.as_ref().and_then(FrameReader::abort_handle)
+    }
+}
+
+impl Drop for VideoPlayer {
+    fn drop(&mut self) {
+        // lets the current file finish
+        if let Some(archiver) = self.archiver.take() {
+            archiver.stop();
+        }
+    }
 }
 
 impl Processor for VideoPlayer {
@@ -94,16 +457,34 @@ impl Processor for VideoPlayer {
     fn control(&mut self, cmd: Self::Command) -> Result<&mut Self, Self::ControlError> {
         match cmd {
             Self::Command::Play(input) => {
-                self.close_video()?;
                 self.input = input;
-                let builder = FFMpegDecoderBuilder::default().input(self.input.clone());
-                self.vid = Some(FFMpegDecoder::try_new(builder)?);
+                self.frames_read = 0;
+                self.decoded_before = 0;
+                let played = self.play();
+                self.restart_archive();
+                played?;
             }
             Self::Command::Pause(paused) => {
                 self.paused = paused;
+                if let Some(vid) = &self.vid {
+                    vid.pause(paused);
+                }
             }
             Self::Command::Stop => {
                 self.close_video()?;
+                self.restart_archive();
+            }
+            Self::Command::Clip(clip) => {
+                let changed = clip != self.clip;
+                self.clip = clip;
+                if changed && self.vid.is_some() {
+                    self.play()?;
+                }
+            }
+            Self::Command::Seek(secs) => {
+                if self.vid.is_some() {
+                    self.play_from(Some(secs.max(0.0)))?;
+                }
             }
         }
         Ok(self)
@@ -117,24 +498,51 @@ impl Processor for VideoPlayer {
         if self.paused {
             return Ok(());
         }
-        if let Some(vid) = self.vid.as_mut() {
-            // either reuse, re-create (on size change) or create a frame with suitable buffer
-            let frame = if let Some(ref mut frame) = out {
-                if frame.img.width() != vid.video_output.width
-                    || frame.img.height() != vid.video_output.height
-                {
-                    frame.img = vid.empty_image();
+        while let Some(vid) = self.vid.as_mut() {
+            // the previous frame's buffer is swapped for the decoded one and reused
+            let frame = out.get_or_insert_with(|| Frame::new(0, Arc::new(vid.empty_image())));
+            let img = writable(&mut frame.img, || vid.empty_image());
+            let max_reopens = self.read_policy.max_reopens(vid.resyncs);
+            let read = vid.read_frame(img);
+            frame.ingested = vid.decoded_at().unwrap_or_else(Instant::now);
+            match read {
+                Ok(id) => {
+                    frame.id = id;
+                    self.frames_read = id;
+                    frame.decoded = self.decoded_before + id;
+                    frame.position = self.position(id);
+                    if is_live(&self.input) {
+                        let now = Instant::now();
+                        let (since, first) = *self.live_since.get_or_insert((now, frame.position));
+                        let elapsed = now.duration_since(since).as_secs_f64();
+                        self.lag = Some(elapsed - (frame.position - first));
+                    }
+                    #[cfg(feature = "shm")]
+                    if let Some(writer) = &mut self.shm_writer {
+                        if let Err(e) = writer.write(frame) {
+                            warn!("stopped sharing frames: {}", e);
+                            self.shm_writer = None;
+                        }
+                    }
+                    self.reopened = 0;
+                    self.check_archive();
+                    break;
                 }
-                frame
-            } else {
-                out.get_or_insert_with(|| Frame { id: 0, img: vid.empty_image() })
-            };
-            let id = vid.read_frame(&mut frame.img);
-            if let Err(VideoProcError::FinishedNormally { .. }) = id {
-                self.close_video()?;
+                Err(e @ VideoProcError::FinishedNormally { .. }) => {
+                    self.close_video()?;
+                    return Err(e);
+                }
+                Err(e) if self.reopened < max_reopens => {
+                    self.reopened += 1;
+                    warn!("reopening video ({}/{}): {}", self.reopened, max_reopens, e);
+                    if let Err(reopen) = self.reopen() {
+                        warn!("couldn't reopen video: {}", reopen);
+                        return Err(e);
+                    }
+                }
+                Err(e) => return Err(e),
             }
-            frame.id = id?;
-        };
+        }
         Ok(())
     }
 }
@@ -175,6 +583,28 @@ impl Deref for ValidScale {
     }
 }
 
+/// Output of scaling that only owns a frame if it was resized
+#[derive(Default)]
+pub(crate) enum ScaledFrame {
+    /// Nothing was scaled yet
+    #[default]
+    Empty,
+    /// Same as the input at unit scale, saves a copy
+    Input,
+    Resized(Frame),
+}
+
+impl ScaledFrame {
+    /// Scaled frame, borrowed from the input it was scaled from if not resized
+    pub(crate) fn get<'a>(&'a self, input: &'a Option<Frame>) -> Option<&'a Frame> {
+        match self {
+            Self::Empty => None,
+            Self::Input => input.as_ref(),
+            Self::Resized(frame) => Some(frame),
+        }
+    }
+}
+
 /// Scale frames by a constant factor
 pub(crate) struct Scale {
     factor: ValidScale,
@@ -196,7 +626,30 @@ impl Scale {
     fn is_unit_scale(&self) -> bool {
         self.factor.0 == 1.0f32
     }
+
+    pub(crate) fn factor(&self) -> f32 {
+        self.factor.0
+    }
+
+    /// Size of a frame after scaling
+    pub(crate) fn output_size(&self, [width, height]: [u32; 2]) -> [u32; 2] {
+        [(width as f32 * self.factor.0) as _, (height as f32 * self.factor.0) as _]
+    }
+}
+/// Resize an image to fill another one
+///
+/// The source may be a cropped view.
+fn resize_into(
+    resizer: &mut fr::Resizer,
+    src: &impl AsFrImageView,
+    dst: &mut impl AsFrImageViewMut,
+) -> Result<(), ScaleProcError> {
+    let src_view = src.fr_view().map_err(|e| ScaleProcError::from_view(e, true))?;
+    let mut dst_view = dst.fr_view_mut().map_err(|e| ScaleProcError::from_view(e, false))?;
+    resizer.resize(&src_view, &mut dst_view)?;
+    Ok(())
 }
+
 /// Error processing scale
 #[derive(Error, Debug)]
 pub(crate) enum ScaleProcError {
@@ -208,13 +661,26 @@ pub(crate) enum ScaleProcError {
     PixelType(#[from] fr::DifferentTypesOfPixelsError),
     #[error(transparent)]
     BufferError(#[from] fr::ImageBufferError),
+    #[error(transparent)]
+    Crop(#[from] fr::CropBoxError),
+}
+
+impl ScaleProcError {
+    fn from_view(e: FrViewError, input: bool) -> Self {
+        match (e, input) {
+            (FrViewError::ZeroSize, true) => Self::ZeroSizeIn,
+            (FrViewError::ZeroSize, false) => Self::ZeroSizeOut,
+            (FrViewError::Buffer(e), _) => Self::BufferError(e),
+            (FrViewError::Crop(e), _) => Self::Crop(e),
+        }
+    }
 }
 
 impl Processor for Scale {
     type Command = f32;
     type ControlError = <ValidScale as TryFrom<f32>>::Error;
     type Input = Option<Frame>;
-    type Output = Option<Frame>;
+    type Output = ScaledFrame;
     type ProcessResult = Result<(), ScaleProcError>;
 
     fn control(&mut self, cmd: Self::Command) -> Result<&mut Self, Self::ControlError> {
@@ -236,46 +702,25 @@ impl Processor for Scale {
             None => return Ok(()),
         };
         if self.is_unit_scale() {
-            // todo: can we at all avoid a clone?
-            *out = Some(Frame { id: input.id, img: input.img.clone() });
+            *out = ScaledFrame::Input;
             return Ok(());
         }
 
-        // todo: some conversion trait
-        // get input view
-        let img_view = fr::ImageView::from_buffer(
-            NonZeroU32::new(input.img.width()).ok_or(ScaleProcError::ZeroSizeIn)?,
-            NonZeroU32::new(input.img.height()).ok_or(ScaleProcError::ZeroSizeIn)?,
-            input.img.as_raw(),
-            fr::PixelType::U8x3,
-        )?;
-
-        let nwidth = (input.img.width() as f32 * self.factor.0) as _;
-        let nheight = (input.img.height() as f32 * self.factor.0) as _;
-        let nwidth0 = NonZeroU32::new(nwidth).ok_or(ScaleProcError::ZeroSizeOut)?;
-        let nheight0 = NonZeroU32::new(nheight).ok_or(ScaleProcError::ZeroSizeOut)?;
-
-        // todo: some conversion trait
-        // get or create new frame
-        let frame = if let Some(ref mut frame) = out {
-            if frame.img.width() != nwidth || frame.img.height() != nheight {
-                frame.img = BgrImage::new(nwidth, nheight);
-            }
-            frame.id = input.id;
-            frame
-        } else {
-            out.get_or_insert_with(|| Frame { id: input.id, img: BgrImage::new(nwidth, nheight) })
-        };
+        let [nwidth, nheight] = self.output_size([input.img.width(), input.img.height()]);
 
-        // get output view
-        let mut img_view_mut = fr::ImageViewMut::from_buffer(
-            nwidth0,
-            nheight0,
-            frame.img.as_mut(),
-            fr::PixelType::U8x3,
-        )?;
-
-        self.resizer.resize(&img_view, &mut img_view_mut)?;
+        // reuse or create new frame
+        let mut frame = match mem::take(out) {
+            ScaledFrame::Resized(frame) if frame.img.dimensions() == (nwidth, nheight) => frame,
+            _ => Frame::new(input.id, Arc::new(BgrImage::new(nwidth, nheight))),
+        };
+        frame.id = input.id;
+        frame.decoded = input.decoded;
+        frame.position = input.position;
+        frame.ingested = input.ingested;
+        let img = writable(&mut frame.img, || BgrImage::new(nwidth, nheight));
+        let resized = resize_into(&mut self.resizer, &*input.img, img);
+        *out = ScaledFrame::Resized(frame);
+        resized?;
         self.dirty = false;
         Ok(())
     }
@@ -284,21 +729,101 @@ impl Processor for Scale {
 #[cfg(test)]
 mod test {
     use super::*;
+    use infur_test_gen::long_small_video;
 
+    #[test]
+    fn live_inputs() {
+        let input = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert!(is_live(&input(&["RTSP://cam/stream"])));
+        assert!(is_live(&input(&["-f", "v4l2", "-i", "/dev/video0"])));
+        assert!(!is_live(&input(&["-re", "video.mp4"])));
+        assert!(!is_live(&input(&["https://host/video.mp4"])));
+    }
+    #[test]
+    fn archive_dirs() {
+        let opts = ArchiveOpts::default();
+        let dir = |args: &[&str]| {
+            let input = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+            opts.input_dir(&input)
+        };
+        assert_eq!(dir(&["rtsp://cam:554/live/1"]), opts.dir.join("cam_554_live_1"));
+        assert_eq!(dir(&["-f", "v4l2", "-i", "/dev/video0"]), opts.dir.join("dev_video0"));
+        assert_eq!(dir(&["rtsp://user:p@ss@cam/live"]), opts.dir.join("cam_live"));
+        assert_eq!(dir(&["http://cam/snap?user=a@b"]), opts.dir.join("cam_snap_user_a_b"));
+    }
+
+    #[test]
+    fn reopen_after_last_frame() {
+        let input = long_small_video().to_string_lossy().to_string();
+        let mut player = VideoPlayer::default();
+        player.control(VideoCmd::Play(vec![input])).unwrap();
+        let mut frame = None;
+        for _ in 0..5 {
+            player.advance(&(), &mut frame).unwrap();
+        }
+        player.reopen().unwrap();
+        player.advance(&(), &mut frame).unwrap();
+        let frame = frame.unwrap();
+        assert_eq!((frame.id, frame.decoded, frame.position), (1, 6, 0.5));
+    }
+    #[test]
+    fn reopen_pipes_instead_of_skipping() {
+        let policy = ReadPolicy { skips: 3, reopens: 2 };
+        assert_eq!(policy.max_reopens(true), 2);
+        assert_eq!(policy.max_reopens(false), 5);
+    }
     #[test]
     fn scale_from_size0() {
-        let zero = Frame { id: 0, img: BgrImage::new(0, 10) };
-        let mut out = None;
+        let zero = Frame::new(0, Arc::new(BgrImage::new(0, 10)));
+        let mut out = ScaledFrame::default();
         let mut scale = Scale::default();
         scale.control(0.99).unwrap();
         assert!(matches!(scale.advance(&Some(zero), &mut out), Err(ScaleProcError::ZeroSizeIn)));
     }
     #[test]
     fn scale_to_size0() {
-        let img = Frame { id: 0, img: BgrImage::new(10, 10) };
-        let mut out = None;
+        let img = Frame::new(0, Arc::new(BgrImage::new(10, 10)));
+        let mut out = ScaledFrame::default();
         let mut scale = Scale::default();
         scale.control(0.00000001).unwrap();
         assert!(matches!(scale.advance(&Some(img), &mut out), Err(ScaleProcError::ZeroSizeOut)));
     }
+    #[test]
+    fn unit_scale_borrows() {
+        let input = Some(Frame::new(3, Arc::new(BgrImage::new(10, 10))));
+        let mut out = ScaledFrame::default();
+        let mut scale = Scale::default();
+        scale.advance(&input, &mut out).unwrap();
+        assert!(matches!(out, ScaledFrame::Input));
+        assert!(std::ptr::eq(out.get(&input).unwrap(), input.as_ref().unwrap()));
+        scale.control(0.5).unwrap();
+        scale.advance(&input, &mut out).unwrap();
+        let scaled = out.get(&input).unwrap();
+        assert_eq!((scaled.id, scaled.img.dimensions()), (3, (5, 5)));
+    }
+    #[test]
+    fn write_unshared() {
+        let mut buffer = Arc::new(vec![1u8]);
+        let ptr = Arc::as_ptr(&buffer);
+        writable(&mut buffer, Vec::new).push(2);
+        assert_eq!((Arc::as_ptr(&buffer), buffer.as_slice()), (ptr, [1, 2].as_slice()));
+        let shared = buffer.clone();
+        writable(&mut buffer, Vec::new).push(3);
+        assert_eq!((shared.as_slice(), buffer.as_slice()), ([1, 2].as_slice(), [3].as_slice()));
+    }
+    #[test]
+    fn resize_gray() {
+        let src = image_ext::GrayImage::from_pixel(4, 4, image_ext::Luma([7]));
+        let mut dst = image_ext::GrayImage::new(2, 2);
+        resize_into(&mut fr::Resizer::new(fr::ResizeAlg::Nearest), &src, &mut dst).unwrap();
+        assert_eq!(dst.as_raw(), &[7; 4]);
+    }
+    #[test]
+    fn resize_crop() {
+        let src = BgrImage::from_fn(4, 4, |x, _| image_ext::Bgr([x as u8; 3]));
+        let crop = image_ext::StridedView::crop(&src, 2, 0, 2, 4).unwrap();
+        let mut dst = BgrImage::new(1, 1);
+        resize_into(&mut fr::Resizer::new(fr::ResizeAlg::Nearest), &crop, &mut dst).unwrap();
+        assert!(dst.as_raw().iter().all(|&v| v >= 2));
+    }
 }
@@ -0,0 +1,259 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use tracing::{debug, info, warn};
+
+use crate::{
+    app::AppCmd,
+    config::{self, AppState},
+    decode_predict::OverlayCmd,
+    gui::ProcConfig,
+    predict_onnx::ModelCmd,
+    processing::VideoCmd,
+};
+
+/// How often a watched config file is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Config file whose changes are applied while running
+pub(crate) struct ConfigWatch {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// As last loaded
+    state: AppState,
+    checked: Instant,
+}
+
+impl ConfigWatch {
+    /// Watch a file for changes since it was loaded as `state`
+    pub(crate) fn new(path: &Path, state: AppState) -> Self {
+        Self { path: path.to_path_buf(), modified: modified(path), state, checked: Instant::now() }
+    }
+
+    /// Configs of each pipeline before and after the file changed, checked at intervals
+    ///
+    /// A file that can't be loaded is reported and skipped until it changes again.
+    pub(crate) fn poll(&mut self) -> Option<Vec<(ProcConfig, ProcConfig)>> {
+        if self.checked.elapsed() < RELOAD_INTERVAL {
+            return None;
+        }
+        self.checked = Instant::now();
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        let state = match config::load(&self.path) {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("not reloading config: {}", e);
+                return None;
+            }
+        };
+        if config::same(&state, &self.state) {
+            debug!("config {:?} is unchanged, e.g. as just saved", self.path);
+            return None;
+        }
+        if state.pipelines.len() != self.state.pipelines.len() {
+            warn!("pipelines can't be added or removed while running");
+        }
+        info!("reloading config {:?}", self.path);
+        let old = std::mem::replace(&mut self.state, state);
+        Some(old.pipelines.into_iter().zip(self.state.pipelines.iter().cloned()).collect())
+    }
+
+    /// Take `state` as loaded if it was saved to the watched file, not to reload it
+    pub(crate) fn saved(&mut self, path: &Path, state: AppState) {
+        if path == self.path {
+            self.state = state;
+        }
+    }
+}
+
+/// Take over what changed from `old` to `new` into `config`, with commands to apply it
+///
+/// Settings left as they were keep what's in `config`, e.g. overrides by arguments.
+pub(crate) fn reload(config: &mut ProcConfig, old: &ProcConfig, new: &ProcConfig) -> Vec<AppCmd> {
+    let mut cmds = vec![];
+    if old.scale != new.scale {
+        config.scale = new.scale;
+        cmds.push(AppCmd::Scale(new.scale));
+    }
+    if old.frame_policy != new.frame_policy {
+        config.frame_policy = new.frame_policy;
+        cmds.push(AppCmd::FramePolicy(new.frame_policy));
+    }
+    if old.read_policy != new.read_policy {
+        config.read_policy = new.read_policy;
+        cmds.push(AppCmd::ReadPolicy(new.read_policy));
+    }
+    if old.error_policy != new.error_policy {
+        config.error_policy = new.error_policy;
+        cmds.push(AppCmd::ErrorPolicy(new.error_policy));
+    }
+    if old.lag_policy != new.lag_policy {
+        config.lag_policy = new.lag_policy;
+        cmds.push(AppCmd::LagPolicy(new.lag_policy));
+    }
+    if old.latency_budget != new.latency_budget {
+        config.latency_budget = new.latency_budget;
+        cmds.push(AppCmd::LatencyBudget(new.latency_budget));
+    }
+    if old.shm != new.shm {
+        config.shm = new.shm.clone();
+        cmds.push(AppCmd::Share(new.shm.clone()));
+    }
+    if old.archive != new.archive {
+        config.archive = new.archive.clone();
+        cmds.push(AppCmd::Archive(new.archive.clone()));
+    }
+    if old.alpha_curve != new.alpha_curve {
+        config.alpha_curve = new.alpha_curve;
+        cmds.push(AppCmd::Overlay(OverlayCmd::AlphaCurve(new.alpha_curve)));
+    }
+    if old.hatch_margin != new.hatch_margin {
+        config.hatch_margin = new.hatch_margin;
+        cmds.push(AppCmd::Overlay(OverlayCmd::HatchMargin(new.hatch_margin)));
+    }
+    if old.class_map != new.class_map {
+        config.class_map = new.class_map.clone();
+        cmds.push(AppCmd::Overlay(OverlayCmd::ClassMap(new.class_map.clone())));
+    }
+    if old.overlay_style != new.overlay_style {
+        config.overlay_style = new.overlay_style;
+        cmds.push(AppCmd::OverlayStyle(new.overlay_style));
+    }
+    if old.zones != new.zones {
+        config.zones = new.zones.clone();
+        cmds.push(AppCmd::Zones(new.zones.clone()));
+    }
+    if old.calibration != new.calibration {
+        config.calibration = new.calibration;
+        cmds.push(AppCmd::Calibration(new.calibration));
+    }
+    if old.providers != new.providers {
+        config.providers = new.providers.clone();
+        cmds.push(AppCmd::Model(ModelCmd::Providers(new.providers.clone())));
+    }
+    if old.preview_model_input != new.preview_model_input {
+        config.preview_model_input = new.preview_model_input;
+        cmds.push(AppCmd::Model(ModelCmd::PreviewInput(new.preview_model_input)));
+    }
+
+    // overrides of the model in effect, re-loaded if it changed
+    if old.model_input != new.model_input {
+        config.model_input = new.model_input.clone();
+    }
+    let model = &config.model_input;
+    let routes_changed = old.output_routes.get(model) != new.output_routes.get(model);
+    let format_changed = old.input_formats.get(model) != new.input_formats.get(model);
+    if old.output_routes != new.output_routes {
+        config.output_routes = new.output_routes.clone();
+    }
+    if old.input_formats != new.input_formats {
+        config.input_formats = new.input_formats.clone();
+    }
+    let load = old.model_input != new.model_input;
+    if load || routes_changed {
+        let routes = config.output_routes.get(&config.model_input).cloned();
+        cmds.push(AppCmd::Route(routes.unwrap_or_default()));
+    }
+    if load || format_changed {
+        let format = config.input_formats.get(&config.model_input).copied();
+        cmds.push(AppCmd::Model(ModelCmd::InputFormat(format.unwrap_or_default())));
+    }
+    if load {
+        cmds.push(AppCmd::Model(ModelCmd::Load(config.model_input.clone())));
+    }
+
+    if old.clip != new.clip {
+        config.clip = new.clip;
+        cmds.push(AppCmd::Video(VideoCmd::Clip(new.clip)));
+    }
+    if old.video_input != new.video_input {
+        config.video_input = new.video_input.clone();
+        let input = new.video_input.iter().filter(|s| !s.is_empty()).cloned().collect();
+        cmds.push(AppCmd::Video(VideoCmd::Play(input)));
+    }
+    if old.paused != new.paused {
+        config.paused = new.paused;
+        cmds.push(AppCmd::Video(VideoCmd::Pause(new.paused)));
+    }
+    if old.history != new.history {
+        config.history = new.history;
+    }
+    if old.sinks != new.sinks || old.rules != new.rules {
+        warn!("sinks and rules are only opened at startup");
+    }
+    cmds
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reload_changes_only() {
+        let old = ProcConfig { model_input: "a.onnx".to_string(), ..Default::default() };
+        let mut config = ProcConfig { video_input: vec!["arg.mp4".to_string()], ..old.clone() };
+        assert!(reload(&mut config, &old, &old.clone()).is_empty());
+
+        let new = ProcConfig { scale: 0.25, ..old.clone() };
+        let cmds = reload(&mut config, &old, &new);
+        assert!(matches!(cmds[..], [AppCmd::Scale(s)] if s == 0.25));
+        assert_eq!(config.scale, 0.25);
+        assert_eq!(config.video_input, ["arg.mp4"], "overrides should be kept");
+    }
+
+    #[test]
+    fn reload_model() {
+        let old = ProcConfig { model_input: "a.onnx".to_string(), ..Default::default() };
+        let mut config = old.clone();
+        let new = ProcConfig { model_input: "b.onnx".to_string(), ..old.clone() };
+        let cmds = reload(&mut config, &old, &new);
+        assert_eq!(cmds.len(), 3);
+        assert!(matches!(cmds[0], AppCmd::Route(_)));
+        assert!(matches!(cmds[2], AppCmd::Model(ModelCmd::Load(ref m)) if m == "b.onnx"));
+        assert_eq!(config.model_input, "b.onnx");
+    }
+
+    #[test]
+    fn poll_changed_file() {
+        let path = std::env::temp_dir().join(format!("infur_reload_{}.toml", std::process::id()));
+        fs::write(&path, "scale = 0.5").unwrap();
+        let mut watch = ConfigWatch::new(&path, config::load(&path).unwrap());
+        watch.checked -= RELOAD_INTERVAL;
+        assert!(watch.poll().is_none());
+
+        fs::write(&path, "scale = 0.25").unwrap();
+        // regardless of the file system's timestamp resolution
+        watch.modified = None;
+        watch.checked -= RELOAD_INTERVAL;
+        let changes = watch.poll().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].0.scale, changes[0].1.scale), (0.5, 0.25));
+    }
+
+    #[test]
+    fn skip_saved_file() {
+        let path = std::env::temp_dir().join(format!("infur_saved_{}.toml", std::process::id()));
+        fs::write(&path, "scale = 0.5").unwrap();
+        let mut watch = ConfigWatch::new(&path, config::load(&path).unwrap());
+        let state = AppState::from(ProcConfig { scale: 0.25, ..Default::default() });
+        config::save(&state, &path).unwrap();
+        watch.saved(&path, state);
+        watch.modified = None;
+        watch.checked -= RELOAD_INTERVAL;
+        let changes = watch.poll();
+        fs::remove_file(&path).unwrap();
+        assert!(changes.is_none(), "saved by the GUI itself");
+    }
+}
@@ -0,0 +1,271 @@
+use std::{path::Path, time::Instant};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{
+    app::FrameRecord,
+    rules::RuleEvent,
+    sink::{ResultSink, SinkError},
+};
+
+/// Frames written per transaction
+const COMMIT_EVERY: usize = 32;
+/// Longest a transaction stays open while frames are written, e.g. of a slow stream
+const COMMIT_AFTER: std::time::Duration = std::time::Duration::from_secs(1);
+/// Wait on other connections writing
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS frames (
+        id INTEGER NOT NULL,
+        timestamp REAL NOT NULL,
+        total_area INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS frames_timestamp ON frames (timestamp);
+    CREATE TABLE IF NOT EXISTS class_areas (
+        frame_id INTEGER NOT NULL,
+        timestamp REAL NOT NULL,
+        class INTEGER NOT NULL,
+        area INTEGER NOT NULL,
+        share REAL NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS class_areas_class ON class_areas (class, timestamp);
+    CREATE TABLE IF NOT EXISTS events (
+        rule TEXT NOT NULL,
+        frame_id INTEGER NOT NULL,
+        timestamp REAL NOT NULL,
+        since REAL NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS events_timestamp ON events (timestamp);
+";
+
+/// Frame found by a query
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Hit {
+    pub(crate) frame_id: u64,
+    pub(crate) timestamp: f64,
+}
+
+/// Which way to search from a timestamp
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Next,
+    Previous,
+}
+
+impl Direction {
+    /// Comparison to the timestamp and ordering of results
+    fn sql(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Next => (">", "ASC"),
+            Self::Previous => ("<", "DESC"),
+        }
+    }
+}
+
+fn hit(row: &rusqlite::Row) -> rusqlite::Result<Hit> {
+    Ok(Hit { frame_id: row.get::<_, i64>(0)? as u64, timestamp: row.get(1)? })
+}
+
+/// SQLite database of per frame results and rule events
+///
+/// Frames are committed in batches, others see them after at most `COMMIT_EVERY` frames or
+/// `COMMIT_AFTER`, whichever comes first. Idle pipelines flush, e.g. when paused.
+pub(crate) struct ResultsDb {
+    conn: Connection,
+    /// Frames written since the last commit
+    pending: usize,
+    /// When the open transaction began
+    begun: Option<Instant>,
+}
+
+impl ResultsDb {
+    /// Open or create a database with its tables
+    pub(crate) fn open(path: &Path) -> Result<Self, SinkError> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // let readers query while results are written
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn, pending: 0, begun: None })
+    }
+
+    fn begin(&mut self) -> Result<(), SinkError> {
+        if self.conn.is_autocommit() {
+            self.conn.execute_batch("BEGIN")?;
+            self.begun = Some(Instant::now());
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), SinkError> {
+        if !self.conn.is_autocommit() {
+            self.conn.execute_batch("COMMIT")?;
+        }
+        self.pending = 0;
+        self.begun = None;
+        Ok(())
+    }
+
+    /// Store a fired rule right away
+    pub(crate) fn write_event(&mut self, event: &RuleEvent) -> Result<(), SinkError> {
+        self.conn.execute(
+            "INSERT INTO events (rule, frame_id, timestamp, since) VALUES (?1, ?2, ?3, ?4)",
+            params![event.rule, event.frame_id as i64, event.timestamp, event.since],
+        )?;
+        Ok(())
+    }
+
+    /// Closest frame before or after `timestamp` with a class covering at least a share of it
+    pub(crate) fn find_class(
+        &self,
+        class: usize,
+        min_share: f64,
+        timestamp: f64,
+        direction: Direction,
+    ) -> Result<Option<Hit>, SinkError> {
+        let (cmp, order) = direction.sql();
+        let sql = format!(
+            "SELECT frame_id, timestamp FROM class_areas
+             WHERE class = ?1 AND share >= ?2 AND timestamp {cmp} ?3
+             ORDER BY timestamp {order} LIMIT 1"
+        );
+        let params = params![class as i64, min_share, timestamp];
+        Ok(self.conn.query_row(&sql, params, hit).optional()?)
+    }
+
+    /// Closest frame before or after `timestamp` at which a rule fired, any rule if `None`
+    pub(crate) fn find_event(
+        &self,
+        rule: Option<&str>,
+        timestamp: f64,
+        direction: Direction,
+    ) -> Result<Option<Hit>, SinkError> {
+        let (cmp, order) = direction.sql();
+        let sql = format!(
+            "SELECT frame_id, timestamp FROM events
+             WHERE (?1 IS NULL OR rule = ?1) AND timestamp {cmp} ?2
+             ORDER BY timestamp {order} LIMIT 1"
+        );
+        Ok(self.conn.query_row(&sql, params![rule, timestamp], hit).optional()?)
+    }
+
+    /// Names of rules that fired
+    pub(crate) fn rules(&self) -> Result<Vec<String>, SinkError> {
+        let mut stmt = self.conn.prepare("SELECT DISTINCT rule FROM events ORDER BY rule")?;
+        let rules = stmt.query_map([], |row| row.get(0))?;
+        Ok(rules.collect::<Result<_, _>>()?)
+    }
+
+    /// Most recent events, latest first
+    pub(crate) fn events(&self, limit: usize) -> Result<Vec<RuleEvent>, SinkError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT rule, frame_id, timestamp, since FROM events
+             ORDER BY timestamp DESC LIMIT ?1",
+        )?;
+        let events = stmt.query_map(params![limit as i64], |row| {
+            Ok(RuleEvent {
+                rule: row.get(0)?,
+                frame_id: row.get::<_, i64>(1)? as u64,
+                timestamp: row.get(2)?,
+                since: row.get(3)?,
+            })
+        })?;
+        Ok(events.collect::<Result<_, _>>()?)
+    }
+}
+
+impl ResultSink for ResultsDb {
+    fn write(&mut self, record: &FrameRecord) -> Result<(), SinkError> {
+        self.begin()?;
+        let total = record.class_areas.iter().sum::<u64>();
+        self.conn.execute(
+            "INSERT INTO frames (id, timestamp, total_area) VALUES (?1, ?2, ?3)",
+            params![record.id as i64, record.timestamp, total as i64],
+        )?;
+        let mut insert = self.conn.prepare_cached(
+            "INSERT INTO class_areas (frame_id, timestamp, class, area, share)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        // absent classes are implied
+        for (class, area) in record.class_areas.iter().enumerate().filter(|(_, a)| **a > 0) {
+            let share = *area as f64 / total as f64;
+            insert.execute(params![
+                record.id as i64,
+                record.timestamp,
+                class as i64,
+                *area as i64,
+                share
+            ])?;
+        }
+        drop(insert);
+        self.pending += 1;
+        let overdue = self.begun.map_or(false, |begun| begun.elapsed() >= COMMIT_AFTER);
+        if self.pending >= COMMIT_EVERY || overdue {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        self.commit()
+    }
+}
+
+impl Drop for ResultsDb {
+    fn drop(&mut self) {
+        let _ = self.commit();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(id: u64, class_areas: &[u64]) -> FrameRecord<'_> {
+        FrameRecord::of_classes(id, id as f64 / 10.0, class_areas)
+    }
+
+    #[test]
+    fn query_frames_by_class() {
+        let mut db = ResultsDb::open(Path::new(":memory:")).unwrap();
+        db.write(&record(1, &[10, 0])).unwrap();
+        db.write(&record(2, &[9, 1])).unwrap();
+        db.write(&record(3, &[5, 5])).unwrap();
+        db.flush().unwrap();
+        let next = |share, after| db.find_class(1, share, after, Direction::Next).unwrap();
+        assert_eq!(next(0.0, 0.0), Some(Hit { frame_id: 2, timestamp: 0.2 }));
+        assert_eq!(next(0.5, 0.0).map(|h| h.frame_id), Some(3));
+        assert_eq!(next(0.0, 0.3), None);
+        let previous = db.find_class(1, 0.0, 0.3, Direction::Previous).unwrap();
+        assert_eq!(previous.map(|h| h.frame_id), Some(2));
+    }
+
+    #[test]
+    fn commit_slow_frames() {
+        let mut db = ResultsDb::open(Path::new(":memory:")).unwrap();
+        db.write(&record(1, &[10])).unwrap();
+        assert!(!db.conn.is_autocommit(), "frames are batched");
+        db.begun = db.begun.map(|begun| begun - COMMIT_AFTER);
+        db.write(&record(2, &[10])).unwrap();
+        assert!(db.conn.is_autocommit(), "transaction open for too long");
+    }
+
+    #[test]
+    fn find_events() {
+        let mut db = ResultsDb::open(Path::new(":memory:")).unwrap();
+        for (frame_id, rule) in [(4, "person"), (9, "car")] {
+            let timestamp = frame_id as f64;
+            let event = RuleEvent { rule: rule.to_string(), frame_id, timestamp, since: 1.0 };
+            db.write_event(&event).unwrap();
+        }
+        let rules = db.events(5).unwrap().into_iter().map(|e| e.rule).collect::<Vec<_>>();
+        assert_eq!(rules, ["car", "person"]);
+        assert_eq!(db.rules().unwrap(), ["car", "person"]);
+        let find =
+            |rule, direction| db.find_event(rule, 5.0, direction).unwrap().map(|h| h.frame_id);
+        assert_eq!(find(None, Direction::Next), Some(9));
+        assert_eq!(find(None, Direction::Previous), Some(4));
+        assert_eq!(find(Some("car"), Direction::Previous), None);
+    }
+}
@@ -0,0 +1,315 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use eframe::epaint::{Color32, ColorImage};
+use ndarray::{Array3, Ix2};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    annotations::Annotations,
+    app::Processor,
+    decode_predict::{squeeze, Boxes, ColorCode, HeatMap, Histogram, OverlayCmd, SegMap},
+    embeddings::Embedding,
+    predict_onnx::ModelOutput,
+};
+
+/// How a model output is visualized
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OutputDecoder {
+    /// Class palette of confidences (KxHxW) or class indices (HxW)
+    Segmentation,
+    /// Shades of a single channel, e.g. depth (HxW)
+    Heatmap,
+    /// Outlines of detections (Nx4+)
+    Boxes,
+    /// Features to search similar frames by (C, 1xC or CxHxW), not drawn
+    Embedding,
+    Ignore,
+}
+
+impl OutputDecoder {
+    pub(crate) const ALL: [Self; 5] =
+        [Self::Segmentation, Self::Heatmap, Self::Boxes, Self::Embedding, Self::Ignore];
+}
+
+/// Decoders by output name, outputs not routed are ignored except for the first one
+pub(crate) type OutputRoutes = BTreeMap<String, OutputDecoder>;
+
+/// Decoder of an output if not routed
+pub(crate) fn default_decoder(index: usize) -> OutputDecoder {
+    match index {
+        0 => OutputDecoder::Segmentation,
+        _ => OutputDecoder::Ignore,
+    }
+}
+
+/// `src` over `dst` of premultiplied colors
+fn over(dst: Color32, src: Color32) -> Color32 {
+    let rest = 255 - src.a() as u32;
+    let mix = |d: u8, s: u8| s.saturating_add((d as u32 * rest / 255) as u8);
+    Color32::from_rgba_premultiplied(
+        mix(dst.r(), src.r()),
+        mix(dst.g(), src.g()),
+        mix(dst.b(), src.b()),
+        mix(dst.a(), src.a()),
+    )
+}
+
+/// Blend `layer` over `img`, scaled to it by nearest neighbor
+fn blend(img: &mut ColorImage, layer: &ColorImage) {
+    let [w, h] = img.size;
+    let [lw, lh] = layer.size;
+    for (i, px) in img.pixels.iter_mut().enumerate() {
+        let (x, y) = (i % w * lw / w, i / w * lh / h);
+        *px = over(*px, layer.pixels[y * lw + x]);
+    }
+}
+
+/// Visualizes each model output by its decoder in one overlay
+#[derive(Default)]
+pub(crate) struct Router {
+    routes: OutputRoutes,
+    seg: ColorCode,
+    heatmap: HeatMap,
+    boxes: Boxes,
+    /// Decoded image per output
+    layers: Vec<Option<Arc<ColorImage>>>,
+    /// Number of pixels per class of the first segmentation, empty without any
+    pub(crate) class_areas: Vec<u64>,
+    /// Maximum confidences per class of the first segmentation
+    pub(crate) confidences: Vec<Histogram>,
+    /// Features of the first output routed as embedding
+    pub(crate) embedding: Option<Arc<Embedding>>,
+    /// Classes of the first segmentation if kept, and all boxes, to pre-annotate frames with
+    pub(crate) annotations: Annotations,
+    /// Hand out classes per pixel, otherwise their buffer is reused
+    pub(crate) keep_classes: bool,
+    /// Of classes after mapping
+    names: Arc<Vec<String>>,
+    /// Segmentations of confidences are drawn on the GPU
+    offload: bool,
+    /// Confidences of the first segmentation left to draw on the GPU
+    pub(crate) offloaded: Option<Arc<Array3<f32>>>,
+}
+
+impl Router {
+    pub(crate) fn set_routes(&mut self, routes: OutputRoutes) {
+        self.routes = routes;
+    }
+
+    /// Change how segmentations are drawn
+    pub(crate) fn control_overlay(&mut self, cmd: OverlayCmd) {
+        if let OverlayCmd::ClassMap(map) = &cmd {
+            _ = self.boxes.control(map.clone());
+            self.names = Arc::new(map.names.clone());
+        }
+        if let OverlayCmd::Offload(offload) = cmd {
+            self.offload = offload;
+        }
+        _ = self.seg.control(cmd);
+    }
+
+    /// Decode outputs named by `names` into `out`, `None` if none was visualized
+    ///
+    /// Boxes are drawn on an image of `size`, the model's input. Outputs are handed back to be
+    /// written into by the next inference, except offloaded confidences.
+    pub(crate) fn decode(
+        &mut self,
+        names: &[String],
+        outputs: &mut [ModelOutput],
+        size: [usize; 2],
+        out: &mut Option<Arc<ColorImage>>,
+    ) {
+        self.class_areas.clear();
+        self.confidences.clear();
+        self.embedding = None;
+        self.offloaded = None;
+        self.annotations = Annotations { names: self.names.clone(), ..Annotations::default() };
+        self.layers.resize(outputs.len(), None);
+        let mut decoded = vec![];
+        for (i, output) in outputs.iter_mut().enumerate() {
+            let route = names.get(i).and_then(|name| self.routes.get(name)).copied();
+            let layer = &mut self.layers[i];
+            let done = match (route.unwrap_or_else(|| default_decoder(i)), std::mem::take(output)) {
+                (OutputDecoder::Segmentation, taken) => SegMap::from_output(taken)
+                    .map(|seg| {
+                        self.seg.advance(&seg, layer);
+                        if !self.class_areas.is_empty() {
+                            *output = seg.into_output();
+                            return;
+                        }
+                        self.class_areas.clone_from(&self.seg.class_areas);
+                        self.confidences.clone_from(&self.seg.confidences);
+                        if self.keep_classes {
+                            self.annotations.classes = self.seg.classes.clone();
+                        }
+                        match seg {
+                            SegMap::Confidences(c) if self.offload => {
+                                self.offloaded = Some(Arc::new(c))
+                            }
+                            seg => *output = seg.into_output(),
+                        }
+                    })
+                    .is_some(),
+                (OutputDecoder::Heatmap, ModelOutput::Float(values)) => squeeze::<_, Ix2>(values)
+                    .map(|values| {
+                        self.heatmap.advance(&values, layer);
+                        *output = ModelOutput::Float(values.into_dyn());
+                    })
+                    .is_some(),
+                (OutputDecoder::Boxes, ModelOutput::Float(boxes)) => squeeze::<_, Ix2>(boxes)
+                    .map(|boxes| {
+                        let inp = (boxes, size);
+                        self.boxes.advance(&inp, layer);
+                        self.annotations.boxes.extend_from_slice(&self.boxes.detections);
+                        *output = ModelOutput::Float(inp.0.into_dyn());
+                    })
+                    .is_some(),
+                (OutputDecoder::Embedding, taken) => {
+                    if self.embedding.is_none() {
+                        self.embedding = Embedding::from_output(&taken).map(Arc::new);
+                    }
+                    *output = taken;
+                    false
+                }
+                (_, taken) => {
+                    *output = taken;
+                    false
+                }
+            };
+            // unless left to the GPU
+            if done && self.layers[i].is_some() {
+                decoded.push(i);
+            }
+        }
+        *out = match decoded.as_slice() {
+            [] => None,
+            // hand over without copying
+            [i] => self.layers[*i].clone(),
+            [first, rest @ ..] => {
+                let mut img = self.layers[*first].as_deref().cloned().unwrap_or_default();
+                for layer in rest.iter().filter_map(|i| self.layers[*i].as_deref()) {
+                    blend(&mut img, layer);
+                }
+                Some(Arc::new(img))
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ndarray::{Array2, Array3};
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn route_by_name() {
+        let seg = ModelOutput::Float(Array3::<f32>::ones([2, 4, 6]).into_dyn());
+        let depth = ModelOutput::Float(Array2::<f32>::zeros([2, 3]).into_dyn());
+        let mut router = Router::default();
+        let mut out = None;
+        router.decode(
+            &names(&["seg", "depth"]),
+            &mut [seg.clone(), depth.clone()],
+            [6, 4],
+            &mut out,
+        );
+        assert_eq!(router.class_areas.len(), 2, "first output should be segmented by default");
+        assert_eq!(out.as_ref().unwrap().size, [6, 4]);
+
+        router.set_routes(OutputRoutes::from([
+            ("seg".to_string(), OutputDecoder::Ignore),
+            ("depth".to_string(), OutputDecoder::Heatmap),
+        ]));
+        router.decode(&names(&["seg", "depth"]), &mut [seg, depth], [6, 4], &mut out);
+        assert!(router.class_areas.is_empty());
+        assert_eq!(out.unwrap().size, [3, 2]);
+    }
+
+    #[test]
+    fn keep_classes_if_asked() {
+        let seg = ModelOutput::Float(Array3::<f32>::ones([2, 4, 6]).into_dyn());
+        let mut router = Router::default();
+        let mut out = None;
+        router.decode(&names(&["seg"]), &mut [seg.clone()], [6, 4], &mut out);
+        assert!(router.annotations.classes.is_none());
+        router.keep_classes = true;
+        router.decode(&names(&["seg"]), &mut [seg], [6, 4], &mut out);
+        assert_eq!(router.annotations.classes.unwrap().dim(), (4, 6));
+    }
+
+    #[test]
+    fn blend_layers() {
+        let red = Color32::from_rgba_premultiplied(255, 0, 0, 255);
+        let mut img = ColorImage::new([4, 2], red);
+        let mut layer = ColorImage::new([2, 1], Color32::TRANSPARENT);
+        layer.pixels[1] = Color32::from_rgba_premultiplied(0, 0, 128, 128);
+        blend(&mut img, &layer);
+        assert_eq!(img.pixels[0], red);
+        assert_eq!(img.pixels[7], Color32::from_rgba_premultiplied(127, 0, 128, 255));
+    }
+
+    #[test]
+    fn skip_mismatching_outputs() {
+        let mut router = Router::default();
+        router.set_routes(OutputRoutes::from([("boxes".to_string(), OutputDecoder::Boxes)]));
+        let classes = ModelOutput::ClassIndex(Array2::<u16>::zeros([2, 2]).into_dyn());
+        let mut out = None;
+        router.decode(
+            &names(&["x", "boxes"]),
+            &mut [ModelOutput::Float(Array2::<f32>::zeros([1, 1]).into_dyn()), classes],
+            [4, 4],
+            &mut out,
+        );
+        assert!(out.is_none());
+    }
+
+    #[test]
+    fn offload_confidences() {
+        let seg = ModelOutput::Float(Array3::<f32>::ones([2, 4, 6]).into_dyn());
+        let depth = ModelOutput::Float(Array2::<f32>::zeros([2, 3]).into_dyn());
+        let mut router = Router::default();
+        router.set_routes(OutputRoutes::from([("depth".to_string(), OutputDecoder::Heatmap)]));
+        router.control_overlay(OverlayCmd::Offload(true));
+        let mut out = None;
+        router.decode(&names(&["seg", "depth"]), &mut [seg, depth], [6, 4], &mut out);
+        assert_eq!(router.offloaded.as_ref().unwrap().dim(), (2, 4, 6));
+        assert_eq!(router.class_areas.len(), 2, "classes should still be decoded");
+        assert_eq!(out.unwrap().size, [3, 2], "only the heatmap should be drawn");
+    }
+
+    #[test]
+    fn keep_embedding_undrawn() {
+        let mut router = Router::default();
+        router.set_routes(OutputRoutes::from([("emb".to_string(), OutputDecoder::Embedding)]));
+        let features = ModelOutput::Float(Array2::<f32>::ones([1, 8]).into_dyn());
+        let mut out = None;
+        router.decode(&names(&["emb"]), &mut [features], [4, 4], &mut out);
+        assert!(out.is_none());
+        assert!(router.embedding.is_some());
+        router.decode(&names(&["emb"]), &mut [], [4, 4], &mut out);
+        assert!(router.embedding.is_none());
+    }
+
+    #[test]
+    fn hand_back_outputs() {
+        let seg = ModelOutput::Float(Array3::<f32>::ones([2, 4, 6]).into_dyn());
+        let depth = ModelOutput::Float(Array2::<f32>::zeros([2, 3]).into_dyn());
+        let mut router = Router::default();
+        router.set_routes(OutputRoutes::from([("depth".to_string(), OutputDecoder::Heatmap)]));
+        let mut outputs = [seg, depth];
+        let mut out = None;
+        router.decode(&names(&["seg", "depth"]), &mut outputs, [6, 4], &mut out);
+        let shapes = outputs.iter().map(|o| o.shape().to_vec()).collect::<Vec<_>>();
+        assert_eq!(shapes, [vec![2, 4, 6], vec![2, 3]]);
+
+        router.control_overlay(OverlayCmd::Offload(true));
+        router.decode(&names(&["seg", "depth"]), &mut outputs, [6, 4], &mut out);
+        assert_eq!(outputs[0].shape(), [0], "offloaded confidences should be moved out");
+        assert_eq!(outputs[1].shape(), [2, 3]);
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    app::FrameRecord,
+    clips::{ClipOpts, FrameHistory},
+    sink::{ResultSink, SinkError},
+};
+
+/// Events kept for the GUI until it takes them
+const TOASTS_CAP: usize = 16;
+
+/// Condition on the results of one frame
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum Condition {
+    /// Share of the frame covered by a class, e.g. `0.1` for a tenth
+    ClassArea { class: usize, min: Option<f64>, max: Option<f64> },
+    /// Number of classes besides the background (class 0) covering any pixel
+    Classes { min: Option<usize>, max: Option<usize> },
+    /// Share of a zone covered by a class
+    ZoneArea { zone: String, class: usize, min: Option<f64>, max: Option<f64> },
+    /// Number of boxes centered in a zone, of a class or of any
+    ZoneDetections { zone: String, class: Option<usize>, min: Option<u64>, max: Option<u64> },
+    /// Seconds from decoding a frame to its results, e.g. `min` as a budget to alert beyond
+    Latency { min: Option<f64>, max: Option<f64> },
+}
+
+fn within<T: PartialOrd>(value: T, min: Option<T>, max: Option<T>) -> bool {
+    min.map_or(true, |min| value >= min) && max.map_or(true, |max| value <= max)
+}
+
+/// Share of all pixels counted in `areas` that are of `class`
+fn share(areas: &[u64], class: usize) -> f64 {
+    let total = areas.iter().sum::<u64>();
+    let area = areas.get(class).copied().unwrap_or(0);
+    if total == 0 {
+        0.0
+    } else {
+        area as f64 / total as f64
+    }
+}
+
+impl Condition {
+    fn holds(&self, record: &FrameRecord) -> bool {
+        match self {
+            Self::ClassArea { class, min, max } => {
+                within(share(record.class_areas, *class), *min, *max)
+            }
+            Self::Classes { min, max } => {
+                let classes = record.class_areas.iter().skip(1).filter(|a| **a > 0).count();
+                within(classes, *min, *max)
+            }
+            Self::ZoneArea { zone, class, min, max } => {
+                let areas = record.zone(zone).map_or(&[][..], |z| z.class_areas.as_slice());
+                within(share(areas, *class), *min, *max)
+            }
+            Self::ZoneDetections { zone, class, min, max } => {
+                let counts = record.zone(zone).map_or(&[][..], |z| z.detections.as_slice());
+                let n = match class {
+                    Some(class) => counts.get(*class).copied().unwrap_or(0),
+                    None => counts.iter().sum(),
+                };
+                within(n, *min, *max)
+            }
+            Self::Latency { min, max } => {
+                record.latency.map_or(false, |latency| within(latency, *min, *max))
+            }
+        }
+    }
+}
+
+/// What to do when a rule fires
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(crate) enum Action {
+    /// Log a warning
+    Log,
+    /// Notify in the GUI
+    Toast,
+    /// Export the frames kept around the event as a clip
+    Clip(ClipOpts),
+    /// Store the event in a results database
+    #[cfg(feature = "sqlite")]
+    Sqlite { path: std::path::PathBuf },
+    /// Publish the event to an MQTT broker
+    #[cfg(feature = "mqtt")]
+    Mqtt(crate::mqtt_sink::MqttOpts),
+    /// Post the event as JSON
+    #[cfg(feature = "webhook")]
+    Webhook(crate::webhook_sink::WebhookOpts),
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Fires actions once all conditions held for a while
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub(crate) struct Rule {
+    pub(crate) name: String,
+    #[serde(default = "enabled")]
+    pub(crate) enabled: bool,
+    /// Conditions that must all hold
+    pub(crate) when: Vec<Condition>,
+    /// Seconds the conditions must hold before firing
+    #[serde(default)]
+    pub(crate) sustained: f64,
+    pub(crate) actions: Vec<Action>,
+}
+
+impl Rule {
+    /// Whether it exports annotated clips, which need classes per pixel
+    pub(crate) fn annotates(&self) -> bool {
+        let annotated = |a: &Action| matches!(a, Action::Clip(c) if c.annotations.is_some());
+        self.enabled && self.actions.iter().any(annotated)
+    }
+}
+
+/// A rule that fired
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct RuleEvent {
+    pub(crate) rule: String,
+    pub(crate) frame_id: u64,
+    pub(crate) timestamp: f64,
+    /// Timestamp since when the conditions hold
+    pub(crate) since: f64,
+}
+
+/// Action ready to run
+enum Target {
+    Log,
+    Toast,
+    Clip(ClipOpts),
+    #[cfg(feature = "sqlite")]
+    Sqlite(crate::results_db::ResultsDb),
+    #[cfg(feature = "mqtt")]
+    Mqtt(crate::mqtt_sink::MqttSink),
+    #[cfg(feature = "webhook")]
+    Webhook(crate::webhook_sink::WebhookSink),
+}
+
+impl Target {
+    fn open(action: &Action) -> Result<Self, SinkError> {
+        Ok(match action {
+            Action::Log => Self::Log,
+            Action::Toast => Self::Toast,
+            Action::Clip(opts) => Self::Clip(opts.clone()),
+            #[cfg(feature = "sqlite")]
+            Action::Sqlite { path } => Self::Sqlite(crate::results_db::ResultsDb::open(path)?),
+            #[cfg(feature = "mqtt")]
+            Action::Mqtt(opts) => Self::Mqtt(crate::mqtt_sink::MqttSink::connect(opts)?),
+            #[cfg(feature = "webhook")]
+            Action::Webhook(opts) => {
+                Self::Webhook(crate::webhook_sink::WebhookSink::connect(opts)?)
+            }
+        })
+    }
+}
+
+/// Rule with its progress over frames
+struct RuleState {
+    rule: Rule,
+    targets: Vec<Target>,
+    /// Timestamp since when the conditions hold
+    since: Option<f64>,
+    fired: bool,
+}
+
+impl RuleState {
+    fn new(rule: Rule, targets: Vec<Target>) -> Self {
+        Self { rule, targets, since: None, fired: false }
+    }
+
+    /// Event if the rule fires with this frame, it fires again only after conditions lapsed
+    fn update(&mut self, record: &FrameRecord) -> Option<RuleEvent> {
+        if !self.rule.enabled || !self.rule.when.iter().all(|c| c.holds(record)) {
+            self.since = None;
+            self.fired = false;
+            return None;
+        }
+        // restart timing if the clock went back, e.g. with another video
+        let since = match self.since {
+            Some(since) if since <= record.timestamp => since,
+            _ => *self.since.insert(record.timestamp),
+        };
+        if self.fired || record.timestamp - since < self.rule.sustained {
+            return None;
+        }
+        self.fired = true;
+        Some(RuleEvent {
+            rule: self.rule.name.clone(),
+            frame_id: record.id,
+            timestamp: record.timestamp,
+            since,
+        })
+    }
+}
+
+/// Rules evaluated on every frame, shared with the GUI to toggle them and show events
+pub(crate) struct Rules {
+    states: Mutex<Vec<RuleState>>,
+    toasts: Mutex<VecDeque<RuleEvent>>,
+    /// Frames to export clips from
+    history: Option<Arc<FrameHistory>>,
+}
+
+impl Rules {
+    /// Connect all actions, failing on the first one that can't be
+    ///
+    /// Clips are exported from `history` of the frames evaluated.
+    pub(crate) fn open(
+        rules: &[Rule],
+        history: Option<Arc<FrameHistory>>,
+    ) -> Result<Arc<Self>, SinkError> {
+        let states = rules
+            .iter()
+            .map(|rule| {
+                let targets = rule.actions.iter().map(Target::open).collect::<Result<_, _>>()?;
+                Ok(RuleState::new(rule.clone(), targets))
+            })
+            .collect::<Result<_, SinkError>>()?;
+        Ok(Arc::new(Self { states: Mutex::new(states), toasts: Mutex::default(), history }))
+    }
+
+    /// Current rules
+    pub(crate) fn rules(&self) -> Vec<Rule> {
+        let states = self.states.lock().unwrap_or_else(PoisonError::into_inner);
+        states.iter().map(|s| s.rule.clone()).collect()
+    }
+
+    pub(crate) fn set_enabled(&self, index: usize, enabled: bool) {
+        let mut states = self.states.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(state) = states.get_mut(index) {
+            state.rule.enabled = enabled;
+        }
+    }
+
+    /// Events to notify about since last taken
+    pub(crate) fn take_toasts(&self) -> Vec<RuleEvent> {
+        self.toasts.lock().unwrap_or_else(PoisonError::into_inner).drain(..).collect()
+    }
+
+    /// Evaluate all rules on a frame and run the actions of those that fire
+    pub(crate) fn evaluate(&self, record: &FrameRecord) -> Result<Vec<RuleEvent>, SinkError> {
+        let mut events = vec![];
+        let mut states = self.states.lock().unwrap_or_else(PoisonError::into_inner);
+        for state in states.iter_mut() {
+            let event = match state.update(record) {
+                Some(event) => event,
+                None => continue,
+            };
+            for target in state.targets.iter_mut() {
+                match target {
+                    Target::Log => warn!(
+                        "rule {:?} fired at frame {} after {:.1}s",
+                        event.rule,
+                        event.frame_id,
+                        event.timestamp - event.since
+                    ),
+                    Target::Toast => {
+                        let mut toasts = self.toasts.lock().unwrap_or_else(PoisonError::into_inner);
+                        if toasts.len() == TOASTS_CAP {
+                            toasts.pop_front();
+                        }
+                        toasts.push_back(event.clone());
+                    }
+                    Target::Clip(opts) => match &self.history {
+                        Some(history) => history.flush(opts.sink(&event.rule, event.frame_id)),
+                        None => warn!("rule {:?} can't export clips here", event.rule),
+                    },
+                    #[cfg(feature = "sqlite")]
+                    Target::Sqlite(db) => db.write_event(&event)?,
+                    #[cfg(feature = "mqtt")]
+                    Target::Mqtt(sink) => sink.publish(&event)?,
+                    #[cfg(feature = "webhook")]
+                    Target::Webhook(sink) => sink.publish(&event)?,
+                }
+            }
+            events.push(event);
+        }
+        Ok(events)
+    }
+}
+
+/// Evaluates rules as results are written
+pub(crate) struct RuleSink(pub(crate) Arc<Rules>);
+
+impl ResultSink for RuleSink {
+    fn write(&mut self, record: &FrameRecord) -> Result<(), SinkError> {
+        self.0.evaluate(record).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::zones::ZoneStats;
+
+    fn record(timestamp: f64, class_areas: &[u64]) -> FrameRecord<'_> {
+        FrameRecord::of_classes(timestamp as u64, timestamp, class_areas)
+    }
+
+    fn rule(when: Vec<Condition>, sustained: f64) -> Rule {
+        Rule { name: "r".to_string(), enabled: true, when, sustained, actions: vec![] }
+    }
+
+    #[test]
+    fn conditions() {
+        let area = Condition::ClassArea { class: 1, min: Some(0.25), max: None };
+        assert!(area.holds(&record(0.0, &[3, 1])));
+        assert!(!area.holds(&record(0.0, &[4, 1])));
+        assert!(!area.holds(&record(0.0, &[])));
+        let classes = Condition::Classes { min: Some(1), max: Some(1) };
+        assert!(classes.holds(&record(0.0, &[5, 0, 2])));
+        assert!(!classes.holds(&record(0.0, &[5, 1, 2])));
+        let budget = Condition::Latency { min: Some(0.1), max: None };
+        assert!(budget.holds(&FrameRecord { latency: Some(0.2), ..record(0.0, &[]) }));
+        assert!(!budget.holds(&FrameRecord { latency: Some(0.05), ..record(0.0, &[]) }));
+        assert!(!budget.holds(&record(0.0, &[])), "unmeasured latency shouldn't alert");
+    }
+
+    #[test]
+    fn zone_conditions() {
+        let zones = [ZoneStats {
+            name: "spot".to_string(),
+            class_areas: vec![1, 3],
+            detections: vec![0, 2],
+            area: None,
+        }];
+        let record = FrameRecord { zones: &zones, ..FrameRecord::of_classes(0, 0.0, &[]) };
+        let zone = |name: &str| name.to_string();
+        let occupied =
+            Condition::ZoneArea { zone: zone("spot"), class: 1, min: Some(0.5), max: None };
+        assert!(occupied.holds(&record));
+        let cars = Condition::ZoneDetections {
+            zone: zone("spot"),
+            class: Some(1),
+            min: Some(2),
+            max: None,
+        };
+        assert!(cars.holds(&record));
+        let any =
+            Condition::ZoneDetections { zone: zone("other"), class: None, min: Some(1), max: None };
+        assert!(!any.holds(&record), "unknown zones should be empty");
+    }
+
+    #[test]
+    fn fire_once_sustained() {
+        let when = vec![Condition::Classes { min: Some(1), max: None }];
+        let mut state = RuleState::new(rule(when, 2.0), vec![]);
+        let fired = [(0.0, 1), (1.0, 1), (2.0, 1), (3.0, 1), (4.0, 0), (5.0, 1), (7.0, 1)]
+            .iter()
+            .filter_map(|&(t, area)| state.update(&record(t, &[1, area])))
+            .map(|e| (e.since, e.timestamp))
+            .collect::<Vec<_>>();
+        assert_eq!(fired, [(0.0, 2.0), (5.0, 7.0)]);
+    }
+
+    #[test]
+    fn toast_events() {
+        let mut toasting = rule(vec![], 0.0);
+        toasting.actions = vec![Action::Toast];
+        let rules = Rules::open(&[toasting, rule(vec![], 0.0)], None).unwrap();
+        rules.set_enabled(1, false);
+        assert_eq!(rules.evaluate(&record(1.0, &[])).unwrap().len(), 1);
+        assert_eq!(rules.take_toasts().len(), 1);
+        assert!(rules.take_toasts().is_empty());
+    }
+
+    #[test]
+    fn parse_toml() {
+        let rule: Rule = toml::from_str(
+            r#"
+            name = "crowded"
+            sustained = 2.5
+            when = [{ kind = "class_area", class = 15, min = 0.2 }]
+            actions = [{ kind = "log" }, { kind = "clip", dir = "clips", format = "gif" }]
+            "#,
+        )
+        .unwrap();
+        assert!(rule.enabled);
+        assert_eq!(rule.when, [Condition::ClassArea { class: 15, min: Some(0.2), max: None }]);
+        let clip = ClipOpts {
+            dir: "clips".into(),
+            format: crate::clips::ClipFormat::Gif,
+            annotations: None,
+        };
+        assert_eq!(rule.actions, [Action::Log, Action::Clip(clip)]);
+    }
+}
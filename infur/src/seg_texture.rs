@@ -0,0 +1,297 @@
+use std::{num::NonZeroU64, sync::Arc};
+
+use eframe::{
+    egui::{PaintCallback, Rect},
+    egui_wgpu::{self, RenderState},
+    wgpu,
+};
+use ndarray::Array3;
+use tracing::warn;
+
+use crate::{
+    class_map::ClassMap,
+    decode_predict::{AlphaCurve, COLORS_PALETTE, HATCH_WIDTH},
+    preview::{BlendMode, OverlayStyle},
+};
+
+/// Bytes of the uniform parameters, 12 scalars and the palette
+const PARAMS_BYTES: u64 = 12 * 4 + COLORS_PALETTE.len() as u64 * 16;
+
+/// How confidences are colorized, as `ColorCode` and `composite` would on the CPU
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SegStyle {
+    pub(crate) alpha_curve: AlphaCurve,
+    pub(crate) isolated: Option<usize>,
+    pub(crate) hatch_margin: Option<f32>,
+    pub(crate) class_map: ClassMap,
+    pub(crate) overlay: OverlayStyle,
+}
+
+/// Fixed function blending of premultiplied colors as `OverlayStyle::mix` does
+fn blend_state(mode: BlendMode) -> wgpu::BlendState {
+    use wgpu::BlendFactor::{Dst, One, OneMinusDst, OneMinusSrcAlpha};
+    let component = |src_factor, dst_factor| wgpu::BlendComponent {
+        src_factor,
+        dst_factor,
+        operation: wgpu::BlendOperation::Add,
+    };
+    let color = match mode {
+        BlendMode::Normal => component(One, OneMinusSrcAlpha),
+        BlendMode::Multiply => component(Dst, OneMinusSrcAlpha),
+        BlendMode::Screen => component(OneMinusDst, One),
+    };
+    wgpu::BlendState { color, alpha: component(One, OneMinusSrcAlpha) }
+}
+
+/// Bytes of a storage buffer of at least one element
+fn storage_bytes(values: usize) -> u64 {
+    values.max(1) as u64 * 4
+}
+
+/// GPU resources to colorize segmentations without a pass over confidences on the CPU
+///
+/// Confidences are uploaded as they are, their classes picked and drawn by the fragment shader.
+pub(crate) struct SegRenderResources {
+    /// By blend mode in the order of `BlendMode::ALL`
+    pipelines: [wgpu::RenderPipeline; 3],
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    linearize: bool,
+    confidences: Option<wgpu::Buffer>,
+    ids: Option<wgpu::Buffer>,
+    bind_group: Option<wgpu::BindGroup>,
+    blend: BlendMode,
+    uploaded: Option<u64>,
+    staging: Vec<u8>,
+    /// Confidences too large to bind were reported
+    warned: bool,
+}
+
+impl SegRenderResources {
+    fn new(device: &wgpu::Device, target_format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("seg_texture"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("seg_texture.wgsl").into()),
+        });
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(4),
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("seg_texture"),
+            entries: &[
+                storage(0),
+                storage(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: NonZeroU64::new(PARAMS_BYTES),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("seg_texture"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipelines = BlendMode::ALL.map(|mode| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("seg_texture"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: target_format,
+                        blend: Some(blend_state(mode)),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("seg_texture"),
+            size: PARAMS_BYTES,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            pipelines,
+            bind_group_layout,
+            uniform_buffer,
+            linearize: target_format.describe().srgb,
+            confidences: None,
+            ids: None,
+            bind_group: None,
+            blend: BlendMode::Normal,
+            uploaded: None,
+            staging: vec![],
+            warned: false,
+        }
+    }
+
+    /// Re-create a storage buffer unless it has `size` already, true if re-created
+    fn ensure_buffer(device: &wgpu::Device, buffer: &mut Option<wgpu::Buffer>, size: u64) -> bool {
+        if buffer.as_ref().map_or(false, |b| b.size() == size) {
+            return false;
+        }
+        *buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("seg_texture"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+        true
+    }
+
+    /// Upload confidences unless they were already uploaded under the same key, and the style
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        key: u64,
+        confidences: &Array3<f32>,
+        style: &SegStyle,
+    ) {
+        let (k, h, w) = confidences.dim();
+        let size = storage_bytes(confidences.len());
+        if size > device.limits().max_storage_buffer_binding_size as u64 {
+            if !self.warned {
+                warn!("{}x{}x{} confidences exceed what the GPU can bind", k, h, w);
+                self.warned = true;
+            }
+            self.bind_group = None;
+            self.uploaded = None;
+            return;
+        }
+        let mut rebind = Self::ensure_buffer(device, &mut self.confidences, size);
+        rebind |= Self::ensure_buffer(device, &mut self.ids, storage_bytes(k));
+        if let (Some(confidences), Some(ids)) = (&self.confidences, &self.ids) {
+            if rebind || self.bind_group.is_none() {
+                self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("seg_texture"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: confidences.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry { binding: 1, resource: ids.as_entire_binding() },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: self.uniform_buffer.as_entire_binding(),
+                        },
+                    ],
+                }));
+            }
+        }
+        if self.uploaded != Some(key) || rebind {
+            self.staging.clear();
+            self.staging.extend(confidences.iter().flat_map(|c| c.to_ne_bytes()));
+            if let Some(buffer) = &self.confidences {
+                queue.write_buffer(buffer, 0, &self.staging);
+            }
+            self.uploaded = Some(key);
+        }
+
+        // style may change while paused, thus always write it
+        let map = &style.class_map;
+        let ids = (0..k.max(1)).flat_map(|class| (map.map(class) as u32).to_ne_bytes());
+        if let Some(buffer) = &self.ids {
+            queue.write_buffer(buffer, 0, &ids.collect::<Vec<_>>());
+        }
+        let (curve, curve_param) = match style.alpha_curve {
+            AlphaCurve::Linear => (0, 0.0),
+            AlphaCurve::Gamma(gamma) => (1, gamma),
+            AlphaCurve::Step(threshold) => (2, threshold),
+        };
+        let mut params = vec![];
+        for u in [w, h, k, map.len(k), curve] {
+            params.extend((u as u32).to_ne_bytes());
+        }
+        params.extend(curve_param.to_ne_bytes());
+        params.extend(style.isolated.map_or(-1, |k| k as i32).to_ne_bytes());
+        params.extend(style.hatch_margin.unwrap_or(-1.0).to_ne_bytes());
+        params.extend(style.overlay.opacity.to_ne_bytes());
+        for u in [self.linearize as usize, HATCH_WIDTH, 0] {
+            params.extend((u as u32).to_ne_bytes());
+        }
+        for (r, g, b) in COLORS_PALETTE {
+            for c in [r, g, b, 255] {
+                params.extend((c as f32 / 255.0).to_ne_bytes());
+            }
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, &params);
+        self.blend = style.overlay.blend;
+    }
+
+    fn paint<'rp>(&'rp self, rpass: &mut wgpu::RenderPass<'rp>) {
+        if let Some(bind_group) = &self.bind_group {
+            let mode = BlendMode::ALL.iter().position(|&m| m == self.blend).unwrap_or(0);
+            rpass.set_pipeline(&self.pipelines[mode]);
+            rpass.set_bind_group(0, bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+    }
+}
+
+/// Register GPU resources needed by `paint_callback`
+pub(crate) fn register(render_state: &RenderState) {
+    let resources = SegRenderResources::new(&render_state.device, render_state.target_format);
+    render_state.egui_rpass.write().paint_callback_resources.insert(resources);
+}
+
+/// Paint KxHxW confidences over rect, uploading them only if key changed
+pub(crate) fn paint_callback(
+    rect: Rect,
+    key: u64,
+    confidences: Arc<Array3<f32>>,
+    style: SegStyle,
+) -> PaintCallback {
+    let cb = egui_wgpu::CallbackFn::new()
+        .prepare(move |device, queue, paint_callback_resources| {
+            if let Some(resources) = paint_callback_resources.get_mut::<SegRenderResources>() {
+                resources.prepare(device, queue, key, &confidences, &style);
+            }
+        })
+        .paint(move |_info, rpass, paint_callback_resources| {
+            if let Some(resources) = paint_callback_resources.get::<SegRenderResources>() {
+                resources.paint(rpass);
+            }
+        });
+    PaintCallback { rect, callback: Arc::new(cb) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blend_like_cpu() {
+        // multiply: dst * src + dst * (1 - src_alpha)
+        let multiply = blend_state(BlendMode::Multiply).color;
+        assert_eq!(multiply.src_factor, wgpu::BlendFactor::Dst);
+        assert_eq!(multiply.dst_factor, wgpu::BlendFactor::OneMinusSrcAlpha);
+        // screen: src * (1 - dst) + dst
+        let screen = blend_state(BlendMode::Screen).color;
+        assert_eq!(screen.src_factor, wgpu::BlendFactor::OneMinusDst);
+        assert_eq!(screen.dst_factor, wgpu::BlendFactor::One);
+        assert_eq!(blend_state(BlendMode::Normal), wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING);
+    }
+}
@@ -0,0 +1,109 @@
+// Colorize KxHxW confidences by their most confident class, blended over what's drawn
+
+struct Params {
+    width: u32,
+    height: u32,
+    // of the model
+    classes: u32,
+    // after merging by ids
+    mapped: u32,
+    // 0 linear, 1 gamma, 2 step
+    curve: u32,
+    curve_param: f32,
+    // only class drawn, all if negative
+    isolated: i32,
+    // hatch runner-up classes within, never if negative
+    hatch_margin: f32,
+    opacity: f32,
+    // 1 if the render target expects linear colors
+    linearize: u32,
+    hatch_width: u32,
+    _pad: u32,
+    palette: array<vec4<f32>, 20>,
+};
+
+@group(0) @binding(0)
+var<storage, read> confidences: array<f32>;
+@group(0) @binding(1)
+var<storage, read> ids: array<u32>;
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// one triangle covering the entire viewport
+@vertex
+fn vs_main(@builtin(vertex_index) idx: u32) -> VertexOut {
+    let uv = vec2<f32>(f32((idx << 1u) & 2u), f32(idx & 2u));
+    var out: VertexOut;
+    out.position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn to_linear(c: vec3<f32>) -> vec3<f32> {
+    let lo = c / 12.92;
+    let hi = pow((c + vec3<f32>(0.055)) / 1.055, vec3<f32>(2.4));
+    return select(hi, lo, c <= vec3<f32>(0.04045));
+}
+
+fn alpha(confidence: f32) -> f32 {
+    if (params.curve == 1u) {
+        return pow(max(confidence, 0.0), params.curve_param);
+    }
+    if (params.curve == 2u) {
+        return select(0.0, 1.0, confidence >= params.curve_param);
+    }
+    return confidence;
+}
+
+@fragment
+fn fs_main(in: VertexOut) -> @location(0) vec4<f32> {
+    let x = min(u32(in.uv.x * f32(params.width)), params.width - 1u);
+    let y = min(u32(in.uv.y * f32(params.height)), params.height - 1u);
+    let pixels = params.width * params.height;
+    let p = y * params.width + x;
+
+    // best and runner-up class, confidences of merged classes add up
+    var k_max = 0u;
+    var k_second = 0u;
+    var c_max = 0.0;
+    var c_second = 0.0;
+    for (var m = 0u; m < params.mapped; m = m + 1u) {
+        var c = 0.0;
+        for (var i = 0u; i < params.classes; i = i + 1u) {
+            if (ids[i] == m) {
+                c = c + confidences[i * pixels + p];
+            }
+        }
+        if (c > c_max) {
+            k_second = k_max;
+            c_second = c_max;
+            k_max = m;
+            c_max = c;
+        } else if (c > c_second) {
+            k_second = m;
+            c_second = c;
+        }
+    }
+
+    var shown = k_max;
+    let ambiguous = params.hatch_margin >= 0.0 && k_second != k_max
+        && c_max - c_second < params.hatch_margin;
+    if (ambiguous && (x + y) / params.hatch_width % 2u == 1u) {
+        shown = k_second;
+    }
+    if (params.isolated >= 0 && u32(params.isolated) != shown) {
+        return vec4<f32>(0.0);
+    }
+    var rgb = params.palette[shown % 20u].rgb;
+    if (params.linearize != 0u) {
+        rgb = to_linear(rgb);
+    }
+    // premultiplied
+    let a = alpha(c_max) * params.opacity;
+    return vec4<f32>(rgb * a, a);
+}
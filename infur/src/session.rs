@@ -0,0 +1,138 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, info};
+
+use crate::app::AppCmd;
+
+/// Error recording or replaying a session
+#[derive(Error, Debug)]
+pub(crate) enum SessionError {
+    #[error("couldn't access session {path:?}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("invalid command in line {line} of session {path:?}")]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error(transparent)]
+    Write(#[from] std::io::Error),
+    #[error(transparent)]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// Command issued some seconds after the session started
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub(crate) struct RecordedCmd {
+    pub(crate) secs: f64,
+    pub(crate) cmd: AppCmd,
+}
+
+/// Writes every command as one JSON line
+pub(crate) struct CmdRecorder {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl CmdRecorder {
+    pub(crate) fn create(path: &Path) -> Result<Self, SessionError> {
+        let file = File::create(path)
+            .map_err(|source| SessionError::Io { path: path.to_path_buf(), source })?;
+        Ok(Self { writer: BufWriter::new(file), start: Instant::now() })
+    }
+
+    pub(crate) fn record(&mut self, cmd: &AppCmd) -> Result<(), SessionError> {
+        let secs = self.start.elapsed().as_secs_f64();
+        serde_json::to_writer(&mut self.writer, &RecordedCmd { secs, cmd: cmd.clone() })?;
+        writeln!(self.writer)?;
+        // keep what happened up to a crash
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Read a recorded session
+pub(crate) fn load(path: &Path) -> Result<Vec<RecordedCmd>, SessionError> {
+    let io_err = |source| SessionError::Io { path: path.to_path_buf(), source };
+    let reader = BufReader::new(File::open(path).map_err(io_err)?);
+    let mut cmds = vec![];
+    for (n, line) in reader.lines().enumerate() {
+        let line = line.map_err(io_err)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let cmd = serde_json::from_str(&line).map_err(|source| SessionError::Parse {
+            path: path.to_path_buf(),
+            line: n + 1,
+            source,
+        })?;
+        cmds.push(cmd);
+    }
+    Ok(cmds)
+}
+
+/// Send commands with their original timing until done or processing hung up
+pub(crate) fn spawn_replay(cmds: Vec<RecordedCmd>, ctrl_tx: Sender<AppCmd>) -> std::io::Result<()> {
+    thread::Builder::new().name("Replay".to_string()).spawn(move || {
+        info!("replaying {} commands", cmds.len());
+        let start = Instant::now();
+        for RecordedCmd { secs, cmd } in cmds {
+            let due = Duration::from_secs_f64(secs.max(0.0));
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                thread::sleep(wait);
+            }
+            debug!("replaying command: {:?}", cmd);
+            if ctrl_tx.send(cmd).is_err() {
+                return;
+            }
+        }
+        info!("replay finished");
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::processing::VideoCmd;
+
+    #[test]
+    fn record_load() {
+        let path = std::env::temp_dir().join(format!("infur_session_{}.jsonl", std::process::id()));
+        let mut recorder = CmdRecorder::create(&path).unwrap();
+        recorder.record(&AppCmd::Video(VideoCmd::Play(vec!["a.mp4".to_string()]))).unwrap();
+        recorder.record(&AppCmd::Scale(0.5)).unwrap();
+        drop(recorder);
+        let cmds = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(cmds.len(), 2);
+        assert!(cmds[0].secs <= cmds[1].secs);
+        assert!(matches!(&cmds[0].cmd, AppCmd::Video(VideoCmd::Play(args)) if args == &["a.mp4"]));
+        assert!(matches!(cmds[1].cmd, AppCmd::Scale(s) if s == 0.5));
+    }
+
+    #[test]
+    fn replay_all() {
+        let cmds =
+            [0.0, 0.01, 0.0].map(|secs| RecordedCmd { secs, cmd: AppCmd::Scale(secs as f32) });
+        let (tx, rx) = std::sync::mpsc::channel();
+        spawn_replay(cmds.to_vec(), tx).unwrap();
+        let scales = rx.iter().filter(|c| matches!(c, AppCmd::Scale(_))).count();
+        assert_eq!(scales, 3);
+    }
+}
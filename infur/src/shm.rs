@@ -0,0 +1,197 @@
+//! Decoded frames shared by a memory mapped file for other local processes to read
+//!
+//! The file starts with a header of 64 bytes, all little endian:
+//!
+//! | offset | type     | field                                         |
+//! |--------|----------|-----------------------------------------------|
+//! | 0      | [u8; 8]  | magic `INFURSHM`                              |
+//! | 8      | u32      | version, 1                                    |
+//! | 12     | u32      | bytes of the header, pixels start after       |
+//! | 16     | u64      | sequence, odd while a frame is written        |
+//! | 24     | u64      | frame id                                      |
+//! | 32     | u32      | width                                         |
+//! | 36     | u32      | height                                        |
+//! | 40     | u32      | channels, 3 for BGR                           |
+//! | 48     | f64      | seconds into the video                        |
+//! | 56     | u64      | frames decoded since the video was played     |
+//!
+//! A reader loads the sequence with acquire ordering, copies the header and pixels, issues an
+//! acquire fence and keeps the copy if the sequence loaded again is the same even number. The file
+//! only grows, when frames get larger than any before, readers map it again then. It's never
+//! truncated, not even when it's shared again, so that mapping readers don't fault.
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Where decoded frames are shared
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct ShmOpts {
+    /// E.g. in `/dev/shm` to stay in memory
+    pub(crate) path: PathBuf,
+}
+
+impl ShmOpts {
+    /// Of a pipeline, others than the first append their index to the path
+    pub(crate) fn for_pipeline(&self, index: usize) -> Self {
+        if index == 0 {
+            return self.clone();
+        }
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        Self { path: path.into() }
+    }
+}
+
+#[cfg(feature = "shm")]
+pub(crate) use writer::ShmWriter;
+
+#[cfg(feature = "shm")]
+mod writer {
+    use std::{
+        fs::{File, OpenOptions},
+        io,
+        path::Path,
+        sync::atomic::{fence, AtomicU64, Ordering},
+    };
+
+    use memmap2::MmapMut;
+
+    use crate::processing::Frame;
+
+    pub(super) const MAGIC: &[u8; 8] = b"INFURSHM";
+    pub(super) const VERSION: u32 = 1;
+    pub(super) const HEADER_BYTES: usize = 64;
+    pub(super) const SEQ: usize = 16;
+
+    /// Writes each frame over the last one
+    pub(crate) struct ShmWriter {
+        file: File,
+        map: MmapMut,
+        seq: u64,
+    }
+
+    impl ShmWriter {
+        /// Share by `path`, continuing the sequence of an earlier writer
+        pub(crate) fn create(path: &Path) -> io::Result<Self> {
+            let file = OpenOptions::new().read(true).write(true).create(true).open(path)?;
+            let len = file.metadata()?.len() as usize;
+            let map = Self::map(&file, len.max(HEADER_BYTES))?;
+            let mut writer = Self { file, map, seq: 0 };
+            if &writer.map[..8] == MAGIC {
+                // readers must not take frames of this writer for those seen before
+                writer.seq = (u64::from_le(writer.seq().load(Ordering::Relaxed)) + 1) & !1;
+            }
+            writer.map[..8].copy_from_slice(MAGIC);
+            writer.map[8..12].copy_from_slice(&VERSION.to_le_bytes());
+            writer.map[12..16].copy_from_slice(&(HEADER_BYTES as u32).to_le_bytes());
+            Ok(writer)
+        }
+
+        /// Map `len` bytes, the file is only ever extended
+        fn map(file: &File, len: usize) -> io::Result<MmapMut> {
+            if file.metadata()?.len() < len as u64 {
+                file.set_len(len as u64)?;
+            }
+            // the file is only written through this map, readers only read it
+            unsafe { MmapMut::map_mut(file) }
+        }
+
+        fn seq(&self) -> &AtomicU64 {
+            // page aligned map, thus the sequence is aligned as well
+            unsafe { &*(self.map.as_ptr().add(SEQ) as *const AtomicU64) }
+        }
+
+        pub(crate) fn write(&mut self, frame: &Frame) -> io::Result<()> {
+            let pixels = frame.img.as_raw();
+            let len = HEADER_BYTES + pixels.len();
+            self.seq += 1;
+            self.seq().store(self.seq.to_le(), Ordering::Relaxed);
+            // keeps writes of the frame from becoming visible before the odd sequence
+            fence(Ordering::Release);
+            if self.map.len() < len {
+                let header = self.map[..HEADER_BYTES].to_vec();
+                self.map = Self::map(&self.file, len)?;
+                self.map[..HEADER_BYTES].copy_from_slice(&header);
+            }
+            let (width, height) = frame.img.dimensions();
+            self.map[24..32].copy_from_slice(&frame.id.to_le_bytes());
+            self.map[32..36].copy_from_slice(&width.to_le_bytes());
+            self.map[36..40].copy_from_slice(&height.to_le_bytes());
+            self.map[40..44].copy_from_slice(&3u32.to_le_bytes());
+            self.map[48..56].copy_from_slice(&frame.position.to_le_bytes());
+            self.map[56..64].copy_from_slice(&frame.decoded.to_le_bytes());
+            self.map[HEADER_BYTES..len].copy_from_slice(pixels);
+            self.seq += 1;
+            self.seq().store(self.seq.to_le(), Ordering::Release);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Frame id, size and pixels as a reader would, none before or while written
+    #[cfg(feature = "shm")]
+    fn read_frame(data: &[u8]) -> Option<(u64, [u32; 2], &[u8])> {
+        use writer::{MAGIC, SEQ, VERSION};
+        let u32_at =
+            |at: usize| data.get(at..at + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+        let u64_at =
+            |at: usize| data.get(at..at + 8).map(|b| u64::from_le_bytes(b.try_into().unwrap()));
+        if data.get(..8)? != MAGIC
+            || u32_at(8)? != VERSION
+            || !matches!(u64_at(SEQ)?, seq if seq > 0 && seq % 2 == 0)
+        {
+            return None;
+        }
+        let header = u32_at(12)? as usize;
+        let size = [u32_at(32)?, u32_at(36)?];
+        let len = size[0] as usize * size[1] as usize * u32_at(40)? as usize;
+        Some((u64_at(24)?, size, data.get(header..header + len)?))
+    }
+
+    #[test]
+    fn pipeline_paths() {
+        let opts = ShmOpts { path: "/dev/shm/infur".into() };
+        assert_eq!(opts.for_pipeline(0), opts);
+        assert_eq!(opts.for_pipeline(2).path, PathBuf::from("/dev/shm/infur.2"));
+    }
+
+    #[cfg(feature = "shm")]
+    #[test]
+    fn write_frames() {
+        use std::sync::Arc;
+
+        use image_ext::BgrImage;
+
+        use crate::processing::Frame;
+
+        let path = std::env::temp_dir().join(format!("infur_shm_{}", std::process::id()));
+        let mut writer = ShmWriter::create(&path).unwrap();
+        assert!(read_frame(&std::fs::read(&path).unwrap()).is_none(), "nothing written yet");
+        for (id, size) in [(1, 4), (2, 2)] {
+            let img = BgrImage::from_raw(size, size, vec![id as u8; (size * size * 3) as usize]);
+            writer.write(&Frame::new(id, Arc::new(img.unwrap()))).unwrap();
+            let data = std::fs::read(&path).unwrap();
+            let (read_id, read_size, pixels) = read_frame(&data).unwrap();
+            assert_eq!((read_id, read_size), (id, [size, size]));
+            assert!(pixels.iter().all(|p| *p == id as u8));
+        }
+        let len = std::fs::metadata(&path).unwrap().len();
+        assert_eq!(len, 64 + 4 * 4 * 3, "smaller frames don't shrink the file");
+
+        // sharing again neither truncates the file nor restarts the sequence
+        let seq = |data: &[u8]| u64::from_le_bytes(data[16..24].try_into().unwrap());
+        let before = seq(&std::fs::read(&path).unwrap());
+        let mut writer = ShmWriter::create(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+        let img = BgrImage::new(1, 1);
+        writer.write(&Frame::new(3, Arc::new(img))).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        assert!(seq(&data) > before);
+        assert_eq!(read_frame(&data).unwrap().0, 3);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
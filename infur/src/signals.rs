@@ -0,0 +1,22 @@
+use tracing::{info, warn};
+
+use crate::{app::AppCmd, processing::VideoCmd};
+
+/// Commands to wind down processing cleanly
+pub(crate) fn stop_cmds() -> [AppCmd; 2] {
+    [AppCmd::Video(VideoCmd::Stop), AppCmd::Exit]
+}
+
+/// Call `on_stop` on the first SIGINT or SIGTERM and exit right away on the next
+pub(crate) fn on_stop(mut on_stop: impl FnMut() + Send + 'static) -> Result<(), ctrlc::Error> {
+    let mut stopping = false;
+    ctrlc::set_handler(move || {
+        if stopping {
+            warn!("forced exit");
+            std::process::exit(130);
+        }
+        stopping = true;
+        info!("stopping, repeat to force exit");
+        on_stop();
+    })
+}
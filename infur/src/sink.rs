@@ -0,0 +1,227 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::app::FrameRecord;
+
+/// Error persisting results
+#[derive(Error, Debug)]
+pub(crate) enum SinkError {
+    #[error("couldn't create sink {path:?}")]
+    Create {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("results database: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(
+        "unknown sink {0:?}, expected stdout, jsonl:PATH, csv:PATH, sqlite:PATH or webhook:URL"
+    )]
+    Spec(String),
+}
+
+/// Receives structured results of every processed frame
+pub(crate) trait ResultSink: Send {
+    fn write(&mut self, record: &FrameRecord) -> Result<(), SinkError>;
+
+    /// Persist buffered results
+    fn flush(&mut self) -> Result<(), SinkError> {
+        Ok(())
+    }
+}
+
+/// One JSON object per line
+pub(crate) struct JsonlSink<W: Write> {
+    writer: W,
+}
+
+impl JsonlSink<BufWriter<File>> {
+    pub(crate) fn create(path: &Path) -> Result<Self, SinkError> {
+        Ok(Self::new(BufWriter::new(create(path)?)))
+    }
+}
+
+impl<W: Write> JsonlSink<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write + Send> ResultSink for JsonlSink<W> {
+    fn write(&mut self, record: &FrameRecord) -> Result<(), SinkError> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// Comma separated values with class areas joined by `;`
+pub(crate) struct CsvSink<W: Write> {
+    writer: W,
+    header_written: bool,
+}
+
+impl CsvSink<BufWriter<File>> {
+    pub(crate) fn create(path: &Path) -> Result<Self, SinkError> {
+        Ok(Self::new(BufWriter::new(create(path)?)))
+    }
+}
+
+impl<W: Write> CsvSink<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self { writer, header_written: false }
+    }
+}
+
+impl<W: Write + Send> ResultSink for CsvSink<W> {
+    fn write(&mut self, record: &FrameRecord) -> Result<(), SinkError> {
+        if !self.header_written {
+            writeln!(self.writer, "id,timestamp,class_areas")?;
+            self.header_written = true;
+        }
+        let areas = record.class_areas.iter().map(u64::to_string).collect::<Vec<_>>().join(";");
+        writeln!(self.writer, "{},{:.6},{}", record.id, record.timestamp, areas)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), SinkError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+fn create(path: &Path) -> Result<File, SinkError> {
+    File::create(path).map_err(|source| SinkError::Create { path: path.to_path_buf(), source })
+}
+
+/// Where to write results to
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub(crate) enum SinkSpec {
+    /// JSON lines on standard output
+    Stdout,
+    Jsonl {
+        path: PathBuf,
+    },
+    Csv {
+        path: PathBuf,
+    },
+    /// Database to query results by
+    #[cfg(feature = "sqlite")]
+    Sqlite {
+        path: PathBuf,
+    },
+    /// Publish to an MQTT broker
+    #[cfg(feature = "mqtt")]
+    Mqtt(crate::mqtt_sink::MqttOpts),
+    /// Post to a URL
+    #[cfg(feature = "webhook")]
+    Webhook(crate::webhook_sink::WebhookOpts),
+}
+
+impl FromStr for SinkSpec {
+    type Err = SinkError;
+
+    /// Parse `stdout`, `jsonl:PATH`, `csv:PATH`, `sqlite:PATH` or `webhook:URL`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "stdout" => Ok(Self::Stdout),
+            Some(("jsonl", path)) if !path.is_empty() => Ok(Self::Jsonl { path: path.into() }),
+            Some(("csv", path)) if !path.is_empty() => Ok(Self::Csv { path: path.into() }),
+            #[cfg(feature = "sqlite")]
+            Some(("sqlite", path)) if !path.is_empty() => Ok(Self::Sqlite { path: path.into() }),
+            #[cfg(feature = "webhook")]
+            Some(("webhook", url)) if !url.is_empty() => {
+                Ok(Self::Webhook(crate::webhook_sink::WebhookOpts::new(url)))
+            }
+            _ => Err(SinkError::Spec(s.to_string())),
+        }
+    }
+}
+
+impl SinkSpec {
+    pub(crate) fn open(&self) -> Result<Box<dyn ResultSink>, SinkError> {
+        Ok(match self {
+            Self::Stdout => Box::new(JsonlSink::new(std::io::stdout())),
+            Self::Jsonl { path } => Box::new(JsonlSink::create(path)?),
+            Self::Csv { path } => Box::new(CsvSink::create(path)?),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite { path } => Box::new(crate::results_db::ResultsDb::open(path)?),
+            #[cfg(feature = "mqtt")]
+            Self::Mqtt(opts) => Box::new(crate::mqtt_sink::MqttSink::connect(opts)?),
+            #[cfg(feature = "webhook")]
+            Self::Webhook(opts) => Box::new(crate::webhook_sink::WebhookSink::connect(opts)?),
+        })
+    }
+}
+
+/// Database of the first SQLite sink, if any
+#[cfg(feature = "sqlite")]
+pub(crate) fn results_db(specs: &[SinkSpec]) -> Option<PathBuf> {
+    specs.iter().find_map(|spec| match spec {
+        SinkSpec::Sqlite { path } => Some(path.clone()),
+        _ => None,
+    })
+}
+
+/// Open all sinks, failing on the first one that can't be created
+pub(crate) fn open_all(specs: &[SinkSpec]) -> Result<Vec<Box<dyn ResultSink>>, SinkError> {
+    specs.iter().map(SinkSpec::open).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(class_areas: &[u64]) -> FrameRecord<'_> {
+        FrameRecord::of_classes(3, 1.5, class_areas)
+    }
+
+    #[test]
+    fn parse_specs() {
+        assert_eq!("stdout".parse::<SinkSpec>().unwrap(), SinkSpec::Stdout);
+        assert_eq!(
+            "csv:out/a.csv".parse::<SinkSpec>().unwrap(),
+            SinkSpec::Csv { path: "out/a.csv".into() }
+        );
+        assert!("csv:".parse::<SinkSpec>().is_err());
+        assert!("parquet:a.pq".parse::<SinkSpec>().is_err());
+    }
+
+    #[test]
+    fn write_jsonl() {
+        let mut sink = JsonlSink::new(vec![]);
+        sink.write(&record(&[1, 2])).unwrap();
+        sink.write(&record(&[])).unwrap();
+        let text = String::from_utf8(sink.writer).unwrap();
+        assert_eq!(
+            text,
+            "{\"id\":3,\"timestamp\":1.5,\"class_areas\":[1,2]}\n\
+             {\"id\":3,\"timestamp\":1.5,\"class_areas\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn write_csv() {
+        let mut sink = CsvSink::new(vec![]);
+        sink.write(&record(&[1, 2])).unwrap();
+        sink.write(&record(&[])).unwrap();
+        let text = String::from_utf8(sink.writer).unwrap();
+        assert_eq!(text, "id,timestamp,class_areas\n3,1.500000,1;2\n3,1.500000,\n");
+    }
+}
@@ -0,0 +1,227 @@
+//! Drive headless processing as a subprocess by JSON lines
+//!
+//! Each line on stdin is a command like `{"cmd":"play","input":["video.mp4"]}`, each line on
+//! stdout an event like `{"event":"frame","id":1,...}`. Logs go to stderr.
+use std::{
+    io::{self, BufRead, Write},
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, RecvTimeoutError, TryRecvError},
+    },
+    thread,
+    time::Duration,
+};
+
+use ff_video::VideoProcError;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::{
+    app::{prioritize, AppCmd, AppInfo, AppProcError, FrameRecord, Processor},
+    headless::{configured_app, BatchOpts},
+    predict_onnx::ModelCmd,
+    processing::VideoCmd,
+    signals, Result,
+};
+
+/// How often a stop signal is checked while waiting on commands
+const STOP_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Command of a line on stdin
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+enum StdioCmd {
+    /// Start or restart playing video from this ffmpeg input
+    Play {
+        input: Vec<String>,
+    },
+    Pause {
+        paused: bool,
+    },
+    Stop,
+    Seek {
+        secs: f64,
+    },
+    /// Load an ONNX model
+    Model {
+        path: String,
+    },
+    Scale {
+        factor: f32,
+    },
+    /// Stop and quit
+    Exit,
+}
+
+impl From<StdioCmd> for AppCmd {
+    fn from(cmd: StdioCmd) -> Self {
+        match cmd {
+            StdioCmd::Play { input } => AppCmd::Video(VideoCmd::Play(input)),
+            StdioCmd::Pause { paused } => AppCmd::Video(VideoCmd::Pause(paused)),
+            StdioCmd::Stop => AppCmd::Video(VideoCmd::Stop),
+            StdioCmd::Seek { secs } => AppCmd::Video(VideoCmd::Seek(secs)),
+            StdioCmd::Model { path } => AppCmd::Model(ModelCmd::Load(path)),
+            StdioCmd::Scale { factor } => AppCmd::Scale(factor),
+            StdioCmd::Exit => AppCmd::Exit,
+        }
+    }
+}
+
+/// Event of a line on stdout
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum StdioEvent<'a> {
+    /// Results of a processed frame
+    Frame(FrameRecord<'a>),
+    /// State after commands were applied
+    Info(&'a AppInfo),
+    /// The current video finished
+    Ended,
+    /// A command was invalid or failed, or a frame failed
+    Error { message: String },
+}
+
+/// Write an event as one line and flush it right away
+fn emit(out: &mut impl Write, event: &StdioEvent) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, event)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+/// Read commands from stdin until it's closed, invalid lines are reported as errors
+fn read_cmds(cmd_tx: mpsc::Sender<std::result::Result<AppCmd, String>>) {
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                debug!("couldn't read stdin: {}", e);
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let cmd = serde_json::from_str::<StdioCmd>(&line)
+            .map(AppCmd::from)
+            .map_err(|e| format!("invalid command: {e}"));
+        if cmd_tx.send(cmd).is_err() {
+            break;
+        }
+    }
+}
+
+/// Process commands from stdin and write events to stdout
+///
+/// Inputs given by arguments are played right away. Once stdin is closed, the current video is
+/// finished before exiting.
+pub(crate) fn run(opts: &BatchOpts) -> Result<()> {
+    let mut app = configured_app(opts, 0)?;
+    let input =
+        opts.inputs.first().map(|i| vec![i.clone()]).unwrap_or_else(|| opts.ffmpeg_args.clone());
+    if !input.is_empty() {
+        app.control(AppCmd::Video(VideoCmd::Play(input)))?;
+    }
+    let (cmd_tx, cmd_rx) = mpsc::channel();
+    thread::Builder::new().name("Stdin".to_string()).spawn(move || read_cmds(cmd_tx))?;
+    let mut out = io::stdout().lock();
+    let mut hung_up = false;
+
+    loop {
+        let mut received = vec![];
+        if !app.is_dirty() {
+            if hung_up {
+                break;
+            }
+            match cmd_rx.recv_timeout(STOP_INTERVAL) {
+                Ok(c) => received.push(c),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => hung_up = true,
+            }
+        }
+        loop {
+            match cmd_rx.try_recv() {
+                Ok(c) => received.push(c),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    hung_up = true;
+                    break;
+                }
+            }
+        }
+        if opts.stop.load(Ordering::Relaxed) {
+            // close the video gracefully
+            for cmd in signals::stop_cmds() {
+                app.control(cmd)?;
+            }
+            return Ok(());
+        }
+
+        let mut cmds = vec![];
+        for cmd in received {
+            match cmd {
+                Ok(cmd) => cmds.push(cmd),
+                Err(message) => emit(&mut out, &StdioEvent::Error { message })?,
+            }
+        }
+        let mut state_change = false;
+        for cmd in prioritize(cmds) {
+            debug!("relaying command: {:?}", cmd);
+            match app.control(cmd) {
+                Ok(()) => state_change = true,
+                Err(e) => emit(&mut out, &StdioEvent::Error { message: e.to_string() })?,
+            }
+            if app.to_exit {
+                return Ok(());
+            }
+        }
+        if state_change {
+            emit(&mut out, &StdioEvent::Info(&app.info()))?;
+        }
+
+        if !app.is_dirty() {
+            continue;
+        }
+        match app.generate() {
+            Ok(Some(frame)) => emit(&mut out, &StdioEvent::Frame(frame.record()))?,
+            Ok(None) => {}
+            Err(AppProcError::Video(VideoProcError::FinishedNormally { .. })) => {
+                emit(&mut out, &StdioEvent::Ended)?;
+            }
+            Err(e) => emit(&mut out, &StdioEvent::Error { message: e.to_string() })?,
+        }
+    }
+    info!("stdin closed, exiting");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_cmds() {
+        let parse = |line: &str| serde_json::from_str::<StdioCmd>(line);
+        assert_eq!(
+            parse(r#"{"cmd":"play","input":["-i","a.mp4"]}"#).unwrap(),
+            StdioCmd::Play { input: vec!["-i".into(), "a.mp4".into()] }
+        );
+        assert_eq!(parse(r#"{"cmd":"seek","secs":2.5}"#).unwrap(), StdioCmd::Seek { secs: 2.5 });
+        assert_eq!(parse(r#"{"cmd":"exit"}"#).unwrap(), StdioCmd::Exit);
+        assert!(parse(r#"{"cmd":"scale"}"#).is_err());
+        assert!(parse(r#"{"cmd":"rewind"}"#).is_err());
+    }
+
+    #[test]
+    fn emit_events() {
+        let mut out = vec![];
+        emit(&mut out, &StdioEvent::Frame(FrameRecord::of_classes(2, 0.5, &[4]))).unwrap();
+        emit(&mut out, &StdioEvent::Ended).unwrap();
+        emit(&mut out, &StdioEvent::Error { message: "nope".into() }).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"event\":\"frame\",\"id\":2,\"timestamp\":0.5,\"class_areas\":[4]}\n\
+             {\"event\":\"ended\"}\n\
+             {\"event\":\"error\",\"message\":\"nope\"}\n"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender, SyncSender},
+        Arc, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use ff_video::AbortHandle;
+use stable_eyre::eyre::eyre;
+use tracing::{info, warn};
+
+use crate::{
+    app::{prioritize, AppCmd, SharedStatus},
+    clips::FrameHistory,
+    embeddings::EmbeddingIndex,
+    events::{FrameSlot, ProcEvent, ProcIssue},
+    preview::Preview,
+    session::CmdRecorder,
+    sink::ResultSink,
+    Result,
+};
+
+/// How often processing is checked on without commands coming in
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// Give up after this many incidents within `INCIDENT_WINDOW`
+const MAX_INCIDENTS: usize = 3;
+const INCIDENT_WINDOW: Duration = Duration::from_secs(30);
+
+/// Outputs of processing that outlive restarts
+pub(crate) struct ProcShared {
+    pub(crate) status: SharedStatus,
+    pub(crate) preview: Arc<Preview>,
+    /// Recent frames to export clips from
+    pub(crate) history: Arc<FrameHistory>,
+    /// Embeddings of recent frames to search
+    pub(crate) embeddings: Arc<EmbeddingIndex>,
+    pub(crate) sinks: Mutex<Vec<Box<dyn ResultSink>>>,
+}
+
+/// Why processing had to be restarted
+enum Failure {
+    Stopped,
+    Hung(Duration),
+}
+
+/// One processing thread
+struct Worker {
+    ctrl_tx: Sender<AppCmd>,
+    abort: Arc<Mutex<Option<AbortHandle>>>,
+    thread: JoinHandle<Result<()>>,
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(msg), _) => msg,
+        (_, Some(msg)) => msg,
+        _ => "unknown reason",
+    }
+}
+
+impl Worker {
+    /// Kill the current video, which unblocks reading it
+    fn abort(&self) {
+        if let Some(abort) = self.abort.lock().unwrap_or_else(PoisonError::into_inner).as_ref() {
+            abort.abort();
+        }
+    }
+
+    fn failure(&self, status: &SharedStatus, timeout: Duration) -> Option<Failure> {
+        if self.thread.is_finished() {
+            return Some(Failure::Stopped);
+        }
+        if timeout.is_zero() {
+            return None;
+        }
+        let busy_since = status.lock().unwrap_or_else(PoisonError::into_inner).busy_since?;
+        (busy_since.elapsed() > timeout).then_some(Failure::Hung(timeout))
+    }
+
+    /// Let go of a failed worker and describe what happened
+    fn discard(self, failure: Failure) -> String {
+        self.abort();
+        match failure {
+            Failure::Hung(timeout) => {
+                // the thread ends by itself once unblocked since its commands hung up
+                format!("processing hung for more than {}s", timeout.as_secs())
+            }
+            Failure::Stopped => match self.thread.join() {
+                Err(panic) => format!("processing panicked: {}", panic_message(panic.as_ref())),
+                Ok(Err(e)) => format!("processing failed: {}", e),
+                Ok(Ok(())) => "processing stopped".to_string(),
+            },
+        }
+    }
+
+    /// Wind down processing as fast as possible
+    fn exit(self) -> Result<()> {
+        self.abort();
+        let _ = self.ctrl_tx.send(AppCmd::Exit);
+        self.thread.join().map_err(|panic| eyre!("{}", panic_message(panic.as_ref())))?
+    }
+}
+
+/// Relays commands to processing and restarts it if it panics or hangs
+pub(crate) struct Supervisor {
+    /// Number of the pipeline, distinguishes threads
+    pub(crate) id: usize,
+    pub(crate) event_tx: SyncSender<ProcEvent>,
+    /// Latest frame for the GUI
+    pub(crate) frames: Arc<FrameSlot>,
+    pub(crate) shared: Arc<ProcShared>,
+    pub(crate) recorder: Option<CmdRecorder>,
+    /// Restart processing if busy for longer without progress
+    pub(crate) timeout: Duration,
+}
+
+impl Supervisor {
+    /// Start processing and bring it to the state of all previous commands
+    fn spawn_worker(&self, history: &[AppCmd]) -> Result<Worker> {
+        let (ctrl_tx, ctrl_rx) = std::sync::mpsc::channel();
+        for cmd in history {
+            ctrl_tx.send(cmd.clone())?;
+        }
+        let abort = Arc::new(Mutex::new(None));
+        let (event_tx, frames) = (self.event_tx.clone(), self.frames.clone());
+        let (shared, proc_abort) = (self.shared.clone(), abort.clone());
+        let thread = thread::Builder::new()
+            .name(format!("Proc{}", self.id))
+            .spawn(move || crate::proc_loop(ctrl_rx, event_tx, frames, shared, proc_abort))?;
+        Ok(Worker { ctrl_tx, abort, thread })
+    }
+
+    /// Relay commands until exit is requested or all senders hung up
+    ///
+    /// Processing is restarted automatically unless incidents pile up,
+    /// in which case it waits for `AppCmd::Restart`.
+    pub(crate) fn run(mut self, ctrl_rx: Receiver<AppCmd>) -> Result<()> {
+        // the latest intent of each kind reproduces the current state
+        let mut history = vec![];
+        let mut incidents = VecDeque::with_capacity(MAX_INCIDENTS);
+        let mut worker = Some(self.spawn_worker(&history)?);
+        loop {
+            let cmd = match ctrl_rx.recv_timeout(CHECK_INTERVAL) {
+                Ok(cmd) => Some(cmd),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return worker.map_or(Ok(()), Worker::exit),
+            };
+            if let Some(cmd) = cmd {
+                if let Some(recorder) = &mut self.recorder {
+                    if let Err(e) = recorder.record(&cmd) {
+                        warn!("couldn't record command: {}", e);
+                    }
+                }
+                match cmd {
+                    AppCmd::Exit => return worker.map_or(Ok(()), Worker::exit),
+                    AppCmd::Restart => {
+                        if let Some(worker) = worker.take() {
+                            worker.abort();
+                        }
+                        incidents.clear();
+                        worker = Some(self.spawn_worker(&history)?);
+                        info!("restarted processing on request");
+                    }
+                    cmd => {
+                        history.push(cmd.clone());
+                        history = prioritize(history);
+                        if let Some(worker) = &worker {
+                            // a failed send is taken care of below
+                            let _ = worker.ctrl_tx.send(cmd);
+                        }
+                    }
+                }
+            }
+
+            let failure =
+                worker.as_ref().and_then(|w| w.failure(&self.shared.status, self.timeout));
+            if let (Some(failure), Some(failed)) = (failure, worker.take()) {
+                let incident = failed.discard(failure);
+                warn!("processing incident: {}", incident);
+                incidents.retain(|t: &Instant| t.elapsed() < INCIDENT_WINDOW);
+                incidents.push_back(Instant::now());
+                {
+                    let mut status =
+                        self.shared.status.lock().unwrap_or_else(PoisonError::into_inner);
+                    status.busy_since = None;
+                    status.error = Some(incident.clone());
+                }
+                if incidents.len() >= MAX_INCIDENTS {
+                    warn!("not restarting after {} incidents", incidents.len());
+                    let _ = self.event_tx.send(ProcEvent::FatalError(incident));
+                    continue;
+                }
+                self.shared.status.lock().unwrap_or_else(PoisonError::into_inner).restarts += 1;
+                let _ = self.event_tx.send(ProcEvent::Progress(ProcIssue::Restarted(incident)));
+                worker = Some(self.spawn_worker(&history)?);
+                info!("restarted processing with {} commands", history.len());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::predict_onnx::ModelCmd;
+    use infur_test_gen::fcn_resnet50_12_int8_onnx;
+
+    #[test]
+    fn panic_messages() {
+        let panic = thread::spawn(|| panic!("static")).join().unwrap_err();
+        assert_eq!(panic_message(panic.as_ref()), "static");
+        let panic = thread::spawn(|| panic!("formatted {}", 1)).join().unwrap_err();
+        assert_eq!(panic_message(panic.as_ref()), "formatted 1");
+    }
+
+    #[test]
+    fn slow_commands_arent_hangs() {
+        let (event_tx, event_rx) = std::sync::mpsc::sync_channel(64);
+        let shared = Arc::new(ProcShared {
+            status: SharedStatus::default(),
+            preview: Arc::default(),
+            history: Arc::default(),
+            embeddings: Arc::default(),
+            sinks: Mutex::default(),
+        });
+        let supervisor = Supervisor {
+            id: 0,
+            event_tx,
+            frames: Arc::default(),
+            shared: shared.clone(),
+            recorder: None,
+            timeout: Duration::from_millis(10),
+        };
+        let (ctrl_tx, ctrl_rx) = std::sync::mpsc::channel();
+        let supervising = thread::spawn(move || supervisor.run(ctrl_rx));
+        let model = fcn_resnet50_12_int8_onnx().to_string_lossy().to_string();
+        ctrl_tx.send(AppCmd::Model(ModelCmd::Load(model))).unwrap();
+        // each command checks on processing, loading the model takes longer than the timeout
+        for _ in 0..30 {
+            thread::sleep(Duration::from_millis(10));
+            ctrl_tx.send(AppCmd::Scale(0.5)).unwrap();
+        }
+        ctrl_tx.send(AppCmd::Exit).unwrap();
+        supervising.join().unwrap().unwrap();
+        assert_eq!(shared.status.lock().unwrap().restarts, 0);
+        let restarted = |e: &ProcEvent| matches!(e, ProcEvent::Progress(ProcIssue::Restarted(_)));
+        assert!(!event_rx.try_iter().any(|e| restarted(&e)));
+    }
+}
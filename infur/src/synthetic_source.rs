@@ -0,0 +1,267 @@
+use std::{
+    io, thread,
+    time::{Duration, Instant},
+};
+
+use ff_video::{Stream, VideoProcError, VideoResult};
+use image_ext::{Bgr, BgrImage};
+
+use crate::{
+    processing::Clip,
+    video_source::{SourceInfo, VideoSource},
+};
+
+/// Prefix of inputs generated in-process, e.g. `synthetic:box?size=320x240&fps=30`
+pub(crate) const SCHEME: &str = "synthetic:";
+
+/// What frames show
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Pattern {
+    /// Colors shifting over time
+    Gradient,
+    /// Random pixels, e.g. to benchmark without compressible content
+    Noise,
+    /// A box bouncing on a dark background
+    Box,
+}
+
+/// Parameters of a synthetic input
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SyntheticSpec {
+    pub(crate) pattern: Pattern,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) fps: f32,
+    /// Frames until the video ends, endless if not given
+    pub(crate) frames: Option<u64>,
+    /// Produce frames no faster than `fps` instead of as fast as they are read
+    pub(crate) realtime: bool,
+}
+
+impl Default for SyntheticSpec {
+    fn default() -> Self {
+        Self {
+            pattern: Pattern::Box,
+            width: 640,
+            height: 480,
+            fps: 30.0,
+            frames: None,
+            realtime: false,
+        }
+    }
+}
+
+impl SyntheticSpec {
+    /// Parameters of `synthetic:[pattern][?key=value&...]`, `None` if not synthetic
+    ///
+    /// Keys are `size` (e.g. `320x240`), `fps`, `frames` and `realtime` (`1` or `true`).
+    pub(crate) fn parse(input: &str) -> Option<Result<Self, String>> {
+        let spec = input.strip_prefix(SCHEME)?;
+        Some(Self::parse_params(spec))
+    }
+
+    fn parse_params(spec: &str) -> Result<Self, String> {
+        let (pattern, params) = spec.split_once('?').unwrap_or((spec, ""));
+        let mut parsed = Self {
+            pattern: match pattern {
+                "" | "box" => Pattern::Box,
+                "gradient" => Pattern::Gradient,
+                "noise" => Pattern::Noise,
+                other => return Err(format!("unknown pattern {other}")),
+            },
+            ..Self::default()
+        };
+        for param in params.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let invalid = || format!("invalid {key} {value:?}");
+            match key {
+                "size" => {
+                    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+                    parsed.width = width.parse().map_err(|_| invalid())?;
+                    parsed.height = height.parse().map_err(|_| invalid())?;
+                }
+                "fps" => parsed.fps = value.parse().map_err(|_| invalid())?,
+                "frames" => parsed.frames = Some(value.parse().map_err(|_| invalid())?),
+                "realtime" => parsed.realtime = matches!(value, "" | "1" | "true"),
+                _ => return Err(format!("unknown parameter {key}")),
+            }
+        }
+        if parsed.width == 0 || parsed.height == 0 || parsed.fps <= 0.0 {
+            return Err("size and fps must be positive".to_string());
+        }
+        Ok(parsed)
+    }
+}
+
+/// Generates frames in-process, e.g. to run without ffmpeg or to benchmark inference
+pub(crate) struct SyntheticSource {
+    spec: SyntheticSpec,
+    input: String,
+    /// Index of the next frame from the video's start, which patterns are drawn at
+    next: u64,
+    /// Index at which the video ends
+    end: Option<u64>,
+    /// Id of the last frame produced
+    id: u64,
+    started: Instant,
+    /// State of the noise generator
+    seed: u64,
+}
+
+impl SyntheticSource {
+    fn stream(&self) -> Stream {
+        let SyntheticSpec { width, height, fps, .. } = self.spec;
+        Stream::new(width, height, Some(fps), Some("synthetic".to_string()))
+    }
+
+    fn draw(&mut self, image: &mut BgrImage) {
+        let (width, height) = image.dimensions();
+        let t = self.next;
+        match self.spec.pattern {
+            Pattern::Gradient => {
+                for (x, y, px) in image.enumerate_pixels_mut() {
+                    let b = (x as u64 * 255 / width as u64 + t) % 256;
+                    let g = y as u64 * 255 / height as u64;
+                    *px = Bgr([b as u8, g as u8, (t * 4 % 256) as u8]);
+                }
+            }
+            Pattern::Noise => {
+                for chunk in image.as_mut().chunks_mut(8) {
+                    // xorshift64
+                    self.seed ^= self.seed << 13;
+                    self.seed ^= self.seed >> 7;
+                    self.seed ^= self.seed << 17;
+                    chunk.copy_from_slice(&self.seed.to_le_bytes()[..chunk.len()]);
+                }
+            }
+            Pattern::Box => {
+                let size = (width.min(height) / 5).max(1);
+                let left = bounce(t * 4, width - size);
+                let top = bounce(t * 3, height - size);
+                for (x, y, px) in image.enumerate_pixels_mut() {
+                    let inside = (left..left + size).contains(&x) && (top..top + size).contains(&y);
+                    *px = if inside { Bgr([0, 200, 255]) } else { Bgr([32, 32, 32]) };
+                }
+            }
+        }
+    }
+}
+
+/// Position moving back and forth within `0..=range`
+fn bounce(pos: u64, range: u32) -> u32 {
+    let range = range as u64;
+    if range == 0 {
+        return 0;
+    }
+    let pos = pos % (2 * range);
+    (if pos <= range { pos } else { 2 * range - pos }) as u32
+}
+
+impl VideoSource for SyntheticSource {
+    fn open(input: &[String], clip: Clip) -> VideoResult<Self> {
+        let input = input.first().cloned().unwrap_or_default();
+        let spec = SyntheticSpec::parse(&input)
+            .unwrap_or_else(|| Err(format!("input doesn't start with {SCHEME}")))
+            .map_err(|e| VideoProcError::Other(format!("invalid synthetic input {input}: {e}")))?;
+        let index = |secs: f64| (secs * spec.fps as f64).round().max(0.0) as u64;
+        let next = clip.start.map_or(0, index);
+        let end = match (clip.end.map(index), spec.frames) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Ok(Self {
+            spec,
+            input,
+            next,
+            end,
+            id: 0,
+            started: Instant::now(),
+            seed: 0x2545_f491_4f6c_dd1d,
+        })
+    }
+
+    fn info(&self) -> SourceInfo {
+        let stream = self.stream();
+        SourceInfo {
+            output: stream.clone(),
+            input: Some((self.input.clone(), stream)),
+            version: None,
+        }
+    }
+
+    fn read_frame(&mut self, image: &mut BgrImage) -> VideoResult<u64> {
+        if self.end.map_or(false, |end| self.next >= end) {
+            let source = io::Error::new(io::ErrorKind::UnexpectedEof, "synthetic video ended");
+            return Err(VideoProcError::FinishedNormally { source });
+        }
+        if self.spec.realtime {
+            let due = self.started + Duration::from_secs_f64(self.id as f64 / self.spec.fps as f64);
+            if let Some(wait) = due.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+        if image.dimensions() != (self.spec.width, self.spec.height) {
+            *image = BgrImage::new(self.spec.width, self.spec.height);
+        }
+        self.draw(image);
+        self.next += 1;
+        self.id += 1;
+        Ok(self.id)
+    }
+
+    fn close(self: Box<Self>) -> VideoResult<()> {
+        Ok(())
+    }
+
+    fn resyncs(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn open(input: &str, clip: Clip) -> SyntheticSource {
+        SyntheticSource::open(&[input.to_string()], clip).unwrap()
+    }
+
+    #[test]
+    fn parse_specs() {
+        assert_eq!(SyntheticSpec::parse("video.mp4"), None);
+        let spec = SyntheticSpec::parse("synthetic:noise?size=32x24&fps=10&frames=3").unwrap();
+        let expected = SyntheticSpec {
+            pattern: Pattern::Noise,
+            width: 32,
+            height: 24,
+            fps: 10.0,
+            frames: Some(3),
+            realtime: false,
+        };
+        assert_eq!(spec, Ok(expected));
+        assert_eq!(SyntheticSpec::parse("synthetic:").unwrap(), Ok(SyntheticSpec::default()));
+        assert!(SyntheticSpec::parse("synthetic:box?size=0x24").unwrap().is_err());
+        assert!(SyntheticSpec::parse("synthetic:plaid").unwrap().is_err());
+    }
+
+    #[test]
+    fn frames_until_end() {
+        let clip = Clip { start: Some(1.0), end: Some(1.5) };
+        let mut vid = open("synthetic:box?size=40x30&fps=10", clip);
+        let mut img = BgrImage::new(0, 0);
+        let ids = (0..5).map(|_| vid.read_frame(&mut img).unwrap()).collect::<Vec<_>>();
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
+        assert_eq!(img.dimensions(), (40, 30));
+        assert!(matches!(vid.read_frame(&mut img), Err(VideoProcError::FinishedNormally { .. })));
+    }
+
+    #[test]
+    fn box_moves() {
+        let mut vid = open("synthetic:box?size=40x30", Clip::default());
+        let (mut first, mut second) = (BgrImage::new(40, 30), BgrImage::new(40, 30));
+        vid.read_frame(&mut first).unwrap();
+        vid.read_frame(&mut second).unwrap();
+        assert!(first.as_raw() != second.as_raw());
+        assert_eq!(bounce(7, 5), 3);
+    }
+}
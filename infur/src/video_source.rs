@@ -0,0 +1,128 @@
+use ff_video::{
+    AbortHandle, FFMpegDecoder, FFMpegDecoderBuilder, ProgressHandle, Stream, VideoResult,
+};
+use image_ext::BgrImage;
+
+use crate::{
+    processing::{is_live, Clip},
+    synthetic_source::{self, SyntheticSource},
+};
+
+/// What a source tells about its video
+#[derive(Clone, Debug)]
+pub(crate) struct SourceInfo {
+    /// Stream of frames read next
+    pub(crate) output: Stream,
+    /// Input stream and where it's from, if known
+    pub(crate) input: Option<(String, Stream)>,
+    /// Of the backend producing frames, e.g. ffmpeg's
+    pub(crate) version: Option<String>,
+}
+
+/// Produces frames played by `VideoPlayer`
+pub(crate) trait VideoSource: Send {
+    /// Start producing frames of input arguments, within the clip if given
+    fn open(input: &[String], clip: Clip) -> VideoResult<Self>
+    where
+        Self: Sized;
+
+    fn info(&self) -> SourceInfo;
+
+    /// Write the next frame into `image` and return its frame id, blocks until available
+    ///
+    /// The image is resized to the output if it differs. Finished sources fail with
+    /// `VideoProcError::FinishedNormally`.
+    fn read_frame(&mut self, image: &mut BgrImage) -> VideoResult<u64>;
+
+    /// Stop producing frames gracefully
+    fn close(self: Box<Self>) -> VideoResult<()>;
+
+    /// Whether frames read after a failed one are whole again
+    ///
+    /// Not so for byte pipes, whose position within a frame is lost on a failed read.
+    fn resyncs(&self) -> bool {
+        false
+    }
+
+    /// Progress as reported by the backend, readable while frames are read elsewhere
+    fn progress_handle(&self) -> Option<ProgressHandle> {
+        None
+    }
+
+    /// Abort a stuck source from elsewhere
+    fn abort_handle(&self) -> Option<AbortHandle> {
+        None
+    }
+}
+
+impl VideoSource for FFMpegDecoder {
+    fn open(input: &[String], clip: Clip) -> VideoResult<Self> {
+        let mut builder =
+            FFMpegDecoderBuilder::default().input(input).module_fields(true).progress_pipe(true);
+        builder = if is_live(input) {
+            builder.low_latency()
+        } else if input.iter().any(|arg| arg.contains("://")) {
+            builder.network_stream()
+        } else {
+            builder.file_playback()
+        };
+        if let Some(start) = clip.start {
+            builder = builder.start(start);
+        }
+        if let Some(end) = clip.end {
+            builder = builder.end(end);
+        }
+        FFMpegDecoder::try_new(builder)
+    }
+
+    fn info(&self) -> SourceInfo {
+        SourceInfo {
+            output: self.video_output.clone(),
+            input: self.video_input.clone(),
+            version: self.capabilities.version.clone(),
+        }
+    }
+
+    fn read_frame(&mut self, image: &mut BgrImage) -> VideoResult<u64> {
+        FFMpegDecoder::read_frame(self, image)
+    }
+
+    fn close(self: Box<Self>) -> VideoResult<()> {
+        FFMpegDecoder::close(*self)
+    }
+
+    fn progress_handle(&self) -> Option<ProgressHandle> {
+        Some(FFMpegDecoder::progress_handle(self))
+    }
+
+    fn abort_handle(&self) -> Option<AbortHandle> {
+        Some(FFMpegDecoder::abort_handle(self))
+    }
+}
+
+/// Open a source for input arguments, generated if synthetic and decoded by ffmpeg otherwise
+pub(crate) fn open(input: &[String], clip: Clip) -> VideoResult<Box<dyn VideoSource>> {
+    let synthetic = input.first().map_or(false, |i| i.starts_with(synthetic_source::SCHEME));
+    if synthetic {
+        return Ok(Box::new(SyntheticSource::open(input, clip)?));
+    }
+    Ok(Box::new(FFMpegDecoder::open(input, clip)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use infur_test_gen::long_small_video;
+
+    #[test]
+    fn ffmpeg_source() {
+        let input = [long_small_video().to_string_lossy().to_string()];
+        let mut vid = open(&input, Clip { start: Some(1.0), end: None }).unwrap();
+        let info = vid.info();
+        assert_eq!([info.output.width, info.output.height], [640, 480]);
+        assert!(vid.abort_handle().is_some());
+        let mut img = BgrImage::new(info.output.width, info.output.height);
+        assert_eq!(vid.read_frame(&mut img).unwrap(), 1);
+        vid.close().unwrap();
+    }
+}
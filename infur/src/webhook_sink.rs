@@ -0,0 +1,161 @@
+use std::{
+    sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    app::FrameRecord,
+    sink::{ResultSink, SinkError},
+};
+
+/// Capacity of events queued for posting before new ones are dropped
+const QUEUE_CAP: usize = 64;
+/// Wait before the first retry, doubled with each further one
+const BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where to post JSON events to
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct WebhookOpts {
+    pub(crate) url: String,
+    /// Retries after a failed post before the event is dropped
+    #[serde(default = "default_retries")]
+    pub(crate) retries: u32,
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+impl WebhookOpts {
+    pub(crate) fn new(url: &str) -> Self {
+        Self { url: url.to_string(), retries: default_retries() }
+    }
+}
+
+/// Wait before retrying the `attempt`-th time
+fn backoff(attempt: u32) -> Duration {
+    BACKOFF.saturating_mul(1 << attempt.min(16)).min(MAX_BACKOFF)
+}
+
+/// Whether posting again may succeed
+fn retryable(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Status(code, _) => *code == 429 || *code >= 500,
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+/// Post each payload, retrying with backoff
+fn post_all(opts: WebhookOpts, payloads: Receiver<Vec<u8>>) {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    for payload in payloads {
+        for attempt in 0..=opts.retries {
+            let post =
+                agent.post(&opts.url).set("Content-Type", "application/json").send_bytes(&payload);
+            match post {
+                Ok(_) => break,
+                Err(e) if retryable(&e) && attempt < opts.retries => {
+                    debug!("retrying post to {}: {}", opts.url, e);
+                    thread::sleep(backoff(attempt));
+                }
+                Err(e) => {
+                    warn!("dropped event for {}: {}", opts.url, e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Posts JSON to a URL
+///
+/// Posting never blocks processing, events are dropped while the endpoint lags behind.
+#[derive(Clone, Debug)]
+pub(crate) struct WebhookSink {
+    tx: SyncSender<Vec<u8>>,
+}
+
+impl WebhookSink {
+    pub(crate) fn connect(opts: &WebhookOpts) -> Result<Self, SinkError> {
+        let (tx, rx) = sync_channel(QUEUE_CAP);
+        let opts = opts.clone();
+        thread::Builder::new().name("Webhook".to_string()).spawn(move || post_all(opts, rx))?;
+        Ok(Self { tx })
+    }
+
+    /// Post any message as JSON
+    pub(crate) fn publish<T: Serialize>(&self, msg: &T) -> Result<(), SinkError> {
+        match self.tx.try_send(serde_json::to_vec(msg)?) {
+            Err(TrySendError::Full(_)) => debug!("dropped webhook event, queue is full"),
+            Err(TrySendError::Disconnected(_)) => warn!("dropped webhook event, poster stopped"),
+            Ok(()) => {}
+        }
+        Ok(())
+    }
+}
+
+impl ResultSink for WebhookSink {
+    fn write(&mut self, record: &FrameRecord) -> Result<(), SinkError> {
+        self.publish(record)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert_eq!(backoff(0), BACKOFF);
+        assert_eq!(backoff(2), BACKOFF * 4);
+        assert_eq!(backoff(40), MAX_BACKOFF);
+    }
+
+    /// Serve `statuses` in turn and return the bodies received
+    fn serve(statuses: &'static [u16]) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut bodies = vec![];
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    match line.to_lowercase().strip_prefix("content-length:") {
+                        Some(n) => len = n.trim().parse().unwrap(),
+                        None if line.trim().is_empty() => break,
+                        None => {}
+                    }
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(String::from_utf8(body).unwrap());
+                let response = format!("HTTP/1.1 {status} X\r\nContent-Length: 0\r\n\r\n");
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+            bodies
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn retry_failed_posts() {
+        let (url, server) = serve(&[503, 200]);
+        let sink = WebhookSink::connect(&WebhookOpts::new(&url)).unwrap();
+        sink.publish(&[1, 2]).unwrap();
+        assert_eq!(server.join().unwrap(), ["[1,2]", "[1,2]"]);
+    }
+}
@@ -0,0 +1,134 @@
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::Annotations;
+
+/// Named polygon on frames to tell results within, e.g. a parking spot
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub(crate) struct Zone {
+    pub(crate) name: String,
+    /// Corners relative to the frame's size, e.g. `[0.5, 0.5]` at its center
+    pub(crate) points: Vec<[f32; 2]>,
+}
+
+/// Results within a zone of one frame
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub(crate) struct ZoneStats {
+    pub(crate) name: String,
+    /// Number of pixels per class of the first segmentation
+    pub(crate) class_areas: Vec<u64>,
+    /// Number of boxes per class centered within
+    pub(crate) detections: Vec<u64>,
+    /// In world units squared if calibrated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) area: Option<f64>,
+}
+
+/// Count one more of `class`
+fn count(counts: &mut Vec<u64>, class: usize) {
+    if counts.len() <= class {
+        counts.resize(class + 1, 0);
+    }
+    counts[class] += 1;
+}
+
+impl Zone {
+    /// Whether a relative point is inside by the even-odd rule
+    pub(crate) fn contains(&self, [x, y]: [f32; 2]) -> bool {
+        let n = self.points.len();
+        let mut inside = false;
+        for i in 0..n {
+            let ([xi, yi], [xj, yj]) = (self.points[i], self.points[(i + n - 1) % n]);
+            if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// Indices of `len` cells along `axis` overlapping the zone's bounds
+    fn cells(&self, axis: usize, len: usize) -> Range<usize> {
+        let values = self.points.iter().map(|p| p[axis] * len as f32);
+        let (min, max) = values.fold((f32::MAX, f32::MIN), |(l, h), v| (l.min(v), h.max(v)));
+        (min.max(0.0) as usize)..(max.ceil().max(0.0) as usize).min(len)
+    }
+
+    pub(crate) fn stats(&self, annotations: &Annotations) -> ZoneStats {
+        let mut stats = ZoneStats { name: self.name.clone(), ..ZoneStats::default() };
+        if let Some(classes) = &annotations.classes {
+            let (h, w) = classes.dim();
+            for y in self.cells(1, h) {
+                for x in self.cells(0, w) {
+                    let center = [(x as f32 + 0.5) / w as f32, (y as f32 + 0.5) / h as f32];
+                    if self.contains(center) {
+                        count(&mut stats.class_areas, classes[[y, x]] as usize);
+                    }
+                }
+            }
+        }
+        for detection in &annotations.boxes {
+            let [x1, y1, x2, y2] = detection.bbox;
+            if self.contains([(x1 + x2) / 2.0, (y1 + y2) / 2.0]) {
+                count(&mut stats.detections, detection.class);
+            }
+        }
+        stats
+    }
+}
+
+/// Results within each zone
+pub(crate) fn zone_stats(zones: &[Zone], annotations: &Annotations) -> Vec<ZoneStats> {
+    zones.iter().map(|zone| zone.stats(annotations)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use ndarray::Array2;
+
+    use super::*;
+    use crate::annotations::Detection;
+
+    /// Left half of the frame
+    fn left() -> Zone {
+        Zone {
+            name: "left".to_string(),
+            points: vec![[0.0, 0.0], [0.5, 0.0], [0.5, 1.0], [0.0, 1.0]],
+        }
+    }
+
+    #[test]
+    fn inside_polygons() {
+        assert!(left().contains([0.25, 0.5]));
+        assert!(!left().contains([0.75, 0.5]));
+        let triangle =
+            Zone { name: "t".to_string(), points: vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]] };
+        assert!(triangle.contains([0.2, 0.2]));
+        assert!(!triangle.contains([0.6, 0.6]));
+        assert!(!Zone { name: "empty".to_string(), points: vec![] }.contains([0.5, 0.5]));
+    }
+
+    #[test]
+    fn stats_within() {
+        let mut classes = Array2::<u16>::zeros((4, 8));
+        classes.slice_mut(ndarray::s![.., 2..6]).fill(1);
+        let boxes = vec![
+            Detection { class: 2, bbox: [0.0, 0.0, 0.2, 0.2] },
+            Detection { class: 2, bbox: [0.8, 0.8, 1.0, 1.0] },
+        ];
+        let annotations =
+            Annotations { classes: Some(Arc::new(classes)), boxes, ..Annotations::default() };
+        let stats = zone_stats(&[left()], &annotations);
+        assert_eq!(
+            stats,
+            [ZoneStats {
+                name: "left".to_string(),
+                class_areas: vec![8, 8],
+                detections: vec![0, 0, 1],
+                area: None,
+            }]
+        );
+    }
+}